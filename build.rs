@@ -0,0 +1,21 @@
+use std::{env, path::Path};
+
+/// The multilayer perceptron bundled by the `embedded-model` feature, see `EMBEDDED_MLP`
+const EMBEDDED_MLP: &str = "models/embedded/mlp.bin";
+
+fn main() {
+    // the feature flags are part of the build script fingerprint, and `include_bytes!` tracks
+    // the bundled file itself
+    println!("cargo:rerun-if-changed=build.rs");
+    if env::var_os("CARGO_FEATURE_EMBEDDED_MODEL").is_none() {
+        return;
+    }
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").expect("cargo sets CARGO_MANIFEST_DIR");
+    if !Path::new(&manifest_dir).join(EMBEDDED_MLP).is_file() {
+        panic!(
+            "The embedded-model feature bundles {}, which doesn't exist. Train it first with \
+             `cargo run --release -- train --example mnist --out {}`",
+            EMBEDDED_MLP, EMBEDDED_MLP
+        );
+    }
+}
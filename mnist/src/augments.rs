@@ -1,10 +1,13 @@
 use std::path::Path;
 
-use image::{imageops, GrayImage, Luma};
+use image::{GrayImage, Luma};
 use imageproc::geometric_transformations::{rotate_about_center, Interpolation};
-use log::{debug, trace};
-use ndarray::{Array, Array2, ArrayD};
-use rand::Rng;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, info, trace};
+use ndarray::{concatenate, Array, Array2, ArrayD, Axis, ShapeError};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
 
 fn array_to_image(arr: &ArrayD<u8>) -> GrayImage {
     let (width, height) = (arr.shape()[1] as u32, arr.shape()[0] as u32);
@@ -18,8 +21,7 @@ fn image_to_array(img: &GrayImage) -> Array2<u8> {
     Array::from_shape_vec((height as usize, width as usize), raw_data.clone()).unwrap()
 }
 
-fn augment_image(image: &ArrayD<u8>, save: bool, index: usize) -> Array2<u8> {
-    let mut rng = rand::thread_rng();
+fn augment_image<R: Rng>(image: &ArrayD<u8>, save: bool, index: usize, rng: &mut R) -> Array2<u8> {
     let mut img = array_to_image(image);
 
     let angle = rng.gen_range(-10.0..10.0);
@@ -60,19 +62,63 @@ fn shift_image(img: &GrayImage, x_shift: i32, y_shift: i32) -> GrayImage {
     shifted_img
 }
 
-pub fn augment_dataset(images: &ArrayD<u8>) -> ArrayD<u8> {
+/// Randomly rotate and shift every image, the transformations being drawn from `rng`.
+/// The images are augmented in parallel, each one with its own generator seeded from `rng`, so
+/// that the result only depends on `rng` and not on the number of threads. The progress is shown
+/// on the terminal.
+pub fn augment_dataset<R: Rng>(images: &ArrayD<u8>, rng: &mut R) -> ArrayD<u8> {
     let num_samples = images.shape()[0];
-    let mut augmented_images = Array::zeros(images.raw_dim());
+    let seeds: Vec<u64> = (0..num_samples).map(|_| rng.gen()).collect();
+    let progress = ProgressBar::new(num_samples as u64).with_style(
+        ProgressStyle::with_template("augmenting {bar:40} {pos}/{len} images [{elapsed} < {eta}]")
+            .expect("the progress template is valid"),
+    );
+
+    let augmented: Vec<Array2<u8>> = seeds
+        .par_iter()
+        .enumerate()
+        .map(|(i, &seed)| {
+            trace!("augmenting the sample {}", i);
+            let image = images.index_axis(Axis(0), i).to_owned();
+            let augmented_image =
+                augment_image(&image, false, i, &mut ChaCha8Rng::seed_from_u64(seed));
+            progress.inc(1);
+            augmented_image
+        })
+        .collect();
+    progress.finish_and_clear();
+    info!("Augmented {} images", num_samples);
 
-    for i in 0..num_samples {
-        trace!("augmenting the sample {}", i);
-        let image = images.index_axis(ndarray::Axis(0), i).to_owned();
-        // save every 1000 images
-        let save = i % 10000 == 0;
-        let augmented_image = augment_image(&image, false, i);
+    let mut augmented_images = Array::zeros(images.raw_dim());
+    for (i, augmented_image) in augmented.iter().enumerate() {
         augmented_images
-            .index_axis_mut(ndarray::Axis(0), i)
-            .assign(&augmented_image);
+            .index_axis_mut(Axis(0), i)
+            .assign(augmented_image);
     }
     augmented_images
 }
+
+/// The images followed by `copies` augmented copies of all of them, see `augment_dataset`, with
+/// their labels repeated `copies + 1` times to match. The originals are kept as is, so that the
+/// network still sees the clean images.
+pub fn augment_with_copies<R: Rng>(
+    images: &ArrayD<u8>,
+    labels: &ArrayD<u8>,
+    copies: usize,
+    rng: &mut R,
+) -> Result<(ArrayD<u8>, ArrayD<u8>), ShapeError> {
+    let augmented: Vec<ArrayD<u8>> = (0..copies)
+        .map(|copy| {
+            debug!("augmented copy {}/{}", copy + 1, copies);
+            augment_dataset(images, rng)
+        })
+        .collect();
+    let image_views: Vec<_> = std::iter::once(images.view())
+        .chain(augmented.iter().map(|images| images.view()))
+        .collect();
+    let label_views = vec![labels.view(); copies + 1];
+    Ok((
+        concatenate(Axis(0), &image_views)?,
+        concatenate(Axis(0), &label_views)?,
+    ))
+}
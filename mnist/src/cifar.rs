@@ -0,0 +1,187 @@
+//! The CIFAR-10 dataset, 60000 32x32 color photos of 10 classes, and an example convolutional
+//! network trained on it.
+//!
+//! The binary version of the dataset (`cifar-10-binary.tar.gz`) is expected extracted in
+//! `mnist/resources/cifar-10-batches-bin`.
+
+use std::{fs, path::Path};
+
+use anyhow::bail;
+use log::{debug, info};
+use ndarray::{concatenate, Array4, ArrayD, Axis};
+use nn_lib::{
+    activation::Activation,
+    data::stratified_split,
+    initialization::InitializerType,
+    layer::{ActivationLayer, ConvolutionalLayer, DenseLayer, FlattenLayer, MaxPoolingLayer},
+    metrics::MetricsType,
+    preprocessing::{one_hot_encode, MinMaxScaler},
+    sequential::{Sequential, SequentialBuilder},
+    Float,
+};
+
+use crate::network_definition::{
+    compile, train_and_evaluate, NetConfig, PreparedDataSet, TrainingOutcome, VALIDATION_FRACTION,
+    VALIDATION_SPLIT_SEED,
+};
+
+const DIRECTORY: &str = "mnist/resources/cifar-10-batches-bin";
+const TRAINING: [&str; 5] = [
+    "data_batch_1.bin",
+    "data_batch_2.bin",
+    "data_batch_3.bin",
+    "data_batch_4.bin",
+    "data_batch_5.bin",
+];
+const TEST: &str = "test_batch.bin";
+
+const SIDE: usize = 32;
+const CHANNELS: usize = 3;
+/// A record is the label byte, followed by the red, green and blue planes of the image, each
+/// one row after row
+const RECORD_SIZE: usize = 1 + SIDE * SIDE * CHANNELS;
+
+pub const CLASS_NAMES: [&str; 10] = [
+    "airplane",
+    "automobile",
+    "bird",
+    "cat",
+    "deer",
+    "dog",
+    "frog",
+    "horse",
+    "ship",
+    "truck",
+];
+
+/// for the images, dimensions are 1: number of images, 2: number of rows, 3: number of columns,
+/// 4: the red, green and blue channels
+/// for the labels, dimension are 1: number of labels
+#[derive(Debug, Clone, PartialEq, Default, Eq, Hash)]
+pub struct CifarData {
+    // [50000, 32, 32, 3], [50000]
+    pub training: (ArrayD<u8>, ArrayD<u8>),
+    // [10000, 32, 32, 3], [10000]
+    pub test: (ArrayD<u8>, ArrayD<u8>),
+}
+
+/// Read a batch file of the binary version of CIFAR-10, returning its images of shape
+/// (n, 32, 32, 3) and their labels
+pub fn read_batch<P: AsRef<Path>>(path: P) -> anyhow::Result<(ArrayD<u8>, ArrayD<u8>)> {
+    let path = path.as_ref();
+    let data = fs::read(path)?;
+    if data.len() % RECORD_SIZE != 0 {
+        bail!(
+            "{:?} is truncated or corrupted : {} bytes isn't a whole number of {} bytes records",
+            path,
+            data.len(),
+            RECORD_SIZE
+        );
+    }
+    let records = data.len() / RECORD_SIZE;
+    let mut labels = Vec::with_capacity(records);
+    let mut pixels = Vec::with_capacity(records * (RECORD_SIZE - 1));
+    for record in data.chunks_exact(RECORD_SIZE) {
+        labels.push(record[0]);
+        pixels.extend_from_slice(&record[1..]);
+    }
+    // the records store the channels first, the layers expect them last
+    let images = Array4::from_shape_vec((records, CHANNELS, SIDE, SIDE), pixels)?
+        .permuted_axes([0, 2, 3, 1])
+        .as_standard_layout()
+        .into_owned();
+    debug!("Data : {:?} has {} images", path, records);
+    Ok((
+        images.into_dyn(),
+        ArrayD::from_shape_vec(vec![records], labels)?,
+    ))
+}
+
+/// Load the 5 training batches and the test batch of CIFAR-10
+pub fn load_cifar10() -> anyhow::Result<CifarData> {
+    let directory = Path::new(DIRECTORY);
+    let batches = TRAINING
+        .iter()
+        .map(|file_name| read_batch(directory.join(file_name)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let images: Vec<_> = batches.iter().map(|(images, _)| images.view()).collect();
+    let labels: Vec<_> = batches.iter().map(|(_, labels)| labels.view()).collect();
+    let training = (
+        concatenate(Axis(0), &images)?,
+        concatenate(Axis(0), &labels)?,
+    );
+    let test = read_batch(directory.join(TEST))?;
+
+    info!("Successfully loaded cifar-10 dataset");
+    Ok(CifarData { training, test })
+}
+
+/// A network of two convolution blocks, each one a 3x3 convolution, a ReLU and a 2x2 max
+/// pooling, followed by the hidden dense layers of `config` (128 neurons by default)
+pub fn get_cifar_net(config: &NetConfig) -> anyhow::Result<Sequential> {
+    let mut net = SequentialBuilder::new()
+        .watch(MetricsType::Accuracy)
+        .push(ConvolutionalLayer::new(
+            (32, 32, 3),
+            (3, 3),
+            16,
+            InitializerType::He,
+        ))
+        .push(ActivationLayer::from(Activation::ReLU))
+        .push(MaxPoolingLayer::new((30, 30, 16), (2, 2)))
+        .push(ConvolutionalLayer::new(
+            (15, 15, 16),
+            (3, 3),
+            32,
+            InitializerType::He,
+        ))
+        .push(ActivationLayer::from(Activation::ReLU))
+        .push(MaxPoolingLayer::new((13, 13, 32), (2, 2)))
+        .push(FlattenLayer::new());
+    let mut input_size = 6 * 6 * 32;
+    for &size in config.hidden_sizes.as_deref().unwrap_or(&[128]) {
+        net = net
+            .push(DenseLayer::new(input_size, size, InitializerType::He))
+            .push(ActivationLayer::from(Activation::ReLU));
+        input_size = size;
+    }
+    let net = net
+        .push(DenseLayer::new(
+            input_size,
+            10,
+            InitializerType::GlorotUniform,
+        ))
+        .push(ActivationLayer::from(Activation::Softmax));
+    compile(net, config, 0.01, SIDE)
+}
+
+/// Train the network on CIFAR-10, a stratified part of the training batches held out for
+/// validation, and evaluate it on the test batch
+pub fn start_cifar(
+    neural_network: &mut Sequential,
+    batch_size: usize,
+    epochs: usize,
+) -> anyhow::Result<TrainingOutcome> {
+    let dataset = load_cifar10()?;
+    let (x_train, y_train) = prepare_data(dataset.training)?;
+    let (train, validation) = stratified_split(
+        &x_train,
+        &y_train,
+        VALIDATION_FRACTION,
+        VALIDATION_SPLIT_SEED,
+    )?;
+    let prepared = PreparedDataSet {
+        train,
+        validation,
+        test: prepare_data(dataset.test)?,
+    };
+    train_and_evaluate(neural_network, &prepared, batch_size, epochs)
+}
+
+/// Scale the images to [0, 1], keeping their (n, 32, 32, 3) shape, and one hot encode their
+/// labels
+fn prepare_data(data: (ArrayD<u8>, ArrayD<u8>)) -> anyhow::Result<(ArrayD<Float>, ArrayD<Float>)> {
+    let x = MinMaxScaler::with_range(0.0, 255.0).transform(&data.0.mapv(|e| e as Float))?;
+    let y = one_hot_encode(&data.1, 10).into_dyn();
+    Ok((x, y))
+}
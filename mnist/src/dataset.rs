@@ -1,12 +1,21 @@
 use std::{
+    fmt::{self, Display},
     fs, io,
     path::{Path, PathBuf},
 };
 
-use log::{debug, info};
+use anyhow::bail;
+use log::{debug, info, warn};
 use ndarray::ArrayD;
+use nn_lib::{preprocessing::one_hot_decode, Float};
 
-use crate::utils::{decompress_gz_file, read_idx_data};
+use crate::{
+    stats::DatasetStats,
+    utils::{
+        decompress_gz_file, md5_file, read_idx_data, read_idx_mmap, sha256_file, write_idx_data,
+        MappedIdx,
+    },
+};
 
 /// for the images, dimensions are 1: number of images, 2: number of raw, 3: number of col
 /// for the labels, dimension are 1: number of labels
@@ -19,57 +28,482 @@ pub struct MnistData {
     pub test: (ArrayD<u8>, ArrayD<u8>),
 }
 
+/// Ratio between the most and least frequent classes above which a dataset is reported as
+/// imbalanced when loaded
+pub const IMBALANCE_THRESHOLD: f64 = 1.5;
+
+/// Number of samples of every class of a labeled dataset
+/// `counts[c]` is the number of samples with label **c**
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ClassBalanceReport {
+    pub counts: Vec<usize>,
+    /// ratio between the most and least frequent classes, infinite if a class has no sample
+    pub imbalance_ratio: f64,
+}
+
+impl ClassBalanceReport {
+    pub fn from_labels(labels: &ArrayD<u8>) -> Self {
+        let num_classes = labels.iter().max().map_or(0, |&max| max as usize + 1);
+        let mut counts = vec![0usize; num_classes];
+        for &label in labels.iter() {
+            counts[label as usize] += 1;
+        }
+        let max = counts.iter().copied().max().unwrap_or(0);
+        let min = counts.iter().copied().min().unwrap_or(0);
+        let imbalance_ratio = if min == 0 {
+            f64::INFINITY
+        } else {
+            max as f64 / min as f64
+        };
+        Self {
+            counts,
+            imbalance_ratio,
+        }
+    }
+
+    pub fn is_imbalanced(&self, threshold: f64) -> bool {
+        self.imbalance_ratio > threshold
+    }
+
+    /// Return the classes that have no sample
+    pub fn missing_classes(&self) -> Vec<usize> {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count == 0)
+            .map(|(class, _)| class)
+            .collect()
+    }
+
+    fn warn_if_imbalanced(&self, name: &str) {
+        debug!("{} class counts : {:?}", name, self.counts);
+        if self.is_imbalanced(IMBALANCE_THRESHOLD) {
+            warn!(
+                "{} labels are imbalanced (ratio of {:.2} between the most and least frequent classes, missing classes : {:?}), \
+                consider weighting the classes in the cost function or oversampling the minority classes",
+                name,
+                self.imbalance_ratio,
+                self.missing_classes()
+            );
+        }
+    }
+}
+
+impl MnistData {
+    pub fn training_class_balance(&self) -> ClassBalanceReport {
+        ClassBalanceReport::from_labels(&self.training.1)
+    }
+
+    pub fn test_class_balance(&self) -> ClassBalanceReport {
+        ClassBalanceReport::from_labels(&self.test.1)
+    }
+
+    pub fn training_stats(&self, number_of_previews: usize) -> DatasetStats {
+        DatasetStats::compute(&self.training.0, &self.training.1, number_of_previews)
+    }
+
+    pub fn test_stats(&self, number_of_previews: usize) -> DatasetStats {
+        DatasetStats::compute(&self.test.0, &self.test.1, number_of_previews)
+    }
+
+    /// Write both sets in `dir` as uncompressed IDX files, under the names of the mnist files
+    /// (ex: `train-images-idx3-ubyte`), ex: to keep an augmented training set
+    pub fn save<P: AsRef<Path>>(&self, dir: P) -> anyhow::Result<()> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let files = TRAINING.iter().chain(TEST.iter());
+        let arrays = [
+            &self.training.0,
+            &self.training.1,
+            &self.test.0,
+            &self.test.1,
+        ];
+        for (file_name, array) in files.zip(arrays) {
+            write_idx_data(dir.join(raw_name(file_name)), array)?;
+        }
+        info!("Dataset written to {:?}", dir);
+        Ok(())
+    }
+
+    /// Read a dataset written by `save` from `dir`
+    pub fn load_from<P: AsRef<Path>>(dir: P) -> anyhow::Result<Self> {
+        let dir = dir.as_ref();
+        let load = |file_name: &str| read_idx_data(dir.join(raw_name(file_name)).as_path());
+        Ok(Self {
+            training: (load(TRAINING[0])?, load(TRAINING[1])?),
+            test: (load(TEST[0])?, load(TEST[1])?),
+        })
+    }
+}
+
+/// Inputs whose magnitude exceeds this are reported as not normalized by `describe`
+const UNNORMALIZED_THRESHOLD: Float = 10.0;
+
+/// Summary of the data a network is about to be trained on, once prepared: the shapes, the
+/// range and distribution of the inputs, and the number of data points of every class, along
+/// with warnings about what is likely to hinder the training, see `describe`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DatasetDescription {
+    pub input_shape: Vec<usize>,
+    pub output_shape: Vec<usize>,
+    pub min: Float,
+    pub max: Float,
+    pub mean: Float,
+    pub std: Float,
+    /// `class_counts[c]` is the number of data points of class **c**
+    pub class_counts: Vec<usize>,
+    pub warnings: Vec<String>,
+}
+
+/// Describe the inputs `x` and one hot encoded outputs `y` of a dataset, the class of a data
+/// point being its most probable one, and check that they are fit for a training: finite and
+/// normalized inputs, and data points for every class
+pub fn describe(x: &ArrayD<Float>, y: &ArrayD<Float>) -> DatasetDescription {
+    let num_classes = match y.shape().get(1) {
+        Some(&columns) if columns > 1 => columns,
+        _ => 2,
+    };
+    let mut class_counts = vec![0; num_classes];
+    for class in one_hot_decode(y) {
+        class_counts[class] += 1;
+    }
+    let description = DatasetDescription {
+        input_shape: x.shape().to_vec(),
+        output_shape: y.shape().to_vec(),
+        min: x.fold(Float::INFINITY, |min, &v| min.min(v)),
+        max: x.fold(Float::NEG_INFINITY, |max, &v| max.max(v)),
+        mean: x.mean().unwrap_or(0.0),
+        std: x.std(0.0),
+        class_counts,
+        warnings: vec![],
+    };
+
+    let mut warnings = vec![];
+    if x.shape().first() != y.shape().first() {
+        warnings.push(format!(
+            "{} inputs for {} outputs",
+            x.shape().first().unwrap_or(&0),
+            y.shape().first().unwrap_or(&0)
+        ));
+    }
+    if x.iter().any(|v| !v.is_finite()) {
+        warnings.push(String::from("some inputs are NaN or infinite"));
+    } else if description.min.abs().max(description.max.abs()) > UNNORMALIZED_THRESHOLD {
+        warnings.push(format!(
+            "the inputs range over [{}, {}] and don't look normalized, consider scaling them \
+            (ex: with `MinMaxScaler` or `StandardScaler`)",
+            description.min, description.max
+        ));
+    } else if description.std == 0.0 && x.len() > 1 {
+        warnings.push(format!("every input is {}", description.min));
+    }
+    let missing: Vec<usize> = (0..num_classes)
+        .filter(|&class| description.class_counts[class] == 0)
+        .collect();
+    if !missing.is_empty() {
+        warnings.push(format!(
+            "no data point of the classes {:?}, the network can't learn them",
+            missing
+        ));
+    }
+    DatasetDescription {
+        warnings,
+        ..description
+    }
+}
+
+impl Display for DatasetDescription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "input shape : {:?}, output shape : {:?}",
+            self.input_shape, self.output_shape
+        )?;
+        writeln!(f, "input range : [{:.4}, {:.4}]", self.min, self.max)?;
+        writeln!(f, "input mean : {:.4}, std : {:.4}", self.mean, self.std)?;
+        let total = self.class_counts.iter().sum::<usize>().max(1);
+        writeln!(f, "class distribution :")?;
+        for (class, count) in self.class_counts.iter().enumerate() {
+            writeln!(
+                f,
+                "  {} : {} ({:.2}%)",
+                class,
+                count,
+                *count as f64 / total as f64 * 100.0
+            )?;
+        }
+        for warning in &self.warnings {
+            writeln!(f, "warning : {}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+/// The name of the decompressed `file_name`
+fn raw_name(file_name: &str) -> &str {
+    file_name.strip_suffix(".gz").unwrap_or(file_name)
+}
+
+/// The datasets of 28x28 grayscale images of 10 classes in the IDX format of mnist, each one in
+/// its own resources directory under the file names of mnist
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Dataset {
+    /// The handwritten digits of mnist
+    #[default]
+    Digits,
+    /// Fashion-MNIST, photos of 10 kinds of clothes and shoes
+    Fashion,
+}
+
+const DIGITS_CLASS_NAMES: [&str; 10] = ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"];
+const FASHION_CLASS_NAMES: [&str; 10] = [
+    "T-shirt/top",
+    "Trouser",
+    "Pullover",
+    "Dress",
+    "Coat",
+    "Sandal",
+    "Shirt",
+    "Sneaker",
+    "Bag",
+    "Ankle boot",
+];
+
+impl Dataset {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Digits => "mnist",
+            Self::Fashion => "fashion-mnist",
+        }
+    }
+
+    /// The names of the classes, `class_names()[c]` being the name of the label **c**
+    pub fn class_names(self) -> &'static [&'static str; 10] {
+        match self {
+            Self::Digits => &DIGITS_CLASS_NAMES,
+            Self::Fashion => &FASHION_CLASS_NAMES,
+        }
+    }
+
+    /// The name of the label `class`, the label itself if it isn't a class of the dataset
+    pub fn class_name(self, class: usize) -> String {
+        self.class_names()
+            .get(class)
+            .map_or_else(|| class.to_string(), |name| name.to_string())
+    }
+
+    /// Directory holding the `compressed` files of the dataset, and their `raw` decompressed
+    /// copies
+    fn resources(self) -> PathBuf {
+        match self {
+            Self::Digits => PathBuf::from("mnist/resources"),
+            Self::Fashion => PathBuf::from("mnist/resources/fashion"),
+        }
+    }
+
+    fn checksums(self) -> &'static [(&'static str, Checksum)] {
+        match self {
+            Self::Digits => &CHECKSUMS,
+            Self::Fashion => &FASHION_CHECKSUMS,
+        }
+    }
+}
+
 const TRAINING: [&str; 2] = ["train-images-idx3-ubyte.gz", "train-labels-idx1-ubyte.gz"];
 const TEST: [&str; 2] = ["t10k-images-idx3-ubyte.gz", "t10k-labels-idx1-ubyte.gz"];
 
-fn load_file(file_name: &str) -> anyhow::Result<ArrayD<u8>> {
-    debug!("Trying to load the file : {}", file_name);
-    let base_path = PathBuf::from("mnist/resources");
+/// Known digest of a compressed dataset file
+#[derive(Copy, Clone, Debug)]
+enum Checksum {
+    Sha256(&'static str),
+    /// only MD5 digests are published for Fashion-MNIST
+    Md5(&'static str),
+}
+
+/// Known SHA-256 digests of the compressed mnist files
+const CHECKSUMS: [(&str, Checksum); 4] = [
+    (
+        "train-images-idx3-ubyte.gz",
+        Checksum::Sha256("440fcabf73cc546fa21475e81ea370265605f56be210a4024d2ca8f203523609"),
+    ),
+    (
+        "train-labels-idx1-ubyte.gz",
+        Checksum::Sha256("3552534a0a558bbed6aed32b30c495cca23d567ec52cac8be1a0730e8010255c"),
+    ),
+    (
+        "t10k-images-idx3-ubyte.gz",
+        Checksum::Sha256("8d422c7b0a1c1c79245a5bcf07fe86e33eeafee792b84584aec276f5a2dbc4e6"),
+    ),
+    (
+        "t10k-labels-idx1-ubyte.gz",
+        Checksum::Sha256("f7ae60f92e00ec6debd23a6088c31dbd2371eca3ffa0defaefb259924204aec6"),
+    ),
+];
+
+/// MD5 digests of the compressed Fashion-MNIST files, as published with the dataset
+const FASHION_CHECKSUMS: [(&str, Checksum); 4] = [
+    (
+        "train-images-idx3-ubyte.gz",
+        Checksum::Md5("8d4fb7e6c68d591d4c3dfef9ec88bf0d"),
+    ),
+    (
+        "train-labels-idx1-ubyte.gz",
+        Checksum::Md5("25c81989df183df01b3e8a0aad5dffbe"),
+    ),
+    (
+        "t10k-images-idx3-ubyte.gz",
+        Checksum::Md5("bef4ecab320f06d8554ea6380940ec79"),
+    ),
+    (
+        "t10k-labels-idx1-ubyte.gz",
+        Checksum::Md5("bb300cfdad3c16e7a12a480ee83cd310"),
+    ),
+];
+
+/// Check the compressed file against its known checksum, failing with the expected and actual
+/// digests on mismatch, which usually mean a truncated or corrupted download.
+fn verify_checksum(dataset: Dataset, file_name: &str, path: &Path) -> anyhow::Result<()> {
+    let Some((_, expected)) = dataset
+        .checksums()
+        .iter()
+        .find(|(name, _)| *name == file_name)
+    else {
+        warn!("No known checksum for {}, skipping verification", file_name);
+        return Ok(());
+    };
+    if !path.exists() {
+        bail!(
+            "Missing dataset file {:?}, put it there or build with the `download` feature to fetch it",
+            path
+        );
+    }
+    let (expected, actual) = match *expected {
+        Checksum::Sha256(expected) => (expected, sha256_file(path)?),
+        Checksum::Md5(expected) => (expected, md5_file(path)?),
+    };
+    if actual != expected {
+        bail!(
+            "Checksum mismatch for {:?} : expected {}, found {}. The file is probably truncated or corrupted, download it again",
+            path,
+            expected,
+            actual
+        );
+    }
+    debug!("Checksum verified for {:?}", path);
+    Ok(())
+}
+
+/// The directory holding the compressed `file_name` of `dataset`: the resources directory of
+/// the dataset if the file was put there, otherwise with the `download` feature the user cache
+/// directory, the file being downloaded and verified there the first time.
+fn base_dir(dataset: Dataset, file_name: &str) -> anyhow::Result<PathBuf> {
+    let resources = dataset.resources();
+    if resources.join("compressed").join(file_name).exists() {
+        return Ok(resources);
+    }
+    #[cfg(feature = "download")]
+    {
+        let cache = crate::download::cache_dir(dataset)?;
+        let compressed_dir = cache.join("compressed");
+        fs::create_dir_all(&compressed_dir)?;
+        let compressed = compressed_dir.join(file_name);
+        if !compressed.exists() {
+            crate::download::download(dataset, file_name, &compressed)?;
+            if let Err(error) = verify_checksum(dataset, file_name, &compressed) {
+                fs::remove_file(&compressed)?;
+                return Err(error);
+            }
+        }
+        Ok(cache)
+    }
+    #[cfg(not(feature = "download"))]
+    Ok(resources)
+}
+
+/// Verify the compressed `file_name` and decompress it in the raw cache directory if it isn't
+/// there yet, returning the path of the raw IDX file.
+fn cache_file(dataset: Dataset, file_name: &str) -> anyhow::Result<PathBuf> {
+    let base_path = base_dir(dataset, file_name)?;
 
     // Ensure the compressed directory exists
     let compressed_dir = base_path.join("compressed");
-    fs::create_dir_all(&compressed_dir).map_err(|e| {
-        io::Error::new(
-            io::ErrorKind::Other,
-            format!("Failed to create compressed directory: {}", e),
-        )
-    })?;
+    fs::create_dir_all(&compressed_dir)
+        .map_err(|e| io::Error::other(format!("Failed to create compressed directory: {}", e)))?;
 
     let compressed = compressed_dir.join(file_name);
+    verify_checksum(dataset, file_name, &compressed)?;
     let file_stem = Path::new(file_name)
         .file_stem()
-        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "stem file creation failed"))?;
+        .ok_or_else(|| io::Error::other("stem file creation failed"))?;
 
     // Ensure the raw directory exists
     let raw_dir = base_path.join("raw");
-    fs::create_dir_all(&raw_dir).map_err(|e| {
-        io::Error::new(
-            io::ErrorKind::Other,
-            format!("Failed to create raw directory: {}", e),
-        )
-    })?;
+    fs::create_dir_all(&raw_dir)
+        .map_err(|e| io::Error::other(format!("Failed to create raw directory: {}", e)))?;
 
     let raw = raw_dir.join(
         file_stem
             .to_str()
-            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "osstr conversion failed"))?,
+            .ok_or_else(|| io::Error::other("osstr conversion failed"))?,
     );
 
-    decompress_gz_file(&compressed, &raw)?;
+    if !raw.exists() {
+        decompress_gz_file(&compressed, &raw)?;
+    }
+    Ok(raw)
+}
+
+fn load_file(dataset: Dataset, file_name: &str) -> anyhow::Result<ArrayD<u8>> {
+    debug!("Trying to load the file : {}", file_name);
+    let raw = cache_file(dataset, file_name)?;
 
     let container = read_idx_data(&raw)?;
     debug!("Data : {:?} has shape : {:?}", raw, &container.shape());
     Ok(container)
 }
 
-pub fn load_dataset() -> anyhow::Result<MnistData> {
-    let (training_images, training_labels) = (load_file(TRAINING[0])?, load_file(TRAINING[1])?);
-    let (test_images, test_labels) = (load_file(TEST[0])?, load_file(TEST[1])?);
+/// Load `dataset` from its resources directory, decompressing its files the first time
+pub fn load_dataset(dataset: Dataset) -> anyhow::Result<MnistData> {
+    let (training_images, training_labels) = (
+        load_file(dataset, TRAINING[0])?,
+        load_file(dataset, TRAINING[1])?,
+    );
+    let (test_images, test_labels) = (load_file(dataset, TEST[0])?, load_file(dataset, TEST[1])?);
 
-    info!("Successfully loaded mnist dataset");
+    info!("Successfully loaded {} dataset", dataset.name());
 
-    Ok(MnistData {
+    let data = MnistData {
         training: (training_images, training_labels),
         test: (test_images, test_labels),
-    })
+    };
+    data.training_class_balance().warn_if_imbalanced("Training");
+    data.test_class_balance().warn_if_imbalanced("Test");
+
+    Ok(data)
+}
+
+/// The mnist dataset memory-mapped from the raw cache directory instead of loaded in RAM,
+/// organized like `MnistData`
+pub struct MappedMnistData {
+    pub training: (MappedIdx, MappedIdx),
+    pub test: (MappedIdx, MappedIdx),
+}
+
+fn map_file(dataset: Dataset, file_name: &str) -> anyhow::Result<MappedIdx> {
+    debug!("Trying to map the file : {}", file_name);
+    let mapped = read_idx_mmap(cache_file(dataset, file_name)?)?;
+    debug!("Data : {} has shape : {:?}", file_name, mapped.shape());
+    Ok(mapped)
+}
+
+pub fn load_dataset_mapped(dataset: Dataset) -> anyhow::Result<MappedMnistData> {
+    let training = (
+        map_file(dataset, TRAINING[0])?,
+        map_file(dataset, TRAINING[1])?,
+    );
+    let test = (map_file(dataset, TEST[0])?, map_file(dataset, TEST[1])?);
+
+    info!("Successfully mapped {} dataset", dataset.name());
+
+    Ok(MappedMnistData { training, test })
 }
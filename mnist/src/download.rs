@@ -0,0 +1,74 @@
+//! Download of the missing dataset files from their mirrors, with the `download` feature, into
+//! the user cache directory (ex: `~/.cache/nn_from_scratch` on Linux).
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail};
+use log::{info, warn};
+
+use crate::dataset::Dataset;
+
+/// Mirrors of the compressed files, tried in order
+const MNIST_MIRRORS: [&str; 2] = [
+    "https://ossci-datasets.s3.amazonaws.com/mnist/",
+    "https://storage.googleapis.com/cvdf-datasets/mnist/",
+];
+const FASHION_MIRRORS: [&str; 1] = ["http://fashion-mnist.s3-website.eu-central-1.amazonaws.com/"];
+
+fn mirrors(dataset: Dataset) -> &'static [&'static str] {
+    match dataset {
+        Dataset::Digits => &MNIST_MIRRORS,
+        Dataset::Fashion => &FASHION_MIRRORS,
+    }
+}
+
+/// The directory where the files of `dataset` are downloaded and decompressed, organized as the
+/// resources directory of the dataset
+pub(crate) fn cache_dir(dataset: Dataset) -> anyhow::Result<PathBuf> {
+    let cache = dirs::cache_dir().ok_or_else(|| anyhow!("No cache directory for this user"))?;
+    Ok(cache.join("nn_from_scratch").join(dataset.name()))
+}
+
+/// Download the compressed `file_name` of `dataset` to `destination`, from the first mirror
+/// that answers. The file is written under a temporary name and only renamed once complete, so
+/// an interrupted download is not mistaken for a cached file.
+pub(crate) fn download(
+    dataset: Dataset,
+    file_name: &str,
+    destination: &Path,
+) -> anyhow::Result<()> {
+    let partial = destination.with_extension("part");
+    let mut errors = vec![];
+    for mirror in mirrors(dataset) {
+        let url = format!("{}{}", mirror, file_name);
+        info!("Downloading {}", url);
+        match fetch(&url, &partial) {
+            Ok(()) => {
+                fs::rename(&partial, destination)?;
+                return Ok(());
+            }
+            Err(error) => {
+                warn!("Failed to download {} : {}", url, error);
+                let _ = fs::remove_file(&partial);
+                errors.push(format!("{} : {}", url, error));
+            }
+        }
+    }
+    bail!(
+        "Failed to download {} from every mirror :\n{}",
+        file_name,
+        errors.join("\n")
+    )
+}
+
+fn fetch(url: &str, path: &Path) -> anyhow::Result<()> {
+    let response = ureq::get(url).call()?;
+    let mut writer = BufWriter::new(File::create(path)?);
+    io::copy(&mut response.into_reader(), &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
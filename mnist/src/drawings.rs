@@ -0,0 +1,89 @@
+use std::{fs, path::Path};
+
+use log::info;
+use ndarray::{ArrayD, IxDyn};
+
+use crate::utils::{read_idx_data, write_idx_data};
+
+/// Files of the collected drawings, in the IDX format of the mnist dataset
+const IMAGES_FILE: &str = "drawings-images-idx3-ubyte";
+const LABELS_FILE: &str = "drawings-labels-idx1-ubyte";
+
+/// Side of the drawings, as the mnist images
+const SIDE: usize = 28;
+
+/// Digits drawn by the user and labeled by hand, ex: when the guess of a network is wrong,
+/// stored as the mnist dataset to fine-tune the networks on them
+/// `images` are the (28, 28) images one after the other, `labels[i]` being the digit of the
+/// image **i**
+#[derive(Debug, Clone, PartialEq, Default, Eq)]
+pub struct Drawings {
+    images: Vec<u8>,
+    labels: Vec<u8>,
+}
+
+impl Drawings {
+    /// Read the drawings saved in `dir`, none if it doesn't hold any
+    pub fn load<P: AsRef<Path>>(dir: P) -> anyhow::Result<Self> {
+        let dir = dir.as_ref();
+        let (images_path, labels_path) = (dir.join(IMAGES_FILE), dir.join(LABELS_FILE));
+        if !images_path.exists() || !labels_path.exists() {
+            return Ok(Self::default());
+        }
+        let images = read_idx_data(images_path.as_path())?;
+        let labels = read_idx_data(labels_path.as_path())?;
+        if images.shape() != [labels.len(), SIDE, SIDE] {
+            anyhow::bail!(
+                "The drawings of {:?} are corrupted : {} labels for images of shape {:?}",
+                dir,
+                labels.len(),
+                images.shape()
+            );
+        }
+        info!("Loaded {} drawings from {:?}", labels.len(), dir);
+        Ok(Self {
+            images: images.into_raw_vec(),
+            labels: labels.into_raw_vec(),
+        })
+    }
+
+    /// Write the drawings in `dir`, creating it if needed
+    pub fn save<P: AsRef<Path>>(&self, dir: P) -> anyhow::Result<()> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        write_idx_data(dir.join(IMAGES_FILE), &self.images())?;
+        write_idx_data(dir.join(LABELS_FILE), &self.labels())?;
+        Ok(())
+    }
+
+    /// Add a drawing, given as the 784 pixels of a (28, 28) image row after row
+    ///
+    /// # Panics
+    /// If `image` doesn't have 784 pixels or `label` isn't a digit
+    pub fn push(&mut self, image: &[u8], label: u8) {
+        assert_eq!(image.len(), SIDE * SIDE, "A drawing has 28x28 pixels");
+        assert!(label < 10, "The label of a drawing is a digit");
+        self.images.extend_from_slice(image);
+        self.labels.push(label);
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// The images, of shape (drawings, 28, 28) as the images of `MnistData`
+    pub fn images(&self) -> ArrayD<u8> {
+        ArrayD::from_shape_vec(IxDyn(&[self.len(), SIDE, SIDE]), self.images.clone())
+            .expect("every drawing has 28x28 pixels")
+    }
+
+    /// The labels, of shape (drawings) as the labels of `MnistData`
+    pub fn labels(&self) -> ArrayD<u8> {
+        ArrayD::from_shape_vec(IxDyn(&[self.len()]), self.labels.clone())
+            .expect("a label by drawing")
+    }
+}
@@ -1,7 +1,15 @@
 pub mod augments;
+pub mod cifar;
 pub mod dataset;
+#[cfg(feature = "download")]
+mod download;
+pub mod drawings;
+pub mod loader;
 pub mod network_definition;
+pub mod stats;
 pub mod utils;
 
 pub use network_definition::get_neural_net;
 pub use network_definition::start;
+pub use network_definition::NetConfig;
+pub use network_definition::TrainingOutcome;
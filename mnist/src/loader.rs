@@ -0,0 +1,80 @@
+use ndarray::IxDyn;
+use nn_lib::{
+    data::{Batch, DataLoader},
+    layer::LayerError,
+    preprocessing::one_hot_encode,
+    random, Float,
+};
+use rand::seq::SliceRandom;
+
+use crate::utils::MappedIdx;
+
+/// A `DataLoader` over memory-mapped IDX images and labels files, ex: from
+/// `load_dataset_mapped` or `read_idx_mmap`, for datasets too large to be loaded in RAM.
+/// Only the images of a batch are read from disk, when it is yielded, flattened to (batch,
+/// pixels) and divided by `scale`, their labels being one hot encoded over `num_classes`.
+/// As `ArrayLoader`, the data points are shuffled once at creation and every epoch yields the
+/// same batches in the same order.
+pub struct MappedLoader<'a> {
+    images: &'a MappedIdx,
+    labels: &'a MappedIdx,
+    num_classes: usize,
+    scale: Float,
+    indices: Vec<usize>,
+    batch_size: usize,
+    position: usize,
+}
+
+impl<'a> MappedLoader<'a> {
+    /// The data points are shuffled with the crate generator (see `random::seed`)
+    pub fn new(
+        images: &'a MappedIdx,
+        labels: &'a MappedIdx,
+        num_classes: usize,
+        scale: Float,
+        batch_size: usize,
+    ) -> Result<Self, LayerError> {
+        if images.len() != labels.len() {
+            return Err(LayerError::DimensionMismatch);
+        }
+        let mut indices = (0..images.len()).collect::<Vec<_>>();
+        indices.shuffle(&mut random::generator());
+        Ok(Self {
+            images,
+            labels,
+            num_classes,
+            scale,
+            indices,
+            batch_size: batch_size.max(1),
+            position: 0,
+        })
+    }
+}
+
+impl DataLoader for MappedLoader<'_> {
+    fn number_of_batch(&self) -> usize {
+        self.images.len().div_ceil(self.batch_size)
+    }
+
+    fn start_epoch(&mut self, _epoch: usize) {
+        self.position = 0;
+    }
+
+    fn next_batch(&mut self) -> Option<Batch> {
+        if self.position >= self.indices.len() {
+            return None;
+        }
+        let end = (self.position + self.batch_size).min(self.indices.len());
+        let batch_indices = &self.indices[self.position..end];
+        self.position = end;
+
+        let images = self.images.select(batch_indices);
+        let pixels = images.len() / batch_indices.len();
+        let x = images
+            .mapv(|pixel| pixel as Float / self.scale)
+            .into_shape(IxDyn(&[batch_indices.len(), pixels]))
+            .expect("the selected images are contiguous");
+        let y = one_hot_encode(&self.labels.select(batch_indices), self.num_classes).into_dyn();
+        Some((x, y))
+    }
+}
@@ -1,31 +1,112 @@
-use log::{debug, info, trace};
-use ndarray::{s, Array2, ArrayD};
+use log::{debug, info, trace, warn};
+use ndarray::{Array2, ArrayD, Axis, IxDyn};
 use nn_lib::{
     activation::Activation,
+    augmentation::BatchAugmentation,
     cost::CostFunction,
+    data::{stratified_split, BalancedLoader},
     initialization::InitializerType,
-    layer::{ActivationLayer, ConvolutionalLayer, DenseLayer, MaxPoolingLayer, ReshapeLayer},
-    metrics::MetricsType,
+    layer::{
+        ActivationLayer, ConvolutionalLayer, DenseLayer, DropoutLayer, FlattenLayer,
+        MaxPoolingLayer, ReshapeLayer,
+    },
+    metrics::{Benchmark, ClassificationReport, History, MetricsType},
     optimizer::GradientDescent,
+    preprocessing::{one_hot_encode, MinMaxScaler},
+    random,
     sequential::{Sequential, SequentialBuilder},
+    Float,
 };
 
-use crate::{augments::augment_dataset, dataset::load_dataset};
+use crate::{
+    augments::augment_with_copies,
+    dataset::{describe, load_dataset, Dataset, MnistData},
+    drawings::Drawings,
+};
+
+/// Fraction of the training dataset held out for validation
+pub(crate) const VALIDATION_FRACTION: Float = 0.2;
+
+/// Seed of the training / validation split, fixed so every run validates on the same images
+pub(crate) const VALIDATION_SPLIT_SEED: u64 = 42;
+
+/// Probability of an image of a training batch to be erased by the cutout of `NetConfig`
+const CUTOUT_PROBABILITY: Float = 0.5;
+
+/// The histories of a training and the evaluation of the trained network on the test set
+pub struct TrainingOutcome {
+    pub train_history: History,
+    pub validation_history: History,
+    pub test: Benchmark,
+}
 
 pub enum NetType {
     Mlp,
     Conv,
 }
 
-pub fn get_neural_net(net_type: NetType) -> anyhow::Result<Sequential> {
+/// The optimizers a mnist network can be trained with
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum OptimizerType {
+    /// see `GradientDescent`
+    #[default]
+    GradientDescent,
+}
+
+/// Hyperparameters of a mnist network, those left to `None` keeping the defaults of its
+/// `NetType`
+/// `hidden_sizes` are the sizes of the hidden dense layers, after the convolution block of the
+/// convolutional network
+/// `mixup` is the alpha of the mixup of the training batches and `cutout` the side of the
+/// squares erased in their images, none by default, see `BatchAugmentation`
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct NetConfig {
+    pub learning_rate: Option<Float>,
+    pub optimizer: OptimizerType,
+    pub hidden_sizes: Option<Vec<usize>>,
+    pub mixup: Option<Float>,
+    pub cutout: Option<usize>,
+}
+
+pub fn get_neural_net(net_type: NetType, config: &NetConfig) -> anyhow::Result<Sequential> {
     match net_type {
-        NetType::Mlp => build_mlp_net(),
-        NetType::Conv => build_conv_net(),
+        NetType::Mlp => build_mlp_net(config),
+        NetType::Conv => build_conv_net(config),
     }
 }
 
-fn build_conv_net() -> anyhow::Result<Sequential> {
-    let net = SequentialBuilder::new()
+/// Compile `net` with the optimizer and the batch augmentations of `config`, at
+/// `default_learning_rate` if it has none. The inputs of the network are images of
+/// `image_side` x `image_side` pixels.
+pub(crate) fn compile(
+    mut net: SequentialBuilder,
+    config: &NetConfig,
+    default_learning_rate: Float,
+    image_side: usize,
+) -> anyhow::Result<Sequential> {
+    let learning_rate = config.learning_rate.unwrap_or(default_learning_rate);
+    if let Some(size) = config.cutout {
+        net = net.augment(BatchAugmentation::Cutout {
+            rows: image_side,
+            columns: image_side,
+            size,
+            probability: CUTOUT_PROBABILITY,
+        });
+    }
+    if let Some(alpha) = config.mixup {
+        net = net.augment(BatchAugmentation::Mixup { alpha });
+    }
+    let net = match config.optimizer {
+        OptimizerType::GradientDescent => net.compile(
+            GradientDescent::new(learning_rate),
+            CostFunction::cross_entropy(),
+        )?,
+    };
+    Ok(net)
+}
+
+fn build_conv_net(config: &NetConfig) -> anyhow::Result<Sequential> {
+    let mut net = SequentialBuilder::new()
         .watch(MetricsType::Accuracy)
         .push(ReshapeLayer::new(&[28 * 28], &[28, 28, 1])?)
         .push(ConvolutionalLayer::new(
@@ -39,89 +120,199 @@ fn build_conv_net() -> anyhow::Result<Sequential> {
             (26, 26, 5),
             (2, 2)
         ))
-        .push(ReshapeLayer::new(&[13, 13, 5], &[13 * 13 * 5])?)
+        .push(FlattenLayer::new());
+    let mut input_size = 13 * 13 * 5;
+    for &size in config.hidden_sizes.as_deref().unwrap_or(&[100]) {
+        net = net
+            .push(DenseLayer::new(
+                input_size,
+                size,
+                InitializerType::GlorotUniform,
+            ))
+            .push(ActivationLayer::from(Activation::ReLU));
+        input_size = size;
+    }
+    let net = net
         .push(DenseLayer::new(
-            13 * 13 * 5,
-            100,
+            input_size,
+            10,
             InitializerType::GlorotUniform,
         ))
-        .push(ActivationLayer::from(Activation::ReLU))
-        .push(DenseLayer::new(100, 10, InitializerType::GlorotUniform))
         .push(ActivationLayer::from(Activation::Softmax));
-    Ok(net.compile(GradientDescent::new(0.01), CostFunction::CrossEntropy)?)
+    compile(net, config, 0.01, 28)
 }
 
-fn build_mlp_net() -> anyhow::Result<Sequential> {
-    let net = SequentialBuilder::new()
-        .push(DenseLayer::new(784, 256, InitializerType::He))
-        .push(DenseLayer::new(256, 128, InitializerType::He))
-        .push(ActivationLayer::from(Activation::ReLU))
-        .push(DenseLayer::new(128, 10, InitializerType::He))
+fn build_mlp_net(config: &NetConfig) -> anyhow::Result<Sequential> {
+    let mut net = SequentialBuilder::new();
+    let mut input_size = 784;
+    match &config.hidden_sizes {
+        Some(hidden_sizes) => {
+            for &size in hidden_sizes {
+                net = net
+                    .push(DenseLayer::new(input_size, size, InitializerType::He))
+                    .push(ActivationLayer::from(Activation::ReLU));
+                input_size = size;
+            }
+        }
+        None => {
+            net = net
+                .push(DenseLayer::new(784, 256, InitializerType::He))
+                .push(DenseLayer::new(256, 128, InitializerType::He))
+                .push(ActivationLayer::from(Activation::ReLU));
+            input_size = 128;
+        }
+    }
+    let net = net
+        .push(DropoutLayer::new(0.2)?)
+        .push(DenseLayer::new(input_size, 10, InitializerType::He))
         .push(ActivationLayer::from(Activation::Softmax))
         .watch(MetricsType::Accuracy);
-    Ok(net.compile(GradientDescent::new(0.1), CostFunction::CrossEntropy)?)
+    compile(net, config, 0.1, 28)
 }
 
 #[derive(PartialEq, Debug, Clone, Default)]
-struct PreparedDataSet {
-    train: (ArrayD<f64>, ArrayD<f64>),
-    validation: (ArrayD<f64>, ArrayD<f64>),
-    test: (ArrayD<f64>, ArrayD<f64>),
+pub(crate) struct PreparedDataSet {
+    pub(crate) train: (ArrayD<Float>, ArrayD<Float>),
+    pub(crate) validation: (ArrayD<Float>, ArrayD<Float>),
+    pub(crate) test: (ArrayD<Float>, ArrayD<Float>),
 }
 
 impl PreparedDataSet {
-    pub fn get_train_ref(&self) -> (&ArrayD<f64>, &ArrayD<f64>) {
+    pub fn get_train_ref(&self) -> (&ArrayD<Float>, &ArrayD<Float>) {
         (&self.train.0, &self.train.1)
     }
 
-    pub fn get_validation_ref(&self) -> (&ArrayD<f64>, &ArrayD<f64>) {
+    pub fn get_validation_ref(&self) -> (&ArrayD<Float>, &ArrayD<Float>) {
         (&self.validation.0, &self.validation.1)
     }
 
-    pub fn get_test_ref(&self) -> (&ArrayD<f64>, &ArrayD<f64>) {
+    pub fn get_test_ref(&self) -> (&ArrayD<Float>, &ArrayD<Float>) {
         (&self.test.0, &self.test.1)
     }
 }
 
-fn get_data(augment: bool) -> anyhow::Result<PreparedDataSet> {
-    let mut dataset = load_dataset()?;
+/// The training set split into training / validation, the training part followed by
+/// `augmented_copies` augmented copies of its images, and the test set
+fn get_data(dataset: Dataset, augmented_copies: usize) -> anyhow::Result<PreparedDataSet> {
+    let dataset = load_dataset(dataset)?;
+    let scale = input_scale(&dataset);
 
-    if augment {
-        dataset.training.0 = augment_dataset(&dataset.training.0);
+    // split before the augmentation, so that no copy of a validation image is trained on
+    let (mut train, validation) = split_training(dataset.training)?;
+    if augmented_copies > 0 {
+        // the augmentation follows the global seed, see `random::seed`
+        train = augment_with_copies(
+            &train.0,
+            &train.1,
+            augmented_copies,
+            &mut random::generator(),
+        )?;
+        debug!(
+            "Training on {} images, {} augmented copies of each one included",
+            train.1.len(),
+            augmented_copies
+        );
     }
 
-    let (x_train, y_train) = prepare_data(dataset.training)?;
+    let (x_train, y_train) = prepare_data(train, scale)?;
+    let (x_validation, y_validation) = prepare_data(validation, scale)?;
+    let (x_test, y_test) = prepare_data(dataset.test, scale)?;
+    Ok(PreparedDataSet {
+        train: (x_train.into_dyn(), y_train.into_dyn()),
+        validation: (x_validation.into_dyn(), y_validation.into_dyn()),
+        test: (x_test.into_dyn(), y_test.into_dyn()),
+    })
+}
 
-    // split the training dataset into training / validation
-    let (x_test, y_test) = prepare_data(dataset.test)?;
+/// Split the training images into training / validation, keeping the digits proportions
+#[allow(clippy::type_complexity)]
+fn split_training(
+    (images, labels): (ArrayD<u8>, ArrayD<u8>),
+) -> anyhow::Result<((ArrayD<u8>, ArrayD<u8>), (ArrayD<u8>, ArrayD<u8>))> {
+    // the indices of the images are split rather than the images themselves, to keep them as
+    // bytes for the augmentation
+    let indices = ArrayD::from_shape_fn(IxDyn(&[labels.len(), 1]), |index| index[0] as Float);
+    let (train, validation) = stratified_split(
+        &indices,
+        &one_hot_encode(&labels, 10).into_dyn(),
+        VALIDATION_FRACTION,
+        VALIDATION_SPLIT_SEED,
+    )?;
+    let select = |indices: &ArrayD<Float>| {
+        let indices: Vec<usize> = indices.iter().map(|&index| index as usize).collect();
+        (
+            images.select(Axis(0), &indices),
+            labels.select(Axis(0), &indices),
+        )
+    };
+    Ok((select(&train.0), select(&validation.0)))
+}
 
-    let (x_validation, y_validation) = (
-        x_train.slice(s![48000..60000, ..]),
-        y_train.slice(s![48000..60000, ..]),
-    );
+/// The training and test sets prepared as for the trainings of `start`, without augmentation
+/// nor validation split, ex: to evaluate a saved network
+#[allow(clippy::type_complexity)]
+pub fn get_evaluation_data(
+    dataset: Dataset,
+) -> anyhow::Result<(
+    (ArrayD<Float>, ArrayD<Float>),
+    (ArrayD<Float>, ArrayD<Float>),
+)> {
+    let ((x_train, y_train), (x_test, y_test)) = normalize(load_dataset(dataset)?)?;
+    Ok((
+        (x_train.into_dyn(), y_train.into_dyn()),
+        (x_test.into_dyn(), y_test.into_dyn()),
+    ))
+}
 
-    let (x_train, y_train) = (
-        x_train.slice(s![0..48000, ..]),
-        y_train.slice(s![0..48000, ..]),
-    );
+/// Flatten and scale the images of both sets, and one hot encode their labels
+#[allow(clippy::type_complexity)]
+fn normalize(
+    dataset: MnistData,
+) -> anyhow::Result<(
+    (Array2<Float>, Array2<Float>),
+    (Array2<Float>, Array2<Float>),
+)> {
+    let scale = input_scale(&dataset);
+    Ok((
+        prepare_data(dataset.training, scale)?,
+        prepare_data(dataset.test, scale)?,
+    ))
+}
 
-    Ok(PreparedDataSet {
-        train: (x_train.to_owned().into_dyn(), y_train.to_owned().into_dyn()),
-        validation: (
-            x_validation.to_owned().into_dyn(),
-            y_validation.to_owned().into_dyn(),
-        ),
-        test: (x_test.into_dyn(), y_test.into_dyn()),
-    })
+/// The scale the inputs are divided by, fitted on the training set only and applied to all
+/// the sets
+fn input_scale(dataset: &MnistData) -> Float {
+    let scale = dataset.training_stats(0).max.max(1) as Float;
+    debug!("Normalizing the inputs by {}", scale);
+    scale
 }
 
+/// Train the network on the training set of `dataset`, with `augmented_copies` augmented
+/// copies of its images (see `augment_with_copies`), and evaluate it on the test set
 pub fn start(
     neural_network: &mut Sequential,
+    dataset: Dataset,
     batch_size: usize,
     epochs: usize,
-    augment: bool,
-) -> anyhow::Result<()> {
-    let prepared = get_data(augment)?;
+    augmented_copies: usize,
+) -> anyhow::Result<TrainingOutcome> {
+    let prepared = get_data(dataset, augmented_copies)?;
+    train_and_evaluate(neural_network, &prepared, batch_size, epochs)
+}
+
+/// Train the network on the training set of `prepared`, validating it on its validation set
+/// after every epoch, then evaluate it on its test set, logging the results
+pub(crate) fn train_and_evaluate(
+    neural_network: &mut Sequential,
+    prepared: &PreparedDataSet,
+    batch_size: usize,
+    epochs: usize,
+) -> anyhow::Result<TrainingOutcome> {
+    let description = describe(&prepared.train.0, &prepared.train.1);
+    debug!("training data :\n{}", description);
+    for warning in &description.warnings {
+        warn!("training data : {}", warning);
+    }
 
     let (train_hist, validation_hist) = neural_network.train(
         prepared.get_train_ref(),
@@ -130,6 +321,16 @@ pub fn start(
         batch_size,
     )?;
 
+    let throughput = train_hist.get_throughput_time_series();
+    info!(
+        "training time : {:.2}s, mean throughput : {:.0} samples/s",
+        train_hist.total_duration().as_secs_f64(),
+        throughput.iter().sum::<f64>() / throughput.len().max(1) as f64
+    );
+    if let Some(profile) = train_hist.profiles.last() {
+        info!("time spent by layer during the last epoch :\n{}", profile);
+    }
+
     trace!(
         "validation loss by epochs {:?}",
         validation_hist.as_ref().unwrap().get_loss_time_series()
@@ -154,60 +355,95 @@ pub fn start(
             .unwrap()
     );
 
-    for (i, (train, validation)) in train_hist
-        .history
-        .iter()
-        .zip(validation_hist.unwrap().history.iter())
-        .enumerate()
-    {
+    let validation_hist = validation_hist.unwrap();
+    for (i, train) in train_hist.iter() {
         info!("train loss for epochs {} : {}", i, train.loss);
-        info!("validation loss for epochs {} : {}", i, validation.loss);
         if let Some(accuracy) = train.metrics.get_metric(MetricsType::Accuracy) {
             info!(
                 "network train accuracy for epoch {} : {:.2}%",
                 i,
-                accuracy * 100f64
+                accuracy * 100.0
             );
         } else {
             debug!("accuracy has not been set")
         }
-        if let Some(accuracy) = validation.metrics.get_metric(MetricsType::Accuracy) {
-            info!(
-                "network validation accuracy for epoch {} : {:.2}%",
-                i,
-                accuracy * 100f64
-            );
-        } else {
-            debug!("accuracy has not been set")
+        for validation in validation_hist
+            .iter()
+            .filter(|(epoch, _)| *epoch == i)
+            .map(|(_, validation)| validation)
+        {
+            info!("validation loss for epochs {} : {}", i, validation.loss);
+            if let Some(accuracy) = validation.metrics.get_metric(MetricsType::Accuracy) {
+                info!(
+                    "network validation accuracy for epoch {} : {:.2}%",
+                    i,
+                    accuracy * 100.0
+                );
+            } else {
+                debug!("accuracy has not been set")
+            }
         }
         info!("\n");
     }
 
-    let bench = neural_network.evaluate(prepared.get_test_ref(), 10);
+    let bench = neural_network.evaluate_with_confusion_matrix(prepared.get_test_ref(), 10)?;
 
     info!("loss for test data : {}", bench.loss);
     if let Some(accuracy) = bench.metrics.get_metric(MetricsType::Accuracy) {
-        info!("network test accuracy : {:.2}%", accuracy * 100f64);
+        info!("network test accuracy : {:.2}%", accuracy * 100.0);
     } else {
         debug!("accuracy has not been set")
     }
+    if let Some(confusion_matrix) = &bench.confusion_matrix {
+        info!("test confusion matrix :\n{}", confusion_matrix);
+        info!(
+            "test classification report :\n{}",
+            ClassificationReport::from(confusion_matrix)
+        );
+    }
 
-    Ok(())
+    Ok(TrainingOutcome {
+        train_history: train_hist,
+        validation_history: validation_hist,
+        test: bench,
+    })
+}
+
+/// Train an already trained network for a few more `epochs` on `drawings`, without validation
+/// nor test, ex: to adapt it to the handwriting of the user. The drawings being much fewer than
+/// the mnist images, a few epochs keep it from forgetting the dataset.
+/// The drawings of a user are seldom spread evenly over the digits, so the batches are drawn
+/// balanced between the digits drawn, see `BalancedLoader`.
+pub fn fine_tune(
+    neural_network: &mut Sequential,
+    drawings: &Drawings,
+    epochs: usize,
+    batch_size: usize,
+) -> anyhow::Result<History> {
+    if drawings.is_empty() {
+        anyhow::bail!("No drawing to fine-tune on");
+    }
+    // the drawings span the whole [0, 255] range, as the mnist images
+    let (x, y) = prepare_data((drawings.images(), drawings.labels()), 255.0)?;
+    info!("Fine-tuning on {} drawings", drawings.len());
+    let (x, y) = (x.into_dyn(), y.into_dyn());
+    let batch_size = batch_size.min(drawings.len());
+    let mut loader = BalancedLoader::new(&x, &y, batch_size)?;
+    let (train_hist, _) =
+        neural_network.train_with_loader(&mut loader, None, epochs, batch_size)?;
+    if let Some((_, last)) = train_hist.iter().last() {
+        info!("fine-tuning loss : {}", last.loss);
+    }
+    Ok(train_hist)
 }
 
-fn prepare_data(data: (ArrayD<u8>, ArrayD<u8>)) -> anyhow::Result<(Array2<f64>, Array2<f64>)> {
-    let x = data.0.mapv(|e| e as f64 / 255f64);
+fn prepare_data(
+    data: (ArrayD<u8>, ArrayD<u8>),
+    scale: Float,
+) -> anyhow::Result<(Array2<Float>, Array2<Float>)> {
+    let x = MinMaxScaler::with_range(0.0, scale).transform(&data.0.mapv(|e| e as Float))?;
     let outer = x.shape()[0];
     let x = x.into_shape((outer, 28 * 28))?;
     let y = one_hot_encode(&data.1, 10);
     Ok((x, y))
 }
-
-fn one_hot_encode(labels: &ArrayD<u8>, num_classes: usize) -> Array2<f64> {
-    let num_labels = labels.len();
-    let mut one_hot = Array2::<f64>::zeros((num_labels, num_classes));
-    for (i, &label) in labels.iter().enumerate() {
-        one_hot[[i, label as usize]] = 1.0;
-    }
-    one_hot
-}
@@ -0,0 +1,114 @@
+use std::fmt::{self, Display};
+
+use ndarray::{ArrayD, Axis};
+
+use crate::dataset::ClassBalanceReport;
+
+const ASCII_RAMP: &[u8] = b" .:-=+*#%@";
+
+/// Summary statistics of a labeled image dataset, where `images` is of shape (n, ...) and
+/// `labels` of shape (n)
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DatasetStats {
+    pub shape: Vec<usize>,
+    pub min: u8,
+    pub max: u8,
+    pub mean: f64,
+    pub std: f64,
+    /// mean of every feature (pixel) over the samples, of the shape of a single sample
+    pub feature_mean: ArrayD<f64>,
+    /// standard deviation of every feature (pixel) over the samples
+    pub feature_std: ArrayD<f64>,
+    pub class_balance: ClassBalanceReport,
+    /// label and ascii rendering of the first samples of the dataset
+    pub previews: Vec<(u8, String)>,
+}
+
+impl DatasetStats {
+    /// Compute the statistics of a dataset, rendering the first `number_of_previews` samples
+    pub fn compute(images: &ArrayD<u8>, labels: &ArrayD<u8>, number_of_previews: usize) -> Self {
+        let values = images.mapv(|e| e as f64);
+        let feature_mean = values
+            .mean_axis(Axis(0))
+            .unwrap_or_else(|| ArrayD::zeros(&images.shape()[1..]));
+        let feature_std = values.std_axis(Axis(0), 0.0);
+
+        let previews = images
+            .outer_iter()
+            .zip(labels.iter())
+            .take(number_of_previews)
+            .filter_map(|(image, &label)| Some((label, render_ascii(&image.to_owned())?)))
+            .collect();
+
+        Self {
+            shape: images.shape().to_vec(),
+            min: images.iter().copied().min().unwrap_or(0),
+            max: images.iter().copied().max().unwrap_or(0),
+            mean: values.mean().unwrap_or(0.0),
+            std: values.std(0.0),
+            feature_mean,
+            feature_std,
+            class_balance: ClassBalanceReport::from_labels(labels),
+            previews,
+        }
+    }
+
+    /// Number of samples in the dataset
+    pub fn len(&self) -> usize {
+        self.shape.first().copied().unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Render a 2 dimensional grayscale image as ascii art, `None` for any other dimension
+fn render_ascii(image: &ArrayD<u8>) -> Option<String> {
+    if image.ndim() != 2 {
+        return None;
+    }
+    let mut rendered = String::new();
+    for row in image.outer_iter() {
+        for &pixel in row.iter() {
+            let index = pixel as usize * (ASCII_RAMP.len() - 1) / 255;
+            rendered.push(ASCII_RAMP[index] as char);
+        }
+        rendered.push('\n');
+    }
+    Some(rendered)
+}
+
+impl Display for DatasetStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "shape : {:?}", self.shape)?;
+        writeln!(f, "value range : [{}, {}]", self.min, self.max)?;
+        writeln!(f, "value mean : {:.4}, std : {:.4}", self.mean, self.std)?;
+        writeln!(
+            f,
+            "feature std range : [{:.4}, {:.4}]",
+            self.feature_std.fold(f64::INFINITY, |min, &v| min.min(v)),
+            self.feature_std
+                .fold(f64::NEG_INFINITY, |max, &v| max.max(v)),
+        )?;
+        writeln!(
+            f,
+            "class distribution (imbalance ratio {:.2}) :",
+            self.class_balance.imbalance_ratio
+        )?;
+        for (class, count) in self.class_balance.counts.iter().enumerate() {
+            writeln!(
+                f,
+                "  {} : {} ({:.2}%)",
+                class,
+                count,
+                *count as f64 / self.len().max(1) as f64 * 100.0
+            )?;
+        }
+        for (label, preview) in &self.previews {
+            writeln!(f, "sample with label {} :", label)?;
+            write!(f, "{}", preview)?;
+        }
+        Ok(())
+    }
+}
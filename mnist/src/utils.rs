@@ -1,12 +1,15 @@
 use std::{
     fs::File,
-    io::{self, BufReader, BufWriter, Read},
+    io::{self, BufReader, BufWriter, Read, Write},
     path::Path,
 };
 
-use byteorder::{BigEndian, ReadBytesExt};
+use anyhow::bail;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use flate2::bufread::GzDecoder;
-use ndarray::ArrayD;
+use memmap2::Mmap;
+use ndarray::{ArrayD, ArrayViewD, Axis};
+use sha2::{Digest, Sha256};
 
 pub fn decompress_gz_file<P: AsRef<Path>>(input: P, output: P) -> anyhow::Result<()> {
     let file = File::open(input)?;
@@ -20,26 +23,207 @@ pub fn decompress_gz_file<P: AsRef<Path>>(input: P, output: P) -> anyhow::Result
     Ok(())
 }
 
+/// Return the hexadecimal SHA-256 digest of a file
+pub fn sha256_file<P: AsRef<Path>>(path: P) -> anyhow::Result<String> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = Sha256::new();
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Left rotation of every MD5 step, by round of 16 steps
+const MD5_SHIFTS: [[u32; 4]; 4] = [
+    [7, 12, 17, 22],
+    [5, 9, 14, 20],
+    [4, 11, 16, 23],
+    [6, 10, 15, 21],
+];
+
+/// Return the hexadecimal MD5 digest of a file, for the datasets only publishing MD5 digests
+pub fn md5_file<P: AsRef<Path>>(path: P) -> anyhow::Result<String> {
+    let mut data = vec![];
+    File::open(path)?.read_to_end(&mut data)?;
+    // padded to a whole number of 64 bytes blocks, ending with the message length in bits
+    let bit_length = (data.len() as u64).wrapping_mul(8);
+    data.push(0x80);
+    data.resize(data.len() + (120 - data.len() % 64) % 64, 0);
+    data.extend_from_slice(&bit_length.to_le_bytes());
+
+    let constants = (1..=64)
+        .map(|i| ((i as f64).sin().abs() * 4_294_967_296.0) as u32)
+        .collect::<Vec<_>>();
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+    for block in data.chunks_exact(64) {
+        let words = block
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect::<Vec<_>>();
+        let [mut a, mut b, mut c, mut d] = state;
+        for (step, constant) in constants.iter().enumerate() {
+            let (mixed, word) = match step / 16 {
+                0 => ((b & c) | (!b & d), step),
+                1 => ((d & b) | (!d & c), (5 * step + 1) % 16),
+                2 => (b ^ c ^ d, (3 * step + 5) % 16),
+                _ => (c ^ (b | !d), (7 * step) % 16),
+            };
+            let rotated = a
+                .wrapping_add(mixed)
+                .wrapping_add(*constant)
+                .wrapping_add(words[word])
+                .rotate_left(MD5_SHIFTS[step / 16][step % 4]);
+            (a, d, c) = (d, c, b);
+            b = b.wrapping_add(rotated);
+        }
+        for (value, added) in state.iter_mut().zip([a, b, c, d]) {
+            *value = value.wrapping_add(added);
+        }
+    }
+    Ok(state
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Shape and header size parsed from the header of an unsigned byte IDX file
+struct IdxHeader {
+    shape: Vec<usize>,
+    header_size: usize,
+}
+
 // First 4 bytes are the magic number
 // - two first bytes are 0
 // - third byte is the data type
 // - fourth byte is the number of dimension
 // dimensions are given next, each dimension is given by (big endian) 4 bytes
-pub fn read_idx_data<P: AsRef<Path> + std::fmt::Debug + Copy>(
-    path: P,
-) -> anyhow::Result<ArrayD<u8>> {
-    let mut f = BufReader::new(File::open(path)?);
-    let magic_number = f.read_u32::<BigEndian>()?;
+fn read_idx_header<R: Read>(
+    reader: &mut R,
+    path: &Path,
+    file_size: usize,
+) -> anyhow::Result<IdxHeader> {
+    let magic_number = reader.read_u32::<BigEndian>()?;
+    let data_type = (magic_number >> 8) & 0xFF;
     let num_dimension = magic_number & 0xFF;
+    if magic_number >> 16 != 0 || data_type != 0x08 {
+        bail!(
+            "{:?} is not an unsigned byte IDX file (magic number {:#010x})",
+            path,
+            magic_number
+        );
+    }
     let mut shape = vec![];
 
     for _ in 0..num_dimension {
-        shape.push(f.read_u32::<BigEndian>()? as usize);
+        shape.push(reader.read_u32::<BigEndian>()? as usize);
     }
 
     let total_size: usize = shape.iter().product();
-    let mut data = vec![0u8; total_size];
+    let header_size = 4 * (1 + num_dimension as usize);
+    if file_size != header_size + total_size {
+        bail!(
+            "{:?} is truncated or corrupted : a header of shape {:?} announce {} bytes of data, found {}",
+            path,
+            shape,
+            total_size,
+            file_size.saturating_sub(header_size)
+        );
+    }
+    Ok(IdxHeader { shape, header_size })
+}
+
+pub fn read_idx_data<P: AsRef<Path> + std::fmt::Debug + Copy>(
+    path: P,
+) -> anyhow::Result<ArrayD<u8>> {
+    let file = File::open(path)?;
+    let file_size = file.metadata()?.len() as usize;
+    let mut f = BufReader::new(file);
+    let header = read_idx_header(&mut f, path.as_ref(), file_size)?;
+
+    let mut data = vec![0u8; header.shape.iter().product()];
     f.read_exact(&mut data)?;
 
-    Ok(ArrayD::from_shape_vec(shape, data)?)
+    Ok(ArrayD::from_shape_vec(header.shape, data)?)
+}
+
+/// Write `data` as an unsigned byte IDX file, readable back with `read_idx_data`
+/// The header holds the number of dimensions on a byte and every dimension on 4 bytes, larger
+/// arrays can't be written.
+pub fn write_idx_data<P: AsRef<Path>>(path: P, data: &ArrayD<u8>) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    if data.ndim() > u8::MAX as usize || data.shape().iter().any(|&d| d > u32::MAX as usize) {
+        bail!(
+            "An array of shape {:?} can't be written as the IDX file {:?}",
+            data.shape(),
+            path
+        );
+    }
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_u32::<BigEndian>(0x0800 | data.ndim() as u32)?;
+    for &dimension in data.shape() {
+        writer.write_u32::<BigEndian>(dimension as u32)?;
+    }
+    // iterated in logical order, whatever the memory layout of `data`
+    for &value in data.iter() {
+        writer.write_u8(value)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Map the unsigned byte IDX file at `path` in memory, the lazily read counterpart of
+/// `read_idx_data` for the files too large to be loaded at once, see `MappedIdx`
+pub fn read_idx_mmap<P: AsRef<Path>>(path: P) -> anyhow::Result<MappedIdx> {
+    MappedIdx::open(path)
+}
+
+/// An unsigned byte IDX file mapped in memory instead of being loaded in RAM,
+/// the samples (along the first dimension) are only read from disk when accessed.
+pub struct MappedIdx {
+    mmap: Mmap,
+    shape: Vec<usize>,
+    header_size: usize,
+}
+
+impl MappedIdx {
+    pub fn open<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let file_size = file.metadata()?.len() as usize;
+        let header = read_idx_header(&mut BufReader::new(&file), path, file_size)?;
+        // SAFETY: the cached dataset files are only written when missing, never while mapped
+        let mmap = unsafe { Mmap::map(&file)? };
+        Ok(Self {
+            mmap,
+            shape: header.shape,
+            header_size: header.header_size,
+        })
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    /// Number of samples, ie: size of the first dimension
+    pub fn len(&self) -> usize {
+        self.shape.first().copied().unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// A zero-copy view over the whole mapped array
+    pub fn view(&self) -> ArrayViewD<'_, u8> {
+        ArrayViewD::from_shape(self.shape.clone(), &self.mmap[self.header_size..])
+            .expect("the header size has been checked against the file size")
+    }
+
+    /// Copy the samples at `indices` into an owned array of shape (indices.len(), ...)
+    pub fn select(&self, indices: &[usize]) -> ArrayD<u8> {
+        self.view().select(Axis(0), indices)
+    }
 }
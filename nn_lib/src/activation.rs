@@ -1,19 +1,42 @@
-use log::error;
+use crate::{
+    consts::{FRAC_1_SQRT_2, FRAC_2_SQRT_PI},
+    Float,
+};
 use ndarray::{Array1, ArrayD, ArrayView1, Axis};
+use serde::{Deserialize, Serialize};
 
-fn check_nan(array: &ArrayD<f64>, operation: &str) {
-    if array.iter().any(|&x| x.is_nan()) {
-        error!("NaN detected after {} operation", operation);
-    }
-}
+/// sqrt(2 / pi), scale of the tanh approximation of the GELU
+pub(crate) const GELU_SCALE: Float = FRAC_2_SQRT_PI * FRAC_1_SQRT_2;
+/// Cubic coefficient of the tanh approximation of the GELU
+pub(crate) const GELU_CUBIC: Float = 0.044715;
 
-#[derive(Eq, PartialEq, Debug, Clone, Default, Copy, PartialOrd, Ord, Hash)]
+// `Custom` compares its functions by address, two identical closures may not be equal
+#[allow(unpredictable_function_pointer_comparisons)]
+#[derive(PartialEq, Debug, Clone, Default, Copy, PartialOrd, Serialize, Deserialize)]
 pub enum Activation {
     #[default]
     ReLU,
     Tanh,
     Sigmoid,
     Softmax,
+    /// ReLU with a slope of `alpha` (ex: 0.01) for the negative inputs instead of 0,
+    /// so that the neurons are never completely dead
+    LeakyReLU(Float),
+    /// Identity for the positive inputs, `alpha * (exp(x) - 1)` for the negative ones
+    ELU(Float),
+    /// Gaussian error linear unit, computed with its tanh approximation
+    GELU,
+    /// Sigmoid linear unit, also called Swish: `x * sigmoid(x)`
+    SiLU,
+    /// A user defined element wise function `f` and its derivative `df`, given as functions
+    /// or closures that don't capture anything
+    /// (ex: `Activation::Custom { f: f64::sin, df: f64::cos }`).
+    /// Networks using it can't be saved, nor exported to ONNX.
+    #[serde(skip)]
+    Custom {
+        f: fn(Float) -> Float,
+        df: fn(Float) -> Float,
+    },
 }
 
 impl Activation {
@@ -25,44 +48,97 @@ impl Activation {
     /// for every element in the outermost dimension.
     /// # Arguments
     /// * `input` - a multidimensional array;
-    pub fn apply(&self, input: &ArrayD<f64>) -> ArrayD<f64> {
-        let result = match self {
-            Self::ReLU => input.mapv(|e| 0f64.max(e)),
+    pub fn apply(&self, input: &ArrayD<Float>) -> ArrayD<Float> {
+        match self {
+            Self::ReLU => input.mapv(|e| e.max(0.0)),
             Self::Tanh => input.mapv(|e| e.tanh()),
-            Self::Sigmoid => input.mapv(|e| 1.0 / (1.0 + f64::exp(-e))),
+            Self::Sigmoid => input.mapv(sigmoid),
+            Self::LeakyReLU(alpha) => input.mapv(|e| if e > 0.0 { e } else { alpha * e }),
+            Self::ELU(alpha) => input.mapv(|e| if e > 0.0 { e } else { alpha * e.exp_m1() }),
+            Self::GELU => input.mapv(|e| 0.5 * e * (1.0 + gelu_tanh(e))),
+            Self::SiLU => input.mapv(|e| e * sigmoid(e)),
+            Self::Custom { f, .. } => input.mapv(*f),
             Self::Softmax => {
                 let mut result = input.clone();
                 for mut row in result.axis_iter_mut(Axis(0)) {
-                    let row_as_view1: ArrayView1<f64> = row.view().into_dimensionality().unwrap();
-                    let max_logit = row_as_view1.fold(f64::NEG_INFINITY, |max, &val| max.max(val));
-                    let exps: Array1<f64> =
-                        row_as_view1.mapv(|x| f64::exp(x - max_logit)).to_owned();
-                    let sum_exps: f64 = exps.sum() + 1e-10; // to avoid division by zero
-                    let softmax_row: Array1<f64> = exps.mapv(|x| x / sum_exps);
+                    let row_as_view1: ArrayView1<Float> = row.view().into_dimensionality().unwrap();
+                    let max_logit =
+                        row_as_view1.fold(Float::NEG_INFINITY, |max, &val| max.max(val));
+                    let exps: Array1<Float> =
+                        row_as_view1.mapv(|x| Float::exp(x - max_logit)).to_owned();
+                    let sum_exps: Float = exps.sum() + 1e-10; // to avoid division by zero
+                    let softmax_row: Array1<Float> = exps.mapv(|x| x / sum_exps);
                     row.assign(&softmax_row);
                 }
                 result
             }
-        };
-        check_nan(&result, &format!("{:?}", self));
-        result
+        }
     }
 
     /// Apply the activation function derivative to each element of a multidimensional array
     /// not that the dimensions doesn't matter as the transformation is applied element wise.
+    /// The softmax isn't element wise, see `backward` instead.
     /// # Arguments
     /// * `input` - a multidimensional array;
-    pub fn apply_derivative(&self, input: &ArrayD<f64>) -> ArrayD<f64> {
-        let result = match self {
-            Self::ReLU => input.mapv(|e| if e > 0f64 { 1f64 } else { 0f64 }),
-            Self::Tanh => input.mapv(|e| 1f64 - e.tanh().powi(2)),
+    pub fn apply_derivative(&self, input: &ArrayD<Float>) -> ArrayD<Float> {
+        match self {
+            Self::ReLU => input.mapv(|e| if e > 0.0 { 1.0 } else { 0.0 }),
+            Self::Tanh => input.mapv(|e| 1.0 - e.tanh().powi(2)),
             Self::Sigmoid => {
                 let sigmoid_output = self.apply(input);
                 &sigmoid_output * &(1.0 - &sigmoid_output)
             }
-            Self::Softmax => unimplemented!("We don't use the softmax jacobian matrix in practice"),
-        };
-        check_nan(&result, &format!("{:?}", self));
-        result
+            Self::LeakyReLU(alpha) => input.mapv(|e| if e > 0.0 { 1.0 } else { *alpha }),
+            Self::ELU(alpha) => input.mapv(|e| if e > 0.0 { 1.0 } else { alpha * e.exp() }),
+            Self::GELU => input.mapv(|e| {
+                let tanh = gelu_tanh(e);
+                0.5 * (1.0 + tanh)
+                    + 0.5 * e * (1.0 - tanh.powi(2)) * GELU_SCALE * (1.0 + 3.0 * GELU_CUBIC * e * e)
+            }),
+            Self::SiLU => input.mapv(|e| {
+                let sigmoid = sigmoid(e);
+                sigmoid * (1.0 + e * (1.0 - sigmoid))
+            }),
+            Self::Custom { df, .. } => input.mapv(*df),
+            Self::Softmax => unimplemented!("The softmax has a jacobian matrix, see backward"),
+        }
     }
+
+    /// Return the gradient with respect to `input` given the gradient with respect to the
+    /// output of the activation. For the element wise functions it is the product with the
+    /// derivative, for the softmax it is the product with its jacobian matrix, computed for every
+    /// row as `s * (g - sum(g * s))` without building the matrix.
+    /// # Arguments
+    /// * `input` - the input of the activation, shape (n, i)
+    /// * `output_gradient` - shape (n, i)
+    pub fn backward(
+        &self,
+        input: &ArrayD<Float>,
+        output_gradient: &ArrayD<Float>,
+    ) -> ArrayD<Float> {
+        match self {
+            Self::Softmax => {
+                let mut result = self.apply(input);
+                for (mut softmax_row, gradient_row) in result
+                    .axis_iter_mut(Axis(0))
+                    .zip(output_gradient.axis_iter(Axis(0)))
+                {
+                    let dot = (&softmax_row * &gradient_row).sum();
+                    softmax_row.zip_mut_with(&gradient_row, |s, &g| *s *= g - dot);
+                }
+                result
+            }
+            _ => output_gradient * &self.apply_derivative(input),
+        }
+    }
+}
+
+fn sigmoid(x: Float) -> Float {
+    1.0 / (1.0 + Float::exp(-x))
+}
+
+/// The tanh term of the GELU approximation
+/// `0.5 * x * (1 + tanh(sqrt(2 / pi) * (x + 0.044715 * x^3)))`
+fn gelu_tanh(x: Float) -> Float {
+    (GELU_SCALE * (x + GELU_CUBIC * x.powi(3))).tanh()
 }
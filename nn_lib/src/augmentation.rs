@@ -0,0 +1,109 @@
+//! Augmentations of the training batches, drawn anew for every batch by the training generator
+//! of the network, see `SequentialBuilder::augment`.
+//!
+//! Unlike the augmentations of a dataset done once before the training, they can change the
+//! observed outputs of the batch (ex: the soft labels of mixup), which are the ones given to the
+//! cost function and the metrics of the training.
+
+use ndarray::{s, ArrayD, Axis};
+use ndarray_rand::{
+    rand::{seq::SliceRandom, Rng, RngCore},
+    rand_distr::{Beta, Distribution},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{layer::LayerError, Float};
+
+/// An augmentation of every training batch, applied in the order they are added to the network
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum BatchAugmentation {
+    /// Replace the batch by convex combinations of its data points with those of a shuffled copy,
+    /// inputs and observed outputs alike: `λ * x_i + (1 - λ) * x_j`, λ being drawn from a
+    /// Beta(`alpha`, `alpha`) distribution for every batch. Small values of `alpha` (ex: 0.2)
+    /// keep most combinations close to one of the two data points.
+    Mixup { alpha: Float },
+    /// Zero a square of `size` pixels side, centered on a random pixel and cropped by the
+    /// borders, in every input with the given `probability`. The inputs are read as images of
+    /// `rows` x `columns` pixels, with their features row after row and the channels last, so
+    /// that flattened images (ex: the 784 pixels of mnist) are erased as well as
+    /// (rows, columns, channels) ones.
+    Cutout {
+        rows: usize,
+        columns: usize,
+        size: usize,
+        probability: Float,
+    },
+}
+
+impl BatchAugmentation {
+    /// Augment the batch (`x`, `y`) in place, drawing the transformations from `rng`
+    pub(crate) fn apply(
+        &self,
+        x: &mut ArrayD<Float>,
+        y: &mut ArrayD<Float>,
+        rng: &mut dyn RngCore,
+    ) -> Result<(), LayerError> {
+        match *self {
+            Self::Mixup { alpha } => mixup(x, y, alpha, rng),
+            Self::Cutout {
+                rows,
+                columns,
+                size,
+                probability,
+            } => cutout(x, (rows, columns), size, probability, rng),
+        }
+    }
+}
+
+fn mixup(
+    x: &mut ArrayD<Float>,
+    y: &mut ArrayD<Float>,
+    alpha: Float,
+    rng: &mut dyn RngCore,
+) -> Result<(), LayerError> {
+    let n = x.len_of(Axis(0));
+    if n != y.len_of(Axis(0)) {
+        return Err(LayerError::DimensionMismatch);
+    }
+    let beta = Beta::new(alpha, alpha).map_err(|error| {
+        LayerError::InvalidAugmentation(format!("mixup alpha {} : {}", alpha, error))
+    })?;
+    let lambda = beta.sample(rng);
+    let mut permutation = (0..n).collect::<Vec<_>>();
+    permutation.shuffle(rng);
+
+    *x = &*x * lambda + x.select(Axis(0), &permutation) * (1.0 - lambda);
+    *y = &*y * lambda + y.select(Axis(0), &permutation) * (1.0 - lambda);
+    Ok(())
+}
+
+fn cutout(
+    x: &mut ArrayD<Float>,
+    (rows, columns): (usize, usize),
+    size: usize,
+    probability: Float,
+    rng: &mut dyn RngCore,
+) -> Result<(), LayerError> {
+    let n = x.len_of(Axis(0));
+    let features = x.len() / n.max(1);
+    if rows == 0 || columns == 0 || !features.is_multiple_of(rows * columns) {
+        return Err(LayerError::DimensionMismatch);
+    }
+    let channels = features / (rows * columns);
+    let mut images = x.view_mut().into_shape((n, rows, columns, channels))?;
+    let half = size / 2;
+
+    for mut image in images.outer_iter_mut() {
+        if rng.gen::<Float>() >= probability {
+            continue;
+        }
+        let (row, column) = (rng.gen_range(0..rows), rng.gen_range(0..columns));
+        let (top, left) = (row.saturating_sub(half), column.saturating_sub(half));
+        let (bottom, right) = (
+            (row + size - half).min(rows),
+            (column + size - half).min(columns),
+        );
+        image.slice_mut(s![top..bottom, left..right, ..]).fill(0.0);
+    }
+    Ok(())
+}
@@ -0,0 +1,302 @@
+//! The device computing the matrix products of the dense and convolutional layers (the
+//! convolutions being unrolled by im2col into a single product), see
+//! `SequentialBuilder::backend`.
+//!
+//! Everything else (activations, pooling, im2col itself and the optimizer step) stays on the CPU,
+//! the operands being copied to the device and the product back for every call.
+
+use ndarray::{linalg, ArrayView2, ArrayViewMut2};
+#[cfg(feature = "wgpu")]
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::Float;
+
+#[derive(Debug, Clone, Default)]
+pub enum Backend {
+    /// ndarray products, through BLAS with the `openblas` or `accelerate` feature
+    #[default]
+    Cpu,
+    /// A wgpu compute shader, on the Vulkan, Metal or DirectX 12 GPU of the machine, see
+    /// `Backend::wgpu`
+    #[cfg(feature = "wgpu")]
+    Wgpu(Arc<gpu::WgpuContext>),
+}
+
+impl Backend {
+    /// Open the most powerful GPU adapter of the machine and compile the matrix product shader.
+    /// The shader computes in `f32`, whatever the `Float` type of the crate.
+    #[cfg(feature = "wgpu")]
+    pub fn wgpu() -> Result<Self, BackendError> {
+        Ok(Self::Wgpu(Arc::new(gpu::WgpuContext::new()?)))
+    }
+
+    /// The name of the device, ex: the GPU model
+    pub fn device_name(&self) -> String {
+        match self {
+            Self::Cpu => "cpu".to_string(),
+            #[cfg(feature = "wgpu")]
+            Self::Wgpu(context) => context.adapter_name().to_string(),
+        }
+    }
+
+    /// Compute `alpha * a.dot(b) + beta * c` into `c`, as `ndarray::linalg::general_mat_mul`
+    pub(crate) fn gemm(
+        &self,
+        alpha: Float,
+        a: &ArrayView2<Float>,
+        b: &ArrayView2<Float>,
+        beta: Float,
+        c: &mut ArrayViewMut2<Float>,
+    ) -> Result<(), BackendError> {
+        match self {
+            Self::Cpu => linalg::general_mat_mul(alpha, a, b, beta, c),
+            #[cfg(feature = "wgpu")]
+            Self::Wgpu(context) => {
+                let product = context.matmul(a, b)?;
+                c.zip_mut_with(&product, |c, &product| *c = alpha * product + beta * *c);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Two backends are equal when they compute on the same device context
+impl PartialEq for Backend {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Cpu, Self::Cpu) => true,
+            #[cfg(feature = "wgpu")]
+            (Self::Wgpu(first), Self::Wgpu(second)) => Arc::ptr_eq(first, second),
+            #[cfg(feature = "wgpu")]
+            _ => false,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum BackendError {
+    #[error("No GPU adapter available")]
+    NoAdapter,
+
+    #[cfg(feature = "wgpu")]
+    #[error("Failed to open the GPU device: {0}")]
+    RequestDevice(#[from] wgpu::RequestDeviceError),
+
+    #[cfg(feature = "wgpu")]
+    #[error("Failed to read the result back from the GPU: {0}")]
+    BufferMap(#[from] wgpu::BufferAsyncError),
+}
+
+#[cfg(feature = "wgpu")]
+mod gpu {
+    use ndarray::{Array2, ArrayView2};
+    use wgpu::util::DeviceExt;
+
+    use super::BackendError;
+    use crate::Float;
+
+    // side of the square of output elements computed by a workgroup
+    const WORKGROUP_SIZE: u32 = 8;
+
+    // one invocation per element of the (m, n) product of a (m, k) and a (k, n) matrix, all
+    // three in row major order
+    const MATMUL_SHADER: &str = r#"
+struct Dimensions {
+    m: u32,
+    k: u32,
+    n: u32,
+    padding: u32,
+}
+
+@group(0) @binding(0) var<uniform> dimensions: Dimensions;
+@group(0) @binding(1) var<storage, read> a: array<f32>;
+@group(0) @binding(2) var<storage, read> b: array<f32>;
+@group(0) @binding(3) var<storage, read_write> c: array<f32>;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let row = id.x;
+    let col = id.y;
+    if (row >= dimensions.m || col >= dimensions.n) {
+        return;
+    }
+    var sum = 0.0;
+    for (var i = 0u; i < dimensions.k; i = i + 1u) {
+        sum = sum + a[row * dimensions.k + i] * b[i * dimensions.n + col];
+    }
+    c[row * dimensions.n + col] = sum;
+}
+"#;
+
+    /// The device, its queue and the compiled matrix product pipeline, shared by all the layers
+    /// of a network
+    pub struct WgpuContext {
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        pipeline: wgpu::ComputePipeline,
+        adapter_name: String,
+    }
+
+    impl std::fmt::Debug for WgpuContext {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("WgpuContext")
+                .field("adapter_name", &self.adapter_name)
+                .finish()
+        }
+    }
+
+    impl WgpuContext {
+        pub(super) fn new() -> Result<Self, BackendError> {
+            let instance = wgpu::Instance::default();
+            let adapter =
+                pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::HighPerformance,
+                    ..Default::default()
+                }))
+                .ok_or(BackendError::NoAdapter)?;
+            let (device, queue) = pollster::block_on(
+                adapter.request_device(&wgpu::DeviceDescriptor::default(), None),
+            )?;
+
+            let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("matmul"),
+                source: wgpu::ShaderSource::Wgsl(MATMUL_SHADER.into()),
+            });
+            let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("matmul"),
+                layout: None,
+                module: &module,
+                entry_point: "main",
+            });
+
+            Ok(Self {
+                device,
+                queue,
+                pipeline,
+                adapter_name: adapter.get_info().name,
+            })
+        }
+
+        pub(super) fn adapter_name(&self) -> &str {
+            &self.adapter_name
+        }
+
+        /// The product of `a` (m, k) and `b` (k, n), computed in `f32` on the GPU
+        #[allow(clippy::unnecessary_cast)]
+        pub(super) fn matmul(
+            &self,
+            a: &ArrayView2<Float>,
+            b: &ArrayView2<Float>,
+        ) -> Result<Array2<Float>, BackendError> {
+            let (m, k) = a.dim();
+            let n = b.dim().1;
+            // wgpu doesn't allow empty buffers
+            if m == 0 || k == 0 || n == 0 {
+                return Ok(Array2::zeros((m, n)));
+            }
+
+            let dimensions = [m as u32, k as u32, n as u32, 0];
+            let dimensions = self.buffer(
+                bytemuck::cast_slice(&dimensions),
+                wgpu::BufferUsages::UNIFORM,
+            );
+            let a = self.buffer(
+                bytemuck::cast_slice(&to_row_major(a)),
+                wgpu::BufferUsages::STORAGE,
+            );
+            let b = self.buffer(
+                bytemuck::cast_slice(&to_row_major(b)),
+                wgpu::BufferUsages::STORAGE,
+            );
+            let output_size = (m * n * std::mem::size_of::<f32>()) as u64;
+            let output = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: output_size,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: output_size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+
+            let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &self.pipeline.get_bind_group_layout(0),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: dimensions.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: a.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: b.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: output.as_entire_binding(),
+                    },
+                ],
+            });
+
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            {
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: None,
+                    timestamp_writes: None,
+                });
+                pass.set_pipeline(&self.pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.dispatch_workgroups(
+                    (m as u32).div_ceil(WORKGROUP_SIZE),
+                    (n as u32).div_ceil(WORKGROUP_SIZE),
+                    1,
+                );
+            }
+            encoder.copy_buffer_to_buffer(&output, 0, &staging, 0, output_size);
+            self.queue.submit(Some(encoder.finish()));
+
+            let slice = staging.slice(..);
+            let (sender, receiver) = std::sync::mpsc::channel();
+            slice.map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+            self.device.poll(wgpu::Maintain::Wait);
+            receiver
+                .recv()
+                .expect("The GPU buffer mapping callback was dropped")?;
+
+            let values = bytemuck::cast_slice::<u8, f32>(&slice.get_mapped_range())
+                .iter()
+                .map(|&value| value as Float)
+                .collect();
+            staging.unmap();
+            Ok(Array2::from_shape_vec((m, n), values).expect("The product has m * n elements"))
+        }
+
+        fn buffer(&self, contents: &[u8], usage: wgpu::BufferUsages) -> wgpu::Buffer {
+            self.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: None,
+                    contents,
+                    usage,
+                })
+        }
+    }
+
+    /// The elements of `matrix` in row major order, whatever its memory layout (ex: a transposed
+    /// view), converted to the `f32` of the shader
+    #[allow(clippy::unnecessary_cast)]
+    fn to_row_major(matrix: &ArrayView2<Float>) -> Vec<f32> {
+        matrix.iter().map(|&value| value as f32).collect()
+    }
+}
@@ -0,0 +1,252 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use log::info;
+use ndarray::ArrayD;
+
+use crate::{
+    layer::Layer,
+    metrics::{Benchmark, MetricsType},
+    Float,
+};
+
+/// Informations sent to the callbacks at the end of every training batch
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BatchLogs {
+    pub epoch: usize,
+    pub batch: usize,
+    pub number_of_batch: usize,
+    pub batch_size: usize,
+    pub loss: Float,
+}
+
+/// Informations sent to the callbacks at the end of every training epoch
+/// `validation` is `None` if no validation data was given to `train`
+/// `layers` are the layers of the network being trained, to snapshot their parameters
+#[derive(Clone, Copy)]
+pub struct EpochLogs<'a> {
+    pub epoch: usize,
+    pub epochs: usize,
+    pub train: &'a Benchmark,
+    pub validation: Option<&'a Benchmark>,
+    pub layers: &'a [Box<dyn Layer>],
+}
+
+impl fmt::Debug for EpochLogs<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EpochLogs")
+            .field("epoch", &self.epoch)
+            .field("epochs", &self.epochs)
+            .field("train", &self.train)
+            .field("validation", &self.validation)
+            .finish_non_exhaustive()
+    }
+}
+
+/// What the training loop should do after a callback has been notified
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum CallbackAction {
+    #[default]
+    Continue,
+    Stop,
+}
+
+/// The `Callback` trait can be implemented to observe (or stop) the training of a network.
+/// Every method have a default no-op implementation, so an implementor only need to override
+/// the hooks it is interested in.
+///
+/// If any callback return `CallbackAction::Stop`, the training loop stop after the current
+/// batch (or epoch) and `train` return the history recorded so far.
+pub trait Callback: Send {
+    fn on_train_begin(&mut self, _epochs: usize) {}
+
+    fn on_batch_end(&mut self, _logs: &BatchLogs) -> CallbackAction {
+        CallbackAction::Continue
+    }
+
+    fn on_epoch_end(&mut self, _logs: &EpochLogs) -> CallbackAction {
+        CallbackAction::Continue
+    }
+
+    /// `layers` are the layers of the trained network, a callback can replace their parameters
+    /// (ex: `EarlyStopping` restoring the best ones)
+    fn on_train_end(&mut self, _layers: &mut [Box<dyn Layer>]) {}
+}
+
+/// A cloneable flag used to stop a training from another thread, like a signal handler.
+/// Once registered as a callback and cancelled, the training finish the current batch and
+/// `train` return the partial history.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+impl Callback for CancellationToken {
+    fn on_batch_end(&mut self, _logs: &BatchLogs) -> CallbackAction {
+        if self.is_cancelled() {
+            CallbackAction::Stop
+        } else {
+            CallbackAction::Continue
+        }
+    }
+}
+
+/// The value watched by `EarlyStopping`, the loss and error metrics (mae, rmse) are expected to
+/// decrease, the other metrics to increase
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum Monitor {
+    #[default]
+    ValidationLoss,
+    ValidationMetric(MetricsType),
+    TrainLoss,
+    TrainMetric(MetricsType),
+}
+
+impl Monitor {
+    /// The monitored value for an epoch, `None` if the epoch was not validated or the metric is
+    /// not watched
+    fn value(&self, logs: &EpochLogs) -> Option<Float> {
+        match *self {
+            Self::ValidationLoss => logs.validation.map(|bench| bench.loss),
+            Self::ValidationMetric(metric) => logs
+                .validation
+                .and_then(|bench| bench.metrics.get_metric(metric)),
+            Self::TrainLoss => Some(logs.train.loss),
+            Self::TrainMetric(metric) => logs.train.metrics.get_metric(metric),
+        }
+    }
+
+    fn is_decreasing(&self) -> bool {
+        match self {
+            Self::ValidationLoss | Self::TrainLoss => true,
+            Self::ValidationMetric(metric) | Self::TrainMetric(metric) => {
+                matches!(metric, MetricsType::Mae | MetricsType::Rmse)
+            }
+        }
+    }
+}
+
+/// A `Callback` stopping the training once the monitored value (the validation loss by default)
+/// didn't improve by more than `min_delta` for `patience` epochs.
+/// Epochs without the monitored value (not validated, see `ValidationFrequency`) are ignored.
+///
+/// With `restore_best_weights`, the parameters of the best epoch are restored at the end of the
+/// training, the network is then the best one seen instead of the last one.
+#[derive(Clone, Debug)]
+pub struct EarlyStopping {
+    pub monitor: Monitor,
+    pub patience: usize,
+    pub min_delta: Float,
+    pub restore_best_weights: bool,
+    best: Option<Float>,
+    best_epoch: usize,
+    wait: usize,
+    // parameters of every layer at the best epoch, `None` for the non trainable layers
+    best_weights: Option<Vec<Option<Vec<ArrayD<Float>>>>>,
+}
+
+impl EarlyStopping {
+    pub fn new(monitor: Monitor, patience: usize) -> Self {
+        Self {
+            monitor,
+            patience,
+            min_delta: 0.0,
+            restore_best_weights: false,
+            best: None,
+            best_epoch: 0,
+            wait: 0,
+            best_weights: None,
+        }
+    }
+
+    /// Minimum change of the monitored value to count as an improvement
+    pub fn with_min_delta(mut self, min_delta: Float) -> Self {
+        self.min_delta = min_delta;
+        self
+    }
+
+    /// Restore the parameters of the best epoch at the end of the training
+    pub fn with_restore_best_weights(mut self, restore_best_weights: bool) -> Self {
+        self.restore_best_weights = restore_best_weights;
+        self
+    }
+
+    fn is_improvement(&self, value: Float) -> bool {
+        match self.best {
+            None => true,
+            Some(best) if self.monitor.is_decreasing() => value < best - self.min_delta,
+            Some(best) => value > best + self.min_delta,
+        }
+    }
+}
+
+impl Callback for EarlyStopping {
+    fn on_train_begin(&mut self, _epochs: usize) {
+        self.best = None;
+        self.wait = 0;
+        self.best_weights = None;
+    }
+
+    fn on_epoch_end(&mut self, logs: &EpochLogs) -> CallbackAction {
+        let Some(value) = self.monitor.value(logs) else {
+            return CallbackAction::Continue;
+        };
+        if self.is_improvement(value) {
+            self.best = Some(value);
+            self.best_epoch = logs.epoch;
+            self.wait = 0;
+            if self.restore_best_weights {
+                self.best_weights = Some(
+                    logs.layers
+                        .iter()
+                        .map(|layer| layer.as_trainable().map(|t| t.get_parameters()))
+                        .collect(),
+                );
+            }
+            return CallbackAction::Continue;
+        }
+        self.wait += 1;
+        if self.wait >= self.patience {
+            info!(
+                "Early stopping after epoch {}, no improvement of {:?} since epoch {}",
+                logs.epoch, self.monitor, self.best_epoch
+            );
+            return CallbackAction::Stop;
+        }
+        CallbackAction::Continue
+    }
+
+    fn on_train_end(&mut self, layers: &mut [Box<dyn Layer>]) {
+        let Some(best_weights) = self.best_weights.take() else {
+            return;
+        };
+        info!("Restoring the weights of epoch {}", self.best_epoch);
+        for (layer, weights) in layers.iter_mut().zip(best_weights) {
+            let (Some(trainable), Some(weights)) = (layer.as_trainable_mut(), weights) else {
+                continue;
+            };
+            for (parameter, weight) in trainable.get_parameters_mut().into_iter().zip(weights) {
+                *parameter = weight;
+            }
+        }
+    }
+}
@@ -0,0 +1,374 @@
+//! Networks described in a TOML, YAML or JSON file: the layers, the optimizer, the cost function,
+//! the watched metrics and the training hyperparameters, so that the architecture can change
+//! without recompiling, see `NetworkConfig` and `Sequential::from_config`.
+//!
+//! The layers are tables tagged by their `type`, ex in TOML:
+//!
+//! ```toml
+//! metrics = ["Accuracy"]
+//!
+//! [optimizer]
+//! type = "gradient_descent"
+//! learning_rate = 0.1
+//!
+//! [training]
+//! epochs = 10
+//! batch_size = 128
+//!
+//! [[layers]]
+//! type = "dense"
+//! input_size = 784
+//! output_size = 128
+//! init = "He"
+//!
+//! [[layers]]
+//! type = "activation"
+//! activation = "ReLU"
+//! ```
+
+use std::{fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    activation::Activation,
+    augmentation::BatchAugmentation,
+    cost::CostFunction,
+    guard::NumericGuard,
+    initialization::InitializerType,
+    layer::{
+        ActivationLayer, AveragePoolingLayer, ConvolutionalLayer, DenseLayer, DropoutLayer,
+        FlattenLayer, GlobalAveragePoolingLayer, Layer, LayerError, MaxPoolingLayer, PReLULayer,
+        Padding, ReshapeLayer, SimpleRnnLayer,
+    },
+    metrics::MetricsType,
+    optimizer::{GradientClipping, GradientDescent},
+    sequential::{NeuralNetworkError, Sequential, SequentialBuilder, ValidationFrequency},
+    Float,
+};
+
+/// Everything needed to build and train a `Sequential`, callbacks excepted.
+/// The cost function is the cross entropy and no metric is watched unless given.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NetworkConfig {
+    /// A short description of the network, ex: to name it in the reports
+    #[serde(default)]
+    pub name: Option<String>,
+    pub layers: Vec<LayerConfig>,
+    pub optimizer: OptimizerConfig,
+    #[serde(default)]
+    pub cost: CostFunction,
+    #[serde(default)]
+    pub metrics: Vec<MetricsType>,
+    #[serde(default)]
+    pub training: TrainingConfig,
+}
+
+/// A layer of the network, named if `name` is given (see `SequentialBuilder::push_named`)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LayerConfig {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub layer: LayerType,
+}
+
+/// The type of a layer and the arguments of its constructor, the optional ones taking the
+/// defaults of the layer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LayerType {
+    Dense {
+        input_size: usize,
+        output_size: usize,
+        #[serde(default = "default_initializer")]
+        init: InitializerType,
+        #[serde(default = "default_use_bias")]
+        use_bias: bool,
+    },
+    Activation {
+        activation: Activation,
+    },
+    Convolutional {
+        input_size: (usize, usize, usize),
+        kernel_size: (usize, usize),
+        kernels: usize,
+        #[serde(default = "default_initializer")]
+        init: InitializerType,
+        #[serde(default)]
+        padding: Padding,
+        #[serde(default)]
+        stride: Option<(usize, usize)>,
+    },
+    MaxPooling {
+        input_size: (usize, usize, usize),
+        pool_size: (usize, usize),
+        #[serde(default)]
+        stride: Option<(usize, usize)>,
+    },
+    AveragePooling {
+        input_size: (usize, usize, usize),
+        pool_size: (usize, usize),
+        #[serde(default)]
+        stride: Option<(usize, usize)>,
+    },
+    GlobalAveragePooling {
+        input_size: (usize, usize, usize),
+    },
+    Reshape {
+        input_shape: Vec<usize>,
+        output_shape: Vec<usize>,
+    },
+    Flatten,
+    Dropout {
+        rate: Float,
+    },
+    #[serde(rename = "prelu")]
+    PReLU {
+        input_shape: Vec<usize>,
+        alpha: Float,
+    },
+    SimpleRnn {
+        input_size: usize,
+        hidden_size: usize,
+        #[serde(default = "default_initializer")]
+        init: InitializerType,
+        #[serde(default)]
+        return_sequences: bool,
+    },
+}
+
+fn default_initializer() -> InitializerType {
+    InitializerType::GlorotUniform
+}
+
+fn default_use_bias() -> bool {
+    true
+}
+
+/// The optimizer of the network and its hyperparameters
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OptimizerConfig {
+    /// see `GradientDescent`
+    GradientDescent { learning_rate: Float },
+}
+
+impl OptimizerConfig {
+    pub fn learning_rate(&self) -> Float {
+        match self {
+            Self::GradientDescent { learning_rate } => *learning_rate,
+        }
+    }
+
+    pub fn set_learning_rate(&mut self, rate: Float) {
+        match self {
+            Self::GradientDescent { learning_rate } => *learning_rate = rate,
+        }
+    }
+}
+
+/// How the network is trained. `epochs` and `batch_size` are given to `Sequential::train` by
+/// the caller, the others are set on the built network.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct TrainingConfig {
+    #[serde(default)]
+    pub epochs: Option<usize>,
+    #[serde(default)]
+    pub batch_size: Option<usize>,
+    #[serde(default)]
+    pub validation_frequency: Option<ValidationFrequency>,
+    #[serde(default)]
+    pub gradient_clipping: Option<GradientClipping>,
+    /// Seed of the shuffling of the training data, see `SequentialBuilder::seed`
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Applied to every training batch in order, see `SequentialBuilder::augment`
+    #[serde(default)]
+    pub augmentations: Vec<BatchAugmentation>,
+    /// Handling of the NaN and infinite values of the training, see
+    /// `SequentialBuilder::numeric_guard`
+    #[serde(default)]
+    pub numeric_guard: NumericGuard,
+}
+
+impl NetworkConfig {
+    /// Read a configuration from `path`, parsed after its extension: `.toml`, `.yaml` / `.yml`
+    /// or `.json`
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Ok(toml::from_str(&content)?),
+            Some("yaml" | "yml") => Ok(serde_yaml::from_str(&content)?),
+            Some("json") => Ok(serde_json::from_str(&content)?),
+            _ => Err(ConfigError::UnsupportedFormat(path.display().to_string())),
+        }
+    }
+
+    /// Build and compile the network, its weights being drawn from the crate generator (see
+    /// `random::seed`)
+    pub fn build(&self) -> Result<Sequential, ConfigError> {
+        let mut builder = SequentialBuilder::new().watch_all(self.metrics.clone());
+        for layer in &self.layers {
+            builder = layer.layer.push_onto(builder, layer.name.as_deref())?;
+        }
+        if let Some(frequency) = self.training.validation_frequency {
+            builder = builder.validation_frequency(frequency);
+        }
+        builder = match self.training.gradient_clipping {
+            Some(GradientClipping::Value(max)) => builder.clip_value(max),
+            Some(GradientClipping::Norm(max_norm)) => builder.clip_norm(max_norm),
+            None => builder,
+        };
+        if let Some(seed) = self.training.seed {
+            builder = builder.seed(seed);
+        }
+        for augmentation in &self.training.augmentations {
+            builder = builder.augment(*augmentation);
+        }
+        builder = builder.numeric_guard(self.training.numeric_guard);
+        let net = match self.optimizer {
+            OptimizerConfig::GradientDescent { learning_rate } => {
+                builder.compile(GradientDescent::new(learning_rate), self.cost)?
+            }
+        };
+        Ok(net)
+    }
+}
+
+/// `stride` if none of its sides is zero, which would make the `with_stride` of the layers panic
+fn checked_stride(stride: (usize, usize)) -> Result<(usize, usize), LayerError> {
+    if stride.0 == 0 || stride.1 == 0 {
+        return Err(LayerError::DimensionMismatch);
+    }
+    Ok(stride)
+}
+
+impl LayerType {
+    /// Build the layer and push it at the end of `builder`
+    fn push_onto(
+        &self,
+        builder: SequentialBuilder,
+        name: Option<&str>,
+    ) -> Result<SequentialBuilder, LayerError> {
+        let builder = match self {
+            Self::Dense {
+                input_size,
+                output_size,
+                init,
+                use_bias,
+            } => push(
+                builder,
+                name,
+                DenseLayer::new(*input_size, *output_size, init.clone()).with_bias(*use_bias),
+            ),
+            Self::Activation { activation } => {
+                push(builder, name, ActivationLayer::from(*activation))
+            }
+            Self::Convolutional {
+                input_size,
+                kernel_size,
+                kernels,
+                init,
+                padding,
+                stride,
+            } => {
+                // an empty kernel would make `Padding::Same` panic
+                if kernel_size.0 == 0 || kernel_size.1 == 0 {
+                    return Err(LayerError::DimensionMismatch);
+                }
+                let mut layer =
+                    ConvolutionalLayer::new(*input_size, *kernel_size, *kernels, init.clone())
+                        .with_padding(*padding);
+                if let Some(stride) = stride {
+                    layer = layer.with_stride(checked_stride(*stride)?);
+                }
+                push(builder, name, layer)
+            }
+            Self::MaxPooling {
+                input_size,
+                pool_size,
+                stride,
+            } => {
+                let mut layer = MaxPoolingLayer::new(*input_size, *pool_size);
+                if let Some(stride) = stride {
+                    layer = layer.with_stride(checked_stride(*stride)?);
+                }
+                push(builder, name, layer)
+            }
+            Self::AveragePooling {
+                input_size,
+                pool_size,
+                stride,
+            } => {
+                let mut layer = AveragePoolingLayer::new(*input_size, *pool_size);
+                if let Some(stride) = stride {
+                    layer = layer.with_stride(checked_stride(*stride)?);
+                }
+                push(builder, name, layer)
+            }
+            Self::GlobalAveragePooling { input_size } => {
+                push(builder, name, GlobalAveragePoolingLayer::new(*input_size))
+            }
+            Self::Reshape {
+                input_shape,
+                output_shape,
+            } => push(builder, name, ReshapeLayer::new(input_shape, output_shape)?),
+            Self::Flatten => push(builder, name, FlattenLayer::new()),
+            Self::Dropout { rate } => push(builder, name, DropoutLayer::new(*rate)?),
+            Self::PReLU { input_shape, alpha } => {
+                push(builder, name, PReLULayer::new(input_shape, *alpha))
+            }
+            Self::SimpleRnn {
+                input_size,
+                hidden_size,
+                init,
+                return_sequences,
+            } => {
+                let mut layer = SimpleRnnLayer::new(*input_size, *hidden_size, init.clone());
+                if *return_sequences {
+                    layer = layer.with_return_sequences();
+                }
+                push(builder, name, layer)
+            }
+        };
+        Ok(builder)
+    }
+}
+
+fn push(
+    builder: SequentialBuilder,
+    name: Option<&str>,
+    layer: impl Layer + 'static,
+) -> SequentialBuilder {
+    match name {
+        Some(name) => builder.push_named(name, layer),
+        None => builder.push(layer),
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Unsupported configuration file {0}, expected a .toml, .yaml, .yml or .json file")]
+    UnsupportedFormat(String),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Invalid TOML configuration: {0}")]
+    Toml(#[from] toml::de::Error),
+
+    #[error("Invalid YAML configuration: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+
+    #[error("Invalid JSON configuration: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Invalid layer: {0}")]
+    Layer(#[from] LayerError),
+
+    #[error("Invalid network: {0}")]
+    Network(#[from] NeuralNetworkError),
+}
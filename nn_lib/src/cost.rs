@@ -1,82 +1,395 @@
-use ndarray::{ArrayD, Axis};
+use std::any::Any;
 
-#[derive(Copy, Clone, Eq, PartialEq, PartialOrd, Ord, Hash, Debug, Default)]
+use ndarray::{Array1, ArrayD, Axis};
+use serde::{Deserialize, Serialize};
+
+use crate::{activation::Activation, metrics::classes, Float};
+
+/// A cost function that a `Sequential` can be compiled with. `CostFunction` gives the built-in
+/// ones, any other loss can be defined by implementing `sample_costs` and
+/// `cost_output_gradient`.
+pub trait Cost: Sync + Send {
+    /// The cost of every data point (row) of the batch
+    /// # Arguments
+    /// * `output` - a batch matrices (shape (n, j)) of output of the network
+    /// * `observed` - a batch matrices of observed values (shape (n, j))
+    fn sample_costs(&self, output: &ArrayD<Float>, observed: &ArrayD<Float>) -> Array1<Float>;
+
+    /// Return the gradient of the cost of every data point with respect to its row of `output`,
+    /// whatever the last layer of the network. It is not divided by the batch size, the layers
+    /// averaging their parameter gradients over the batch.
+    /// # Arguments
+    /// * `output` - a batch matrices of neural network output (shape (n, j))
+    /// * `observed` - a batch matrices of observed values (shape (n, j))
+    fn cost_output_gradient(
+        &self,
+        output: &ArrayD<Float>,
+        observed: &ArrayD<Float>,
+    ) -> ArrayD<Float>;
+
+    fn as_any(&self) -> &dyn Any;
+
+    /// Whether the cost function need clamped output (probabilities), thus an activation layer
+    /// at the end of the network, checked by `SequentialBuilder::compile`. False by default.
+    fn is_output_dependant(&self) -> bool {
+        false
+    }
+
+    /// The output activation whose gradient simplify with the one of this cost function, see
+    /// `fused_output_gradient`. None by default.
+    fn fused_activation(&self) -> Option<Activation> {
+        None
+    }
+
+    /// Return the gradient of the cost function with respect to the input of the last layer,
+    /// when it is the `fused_activation` of this cost function. `cost_output_gradient` by
+    /// default.
+    fn fused_output_gradient(
+        &self,
+        output: &ArrayD<Float>,
+        observed: &ArrayD<Float>,
+    ) -> ArrayD<Float> {
+        self.cost_output_gradient(output, observed)
+    }
+
+    /// Compute the mean cost of the neural network with respect to a batch `output` and `observed`
+    /// # Arguments
+    /// * `output` - a batch matrices (shape (n, j)) of output of the network
+    /// * `observed` - a one hotted encoded vector of observed values
+    fn cost(&self, output: &ArrayD<Float>, observed: &ArrayD<Float>) -> Float {
+        self.sample_costs(output, observed).mean().unwrap()
+    }
+
+    /// Same as `cost`, the cost of every data point being multiplied by the weight of its
+    /// observed class (see `class_weights`) so the minority classes of an imbalanced dataset
+    /// contribute more to the loss
+    fn weighted_cost(
+        &self,
+        output: &ArrayD<Float>,
+        observed: &ArrayD<Float>,
+        class_weights: &[Float],
+    ) -> Float {
+        (self.sample_costs(output, observed) * sample_weights(observed, class_weights))
+            .mean()
+            .unwrap()
+    }
+
+    /// Same as `cost_output_gradient`, the gradient of every data point being multiplied by the
+    /// weight of its observed class, see `weighted_cost`
+    fn weighted_cost_output_gradient(
+        &self,
+        output: &ArrayD<Float>,
+        observed: &ArrayD<Float>,
+        class_weights: &[Float],
+    ) -> ArrayD<Float> {
+        weight_rows(
+            self.cost_output_gradient(output, observed),
+            observed,
+            class_weights,
+        )
+    }
+
+    /// Same as `fused_output_gradient`, weighted as `weighted_cost_output_gradient`
+    fn weighted_fused_output_gradient(
+        &self,
+        output: &ArrayD<Float>,
+        observed: &ArrayD<Float>,
+        class_weights: &[Float],
+    ) -> ArrayD<Float> {
+        weight_rows(
+            self.fused_output_gradient(output, observed),
+            observed,
+            class_weights,
+        )
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug, Serialize, Deserialize)]
 pub enum CostFunction {
-    #[default]
-    CrossEntropy,
+    /// Categorical cross entropy over one hot encoded observations.
+    /// With a `label_smoothing` ε, the targets are softened to `(1 - ε) * observed + ε / k`,
+    /// with k the number of classes, keeping the network from being overconfident.
+    /// 0 gives the usual cross entropy, see `CostFunction::cross_entropy`.
+    CrossEntropy {
+        label_smoothing: Float,
+    },
     BinaryCrossEntropy,
     Mse,
+    /// Mean absolute error, less sensitive to outliers than the `Mse`
+    Mae,
+    /// Quadratic for the errors smaller than `delta`, linear above it:
+    /// `0.5 * e^2` if `|e| <= delta`, `delta * (|e| - 0.5 * delta)` otherwise
+    Huber(Float),
+    /// Categorical focal loss `-alpha * (1 - p)^gamma * ln(p)`, with p the probability given to
+    /// the observed class: the well classified data points (p close to 1) are down weighted so
+    /// the training focus on the hard ones. A `gamma` of 0 and `alpha` of 1 is the cross entropy.
+    Focal {
+        gamma: Float,
+        alpha: Float,
+    },
+    /// SVM hinge loss `max(0, 1 - t * o)` on the raw scores o of the network, averaged over the
+    /// outputs, with t = 1 for the observed class and -1 for the others (observations encoded
+    /// as 0 / 1 or -1 / 1 are both accepted)
+    Hinge,
+    /// Squared hinge loss `max(0, 1 - t * o)^2`, see `Hinge`
+    SquaredHinge,
+    /// Kullback-Leibler divergence `sum(q * ln(q / p))` of the output distribution p from the
+    /// observed distribution q (ex: the soft targets of a teacher network), each row of
+    /// `observed` summing to 1. It differs from the cross entropy by the entropy of q only,
+    /// so their gradients are the same.
+    KlDivergence,
+}
+
+impl Default for CostFunction {
+    fn default() -> Self {
+        Self::cross_entropy()
+    }
 }
 
 impl CostFunction {
-    /// This crate don't use any kind of auto diff mechanism,
-    /// thus, for function like BinaryCrossEntropy and CrossEntropy that need clamped output,
-    /// we assume Sigmoid and Softmax respectively as the output activation layer.
-    /// the gradient calculation is done with those activation function in mind.
-    /// Those function are called 'Output dependant' to contrast with function like Mse, of which
-    /// the derivative can be easily calculated with respect to any output layer, because it
+    /// The cross entropy without label smoothing
+    pub const fn cross_entropy() -> Self {
+        Self::CrossEntropy {
+            label_smoothing: 0.0,
+        }
+    }
+}
+
+impl Cost for CostFunction {
+    /// Function like BinaryCrossEntropy and CrossEntropy need clamped output (probabilities),
+    /// thus an activation layer at the end of the network.
+    /// Those function are called 'Output dependant' to contrast with function like Mse, which
     /// doesn't need clamped output.
-    pub fn is_output_dependant(&self) -> bool {
+    fn is_output_dependant(&self) -> bool {
         match self {
-            Self::BinaryCrossEntropy | Self::CrossEntropy => true,
-            Self::Mse => false,
+            Self::BinaryCrossEntropy
+            | Self::CrossEntropy { .. }
+            | Self::Focal { .. }
+            | Self::KlDivergence => true,
+            Self::Mse | Self::Mae | Self::Huber(_) | Self::Hinge | Self::SquaredHinge => false,
         }
     }
 
-    /// Compute the mean cost of the neural network with respect to a batch `output` and `observed`
-    /// # Arguments
-    /// * `output` - a batch matrices (shape (n, j)) of output of the network
-    /// * `observed` - a one hotted encoded vector of observed values
-    pub fn cost(&self, output: &ArrayD<f64>, observed: &ArrayD<f64>) -> f64 {
+    /// The output activation whose gradient simplify with the one of this cost function,
+    /// Softmax for CrossEntropy and KlDivergence, Sigmoid for BinaryCrossEntropy.
+    /// When the network ends with it, the gradient is computed with respect to the input of
+    /// that last activation layer directly (see `fused_output_gradient`), which is both cheaper
+    /// and more stable than going through the activation backward pass.
+    fn fused_activation(&self) -> Option<Activation> {
+        match self {
+            Self::CrossEntropy { .. } | Self::KlDivergence => Some(Activation::Softmax),
+            Self::BinaryCrossEntropy => Some(Activation::Sigmoid),
+            // the focal loss is backpropagated through the softmax
+            Self::Mse
+            | Self::Mae
+            | Self::Huber(_)
+            | Self::Focal { .. }
+            | Self::Hinge
+            | Self::SquaredHinge => None,
+        }
+    }
+
+    /// The cost of every data point (row) of the batch
+    fn sample_costs(&self, output: &ArrayD<Float>, observed: &ArrayD<Float>) -> Array1<Float> {
         let epsilon = 1e-7;
         let clipped_output = output.mapv(|x| x.clamp(epsilon, 1.0 - epsilon));
         match self {
-            Self::CrossEntropy => {
-                observed
-                    .axis_iter(Axis(0))
-                    .enumerate()
-                    .map(|(i, observed_row)| {
-                        let correct_class = observed_row.iter().position(|&x| x == 1.0).unwrap();
-                        -f64::ln(clipped_output[[i, correct_class]])
-                    })
-                    .sum::<f64>()
-                    / output.shape()[0] as f64
+            Self::CrossEntropy { label_smoothing } => {
+                let targets = smooth_labels(observed, *label_smoothing);
+                -(targets * clipped_output.mapv(Float::ln))
+                    .sum_axis(Axis(1))
+                    .into_dimensionality()
+                    .unwrap()
             }
             Self::BinaryCrossEntropy => {
-                let losses = observed * &clipped_output.mapv(f64::ln)
-                    + &(1.0 - observed) * &((1.0 - clipped_output).mapv(f64::ln));
-                -losses.mean().unwrap()
+                let losses = observed * &clipped_output.mapv(Float::ln)
+                    + &(1.0 - observed) * &((1.0 - clipped_output).mapv(Float::ln));
+                -losses
+                    .mean_axis(Axis(1))
+                    .unwrap()
+                    .into_dimensionality()
+                    .unwrap()
             }
             Self::Mse => {
                 let diff = output - observed;
-                diff.mapv(|x| x.powi(2)).mean().unwrap()
+                diff.mapv(|x| x.powi(2))
+                    .mean_axis(Axis(1))
+                    .unwrap()
+                    .into_dimensionality()
+                    .unwrap()
+            }
+            Self::Mae => {
+                let diff = output - observed;
+                diff.mapv(Float::abs)
+                    .mean_axis(Axis(1))
+                    .unwrap()
+                    .into_dimensionality()
+                    .unwrap()
+            }
+            Self::Huber(delta) => {
+                let diff = output - observed;
+                diff.mapv(|x| {
+                    if x.abs() <= *delta {
+                        0.5 * x.powi(2)
+                    } else {
+                        delta * (x.abs() - 0.5 * delta)
+                    }
+                })
+                .mean_axis(Axis(1))
+                .unwrap()
+                .into_dimensionality()
+                .unwrap()
+            }
+            Self::Focal { gamma, alpha } => {
+                let losses =
+                    observed * &clipped_output.mapv(|p| -alpha * (1.0 - p).powf(*gamma) * p.ln());
+                losses.sum_axis(Axis(1)).into_dimensionality().unwrap()
+            }
+            Self::Hinge | Self::SquaredHinge => {
+                let margins = hinge_margins(output, observed);
+                let losses = if *self == Self::SquaredHinge {
+                    margins.mapv(|m| m.powi(2))
+                } else {
+                    margins
+                };
+                losses
+                    .mean_axis(Axis(1))
+                    .unwrap()
+                    .into_dimensionality()
+                    .unwrap()
+            }
+            Self::KlDivergence => {
+                // 0 * ln(0) is taken as 0, the classes absent from observed adding nothing
+                let mut losses = observed.clone();
+                losses.zip_mut_with(&clipped_output, |q, &p| {
+                    if *q > 0.0 {
+                        *q *= (*q / p).ln()
+                    }
+                });
+                losses.sum_axis(Axis(1)).into_dimensionality().unwrap()
             }
         }
     }
 
-    /// Return the gradient of cost function with respect to `output`
-    /// Note that this simple, from 'almost' scratch library don't use auto-differentiation
-    /// thus `BinaryCrossEntropy` calculation assume a Sigmoid activation as the layer.
-    /// `CrossEntropy` calculation assume a Softmax activation as the last
-    /// layer
+    /// Return the gradient of cost function with respect to `output`, whatever the last layer
+    /// of the network, see `fused_output_gradient` for the usual output activations
     /// # Arguments
     /// * `output` - a batch matrices of neural network output (shape (n, j))
     /// * `observed` - a batch matrices of observed values (shape (n, j))
     ///
     /// Note that CrossEntropy and BinaryCrossEntropy assume one hot encoded vector for the
     /// observed vector if the is multi-class.
-    pub fn cost_output_gradient(
+    fn cost_output_gradient(
         &self,
-        output: &ArrayD<f64>,
-        observed: &ArrayD<f64>,
-    ) -> ArrayD<f64> {
+        output: &ArrayD<Float>,
+        observed: &ArrayD<Float>,
+    ) -> ArrayD<Float> {
+        let epsilon = 1e-7;
         match self {
-            Self::CrossEntropy => output - observed,
-            Self::BinaryCrossEntropy => output - observed,
-            Self::Mse => {
-                let batch_size = output.shape()[0];
-                2f64 * (output - observed) / batch_size as f64
+            Self::CrossEntropy { label_smoothing } => {
+                -smooth_labels(observed, *label_smoothing)
+                    / output.mapv(|x| x.clamp(epsilon, 1.0 - epsilon))
+            }
+            Self::KlDivergence => -observed / output.mapv(|x| x.clamp(epsilon, 1.0 - epsilon)),
+            Self::BinaryCrossEntropy => {
+                let clipped_output = output.mapv(|x| x.clamp(epsilon, 1.0 - epsilon));
+                (&clipped_output - observed) / (&clipped_output * &(1.0 - &clipped_output))
             }
+            Self::Mse => 2.0 * (output - observed),
+            Self::Mae => {
+                // the error is not derivable at 0, its subgradient 0 is taken there
+                let sign = |x: Float| if x == 0.0 { 0.0 } else { x.signum() };
+                (output - observed).mapv(sign)
+            }
+            Self::Huber(delta) => (output - observed).mapv(|x| x.clamp(-delta, *delta)),
+            Self::Focal { gamma, alpha } => {
+                let gradients = output.mapv(|p| {
+                    let p = p.clamp(epsilon, 1.0 - epsilon);
+                    alpha
+                        * (gamma * (1.0 - p).powf(gamma - 1.0) * p.ln()
+                            - (1.0 - p).powf(*gamma) / p)
+                });
+                observed * &gradients
+            }
+            Self::Hinge | Self::SquaredHinge => {
+                let margins = hinge_margins(output, observed);
+                // the hinge is not derivable at the margin, its subgradient 0 is taken there
+                let mut gradients = observed.mapv(|y| if y > 0.0 { -1.0 } else { 1.0 });
+                gradients.zip_mut_with(&margins, |gradient, &margin| {
+                    *gradient *= match self {
+                        Self::SquaredHinge => 2.0 * margin,
+                        _ if margin > 0.0 => 1.0,
+                        _ => 0.0,
+                    }
+                });
+                gradients
+            }
+        }
+    }
+
+    /// Return the gradient of the cost function with respect to the input of the last layer,
+    /// when it is the `fused_activation` of this cost function: `output - observed` for
+    /// CrossEntropy and KlDivergence after a Softmax, and BinaryCrossEntropy after a Sigmoid.
+    /// The other cost functions have no fused activation, `cost_output_gradient` is returned.
+    fn fused_output_gradient(
+        &self,
+        output: &ArrayD<Float>,
+        observed: &ArrayD<Float>,
+    ) -> ArrayD<Float> {
+        match self {
+            Self::CrossEntropy { label_smoothing } => {
+                output - smooth_labels(observed, *label_smoothing)
+            }
+            Self::BinaryCrossEntropy | Self::KlDivergence => output - observed,
+            Self::Mse
+            | Self::Mae
+            | Self::Huber(_)
+            | Self::Focal { .. }
+            | Self::Hinge
+            | Self::SquaredHinge => self.cost_output_gradient(output, observed),
         }
     }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// The margins `max(0, 1 - t * o)` of the hinge losses, see `CostFunction::Hinge`
+fn hinge_margins(output: &ArrayD<Float>, observed: &ArrayD<Float>) -> ArrayD<Float> {
+    let mut margins = output.clone();
+    margins.zip_mut_with(observed, |o, &y| {
+        let t = if y > 0.0 { 1.0 } else { -1.0 };
+        *o = (1.0 - t * *o).max(0.0)
+    });
+    margins
+}
+
+/// The one hot `observed` softened by `label_smoothing`, see `CostFunction::CrossEntropy`
+fn smooth_labels(observed: &ArrayD<Float>, label_smoothing: Float) -> ArrayD<Float> {
+    let number_of_class = observed.shape()[1] as Float;
+    observed.mapv(|x| x * (1.0 - label_smoothing) + label_smoothing / number_of_class)
+}
+
+/// Multiply the gradient of every data point by the weight of its observed class
+fn weight_rows(
+    mut gradient: ArrayD<Float>,
+    observed: &ArrayD<Float>,
+    class_weights: &[Float],
+) -> ArrayD<Float> {
+    let weights = sample_weights(observed, class_weights);
+    for (mut row, weight) in gradient.axis_iter_mut(Axis(0)).zip(weights) {
+        row *= weight;
+    }
+    gradient
+}
+
+/// The weight of the observed class of every data point, `class_weights[class]`, or 1 for the
+/// classes without weight. The class is found as in the accuracy: the index of the one, or for a
+/// single output 1 if the observed value is at least 0.5 and 0 otherwise.
+fn sample_weights(observed: &ArrayD<Float>, class_weights: &[Float]) -> Array1<Float> {
+    classes(observed)
+        .into_iter()
+        .map(|class| class_weights.get(class).copied().unwrap_or(1.0))
+        .collect()
 }
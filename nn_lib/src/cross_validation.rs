@@ -0,0 +1,136 @@
+use std::{collections::BTreeMap, fmt};
+
+use log::debug;
+use ndarray::{ArrayD, Axis};
+use ndarray_rand::rand::seq::SliceRandom;
+
+use crate::{
+    layer::LayerError,
+    metrics::{Benchmark, MetricsType},
+    random,
+    sequential::Sequential,
+    Float,
+};
+
+/// Mean and standard deviation of a value over the folds of a cross validation
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Summary {
+    pub mean: Float,
+    pub std: Float,
+}
+
+impl Summary {
+    fn from_values(values: &[Float]) -> Self {
+        let count = values.len().max(1) as Float;
+        let mean = values.iter().sum::<Float>() / count;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<Float>() / count;
+        Self {
+            mean,
+            std: variance.sqrt(),
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.4} ± {:.4}", self.mean, self.std)
+    }
+}
+
+/// The result of `cross_validate`: the benchmark of every model on its held out fold, and the
+/// mean and standard deviation over the folds of the loss and of every watched metric
+#[derive(Clone, PartialEq, Debug)]
+pub struct CrossValidationReport {
+    pub folds: Vec<Benchmark>,
+    pub loss: Summary,
+    pub metrics: BTreeMap<MetricsType, Summary>,
+}
+
+impl CrossValidationReport {
+    fn from_folds(folds: Vec<Benchmark>) -> Self {
+        let losses = folds.iter().map(|bench| bench.loss).collect::<Vec<_>>();
+        let mut values: BTreeMap<MetricsType, Vec<Float>> = BTreeMap::new();
+        for bench in folds.iter() {
+            for (metric, value) in bench.metrics.metrics.iter() {
+                values.entry(*metric).or_default().push(*value);
+            }
+        }
+        Self {
+            loss: Summary::from_values(&losses),
+            metrics: values
+                .into_iter()
+                .map(|(metric, values)| (metric, Summary::from_values(&values)))
+                .collect(),
+            folds,
+        }
+    }
+}
+
+impl fmt::Display for CrossValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} folds", self.folds.len())?;
+        writeln!(f, "{:>12} : {}", "loss", self.loss)?;
+        for (metric, summary) in self.metrics.iter() {
+            writeln!(f, "{:>12} : {}", metric.to_string(), summary)?;
+        }
+        Ok(())
+    }
+}
+
+/// K-fold cross validation: the data points are shuffled and split into `k` folds, then for
+/// every fold a new model given by `builder_fn` is trained on the other folds for `epochs` and
+/// evaluated on the held out fold.
+///
+/// # Panics
+/// If `k` is less than 2 or greater than the number of data points
+pub fn cross_validate<F>(
+    mut builder_fn: F,
+    x: &ArrayD<Float>,
+    y: &ArrayD<Float>,
+    k: usize,
+    epochs: usize,
+    batch_size: usize,
+) -> Result<CrossValidationReport, LayerError>
+where
+    F: FnMut() -> Sequential,
+{
+    if x.shape()[0] != y.shape()[0] {
+        return Err(LayerError::DimensionMismatch);
+    }
+    let samples = x.shape()[0];
+    assert!(
+        (2..=samples).contains(&k),
+        "the number of folds must be between 2 and the number of data points, got {}",
+        k
+    );
+    let mut indices = (0..samples).collect::<Vec<_>>();
+    random::with_rng(|rng| indices.shuffle(rng));
+
+    let mut folds = Vec::with_capacity(k);
+    for fold in 0..k {
+        // the fold sizes differ by at most one data point
+        let start = fold * samples / k;
+        let end = (fold + 1) * samples / k;
+        let held_out = &indices[start..end];
+        let train = [&indices[..start], &indices[end..]].concat();
+        debug!(
+            "Cross validation fold {} : {} train, {} held out",
+            fold,
+            train.len(),
+            held_out.len()
+        );
+
+        let mut network = builder_fn();
+        network.train(
+            (&x.select(Axis(0), &train), &y.select(Axis(0), &train)),
+            None,
+            epochs,
+            batch_size,
+        )?;
+        folds.push(network.evaluate(
+            (&x.select(Axis(0), held_out), &y.select(Axis(0), held_out)),
+            batch_size,
+        )?);
+    }
+    Ok(CrossValidationReport::from_folds(folds))
+}
@@ -0,0 +1,407 @@
+use std::{
+    collections::BTreeMap,
+    panic,
+    sync::mpsc::{self, Receiver, Sender},
+    thread::{self, JoinHandle},
+};
+
+use ndarray::{ArrayD, Axis, Slice};
+use ndarray_rand::rand::seq::SliceRandom;
+use ndarray_rand::rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{layer::LayerError, metrics::classes, random, Float};
+
+/// A batch of inputs with their observed outputs, both with the data points along the first axis
+pub type Batch = (ArrayD<Float>, ArrayD<Float>);
+
+/// Inputs with their observed outputs, both with the data points along the first axis
+pub type Dataset = (ArrayD<Float>, ArrayD<Float>);
+
+/// A source of training batches produced on demand, see `Sequential::train_with_loader`.
+/// Implementing it allows a training over data that is not held in memory at once
+/// (ex: memory-mapped or streamed datasets) or generated on the fly (ex: augmentation).
+pub trait DataLoader {
+    /// Number of batches yielded in every epoch
+    fn number_of_batch(&self) -> usize;
+
+    /// Called before the first batch of every epoch, ex: to rewind or reshuffle the data
+    fn start_epoch(&mut self, _epoch: usize) {}
+
+    /// The next batch of the current epoch, `None` once the epoch is over
+    fn next_batch(&mut self) -> Option<Batch>;
+}
+
+/// A `DataLoader` over two arrays in memory, the data points are shuffled once at creation
+/// and every epoch yields the same batches in the same order.
+/// Only the indices are shuffled, every batch is copied out of the arrays when it is yielded.
+pub struct ArrayLoader<'a> {
+    x: &'a ArrayD<Float>,
+    y: &'a ArrayD<Float>,
+    // none when the data points are yielded in order
+    indices: Option<Vec<usize>>,
+    batch_size: usize,
+    position: usize,
+}
+
+impl<'a> ArrayLoader<'a> {
+    /// # Arguments
+    /// * `x` - the inputs, one data point per entry of the first axis
+    /// * `y` - the observed outputs, as many as the inputs
+    /// * `batch_size` - the number of data point per batch, the last batch may be smaller
+    pub fn new(
+        x: &'a ArrayD<Float>,
+        y: &'a ArrayD<Float>,
+        batch_size: usize,
+    ) -> Result<Self, LayerError> {
+        random::with_rng(|rng| Self::with_rng(x, y, batch_size, rng))
+    }
+
+    /// Same as `new`, shuffling the data points with `rng`
+    pub fn with_rng<R: Rng + ?Sized>(
+        x: &'a ArrayD<Float>,
+        y: &'a ArrayD<Float>,
+        batch_size: usize,
+        rng: &mut R,
+    ) -> Result<Self, LayerError> {
+        if x.shape()[0] != y.shape()[0] {
+            return Err(LayerError::DimensionMismatch);
+        }
+        let mut indices = (0..x.shape()[0]).collect::<Vec<_>>();
+        indices.shuffle(rng);
+        Ok(Self {
+            x,
+            y,
+            indices: Some(indices),
+            batch_size: batch_size.max(1),
+            position: 0,
+        })
+    }
+
+    /// Same as `new` without shuffling, the batches being contiguous slices of the arrays taken
+    /// in order, ex: for an evaluation
+    pub fn in_order(
+        x: &'a ArrayD<Float>,
+        y: &'a ArrayD<Float>,
+        batch_size: usize,
+    ) -> Result<Self, LayerError> {
+        if x.shape()[0] != y.shape()[0] {
+            return Err(LayerError::DimensionMismatch);
+        }
+        Ok(Self {
+            x,
+            y,
+            indices: None,
+            batch_size: batch_size.max(1),
+            position: 0,
+        })
+    }
+}
+
+impl DataLoader for ArrayLoader<'_> {
+    fn number_of_batch(&self) -> usize {
+        self.x.shape()[0].div_ceil(self.batch_size)
+    }
+
+    fn start_epoch(&mut self, _epoch: usize) {
+        self.position = 0;
+    }
+
+    fn next_batch(&mut self) -> Option<Batch> {
+        let len = self.x.shape()[0];
+        if self.position >= len {
+            return None;
+        }
+        let end = (self.position + self.batch_size).min(len);
+        let batch = match &self.indices {
+            Some(indices) => {
+                let batch_indices = &indices[self.position..end];
+                (
+                    self.x.select(Axis(0), batch_indices),
+                    self.y.select(Axis(0), batch_indices),
+                )
+            }
+            None => {
+                let slice = Slice::from(self.position..end);
+                (
+                    self.x.slice_axis(Axis(0), slice).to_owned(),
+                    self.y.slice_axis(Axis(0), slice).to_owned(),
+                )
+            }
+        };
+        self.position = end;
+        Some(batch)
+    }
+}
+
+/// A `DataLoader` of class-balanced batches over two arrays in memory, ex: for a dataset where
+/// some classes are much rarer than others. The data points of a batch are drawn in turn from
+/// every class of `y` (the index of the highest output, or the rounded output for a single
+/// column), at random and with replacement, so that the classes are equally represented
+/// whatever their frequency. An epoch yields as many data points as the arrays hold, the
+/// minority classes being seen several times and the majority ones only partly.
+pub struct BalancedLoader<'a> {
+    x: &'a ArrayD<Float>,
+    y: &'a ArrayD<Float>,
+    // the indices of the data points of every class
+    classes: Vec<Vec<usize>>,
+    batch_size: usize,
+    batch: usize,
+    rng: ChaCha8Rng,
+}
+
+impl<'a> BalancedLoader<'a> {
+    /// Same arguments as `ArrayLoader::new`, the data points being drawn from the crate
+    /// generator (see `random::seed`)
+    pub fn new(
+        x: &'a ArrayD<Float>,
+        y: &'a ArrayD<Float>,
+        batch_size: usize,
+    ) -> Result<Self, LayerError> {
+        random::with_rng(|rng| Self::with_rng(x, y, batch_size, rng))
+    }
+
+    /// Same as `new`, the data points being drawn from a generator seeded by `rng`
+    pub fn with_rng<R: Rng + ?Sized>(
+        x: &'a ArrayD<Float>,
+        y: &'a ArrayD<Float>,
+        batch_size: usize,
+        rng: &mut R,
+    ) -> Result<Self, LayerError> {
+        if x.shape()[0] != y.shape()[0] {
+            return Err(LayerError::DimensionMismatch);
+        }
+        Ok(Self {
+            x,
+            y,
+            classes: class_groups(y),
+            batch_size: batch_size.max(1),
+            batch: 0,
+            rng: ChaCha8Rng::seed_from_u64(rng.gen()),
+        })
+    }
+}
+
+impl DataLoader for BalancedLoader<'_> {
+    fn number_of_batch(&self) -> usize {
+        self.x.shape()[0].div_ceil(self.batch_size)
+    }
+
+    fn start_epoch(&mut self, _epoch: usize) {
+        self.batch = 0;
+    }
+
+    fn next_batch(&mut self) -> Option<Batch> {
+        if self.batch >= self.number_of_batch() {
+            return None;
+        }
+        let start = self.batch * self.batch_size;
+        let end = (start + self.batch_size).min(self.x.shape()[0]);
+        let indices: Vec<usize> = (start..end)
+            .map(|position| {
+                let class = &self.classes[position % self.classes.len()];
+                class[self.rng.gen_range(0..class.len())]
+            })
+            .collect();
+        self.batch += 1;
+        Some((
+            self.x.select(Axis(0), &indices),
+            self.y.select(Axis(0), &indices),
+        ))
+    }
+}
+
+/// A `DataLoader` preparing the batches of another one on a background thread, so that reading,
+/// augmenting or normalizing the next batches overlaps with the training on the current one.
+/// Up to `capacity` batches are prepared ahead, within the current epoch.
+/// The wrapped loader is moved to the thread, it must own its data (ex: behind an `Arc`).
+pub struct PrefetchLoader {
+    number_of_batch: usize,
+    epochs: Sender<usize>,
+    // `None` marks the end of an epoch
+    batches: Receiver<Option<Batch>>,
+    // whether the batches of the current epoch are still to be received
+    pending: bool,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl PrefetchLoader {
+    pub fn new<L: DataLoader + Send + 'static>(loader: L, capacity: usize) -> Self {
+        Self::with_transform(loader, capacity, |batch| batch)
+    }
+
+    /// Same as `new`, every batch being passed through `transform` on the background thread,
+    /// ex: to augment or normalize it
+    pub fn with_transform<L, F>(mut loader: L, capacity: usize, mut transform: F) -> Self
+    where
+        L: DataLoader + Send + 'static,
+        F: FnMut(Batch) -> Batch + Send + 'static,
+    {
+        let number_of_batch = loader.number_of_batch();
+        let (epochs, requested) = mpsc::channel::<usize>();
+        let (sender, batches) = mpsc::sync_channel(capacity.max(1));
+        // the thread ends once the loader is dropped, closing both channels
+        let worker = thread::spawn(move || {
+            for epoch in requested {
+                loader.start_epoch(epoch);
+                while let Some(batch) = loader.next_batch() {
+                    if sender.send(Some(transform(batch))).is_err() {
+                        return;
+                    }
+                }
+                if sender.send(None).is_err() {
+                    return;
+                }
+            }
+        });
+        Self {
+            number_of_batch,
+            epochs,
+            batches,
+            pending: false,
+            worker: Some(worker),
+        }
+    }
+
+    /// Re-raise the panic of the background thread, if it ended with one
+    fn propagate_panic(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            if let Err(payload) = worker.join() {
+                panic::resume_unwind(payload);
+            }
+        }
+    }
+}
+
+impl DataLoader for PrefetchLoader {
+    fn number_of_batch(&self) -> usize {
+        self.number_of_batch
+    }
+
+    fn start_epoch(&mut self, epoch: usize) {
+        // skip the batches left by an epoch cut short
+        while self.pending && matches!(self.batches.recv(), Ok(Some(_))) {}
+        self.pending = self.epochs.send(epoch).is_ok();
+        if !self.pending {
+            self.propagate_panic();
+        }
+    }
+
+    fn next_batch(&mut self) -> Option<Batch> {
+        if !self.pending {
+            return None;
+        }
+        match self.batches.recv() {
+            Ok(Some(batch)) => Some(batch),
+            Ok(None) => {
+                self.pending = false;
+                None
+            }
+            Err(_) => {
+                self.pending = false;
+                self.propagate_panic();
+                None
+            }
+        }
+    }
+}
+
+/// Split `x` and `y` at random into a train and a test set, the test set holding `test_fraction`
+/// of the data points. The same `seed` always gives the same split.
+/// Apply it twice to get a train, validation and test split.
+///
+/// # Panics
+/// If `test_fraction` is not between 0 and 1
+pub fn train_test_split(
+    x: &ArrayD<Float>,
+    y: &ArrayD<Float>,
+    test_fraction: Float,
+    seed: u64,
+) -> Result<(Dataset, Dataset), LayerError> {
+    split(
+        x,
+        y,
+        test_fraction,
+        false,
+        &mut ChaCha8Rng::seed_from_u64(seed),
+    )
+}
+
+/// Same as `train_test_split`, every class of `y` being split on its own so the train and test
+/// sets keep the class proportions of `y`
+pub fn stratified_split(
+    x: &ArrayD<Float>,
+    y: &ArrayD<Float>,
+    test_fraction: Float,
+    seed: u64,
+) -> Result<(Dataset, Dataset), LayerError> {
+    split(
+        x,
+        y,
+        test_fraction,
+        true,
+        &mut ChaCha8Rng::seed_from_u64(seed),
+    )
+}
+
+/// Split `x` and `y` with `split_indices`, the second set holding `fraction` of the data points
+pub(crate) fn split<R: Rng + ?Sized>(
+    x: &ArrayD<Float>,
+    y: &ArrayD<Float>,
+    fraction: Float,
+    stratify: bool,
+    rng: &mut R,
+) -> Result<(Dataset, Dataset), LayerError> {
+    if x.shape()[0] != y.shape()[0] {
+        return Err(LayerError::DimensionMismatch);
+    }
+    let (kept, held_out) = split_indices(y, fraction, stratify, rng);
+    Ok((
+        (x.select(Axis(0), &kept), y.select(Axis(0), &kept)),
+        (x.select(Axis(0), &held_out), y.select(Axis(0), &held_out)),
+    ))
+}
+
+/// Shuffle the indices of the data points of `y` and split them into two sets, the second one
+/// holding `fraction` of the data points. When `stratify` is set, every class of `y` (the index
+/// of the highest output, or the rounded output for a single column) is split on its own so both
+/// sets keep the class proportions of `y`.
+/// Both sets are returned in increasing order.
+fn split_indices<R: Rng + ?Sized>(
+    y: &ArrayD<Float>,
+    fraction: Float,
+    stratify: bool,
+    rng: &mut R,
+) -> (Vec<usize>, Vec<usize>) {
+    assert!(
+        (0.0..=1.0).contains(&fraction),
+        "the split fraction must be between 0 and 1, got {}",
+        fraction
+    );
+    let groups: Vec<Vec<usize>> = if stratify {
+        class_groups(y)
+    } else {
+        vec![(0..y.shape()[0]).collect()]
+    };
+
+    let (mut kept, mut split) = (Vec::new(), Vec::new());
+    for mut group in groups {
+        group.shuffle(rng);
+        let split_size = (group.len() as Float * fraction).round() as usize;
+        split.extend_from_slice(&group[..split_size]);
+        kept.extend_from_slice(&group[split_size..]);
+    }
+    kept.sort_unstable();
+    split.sort_unstable();
+    (kept, split)
+}
+
+/// The indices of the data points of every class of `y` present in it, in increasing order of
+/// class, see `split_indices` for the class of a data point
+fn class_groups(y: &ArrayD<Float>) -> Vec<Vec<usize>> {
+    let mut groups: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (i, class) in classes(y).into_iter().enumerate() {
+        groups.entry(class).or_default().push(i);
+    }
+    groups.into_values().collect()
+}
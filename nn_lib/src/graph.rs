@@ -0,0 +1,404 @@
+use std::{collections::HashMap, time::Instant};
+
+use log::debug;
+use ndarray::{ArrayD, Axis};
+use ndarray_rand::rand::seq::SliceRandom;
+use thiserror::Error;
+
+use crate::{
+    activation::Activation,
+    cost::Cost,
+    layer::{ActivationLayer, Layer, LayerError, MergeLayer},
+    metrics::{Benchmark, History, MetricsType},
+    optimizer::Optimizer,
+    random, Float,
+};
+
+enum Operation {
+    /// The input of the model at this position
+    Input(usize),
+    Layer(Box<dyn Layer>),
+    Merge(Box<dyn MergeLayer>),
+}
+
+struct Node {
+    operation: Operation,
+    inputs: Vec<usize>,
+}
+
+/// Build a `GraphModel` node by node, every node being referred to by its name.
+/// A node can only take as input the nodes added before it, so the graph has no cycle.
+#[derive(Default)]
+pub struct GraphBuilder {
+    nodes: Vec<(String, Operation, Vec<String>)>,
+    metrics: Vec<MetricsType>,
+}
+
+impl GraphBuilder {
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![],
+            metrics: vec![],
+        }
+    }
+
+    /// Add an input of the model, the inputs being given to `GraphModel::predict` and
+    /// `GraphModel::train` in the order they are added
+    pub fn input<S: Into<String>>(mut self, name: S) -> Self {
+        let position = self
+            .nodes
+            .iter()
+            .filter(|(_, operation, _)| matches!(operation, Operation::Input(_)))
+            .count();
+        self.nodes
+            .push((name.into(), Operation::Input(position), vec![]));
+        self
+    }
+
+    /// Add a node applying `layer` to the output of the node `input`
+    pub fn layer<S: Into<String>>(
+        mut self,
+        name: S,
+        layer: impl Layer + 'static,
+        input: &str,
+    ) -> Self {
+        self.nodes.push((
+            name.into(),
+            Operation::Layer(Box::new(layer)),
+            vec![input.to_string()],
+        ));
+        self
+    }
+
+    /// Add a node combining the outputs of the nodes `inputs` with `merge` (ex: `AddLayer`)
+    pub fn merge<S: Into<String>>(
+        mut self,
+        name: S,
+        merge: impl MergeLayer + 'static,
+        inputs: &[&str],
+    ) -> Self {
+        self.nodes.push((
+            name.into(),
+            Operation::Merge(Box::new(merge)),
+            inputs.iter().map(|input| input.to_string()).collect(),
+        ));
+        self
+    }
+
+    /// Add a metric to compute during training and evaluation, see
+    /// `SequentialBuilder::watch`
+    pub fn watch(mut self, metric_type: MetricsType) -> Self {
+        self.metrics.push(metric_type);
+        self
+    }
+
+    /// Build the model, whose prediction is the output of the node `output`.
+    /// Returns a `GraphError` if the graph is wrongly defined.
+    pub fn compile(
+        self,
+        output: &str,
+        optimizer: impl Optimizer + 'static,
+        cost_function: impl Cost + 'static,
+    ) -> Result<GraphModel, GraphError> {
+        let mut indices: HashMap<String, usize> = HashMap::new();
+        let mut nodes = Vec::with_capacity(self.nodes.len());
+        for (index, (name, operation, input_names)) in self.nodes.into_iter().enumerate() {
+            if matches!(operation, Operation::Merge(_)) && input_names.is_empty() {
+                return Err(GraphError::MissingMergeInput(name));
+            }
+            let inputs = input_names
+                .into_iter()
+                .map(|input| {
+                    indices
+                        .get(&input)
+                        .copied()
+                        .ok_or(GraphError::UnknownNode(input))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if indices.insert(name.clone(), index).is_some() {
+                return Err(GraphError::DuplicateNode(name));
+            }
+            nodes.push(Node { operation, inputs });
+        }
+
+        let number_of_inputs = nodes
+            .iter()
+            .filter(|node| matches!(node.operation, Operation::Input(_)))
+            .count();
+        if number_of_inputs == 0 {
+            return Err(GraphError::MissingInput);
+        }
+        let output = *indices
+            .get(output)
+            .ok_or_else(|| GraphError::UnknownNode(output.to_string()))?;
+        // Check if the cost function is given clamped output by an activation layer
+        if cost_function.is_output_dependant() && output_activation(&nodes[output]).is_none() {
+            return Err(GraphError::MissingActivationLayer);
+        }
+
+        Ok(GraphModel {
+            nodes,
+            number_of_inputs,
+            output,
+            cost_function: Box::new(cost_function),
+            optimizer: Box::new(optimizer),
+            metrics: self.metrics,
+        })
+    }
+}
+
+/// A trainable model whose layers form a directed acyclic graph rather than a stack, allowing
+/// skip connections, several inputs and branches merged back together, see `GraphBuilder`.
+///
+/// Every layer is used by a single node, the inputs of the model are given as a slice of
+/// batches (shape (n, ...)) all holding the same n data points.
+pub struct GraphModel {
+    nodes: Vec<Node>,
+    number_of_inputs: usize,
+    output: usize,
+    cost_function: Box<dyn Cost>,
+    optimizer: Box<dyn Optimizer>,
+    metrics: Vec<MetricsType>,
+}
+
+impl GraphModel {
+    /// Predict the output of the model for a batch of every input
+    pub fn predict(&self, inputs: &[&ArrayD<Float>]) -> Result<ArrayD<Float>, LayerError> {
+        self.check_inputs(inputs)?;
+        let mut outputs: Vec<ArrayD<Float>> = Vec::with_capacity(self.nodes.len());
+        for node in self.nodes.iter() {
+            let node_inputs = node.inputs.iter().map(|&i| &outputs[i]).collect::<Vec<_>>();
+            let output = match &node.operation {
+                Operation::Input(position) => inputs[*position].clone(),
+                Operation::Layer(layer) => layer.feed_forward(node_inputs[0])?,
+                Operation::Merge(merge) => merge.feed_forward(&node_inputs)?,
+            };
+            outputs.push(output);
+        }
+        Ok(outputs.swap_remove(self.output))
+    }
+
+    /// Compute the loss and watched metrics of the model over `test_data`
+    pub fn evaluate(
+        &self,
+        test_data: (&[&ArrayD<Float>], &ArrayD<Float>),
+        batch_size: usize,
+    ) -> Result<Benchmark, LayerError> {
+        let evaluation_started = Instant::now();
+        let (x, y) = test_data;
+        self.check_samples(x, y)?;
+        let indices = (0..y.shape()[0]).collect::<Vec<_>>();
+        let mut bench = Benchmark::new(&self.metrics);
+        let mut total_loss = 0.0;
+        let mut batch_count = 0;
+        for batch in indices.chunks(batch_size.max(1)) {
+            let (batched_x, batched_y) = select_batch(x, y, batch);
+            let output = self.predict(&batched_x.iter().collect::<Vec<_>>())?;
+            total_loss += self.cost_function.cost(&output, &batched_y);
+            if !self.metrics.is_empty() {
+                bench.metrics.accumulate(&output, &batched_y);
+            }
+            batch_count += 1;
+        }
+        bench.metrics.mean_all(batch_count);
+        bench.loss = total_loss / batch_count.max(1) as Float;
+        bench.samples = y.shape()[0];
+        bench.duration = evaluation_started.elapsed();
+        Ok(bench)
+    }
+
+    /// Train the model with gradient descent, the data points being shuffled every epoch.
+    /// Return the train history, with the loss and watched metrics of every epoch.
+    pub fn train(
+        &mut self,
+        train_data: (&[&ArrayD<Float>], &ArrayD<Float>),
+        epochs: usize,
+        batch_size: usize,
+    ) -> Result<History, LayerError> {
+        self.set_training(true);
+        let history = self.train_epochs(train_data, epochs, batch_size);
+        self.set_training(false);
+        history
+    }
+
+    /// Switch the layers between training and inference, see `Layer::set_training`
+    fn set_training(&mut self, training: bool) {
+        for node in self.nodes.iter_mut() {
+            if let Operation::Layer(layer) = &mut node.operation {
+                layer.set_training(training);
+            }
+        }
+    }
+
+    fn train_epochs(
+        &mut self,
+        train_data: (&[&ArrayD<Float>], &ArrayD<Float>),
+        epochs: usize,
+        batch_size: usize,
+    ) -> Result<History, LayerError> {
+        let (x, y) = train_data;
+        self.check_samples(x, y)?;
+        let mut history = History::new();
+        let mut indices = (0..y.shape()[0]).collect::<Vec<_>>();
+        for epoch in 0..epochs {
+            debug!("Training epochs : {}", epoch);
+            let epoch_started = Instant::now();
+            random::with_rng(|rng| indices.shuffle(rng));
+            let mut bench = Benchmark::new(&self.metrics);
+            let mut total_loss = 0.0;
+            let mut batch_count = 0;
+            for batch in indices.chunks(batch_size.max(1)) {
+                let (batched_x, batched_y) = select_batch(x, y, batch);
+                let output = self.feed_forward_save(&batched_x.iter().collect::<Vec<_>>())?;
+                total_loss += self.cost_function.cost(&output, &batched_y);
+                if !self.metrics.is_empty() {
+                    bench.metrics.accumulate(&output, &batched_y);
+                }
+                self.backpropagation(&output, &batched_y)?;
+                batch_count += 1;
+            }
+            bench.metrics.mean_all(batch_count);
+            bench.loss = total_loss / batch_count.max(1) as Float;
+            bench.samples = y.shape()[0];
+            bench.duration = epoch_started.elapsed();
+            history.push(epoch, bench);
+        }
+        Ok(history)
+    }
+
+    fn check_inputs(&self, inputs: &[&ArrayD<Float>]) -> Result<(), LayerError> {
+        if inputs.len() != self.number_of_inputs {
+            return Err(LayerError::DimensionMismatch);
+        }
+        Ok(())
+    }
+
+    fn check_samples(&self, x: &[&ArrayD<Float>], y: &ArrayD<Float>) -> Result<(), LayerError> {
+        self.check_inputs(x)?;
+        if x.iter().any(|input| input.shape()[0] != y.shape()[0]) {
+            return Err(LayerError::DimensionMismatch);
+        }
+        Ok(())
+    }
+
+    /// Same as `predict`, the layers saving what they need for the backpropagation
+    fn feed_forward_save(
+        &mut self,
+        inputs: &[&ArrayD<Float>],
+    ) -> Result<ArrayD<Float>, LayerError> {
+        self.check_inputs(inputs)?;
+        let mut outputs: Vec<ArrayD<Float>> = Vec::with_capacity(self.nodes.len());
+        for node in self.nodes.iter_mut() {
+            let node_inputs = node.inputs.iter().map(|&i| &outputs[i]).collect::<Vec<_>>();
+            let output = match &mut node.operation {
+                Operation::Input(position) => inputs[*position].clone(),
+                Operation::Layer(layer) => layer.feed_forward_save(node_inputs[0])?,
+                Operation::Merge(merge) => merge.feed_forward_save(&node_inputs)?,
+            };
+            outputs.push(output);
+        }
+        Ok(outputs.swap_remove(self.output))
+    }
+
+    /// Whether the output node is the activation fused with the cost function, see
+    /// `Cost::fused_activation`
+    fn is_output_fused(&self) -> bool {
+        let last_activation = output_activation(&self.nodes[self.output]);
+        last_activation.is_some() && last_activation == self.cost_function.fused_activation()
+    }
+
+    /// Propagate the gradient of the cost from the output node back to the inputs, in the
+    /// reverse order of the nodes, the gradients of a node used by several others being summed,
+    /// then update the layers reached by the gradient
+    fn backpropagation(
+        &mut self,
+        net_output: &ArrayD<Float>,
+        observed: &ArrayD<Float>,
+    ) -> Result<(), LayerError> {
+        let fused = self.is_output_fused();
+        let output = self.output;
+        let mut gradients: Vec<Option<ArrayD<Float>>> = vec![None; self.nodes.len()];
+        gradients[output] = Some(if fused {
+            self.cost_function
+                .fused_output_gradient(net_output, observed)
+        } else {
+            self.cost_function
+                .cost_output_gradient(net_output, observed)
+        });
+        let mut reached = vec![false; self.nodes.len()];
+
+        for index in (0..self.nodes.len()).rev() {
+            // the nodes that don't lead to the output have no gradient
+            let Some(gradient) = gradients[index].take() else {
+                continue;
+            };
+            reached[index] = true;
+            let node = &mut self.nodes[index];
+            let input_gradients = match &mut node.operation {
+                Operation::Input(_) => continue,
+                // the gradient is already with respect to the input of the fused activation
+                Operation::Layer(_) if fused && index == output => vec![gradient],
+                Operation::Layer(layer) => vec![layer.propagate_backward(&gradient)?],
+                Operation::Merge(merge) => merge.propagate_backward(&gradient)?,
+            };
+            for (&input, input_gradient) in node.inputs.iter().zip(input_gradients) {
+                match gradients[input].as_mut() {
+                    Some(sum) => *sum += &input_gradient,
+                    None => gradients[input] = Some(input_gradient),
+                }
+            }
+        }
+
+        for (node, reached) in self.nodes.iter_mut().zip(reached) {
+            if let (Operation::Layer(layer), true) = (&mut node.operation, reached) {
+                if let Some(trainable) = layer.as_trainable_mut() {
+                    self.optimizer.step(trainable)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The activation of `node` if it is an activation layer
+fn output_activation(node: &Node) -> Option<Activation> {
+    match &node.operation {
+        Operation::Layer(layer) => layer
+            .as_any()
+            .downcast_ref::<ActivationLayer>()
+            .map(|layer| layer.activation),
+        _ => None,
+    }
+}
+
+/// The data points at `indices` of every input and of the observed values
+fn select_batch(
+    x: &[&ArrayD<Float>],
+    y: &ArrayD<Float>,
+    indices: &[usize],
+) -> (Vec<ArrayD<Float>>, ArrayD<Float>) {
+    (
+        x.iter()
+            .map(|input| input.select(Axis(0), indices))
+            .collect(),
+        y.select(Axis(0), indices),
+    )
+}
+
+#[derive(Error, Debug)]
+pub enum GraphError {
+    #[error("Two nodes are named {0}")]
+    DuplicateNode(String),
+
+    #[error("Unknown node {0}, a node can only take as input the nodes added before it")]
+    UnknownNode(String),
+
+    #[error("Merge node {0} has no input")]
+    MissingMergeInput(String),
+
+    #[error("The graph has no input node")]
+    MissingInput,
+
+    #[error("Missing a last activation layer before the output")]
+    MissingActivationLayer,
+}
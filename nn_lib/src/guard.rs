@@ -0,0 +1,98 @@
+//! Detection of the NaN and infinite values appearing during a training, in the outputs of the
+//! layers, the loss and the gradients, see `SequentialBuilder::numeric_guard`.
+
+use std::fmt;
+
+use log::warn;
+use ndarray::ArrayD;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    layer::{Layer, LayerError},
+    Float,
+};
+
+/// What the training does when a NaN or infinite value is found
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum NumericGuard {
+    /// Don't look for them, saving a pass over the values of every layer
+    Off,
+    /// Log a warning for every array holding one, the training going on
+    #[default]
+    Warn,
+    /// Stop the training at the first one, with `LayerError::NonFinite` (or
+    /// `LayerError::NonFiniteLoss`) naming where it was found
+    AbortWithError,
+}
+
+/// The values of a layer checked by a `NumericGuard`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GuardedValues {
+    Output,
+    InputGradient,
+    ParameterGradients,
+}
+
+impl fmt::Display for GuardedValues {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Output => "output",
+            Self::InputGradient => "input gradient",
+            Self::ParameterGradients => "parameter gradients",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl NumericGuard {
+    /// Check the `values` of the layer at `index` in the network
+    pub(crate) fn check(
+        &self,
+        array: &ArrayD<Float>,
+        values: GuardedValues,
+        index: usize,
+    ) -> Result<(), LayerError> {
+        if *self == Self::Off || array.iter().all(|e| e.is_finite()) {
+            return Ok(());
+        }
+        self.report(LayerError::NonFinite {
+            values,
+            layer: index,
+        })
+    }
+
+    /// Check the gradients of the parameters left in `layer`, at `index` in the network, by its
+    /// last backward pass
+    pub(crate) fn check_parameter_gradients(
+        &self,
+        layer: &mut dyn Layer,
+        index: usize,
+    ) -> Result<(), LayerError> {
+        if *self == Self::Off {
+            return Ok(());
+        }
+        if let Some(trainable) = layer.as_trainable_mut() {
+            for gradient in trainable.get_gradients_mut()? {
+                self.check(gradient, GuardedValues::ParameterGradients, index)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_loss(&self, loss: Float) -> Result<(), LayerError> {
+        if *self == Self::Off || loss.is_finite() {
+            return Ok(());
+        }
+        self.report(LayerError::NonFiniteLoss(loss))
+    }
+
+    fn report(&self, error: LayerError) -> Result<(), LayerError> {
+        match self {
+            Self::AbortWithError => Err(error),
+            _ => {
+                warn!("{}", error);
+                Ok(())
+            }
+        }
+    }
+}
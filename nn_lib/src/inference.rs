@@ -0,0 +1,235 @@
+use ndarray::{Array1, Array2, ArrayD, Axis, IxDyn, ShapeError};
+use thiserror::Error;
+
+use crate::{
+    activation::{Activation, GELU_CUBIC, GELU_SCALE},
+    layer::{
+        average_pool, convolve, flatten, global_average_pool, max_pool, ActivationLayer,
+        AveragePoolingLayer, ConvolutionalLayer, DenseLayer, DropoutLayer, FlattenLayer,
+        GlobalAveragePoolingLayer, Layer, MaxPoolingLayer, PReLULayer, ReshapeLayer,
+    },
+    sequential::Sequential,
+    Float,
+};
+
+/// A single precision copy of a layer, holding only what is needed for a forward pass
+#[derive(Debug, Clone, PartialEq)]
+enum InferenceLayer {
+    Dense {
+        weights: Array2<f32>,
+        bias: Array1<f32>,
+    },
+    Activation(Activation),
+    // the slopes, of the shape of a single input sample
+    PReLU(ArrayD<f32>),
+    Convolutional {
+        // kernels reshaped as (number of kernels, kernel_h * kernel_w * channels)
+        kernels: Array2<f32>,
+        kernel_size: (usize, usize),
+        padding: (usize, usize),
+        stride: (usize, usize),
+        output_size: (usize, usize),
+    },
+    MaxPooling {
+        pool_size: (usize, usize),
+        stride: (usize, usize),
+        output_size: (usize, usize),
+    },
+    AveragePooling {
+        pool_size: (usize, usize),
+        stride: (usize, usize),
+        output_size: (usize, usize),
+    },
+    GlobalAveragePooling,
+    Reshape(Vec<usize>),
+    Flatten,
+}
+
+/// A frozen, `f32` copy of a trained `Sequential` network used for fast inference.
+///
+/// Training stay in `f64` (unless built with the `f32` feature) for numerical stability, but a
+/// prediction doesn't need that precision, halving the size of the parameters make the forward
+/// pass noticeably faster, which matter for interactive use like the drawing application.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InferenceModel {
+    layers: Vec<InferenceLayer>,
+}
+
+impl InferenceModel {
+    /// Copy the parameters of a trained network, converted to `f32`
+    /// layers that are the identity at inference time (like dropout) are left out
+    pub fn from_sequential(network: &Sequential) -> Result<Self, InferenceError> {
+        let layers = network
+            .layers()
+            .iter()
+            .enumerate()
+            .filter_map(|(index, layer)| convert_layer(index, layer.as_ref()).transpose())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { layers })
+    }
+
+    /// predict a value from the model, see `Sequential::predict`
+    ///
+    /// # Arguments
+    /// * `input` : batched input, of size (n, dim i) where **dim i** is the dimension of the
+    ///   network first layer and **n** is the number of point in the batch.
+    pub fn predict(&self, input: &ArrayD<f32>) -> Result<ArrayD<f32>, InferenceError> {
+        let mut output = input.clone();
+        for layer in &self.layers {
+            output = match layer {
+                InferenceLayer::Dense { weights, bias } => {
+                    let batch_size = output.shape()[0];
+                    let input_2d = output.into_shape((batch_size, weights.nrows()))?;
+                    (input_2d.dot(weights) + bias).into_dyn()
+                }
+                InferenceLayer::Activation(activation) => apply_activation(activation, output),
+                InferenceLayer::PReLU(alpha) => {
+                    output.mapv(|e| e.max(0.0)) + output.mapv(|e| e.min(0.0)) * alpha
+                }
+                InferenceLayer::Convolutional {
+                    kernels,
+                    kernel_size,
+                    padding,
+                    stride,
+                    output_size,
+                } => convolve(
+                    &output,
+                    &kernels.view(),
+                    *kernel_size,
+                    *padding,
+                    *stride,
+                    *output_size,
+                )?,
+                InferenceLayer::MaxPooling {
+                    pool_size,
+                    stride,
+                    output_size,
+                } => max_pool(&output, *pool_size, *stride, *output_size),
+                InferenceLayer::AveragePooling {
+                    pool_size,
+                    stride,
+                    output_size,
+                } => average_pool(&output, *pool_size, *stride, *output_size),
+                InferenceLayer::GlobalAveragePooling => global_average_pool(&output),
+                InferenceLayer::Reshape(output_shape) => {
+                    let mut shape = Vec::with_capacity(output_shape.len() + 1);
+                    shape.push(output.shape()[0]);
+                    shape.extend_from_slice(output_shape);
+                    output.into_shape(IxDyn(&shape))?
+                }
+                InferenceLayer::Flatten => flatten(&output)?,
+            };
+        }
+        Ok(output)
+    }
+}
+
+#[allow(clippy::unnecessary_cast)]
+fn convert_layer(
+    index: usize,
+    layer: &dyn Layer,
+) -> Result<Option<InferenceLayer>, InferenceError> {
+    let any = layer.as_any();
+    if let Some(dense) = any.downcast_ref::<DenseLayer>() {
+        let weights = dense.weights();
+        let (input_size, output_size) = (weights.shape()[0], weights.shape()[1]);
+        Ok(Some(InferenceLayer::Dense {
+            weights: weights
+                .mapv(|e| e as f32)
+                .into_shape((input_size, output_size))?,
+            bias: dense.bias().mapv(|e| e as f32).into_shape(output_size)?,
+        }))
+    } else if let Some(activation) = any.downcast_ref::<ActivationLayer>() {
+        Ok(Some(InferenceLayer::Activation(activation.activation)))
+    } else if let Some(prelu) = any.downcast_ref::<PReLULayer>() {
+        Ok(Some(InferenceLayer::PReLU(
+            prelu.alpha().mapv(|e| e as f32),
+        )))
+    } else if let Some(convolutional) = any.downcast_ref::<ConvolutionalLayer>() {
+        let kernels = convolutional.kernels();
+        let (kernel_h, kernel_w, kernel_d, num_kernels) = (
+            kernels.shape()[0],
+            kernels.shape()[1],
+            kernels.shape()[2],
+            kernels.shape()[3],
+        );
+        let (output_h, output_w, _) = convolutional.output_size();
+        Ok(Some(InferenceLayer::Convolutional {
+            kernels: kernels
+                .mapv(|e| e as f32)
+                .into_shape((num_kernels, kernel_h * kernel_w * kernel_d))?,
+            kernel_size: (kernel_h, kernel_w),
+            padding: convolutional.padding(),
+            stride: convolutional.stride(),
+            output_size: (output_h, output_w),
+        }))
+    } else if let Some(pooling) = any.downcast_ref::<MaxPoolingLayer>() {
+        let (output_h, output_w, _) = pooling.output_size();
+        Ok(Some(InferenceLayer::MaxPooling {
+            pool_size: pooling.pool_size(),
+            stride: pooling.stride(),
+            output_size: (output_h, output_w),
+        }))
+    } else if let Some(pooling) = any.downcast_ref::<AveragePoolingLayer>() {
+        let (output_h, output_w, _) = pooling.output_size();
+        Ok(Some(InferenceLayer::AveragePooling {
+            pool_size: pooling.pool_size(),
+            stride: pooling.stride(),
+            output_size: (output_h, output_w),
+        }))
+    } else if any.is::<GlobalAveragePoolingLayer>() {
+        Ok(Some(InferenceLayer::GlobalAveragePooling))
+    } else if let Some(reshape) = any.downcast_ref::<ReshapeLayer>() {
+        Ok(Some(InferenceLayer::Reshape(
+            reshape.output_shape().to_vec(),
+        )))
+    } else if any.is::<FlattenLayer>() {
+        Ok(Some(InferenceLayer::Flatten))
+    } else if any.is::<DropoutLayer>() {
+        Ok(None)
+    } else {
+        Err(InferenceError::UnsupportedLayer(index))
+    }
+}
+
+/// `f32` counterpart of `Activation::apply`
+#[allow(clippy::unnecessary_cast)]
+fn apply_activation(activation: &Activation, mut input: ArrayD<f32>) -> ArrayD<f32> {
+    match activation {
+        Activation::ReLU => input.mapv_inplace(|e| e.max(0.0)),
+        Activation::Tanh => input.mapv_inplace(f32::tanh),
+        Activation::Sigmoid => input.mapv_inplace(|e| 1.0 / (1.0 + (-e).exp())),
+        Activation::LeakyReLU(alpha) => {
+            let alpha = *alpha as f32;
+            input.mapv_inplace(|e| if e > 0.0 { e } else { alpha * e })
+        }
+        Activation::ELU(alpha) => {
+            let alpha = *alpha as f32;
+            input.mapv_inplace(|e| if e > 0.0 { e } else { alpha * e.exp_m1() })
+        }
+        Activation::GELU => {
+            let (scale, cubic) = (GELU_SCALE as f32, GELU_CUBIC as f32);
+            input.mapv_inplace(|e| 0.5 * e * (1.0 + (scale * (e + cubic * e.powi(3))).tanh()))
+        }
+        Activation::SiLU => input.mapv_inplace(|e| e / (1.0 + (-e).exp())),
+        Activation::Custom { f, .. } => input.mapv_inplace(|e| f(e as Float) as f32),
+        Activation::Softmax => {
+            for mut row in input.axis_iter_mut(Axis(0)) {
+                let max_logit = row.fold(f32::NEG_INFINITY, |max, &val| max.max(val));
+                row.mapv_inplace(|x| (x - max_logit).exp());
+                let sum_exps = row.sum() + 1e-10;
+                row.mapv_inplace(|x| x / sum_exps);
+            }
+        }
+    }
+    input
+}
+
+#[derive(Error, Debug)]
+pub enum InferenceError {
+    #[error("Layer {0} has no inference counterpart")]
+    UnsupportedLayer(usize),
+
+    #[error("Error reshaping array: {0}")]
+    ReshapeError(#[from] ShapeError),
+}
@@ -1,39 +1,128 @@
-use ndarray::ArrayD;
+use ndarray::{Array2, ArrayD};
 use ndarray_rand::{
-    rand_distr::{Normal, Uniform},
+    rand::{Rng, SeedableRng},
+    rand_distr::{Normal, StandardNormal, Uniform},
     RandomExt,
 };
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
 
+use crate::{random, Float};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum InitializerType {
     He,
-    RandomNormal(f64, f64),
+    RandomNormal(Float, Float),
     GlorotUniform,
+    /// Normal of standard deviation sqrt(1 / fan_in), to use with SELU
+    LeCunNormal,
+    /// Uniform between -sqrt(3 * scale / fan_in) and sqrt(3 * scale / fan_in), a scale of 1 keeps
+    /// the variance of the inputs
+    UniformScaled(Float),
+    /// (Semi) orthogonal matrix times a gain, the array being seen as a matrix of its last
+    /// dimension columns, suited to recurrent weights
+    Orthogonal(Float),
+    Zeros,
+    Constant(Float),
+    /// The wrapped initializer drawing from its own generator seeded with the given seed,
+    /// instead of the crate one
+    Seeded(Box<InitializerType>, u64),
 }
 
 impl InitializerType {
-    /// Return a new multidimensional array initialized according to the `InitializerType`
+    /// Wrap the initializer so that it always draws the same values for the same seed
+    pub fn seeded(self, seed: u64) -> Self {
+        InitializerType::Seeded(Box::new(self), seed)
+    }
+
+    /// Return a new multidimensional array initialized according to the `InitializerType`,
+    /// drawn from the crate generator (see `random::seed`)
     ///
     /// # Arguments
     /// * `fan_in` - The number of input in the layer
     /// * `fan_out` The number of output in the layer
     /// * `shape` - output matrices shape
-    pub fn initialize(&self, fan_in: usize, fan_out: usize, shape: &[usize]) -> ArrayD<f64> {
+    pub fn initialize(&self, fan_in: usize, fan_out: usize, shape: &[usize]) -> ArrayD<Float> {
+        random::with_rng(|rng| self.initialize_using(fan_in, fan_out, shape, rng))
+    }
+
+    /// Same as `initialize`, drawing the values from `rng`
+    pub fn initialize_using<R: Rng + ?Sized>(
+        &self,
+        fan_in: usize,
+        fan_out: usize,
+        shape: &[usize],
+        rng: &mut R,
+    ) -> ArrayD<Float> {
         match self {
             InitializerType::He => {
-                let std_dev = (2.0 / fan_in as f64).sqrt();
+                let std_dev = (2.0 / fan_in as Float).sqrt();
                 let normal = Normal::new(0.0, std_dev).expect("Can't create normal distribution");
-                ArrayD::random(shape, normal)
+                ArrayD::random_using(shape, normal, rng)
             }
             InitializerType::RandomNormal(mean, std_dev) => {
                 let normal =
                     Normal::new(*mean, *std_dev).expect("Can't create normal distribution");
-                ArrayD::random(shape, normal)
+                ArrayD::random_using(shape, normal, rng)
             }
             InitializerType::GlorotUniform => {
-                let limit = (6.0 / (fan_in + fan_out) as f64).sqrt();
+                let limit = (6.0 / (fan_in + fan_out) as Float).sqrt();
                 let uniform = Uniform::new(-limit, limit);
-                ArrayD::random(shape, uniform)
+                ArrayD::random_using(shape, uniform, rng)
+            }
+            InitializerType::LeCunNormal => {
+                let std_dev = (1.0 / fan_in as Float).sqrt();
+                let normal = Normal::new(0.0, std_dev).expect("Can't create normal distribution");
+                ArrayD::random_using(shape, normal, rng)
+            }
+            InitializerType::UniformScaled(scale) => {
+                let limit = (3.0 * scale / fan_in as Float).sqrt();
+                let uniform = Uniform::new_inclusive(-limit, limit);
+                ArrayD::random_using(shape, uniform, rng)
             }
+            InitializerType::Orthogonal(gain) => orthogonal(shape, rng) * *gain,
+            InitializerType::Zeros => ArrayD::zeros(shape),
+            InitializerType::Constant(value) => ArrayD::from_elem(shape, *value),
+            InitializerType::Seeded(initializer, seed) => initializer.initialize_using(
+                fan_in,
+                fan_out,
+                shape,
+                &mut ChaCha8Rng::seed_from_u64(*seed),
+            ),
+        }
+    }
+}
+
+/// A random array of the given shape whose rows or columns (whichever are the fewest) are
+/// orthonormal, once seen as a matrix of `shape[shape.len() - 1]` columns
+fn orthogonal<R: Rng + ?Sized>(shape: &[usize], rng: &mut R) -> ArrayD<Float> {
+    let columns = shape.last().copied().unwrap_or(1);
+    let rows = shape.iter().product::<usize>() / columns.max(1);
+    let transposed = rows < columns;
+    let (rows, columns) = if transposed {
+        (columns, rows)
+    } else {
+        (rows, columns)
+    };
+    let mut matrix: Array2<Float> = Array2::random_using((rows, columns), StandardNormal, rng);
+    // modified Gram-Schmidt on the columns, there are at most as many columns as rows
+    for j in 0..columns {
+        for k in 0..j {
+            let previous = matrix.column(k).to_owned();
+            let projection = matrix.column(j).dot(&previous);
+            matrix.column_mut(j).scaled_add(-projection, &previous);
         }
+        let norm = matrix.column(j).dot(&matrix.column(j)).sqrt();
+        matrix.column_mut(j).mapv_inplace(|x| x / norm);
     }
+    let matrix = if transposed {
+        matrix.reversed_axes()
+    } else {
+        matrix
+    };
+    matrix
+        .as_standard_layout()
+        .into_owned()
+        .into_shape(shape)
+        .expect("Can't reshape the orthogonal matrix")
 }
@@ -1,9 +1,23 @@
-use ndarray::{linalg, Array2, ArrayD, Axis, Dimension, IxDyn, s, ShapeError};
-use num_traits::zero;
+#[cfg(feature = "parallel")]
+use ndarray::parallel::prelude::*;
+use ndarray::{
+    concatenate, linalg, s, Array, Array1, Array2, Array3, ArrayD, ArrayView2, ArrayViewD,
+    ArrayViewMut2, ArrayViewMutD, Axis, Dimension, Ix1, Ix2, Ix3, IxDyn, LinalgScalar, ShapeError,
+    Slice, Zip,
+};
+use ndarray_rand::{rand::SeedableRng, rand_distr::Uniform, RandomExt};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
 use std::any::Any;
 use thiserror::Error;
 
-use crate::{activation::Activation, initialization::InitializerType};
+use crate::{
+    activation::Activation,
+    backend::{Backend, BackendError},
+    guard::GuardedValues,
+    initialization::InitializerType,
+    random, Float,
+};
 
 /// The `Layer` trait need to be implemented by any nn layer
 //
@@ -14,39 +28,138 @@ use crate::{activation::Activation, initialization::InitializerType};
 /// data point at once).
 /// The convention chosen in the layer implementations is (n, features) where n is the number of
 /// sample in the batch
-pub trait Layer {
-    fn feed_forward_save(&mut self, input: &ArrayD<f64>) -> Result<ArrayD<f64>, LayerError>;
+///
+/// Layers are `Send` so that copies of a network can be trained on several threads, see
+/// `SequentialBuilder::data_parallel`
+pub trait Layer: Send {
+    fn feed_forward_save(&mut self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError>;
 
-    fn feed_forward(&self, input: &ArrayD<f64>) -> Result<ArrayD<f64>, LayerError>;
+    fn feed_forward(&self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError>;
 
     fn propagate_backward(
         &mut self,
-        output_gradient: &ArrayD<f64>,
-    ) -> Result<ArrayD<f64>, LayerError>;
+        output_gradient: &ArrayD<Float>,
+    ) -> Result<ArrayD<Float>, LayerError>;
 
     fn as_any(&self) -> &dyn Any;
 
     fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// Return the layer as `Trainable` if it has parameters to be updated by the optimizer
+    fn as_trainable(&self) -> Option<&dyn Trainable> {
+        None
+    }
+
+    fn as_trainable_mut(&mut self) -> Option<&mut dyn Trainable> {
+        None
+    }
+
+    /// A copy of the parameters of the layer, in the order of `Trainable::get_parameters`,
+    /// empty for the layers without parameters
+    fn get_weights(&self) -> Vec<ArrayD<Float>> {
+        self.as_trainable()
+            .map_or_else(Vec::new, |trainable| trainable.get_parameters())
+    }
+
+    /// Overwrite the parameters of the layer with `weights`, given in the order of `get_weights`.
+    /// Returns `LayerError::DimensionMismatch`, leaving the layer untouched, if their number or
+    /// their shapes differ from the current parameters.
+    fn set_weights(&mut self, weights: Vec<ArrayD<Float>>) -> Result<(), LayerError> {
+        check_weights(&self.get_weights(), &weights)?;
+        if let Some(trainable) = self.as_trainable_mut() {
+            for (parameter, weight) in trainable.get_parameters_mut().into_iter().zip(weights) {
+                *parameter = weight;
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the shape of the output of a single data point given the shape of its input,
+    /// both without the batch dimension, or `LayerError::DimensionMismatch` if the layer can't
+    /// take such an input. The default keep the input shape, as the element wise layers do.
+    fn output_shape(&self, input_shape: &[usize]) -> Result<Vec<usize>, LayerError> {
+        Ok(input_shape.to_vec())
+    }
+
+    /// The shape of a single input (without the batch dimension) when the layer is built for a
+    /// given one, none for the layers taking any shape. Used to infer the input shape of a
+    /// network and check its layers against each other when it is compiled.
+    fn expected_input_shape(&self) -> Option<Vec<usize>> {
+        None
+    }
+
+    /// An estimate of the floating point operations of the forward pass of a single data point
+    /// of shape `input_shape` (without the batch dimension), reported by the profiler, see
+    /// `SequentialBuilder::profile`. The default count one per input element, as the element
+    /// wise layers do.
+    fn flops(&self, input_shape: &[usize]) -> u64 {
+        input_shape.iter().product::<usize>() as u64
+    }
+
+    /// Compute the matrix products of the layer on `backend`, see `SequentialBuilder::backend`.
+    /// Ignored by the layers without such products.
+    fn set_backend(&mut self, _backend: &Backend) {}
+
+    /// Switch the layer between its training and inference behaviours, the network turning
+    /// the training one on for the duration of `Sequential::train`. The layers are in inference
+    /// mode otherwise. Ignored by the layers behaving the same in both (all but dropout).
+    fn set_training(&mut self, _training: bool) {}
+
+    /// Draw the random values of the training (ex: the dropout masks) from a generator of the
+    /// layer seeded with `seed` instead of the global one of `random`, so that the copies of a
+    /// data parallel training don't share it. Ignored by the layers without such values.
+    fn set_generator(&mut self, _seed: u64) {}
+
+    /// The name of the layer type, ex: `DenseLayer`
+    fn type_name(&self) -> &'static str {
+        let path = std::any::type_name::<Self>();
+        path.rsplit("::").next().unwrap_or(path)
+    }
 }
 
 pub trait Trainable {
-    fn get_parameters(&self) -> Vec<ArrayD<f64>>;
+    fn get_parameters(&self) -> Vec<ArrayD<Float>>;
+
+    fn get_parameters_mut(&mut self) -> Vec<&mut ArrayD<Float>>;
+
+    /// The gradients of the parameters computed by the last backward pass, in the order of
+    /// `get_parameters`, or `LayerError::IllegalGradientAccess` before any backward pass
+    fn get_gradients(&self) -> Result<Vec<ArrayD<Float>>, LayerError>;
 
-    fn get_parameters_mut(&mut self) -> Vec<&mut ArrayD<f64>>;
+    fn get_gradients_mut(&mut self) -> Result<Vec<&mut ArrayD<Float>>, LayerError>;
+}
 
-    fn get_gradients(&self) -> Vec<ArrayD<f64>>;
+/// The buffer kept in `slot` by a layer, allocated (with zeros) only when missing or of another
+/// shape than `shape`, so that the same memory is reused from one batch to the next
+fn workspace<D: Dimension>(slot: &mut Option<Array<Float, D>>, shape: D) -> &mut Array<Float, D> {
+    if slot.as_ref().is_none_or(|buffer| buffer.raw_dim() != shape) {
+        *slot = Some(Array::zeros(shape));
+    }
+    slot.as_mut().unwrap()
 }
 
-#[derive(Debug, Clone, PartialEq, Default)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct DenseLayer {
-    weights: ArrayD<f64>,
-    bias: ArrayD<f64>,
-    last_batch_input: Option<ArrayD<f64>>,
+    weights: ArrayD<Float>,
+    bias: ArrayD<Float>,
+    #[serde(skip)]
+    last_batch_input: Option<ArrayD<Float>>,
     // store those for optimizer access (from the trait Trainable)
-    weights_gradient: Option<ArrayD<f64>>,
-    biases_gradient: Option<ArrayD<f64>>,
+    #[serde(skip)]
+    weights_gradient: Option<ArrayD<Float>>,
+    #[serde(skip)]
+    biases_gradient: Option<ArrayD<Float>>,
     input_size: usize,
     output_size: usize,
+    // layers saved before the bias was optional all had one
+    #[serde(default = "use_bias_default")]
+    use_bias: bool,
+    #[serde(skip)]
+    backend: Backend,
+}
+
+fn use_bias_default() -> bool {
+    true
 }
 
 impl DenseLayer {
@@ -61,7 +174,43 @@ impl DenseLayer {
             biases_gradient: None,
             input_size,
             output_size,
+            use_bias: true,
+            backend: Backend::Cpu,
+        }
+    }
+
+    /// Whether the layer has a bias (the default). Without one, the bias stays at zero and isn't
+    /// a parameter of the layer, ex: before a batch normalization which has its own shift.
+    pub fn with_bias(mut self, use_bias: bool) -> Self {
+        self.use_bias = use_bias;
+        if !use_bias {
+            self.bias = ArrayD::zeros(vec![self.output_size]);
+        }
+        self
+    }
+
+    /// Initialize the bias with `init` instead of the weights initializer,
+    /// ex: `InitializerType::Zeros`
+    pub fn with_bias_initializer(mut self, init: InitializerType) -> Self {
+        if self.use_bias {
+            self.bias = init.initialize(self.input_size, self.output_size, &[self.output_size]);
         }
+        self
+    }
+
+    /// Whether the bias is a parameter of the layer, see `with_bias`
+    pub fn use_bias(&self) -> bool {
+        self.use_bias
+    }
+
+    /// The weights matrices, of shape (input size, output size)
+    pub fn weights(&self) -> &ArrayD<Float> {
+        &self.weights
+    }
+
+    /// The bias vector, of shape (output size)
+    pub fn bias(&self) -> &ArrayD<Float> {
+        &self.bias
     }
 }
 
@@ -74,9 +223,8 @@ impl Layer for DenseLayer {
     ///
     /// # Arguments
     /// * `input` - shape (n, i)
-    fn feed_forward_save(&mut self, input: &ArrayD<f64>) -> Result<ArrayD<f64>, LayerError> {
-        // TODO find a without clone method, like in place mutation
-        self.last_batch_input = Some(input.clone());
+    fn feed_forward_save(&mut self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        workspace(&mut self.last_batch_input, input.raw_dim()).assign(input);
         self.feed_forward(input)
     }
 
@@ -86,7 +234,7 @@ impl Layer for DenseLayer {
     ///
     /// # Arguments
     /// * `input` - shape (n, i)
-    fn feed_forward(&self, input: &ArrayD<f64>) -> Result<ArrayD<f64>, LayerError> {
+    fn feed_forward(&self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
         let batch_size = input.shape()[0];
         let input_2d = input.view().into_shape((batch_size, self.input_size))?;
         let weight_2d = self
@@ -94,7 +242,10 @@ impl Layer for DenseLayer {
             .view()
             .into_shape((self.input_size, self.output_size))?;
 
-        Ok((input_2d.dot(&weight_2d) + &self.bias).into_dyn())
+        let mut output = Array2::zeros((batch_size, self.output_size));
+        self.backend
+            .gemm(1.0, &input_2d, &weight_2d, 0.0, &mut output.view_mut())?;
+        Ok((output + &self.bias).into_dyn())
     }
 
     /// Return the input gradient vector (shape (n, i)), by processing the output gradient vector
@@ -107,8 +258,8 @@ impl Layer for DenseLayer {
     /// * `output_gradient` - (shape (n, j))
     fn propagate_backward(
         &mut self,
-        output_gradient: &ArrayD<f64>,
-    ) -> Result<ArrayD<f64>, LayerError> {
+        output_gradient: &ArrayD<Float>,
+    ) -> Result<ArrayD<Float>, LayerError> {
         let input_gradient = match self.last_batch_input.as_ref() {
             Some(input) => {
                 let batch_size = output_gradient.shape()[0];
@@ -123,20 +274,58 @@ impl Layer for DenseLayer {
                     .view()
                     .into_shape((self.input_size, self.output_size))?;
 
-                // mean relative to the batch
-                let weights_gradient = input_2d.t().dot(&output_grad_2d) / batch_size as f64;
-                let biases_gradient = output_grad_2d.sum_axis(Axis(0)) / batch_size as f64;
-
-                self.weights_gradient = Some(weights_gradient.to_owned().into_dyn());
-                self.biases_gradient = Some(biases_gradient.into_dyn());
+                // mean relative to the batch, written over the gradient of the previous batch
+                let weights_gradient = workspace(
+                    &mut self.weights_gradient,
+                    IxDyn(&[self.input_size, self.output_size]),
+                );
+                self.backend.gemm(
+                    1.0 / batch_size as Float,
+                    &input_2d.t(),
+                    &output_grad_2d,
+                    0.0,
+                    &mut weights_gradient
+                        .view_mut()
+                        .into_shape((self.input_size, self.output_size))?,
+                )?;
+                let biases_gradient = output_grad_2d.sum_axis(Axis(0)) / batch_size as Float;
+
+                if self.use_bias {
+                    self.biases_gradient = Some(biases_gradient.into_dyn());
+                }
 
-                Ok((output_grad_2d.dot(&weight_2d.t())).into_dyn())
+                let mut input_gradient = Array2::zeros((batch_size, self.input_size));
+                self.backend.gemm(
+                    1.0,
+                    &output_grad_2d,
+                    &weight_2d.t(),
+                    0.0,
+                    &mut input_gradient.view_mut(),
+                )?;
+                Ok(input_gradient.into_dyn())
             }
             None => Err(LayerError::IllegalInputAccess),
         };
         input_gradient
     }
 
+    fn output_shape(&self, input_shape: &[usize]) -> Result<Vec<usize>, LayerError> {
+        // the input is flattened, ex: (h, w, c) after a convolution
+        if input_shape.iter().product::<usize>() != self.input_size {
+            return Err(LayerError::DimensionMismatch);
+        }
+        Ok(vec![self.output_size])
+    }
+
+    fn expected_input_shape(&self) -> Option<Vec<usize>> {
+        Some(vec![self.input_size])
+    }
+
+    // a multiplication and an addition per weight, plus the bias
+    fn flops(&self, _input_shape: &[usize]) -> u64 {
+        (2 * self.input_size * self.output_size + self.output_size) as u64
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -144,41 +333,75 @@ impl Layer for DenseLayer {
     fn as_any_mut(&mut self) -> &mut dyn Any {
         self
     }
+
+    fn as_trainable(&self) -> Option<&dyn Trainable> {
+        Some(self)
+    }
+
+    fn as_trainable_mut(&mut self) -> Option<&mut dyn Trainable> {
+        Some(self)
+    }
+
+    fn set_backend(&mut self, backend: &Backend) {
+        self.backend = backend.clone();
+    }
 }
 
+// without bias, only the weights are parameters
 impl Trainable for DenseLayer {
-    fn get_parameters(&self) -> Vec<ArrayD<f64>> {
-        vec![
-            self.weights.clone().into_dyn(),
-            self.bias.clone().into_dyn(),
-        ]
+    fn get_parameters(&self) -> Vec<ArrayD<Float>> {
+        let mut parameters = vec![self.weights.clone().into_dyn()];
+        if self.use_bias {
+            parameters.push(self.bias.clone().into_dyn());
+        }
+        parameters
     }
 
-    fn get_parameters_mut(&mut self) -> Vec<&mut ArrayD<f64>> {
-        vec![&mut self.weights, &mut self.bias]
+    fn get_parameters_mut(&mut self) -> Vec<&mut ArrayD<Float>> {
+        let mut parameters = vec![&mut self.weights];
+        if self.use_bias {
+            parameters.push(&mut self.bias);
+        }
+        parameters
     }
 
-    fn get_gradients(&self) -> Vec<ArrayD<f64>> {
-        vec![
-            self.weights_gradient
-                .as_ref()
-                .expect("Illegal access to unset weights gradient")
-                .clone()
-                .into_dyn(),
-            self.biases_gradient
-                .as_ref()
-                .expect("Illegal access to unset biases gradient")
-                .clone()
-                .into_dyn(),
-        ]
+    fn get_gradients(&self) -> Result<Vec<ArrayD<Float>>, LayerError> {
+        let mut gradients = vec![self
+            .weights_gradient
+            .clone()
+            .ok_or(LayerError::IllegalGradientAccess)?];
+        if self.use_bias {
+            gradients.push(
+                self.biases_gradient
+                    .clone()
+                    .ok_or(LayerError::IllegalGradientAccess)?,
+            );
+        }
+        Ok(gradients)
+    }
+
+    fn get_gradients_mut(&mut self) -> Result<Vec<&mut ArrayD<Float>>, LayerError> {
+        let mut gradients = vec![self
+            .weights_gradient
+            .as_mut()
+            .ok_or(LayerError::IllegalGradientAccess)?];
+        if self.use_bias {
+            gradients.push(
+                self.biases_gradient
+                    .as_mut()
+                    .ok_or(LayerError::IllegalGradientAccess)?,
+            );
+        }
+        Ok(gradients)
     }
 }
 
 /// The `ActivationLayer` apply a activation function to it's input node to yield the output nodes.
-#[derive(Debug, Clone, PartialEq, Default)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct ActivationLayer {
     pub activation: Activation,
-    pub input: Option<ArrayD<f64>>,
+    #[serde(skip)]
+    pub input: Option<ArrayD<Float>>,
 }
 
 impl ActivationLayer {
@@ -195,8 +418,8 @@ impl Layer for ActivationLayer {
     ///
     /// # Arguments
     /// * `input` - shape (n, i)
-    fn feed_forward_save(&mut self, input: &ArrayD<f64>) -> Result<ArrayD<f64>, LayerError> {
-        self.input = Some(input.clone());
+    fn feed_forward_save(&mut self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        workspace(&mut self.input, input.raw_dim()).assign(input);
         self.feed_forward(input)
     }
 
@@ -205,7 +428,7 @@ impl Layer for ActivationLayer {
     ///
     /// # Arguments
     /// * `input` - shape (n, i)
-    fn feed_forward(&self, input: &ArrayD<f64>) -> Result<ArrayD<f64>, LayerError> {
+    fn feed_forward(&self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
         Ok(self.activation.apply(input))
     }
 
@@ -214,10 +437,10 @@ impl Layer for ActivationLayer {
     /// * `output_gradient` shape (n, j)
     fn propagate_backward(
         &mut self,
-        output_gradient: &ArrayD<f64>,
-    ) -> Result<ArrayD<f64>, LayerError> {
+        output_gradient: &ArrayD<Float>,
+    ) -> Result<ArrayD<Float>, LayerError> {
         let input_gradient = match self.input.as_ref() {
-            Some(input) => Ok(output_gradient * self.activation.apply_derivative(input)),
+            Some(input) => Ok(self.activation.backward(input, output_gradient)),
             None => Err(LayerError::IllegalInputAccess),
         };
         input_gradient
@@ -232,17 +455,188 @@ impl Layer for ActivationLayer {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Default)]
+/// A leaky ReLU whose negative slopes `alpha` are learned, one per input feature:
+/// `x` for the positive inputs and `alpha * x` for the negative ones.
+/// The slopes are parameters updated by the optimizer like the weights of a `DenseLayer`.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct PReLULayer {
+    alpha: ArrayD<Float>,
+    #[serde(skip)]
+    input: Option<ArrayD<Float>>,
+    #[serde(skip)]
+    alpha_gradient: Option<ArrayD<Float>>,
+}
+
+impl PReLULayer {
+    /// # Arguments
+    /// * `input_shape` - shape of a single input sample (ex: `&[128]` after a dense layer,
+    ///   `&[26, 26, 5]` after a convolutional layer), one slope is learned per feature
+    /// * `initial_alpha` - starting value of every slope (ex: 0.25)
+    pub fn new(input_shape: &[usize], initial_alpha: Float) -> Self {
+        Self {
+            alpha: ArrayD::from_elem(IxDyn(input_shape), initial_alpha),
+            input: None,
+            alpha_gradient: None,
+        }
+    }
+
+    /// The slopes of the negative inputs, of the shape of a single input sample
+    pub fn alpha(&self) -> &ArrayD<Float> {
+        &self.alpha
+    }
+}
+
+impl Layer for PReLULayer {
+    fn feed_forward_save(&mut self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        self.input = Some(input.clone());
+        self.feed_forward(input)
+    }
+
+    /// # Arguments
+    /// * `input` - shape (n, `input_shape`)
+    fn feed_forward(&self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        if &input.shape()[1..] != self.alpha.shape() {
+            return Err(LayerError::DimensionMismatch);
+        }
+        // the slopes are broadcast over the batch
+        let negative = input.mapv(|e| e.min(0.0)) * &self.alpha;
+        Ok(input.mapv(|e| e.max(0.0)) + negative)
+    }
+
+    /// Return the input gradient, while computing and storing the slopes gradient, mean relative
+    /// to the batch
+    fn propagate_backward(
+        &mut self,
+        output_gradient: &ArrayD<Float>,
+    ) -> Result<ArrayD<Float>, LayerError> {
+        let input = self.input.as_ref().ok_or(LayerError::IllegalInputAccess)?;
+        let batch_size = input.shape()[0];
+        let alpha_gradient =
+            (output_gradient * &input.mapv(|e| e.min(0.0))).sum_axis(Axis(0)) / batch_size as Float;
+        // the derivative is 1 for the positive inputs and alpha for the negative ones
+        let mut slopes = self
+            .alpha
+            .broadcast(input.raw_dim())
+            .ok_or(LayerError::DimensionMismatch)?
+            .to_owned();
+        Zip::from(&mut slopes).and(input).for_each(|slope, &e| {
+            if e > 0.0 {
+                *slope = 1.0
+            }
+        });
+        self.alpha_gradient = Some(alpha_gradient);
+        Ok(output_gradient * &slopes)
+    }
+
+    fn output_shape(&self, input_shape: &[usize]) -> Result<Vec<usize>, LayerError> {
+        if input_shape != self.alpha.shape() {
+            return Err(LayerError::DimensionMismatch);
+        }
+        Ok(input_shape.to_vec())
+    }
+
+    fn expected_input_shape(&self) -> Option<Vec<usize>> {
+        Some(self.alpha.shape().to_vec())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn as_trainable(&self) -> Option<&dyn Trainable> {
+        Some(self)
+    }
+
+    fn as_trainable_mut(&mut self) -> Option<&mut dyn Trainable> {
+        Some(self)
+    }
+}
+
+impl Trainable for PReLULayer {
+    fn get_parameters(&self) -> Vec<ArrayD<Float>> {
+        vec![self.alpha.clone()]
+    }
+
+    fn get_parameters_mut(&mut self) -> Vec<&mut ArrayD<Float>> {
+        vec![&mut self.alpha]
+    }
+
+    fn get_gradients(&self) -> Result<Vec<ArrayD<Float>>, LayerError> {
+        let alpha_gradient = self
+            .alpha_gradient
+            .clone()
+            .ok_or(LayerError::IllegalGradientAccess)?;
+        Ok(vec![alpha_gradient])
+    }
+
+    fn get_gradients_mut(&mut self) -> Result<Vec<&mut ArrayD<Float>>, LayerError> {
+        let alpha_gradient = self
+            .alpha_gradient
+            .as_mut()
+            .ok_or(LayerError::IllegalGradientAccess)?;
+        Ok(vec![alpha_gradient])
+    }
+}
+
+/// Zero padding added around the input of a `ConvolutionalLayer`
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default, Serialize, Deserialize)]
+pub enum Padding {
+    /// no padding, the output shrink by kernel size - 1
+    #[default]
+    Valid,
+    /// pad by (kernel size - 1) / 2 on every side, so that an odd kernel keep the input size
+    Same,
+    /// pad by the given number of pixels, as (height, width), on every side
+    Explicit(usize, usize),
+}
+
+impl Padding {
+    /// Number of pixels added on each side, as (height, width), for a kernel of `kernel_size`
+    ///
+    /// # Panics
+    /// With `Padding::Same`, if a side of `kernel_size` is zero
+    pub fn resolve(&self, kernel_size: (usize, usize)) -> (usize, usize) {
+        match self {
+            Self::Valid => (0, 0),
+            Self::Same => {
+                assert!(
+                    kernel_size.0 > 0 && kernel_size.1 > 0,
+                    "Same padding needs a non empty kernel"
+                );
+                ((kernel_size.0 - 1) / 2, (kernel_size.1 - 1) / 2)
+            }
+            Self::Explicit(height, width) => (*height, *width),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct ConvolutionalLayer {
-    kernels: ArrayD<f64>,
-    bias: ArrayD<f64>,
-    input: Option<ArrayD<f64>>,
-    kernel_gradient: Option<ArrayD<f64>>,
-    bias_gradient: Option<ArrayD<f64>>,
+    kernels: ArrayD<Float>,
+    bias: ArrayD<Float>,
+    // the last input unrolled by im2col, and the gradient of the unrolled input, both reused
+    // from one batch to the next
+    #[serde(skip)]
+    col_input: Option<Array2<Float>>,
+    #[serde(skip)]
+    col_gradient: Option<Array2<Float>>,
+    #[serde(skip)]
+    kernel_gradient: Option<ArrayD<Float>>,
+    #[serde(skip)]
+    bias_gradient: Option<ArrayD<Float>>,
 
     input_size: (usize, usize, usize),
     output_size: (usize, usize, usize),
     kernels_size: (usize, usize, usize, usize),
+    // pixels added on each side, as (height, width)
+    padding: (usize, usize),
+    stride: (usize, usize),
+    #[serde(skip)]
+    backend: Backend,
 }
 
 impl ConvolutionalLayer {
@@ -254,12 +648,10 @@ impl ConvolutionalLayer {
     ) -> Self {
         let (kernel_height, kernel_width): (usize, usize) = kernel_size;
         let (input_height, input_width, input_channel): (usize, usize, usize) = input_size;
+        let kernels_size = (kernel_height, kernel_width, input_channel, number_of_kernel);
 
-        let output_size: (usize, usize, usize) = (
-            input_height - kernel_height + 1,
-            input_width - kernel_width + 1,
-            number_of_kernel,
-        );
+        // valid convolution until `with_padding` and `with_stride` are called
+        let output_size = convolution_output_size(input_size, kernels_size, (0, 0), (1, 1));
         let (output_height, output_width, output_channel): (usize, usize, usize) = output_size;
 
         Self {
@@ -273,251 +665,447 @@ impl ConvolutionalLayer {
                 output_height * output_width * output_channel,
                 &[number_of_kernel],
             ),
-            input: None,
+            col_input: None,
+            col_gradient: None,
             kernel_gradient: None,
             bias_gradient: None,
             input_size,
             output_size,
-            kernels_size: (kernel_height, kernel_width, input_channel, number_of_kernel),
+            kernels_size,
+            padding: (0, 0),
+            stride: (1, 1),
+            backend: Backend::Cpu,
         }
     }
 
-    fn flip_kernels(&self) -> ArrayD<f64> {
-        // Ensure the kernels array is 4D
-        assert_eq!(self.kernels.ndim(), 4);
+    /// Zero pad the input before the convolution, see `Padding`
+    ///
+    /// # Panics
+    /// With `Padding::Same`, if a side of the kernels is zero
+    pub fn with_padding(mut self, padding: Padding) -> Self {
+        let (kernel_height, kernel_width, _, _) = self.kernels_size;
+        self.padding = padding.resolve((kernel_height, kernel_width));
+        self.output_size = self.compute_output_size();
+        self
+    }
 
-        let kernel_h = self.kernels.shape()[0];
-        let kernel_w = self.kernels.shape()[1];
-        let kernel_d = self.kernels.shape()[2];
-        let num_kernels = self.kernels.shape()[3];
+    /// Slide the kernels by (vertical, horizontal) pixels instead of one, downsampling the
+    /// output by the same factor
+    ///
+    /// # Panics
+    /// If a side of `stride` is zero
+    pub fn with_stride(mut self, stride: (usize, usize)) -> Self {
+        assert!(
+            stride.0 > 0 && stride.1 > 0,
+            "Convolution stride can't be zero"
+        );
+        self.stride = stride;
+        self.output_size = self.compute_output_size();
+        self
+    }
 
-        let mut flipped_kernels =
-            ArrayD::zeros(IxDyn(&[kernel_h, kernel_w, kernel_d, num_kernels]));
+    fn compute_output_size(&self) -> (usize, usize, usize) {
+        convolution_output_size(
+            self.input_size,
+            self.kernels_size,
+            self.padding,
+            self.stride,
+        )
+    }
 
-        for ky in 0..kernel_h {
-            for kx in 0..kernel_w {
-                for c in 0..kernel_d {
-                    for nk in 0..num_kernels {
-                        let flipped_ky = kernel_h - 1 - ky;
-                        let flipped_kx = kernel_w - 1 - kx;
-                        flipped_kernels[[flipped_ky, flipped_kx, c, nk]] =
-                            self.kernels[[ky, kx, c, nk]];
-                    }
-                }
-            }
+    /// Check that `input` is a batch of (height, width, channels) images of the input size of
+    /// the layer, and that the kernels span all its channels
+    fn check_input(&self, input: &ArrayD<Float>) -> Result<(), LayerError> {
+        let (_kernel_h, _kernel_w, kernel_d, _num_kernels) = self.kernels_size;
+        let (_input_h, _input_w, input_channels) = self.input_size;
+        if kernel_d != input_channels {
+            return Err(LayerError::KernelMismatch {
+                kernel_depth: kernel_d,
+                input_channels,
+            });
         }
-        flipped_kernels
+
+        let expected = spatial_shape(self.input_size);
+        let actual = input.shape().get(1..).unwrap_or_default();
+        if actual != expected {
+            return Err(LayerError::InputShapeMismatch {
+                expected,
+                actual: actual.to_vec(),
+            });
+        }
+        Ok(())
     }
 
-    fn im2col(&self, input: ArrayD<f64>) -> Array2<f64> {
-        assert_eq!(input.ndim(), 4);
+    fn convolve(&self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        self.check_input(input)?;
 
-        let batch_size = input.shape()[0];
-        let (input_h, input_w, input_channels) = self.input_size;
-        let (kernel_h, kernel_w, kernel_d, _num_kernels) = self.kernels_size;
+        let (kernel_h, kernel_w, kernel_d, num_kernels) = self.kernels_size;
         let (output_h, output_w, _output_channels) = self.output_size;
 
-        assert_eq!(input.shape()[1], input_h);
-        assert_eq!(input.shape()[2], input_w);
-        assert_eq!(input.shape()[3], input_channels);
-        assert_eq!(kernel_d, input_channels); // kernel_depth should match input_channels
-
-        let output_size = output_h * output_w * batch_size;
         let kernel_size = kernel_h * kernel_w * kernel_d;
 
-        let mut output = Array2::zeros((output_size, kernel_size));
-
-        for b in 0..batch_size {
-            for y in 0..output_h {
-                for x in 0..output_w {
-                    for ky in 0..kernel_h {
-                        for kx in 0..kernel_w {
-                            for c in 0..kernel_d {
-                                let in_y = y + ky;
-                                let in_x = x + kx;
-                                let output_row = b * output_h * output_w + y * output_w + x;
-                                let output_col = ky * kernel_w * kernel_d + kx * kernel_d + c;
-                                output[[output_row, output_col]] = input[[b, in_y, in_x, c]];
-                            }
-                        }
-                    }
-                }
-            }
-        }
+        let kernels_reshaped = self.kernels.view().into_shape((num_kernels, kernel_size))?;
 
-        output
+        let col = im2col(
+            input,
+            (kernel_h, kernel_w),
+            self.padding,
+            self.stride,
+            (output_h, output_w),
+        );
+        backend_multiply_unrolled(
+            &self.backend,
+            &col,
+            &kernels_reshaped,
+            input.shape()[0],
+            (output_h, output_w),
+        )
     }
 
-    fn im2col_full(&self, output: ArrayD<f64>) -> Array2<f64> {
-        assert_eq!(output.ndim(), 4);
+    /// The learned kernels, of shape (kernel height, kernel width, input channels, kernels)
+    pub fn kernels(&self) -> &ArrayD<Float> {
+        &self.kernels
+    }
 
-        let batch_size = output.shape()[0];
-        let (input_h, input_w, input_channels) = self.input_size;
-        let (kernel_h, kernel_w, kernel_d, num_kernels) = self.kernels_size;
-        let (output_h, output_w, output_channels) = self.output_size;
+    /// Input size as (height, width, channels)
+    pub fn input_size(&self) -> (usize, usize, usize) {
+        self.input_size
+    }
 
-        assert_eq!(output.shape()[1], output_h);
-        assert_eq!(output.shape()[2], output_w);
-        assert_eq!(output.shape()[3], output_channels);
-        assert_eq!(num_kernels, output_channels);
+    /// Output size as (height, width, channels)
+    pub fn output_size(&self) -> (usize, usize, usize) {
+        self.output_size
+    }
 
-        // Calculate padding
-        let pad_h = kernel_h - 1;
-        let pad_w = kernel_w - 1;
+    /// Pixels of zero padding added on each side of the input, as (height, width)
+    pub fn padding(&self) -> (usize, usize) {
+        self.padding
+    }
 
-        // Calculate the total output size as the product of output height, output width, and batch size
-        let input_size = input_h * input_w * batch_size;
-        let kernel_size = kernel_h * kernel_w * num_kernels;
+    /// Stride of the kernels as (height, width)
+    pub fn stride(&self) -> (usize, usize) {
+        self.stride
+    }
+}
 
-        // Initialize the output matrix with zeros, with shape (output_size, kernel_size)
-        let mut result = Array2::zeros((input_size, kernel_size));
+/// Output size, as (height, width, channels), of the convolution of an input of `input_size`
+/// (height, width, channels) with kernels of `kernels_size` (height, width, depth, number),
+/// zero padded by `padding` pixels on each side and slid by `stride`. A side is empty when the
+/// kernels don't fit in the padded input.
+fn convolution_output_size(
+    input_size: (usize, usize, usize),
+    kernels_size: (usize, usize, usize, usize),
+    padding: (usize, usize),
+    stride: (usize, usize),
+) -> (usize, usize, usize) {
+    let (kernel_height, kernel_width, _, number_of_kernel) = kernels_size;
+    let (input_height, input_width, _) = input_size;
+    let (pad_height, pad_width) = padding;
+    let (stride_height, stride_width) = stride;
+    let side = |input: usize, pad: usize, kernel: usize, stride: usize| {
+        (input + 2 * pad)
+            .checked_sub(kernel)
+            .map_or(0, |span| span / stride + 1)
+    };
+    (
+        side(input_height, pad_height, kernel_height, stride_height),
+        side(input_width, pad_width, kernel_width, stride_width),
+        number_of_kernel,
+    )
+}
 
-        // Pad the input tensor
-        let mut padded_input: ArrayD<f64> = ArrayD::zeros(IxDyn(&[
-            batch_size,
-            output_h + 2 * pad_h,
-            output_w + 2 * pad_w,
-            output_channels,
-        ]));
-        for b in 0..batch_size {
-            for c in 0..output_channels {
-                for y in 0..output_h {
-                    for x in 0..output_w {
-                        padded_input[[b, y + pad_h, x + pad_w, c]] = output[[b, y, x, c]];
-                    }
-                }
-            }
-        }
+/// Unroll every receptive field of a batched input (shape (n, h, w, c)), zero padded by
+/// `padding` pixels on each side and spaced by `stride`, into the rows of a matrix of shape
+/// (n * output_h * output_w, kernel_h * kernel_w * c).
+/// The images of the batch are unrolled in parallel with the `parallel` feature.
+pub(crate) fn im2col<T: LinalgScalar + Send + Sync>(
+    input: &ArrayD<T>,
+    kernel_size: (usize, usize),
+    padding: (usize, usize),
+    stride: (usize, usize),
+    output_size: (usize, usize),
+) -> Array2<T> {
+    let batch_size = input.shape()[0];
+    let channels = input.shape()[3];
+    let (kernel_h, kernel_w) = kernel_size;
+    let (output_h, output_w) = output_size;
+
+    let mut output = Array2::zeros((
+        batch_size * output_h * output_w,
+        kernel_h * kernel_w * channels,
+    ));
+    im2col_into(
+        input,
+        kernel_size,
+        padding,
+        stride,
+        output_size,
+        &mut output,
+    );
+    output
+}
 
-        // Iterate over each element in the batch
-        for b in 0..batch_size {
-            for y in 0..input_h {
-                for x in 0..input_w {
-                    for ky in 0..kernel_h {
-                        for kx in 0..kernel_w {
-                            for c in 0..num_kernels {
-                                let in_y = y + ky;
-                                let in_x = x + kx;
-                                let input_row = b * input_h * input_w + y * input_w + x;
-                                let input_col = ky * kernel_w * num_kernels + kx * num_kernels + c;
-                                result[[input_row, input_col]] = padded_input[[b, in_y, in_x, c]];
-                            }
-                        }
+/// Same as `im2col`, writing into `output` (of the shape returned by `im2col`) instead of
+/// allocating the matrix
+pub(crate) fn im2col_into<T: LinalgScalar + Send + Sync>(
+    input: &ArrayD<T>,
+    kernel_size: (usize, usize),
+    padding: (usize, usize),
+    stride: (usize, usize),
+    output_size: (usize, usize),
+    output: &mut Array2<T>,
+) {
+    let (output_h, output_w) = output_size;
+    // the padding is skipped, clear what the previous batch left there
+    if padding != (0, 0) {
+        output.fill(T::zero());
+    }
+
+    // every image fill its own block of output_h * output_w rows
+    let blocks = output.axis_chunks_iter_mut(Axis(0), output_h * output_w);
+    let images = input.axis_iter(Axis(0));
+    let unroll = |(mut rows, image): (ArrayViewMut2<T>, ArrayViewD<T>)| {
+        for_each_receptive_field(
+            image.shape(),
+            kernel_size,
+            padding,
+            stride,
+            output_size,
+            |row, col, pixel| rows[[row, col]] = image[pixel],
+        )
+    };
+    #[cfg(feature = "parallel")]
+    blocks.into_par_iter().zip(images).for_each(unroll);
+    #[cfg(not(feature = "parallel"))]
+    blocks.zip(images).for_each(unroll);
+}
+
+/// Call `f(row, col, [y, x, c])` for every pixel of every receptive field of an image of
+/// `image_shape` (h, w, c), where (row, col) is its position in the unrolled matrix of the image
+/// (shape (output_h * output_w, kernel_h * kernel_w * c)). Pixels falling into the padding are
+/// skipped.
+fn for_each_receptive_field(
+    image_shape: &[usize],
+    kernel_size: (usize, usize),
+    padding: (usize, usize),
+    stride: (usize, usize),
+    output_size: (usize, usize),
+    mut f: impl FnMut(usize, usize, [usize; 3]),
+) {
+    let (input_h, input_w, channels) = (image_shape[0], image_shape[1], image_shape[2]);
+    let (kernel_h, kernel_w) = kernel_size;
+    let (pad_h, pad_w) = padding;
+    let (stride_h, stride_w) = stride;
+    let (output_h, output_w) = output_size;
+
+    for y in 0..output_h {
+        for x in 0..output_w {
+            let row = y * output_w + x;
+            for ky in 0..kernel_h {
+                for kx in 0..kernel_w {
+                    // position in the padded input, the padding itself stay at zero
+                    let (in_y, in_x) = (y * stride_h + ky, x * stride_w + kx);
+                    if in_y < pad_h
+                        || in_x < pad_w
+                        || in_y - pad_h >= input_h
+                        || in_x - pad_w >= input_w
+                    {
+                        continue;
+                    }
+                    for c in 0..channels {
+                        let col = ky * kernel_w * channels + kx * channels + c;
+                        f(row, col, [in_y - pad_h, in_x - pad_w, c]);
                     }
                 }
             }
         }
-
-        result
     }
+}
 
-    fn convolve(&self, input: &ArrayD<f64>) -> ArrayD<f64> {
-        let col = self.im2col(input.clone());
-        let (kernel_h, kernel_w, kernel_d, num_kernels) = self.kernels_size;
-        let (output_h, output_w, output_channels) = self.output_size;
-        let batch_size = input.shape()[0];
-
-        let kernel_size = kernel_h * kernel_w * kernel_d;
-
-        let kernels_reshaped = self
-            .kernels
-            .clone()
-            .into_shape((num_kernels, kernel_size))
-            .unwrap();
-
-        let col_reshaped = col
-            .into_shape((batch_size * output_h * output_w, kernel_size))
-            .unwrap();
-
-        let mut result = Array2::zeros((batch_size * output_h * output_w, output_channels));
+/// Inverse of `im2col`, accumulate the rows of `col` (shape
+/// (n * output_h * output_w, kernel_h * kernel_w * c)) back onto the receptive fields they were
+/// unrolled from, returning an array of shape (n, h, w, c) where `input_size` is (h, w, c).
+/// Values falling into the padding are dropped.
+fn col2im(
+    col: &Array2<Float>,
+    batch_size: usize,
+    input_size: (usize, usize, usize),
+    kernel_size: (usize, usize),
+    padding: (usize, usize),
+    stride: (usize, usize),
+    output_size: (usize, usize),
+) -> ArrayD<Float> {
+    let (input_h, input_w, channels) = input_size;
+    let (output_h, output_w) = output_size;
+
+    let mut image = ArrayD::zeros(IxDyn(&[batch_size, input_h, input_w, channels]));
+
+    let images = image.axis_iter_mut(Axis(0));
+    let blocks = col.axis_chunks_iter(Axis(0), output_h * output_w);
+    let accumulate = |(mut image, rows): (ArrayViewMutD<Float>, ArrayView2<Float>)| {
+        let shape = image.shape().to_vec();
+        for_each_receptive_field(
+            &shape,
+            kernel_size,
+            padding,
+            stride,
+            output_size,
+            |row, col, pixel| image[pixel] += rows[[row, col]],
+        )
+    };
+    #[cfg(feature = "parallel")]
+    images.into_par_iter().zip(blocks).for_each(accumulate);
+    #[cfg(not(feature = "parallel"))]
+    images.zip(blocks).for_each(accumulate);
+
+    image
+}
 
-        linalg::general_mat_mul(1.0, &col_reshaped, &kernels_reshaped.t(), 0.0, &mut result);
+/// Convolve a batched input (shape (n, h, w, c)), zero padded by `padding` pixels on each side,
+/// with `kernels` of shape (number of kernels, kernel_h * kernel_w * c) slid by `stride`,
+/// returning an array of shape (n, output_h, output_w, number of kernels)
+pub(crate) fn convolve<T: LinalgScalar + Send + Sync>(
+    input: &ArrayD<T>,
+    kernels: &ArrayView2<T>,
+    kernel_size: (usize, usize),
+    padding: (usize, usize),
+    stride: (usize, usize),
+    output_size: (usize, usize),
+) -> Result<ArrayD<T>, ShapeError> {
+    let col = im2col(input, kernel_size, padding, stride, output_size);
+    multiply_unrolled(&col, kernels, input.shape()[0], output_size)
+}
 
-        result
-            .into_shape(IxDyn(&[batch_size, output_h, output_w, output_channels]))
-            .unwrap()
-    }
+/// Multiply the receptive fields unrolled by `im2col` with `kernels` of shape
+/// (number of kernels, kernel_h * kernel_w * c), returning the convolution of shape
+/// (n, output_h, output_w, number of kernels)
+fn multiply_unrolled<T: LinalgScalar>(
+    col: &Array2<T>,
+    kernels: &ArrayView2<T>,
+    batch_size: usize,
+    output_size: (usize, usize),
+) -> Result<ArrayD<T>, ShapeError> {
+    let (output_h, output_w) = output_size;
+    let output_channels = kernels.shape()[0];
 
-    fn convolve_full(&self, output: &ArrayD<f64>) -> ArrayD<f64> {
-        let col = self.im2col_full(output.clone());
-        let (kernel_h, kernel_w, kernel_d, num_kernels) = self.kernels_size;
-        let (input_h, input_w, input_channels) = self.input_size;
-        let batch_size = output.shape()[0];
+    let mut result = Array2::zeros((batch_size * output_h * output_w, output_channels));
 
-        let kernel_size = kernel_h * kernel_w * num_kernels;
+    linalg::general_mat_mul(T::one(), col, &kernels.t(), T::zero(), &mut result);
 
-        let kernels_reshaped = self
-            .flip_kernels()
-            .clone()
-            .into_shape((kernel_d, kernel_size))
-            .unwrap();
+    result.into_shape(IxDyn(&[batch_size, output_h, output_w, output_channels]))
+}
 
-        let col_reshaped = col
-            .into_shape((batch_size * input_h * input_w, kernel_size))
-            .unwrap();
+/// `multiply_unrolled` computed on `backend`
+fn backend_multiply_unrolled(
+    backend: &Backend,
+    col: &Array2<Float>,
+    kernels: &ArrayView2<Float>,
+    batch_size: usize,
+    output_size: (usize, usize),
+) -> Result<ArrayD<Float>, LayerError> {
+    let (output_h, output_w) = output_size;
+    let output_channels = kernels.shape()[0];
+
+    let mut result = Array2::zeros((batch_size * output_h * output_w, output_channels));
+    backend.gemm(1.0, &col.view(), &kernels.t(), 0.0, &mut result.view_mut())?;
+
+    Ok(result.into_shape(IxDyn(&[batch_size, output_h, output_w, output_channels]))?)
+}
 
-        let mut result = Array2::zeros((batch_size * input_h * input_w, input_channels));
+impl Layer for ConvolutionalLayer {
+    /// Unroll the input into the layer buffer, kept for the backward pass, before convolving it
+    fn feed_forward_save(&mut self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        self.check_input(input)?;
 
-        linalg::general_mat_mul(1.0, &col_reshaped, &kernels_reshaped.t(), 0.0, &mut result);
+        let (kernel_h, kernel_w, kernel_d, num_kernels) = self.kernels_size;
+        let (output_h, output_w, _output_channels) = self.output_size;
+        let batch_size = input.shape()[0];
 
-        result
-            .into_shape(IxDyn(&[batch_size, input_h, input_w, input_channels]))
-            .unwrap()
-    }
-}
+        let kernel_size = kernel_h * kernel_w * kernel_d;
+        let kernels_reshaped = self.kernels.view().into_shape((num_kernels, kernel_size))?;
 
-impl Layer for ConvolutionalLayer {
-    fn feed_forward_save(&mut self, input: &ArrayD<f64>) -> Result<ArrayD<f64>, LayerError> {
-        self.input = Some(input.clone());
-        self.feed_forward(input)
+        let col_input = workspace(
+            &mut self.col_input,
+            Ix2(batch_size * output_h * output_w, kernel_size),
+        );
+        im2col_into(
+            input,
+            (kernel_h, kernel_w),
+            self.padding,
+            self.stride,
+            (output_h, output_w),
+            col_input,
+        );
+        backend_multiply_unrolled(
+            &self.backend,
+            col_input,
+            &kernels_reshaped,
+            batch_size,
+            (output_h, output_w),
+        )
     }
 
-    fn feed_forward(&self, input: &ArrayD<f64>) -> Result<ArrayD<f64>, LayerError> {
-        let output = self.convolve(&input.clone());
-        Ok(output)
+    fn feed_forward(&self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        self.convolve(input)
     }
 
     fn propagate_backward(
         &mut self,
-        output_gradient: &ArrayD<f64>,
-    ) -> Result<ArrayD<f64>, LayerError> {
-        let input = self
-            .input
+        output_gradient: &ArrayD<Float>,
+    ) -> Result<ArrayD<Float>, LayerError> {
+        let col_input = self
+            .col_input
             .as_ref()
-            .expect("Input not set. Call feed_forward first.");
+            .ok_or(LayerError::IllegalInputAccess)?;
 
         let (kernel_h, kernel_w, kernel_d, num_kernels) = self.kernels_size;
         let (output_h, output_w, output_channels) = self.output_size;
-        let batch_size = input.shape()[0];
-
-        let mut col_input = self.im2col(input.clone());
+        let batch_size = output_gradient.shape()[0];
 
         let kernel_size = kernel_h * kernel_w * kernel_d;
+        let output_gradient = output_gradient.as_standard_layout();
         let output_gradient_flat = output_gradient
-            .clone()
-            .into_shape((batch_size * output_h * output_w, output_channels))
-            .unwrap();
-
-        // Calculate the gradient with respect to the input (dL/dX) using the convolve function with flipped kernels
-        let d_input = self.convolve_full(output_gradient);
-
-        col_input = col_input
-            .into_shape((batch_size * output_h * output_w, kernel_size))
-            .unwrap();
+            .view()
+            .into_shape((batch_size * output_h * output_w, output_channels))?;
+
+        // Calculate the gradient with respect to the input (dL/dX): the gradient of every
+        // unrolled receptive field is scattered back onto the input with col2im
+        let kernels_reshaped = self.kernels.view().into_shape((num_kernels, kernel_size))?;
+        let col_gradient = workspace(
+            &mut self.col_gradient,
+            Ix2(batch_size * output_h * output_w, kernel_size),
+        );
+        self.backend.gemm(
+            1.0,
+            &output_gradient_flat,
+            &kernels_reshaped,
+            0.0,
+            &mut col_gradient.view_mut(),
+        )?;
+        let d_input = col2im(
+            col_gradient,
+            batch_size,
+            self.input_size,
+            (kernel_h, kernel_w),
+            self.padding,
+            self.stride,
+            (output_h, output_w),
+        );
 
-        // Calculate the gradient with respect to the filters (dL/dW)
-        let mut d_kernels = Array2::zeros((num_kernels, kernel_size));
-        linalg::general_mat_mul(
+        // Calculate the gradient with respect to the filters (dL/dW), over the previous one
+        let d_kernels = workspace(
+            &mut self.kernel_gradient,
+            IxDyn(&[kernel_h, kernel_w, kernel_d, num_kernels]),
+        );
+        self.backend.gemm(
             1.0,
             &output_gradient_flat.t(),
-            &col_input,
+            &col_input.view(),
             0.0,
-            &mut d_kernels,
-        );
-        let d_kernels = d_kernels
-            .into_shape(IxDyn(&[kernel_h, kernel_w, kernel_d, num_kernels]))
-            .unwrap();
-        self.kernel_gradient = Some(d_kernels);
+            &mut d_kernels
+                .view_mut()
+                .into_shape((num_kernels, kernel_size))?,
+        )?;
 
         // Calculate the gradient with respect to the biases (dL/db)
         let d_biases = output_gradient
@@ -529,6 +1117,22 @@ impl Layer for ConvolutionalLayer {
         Ok(d_input)
     }
 
+    fn output_shape(&self, input_shape: &[usize]) -> Result<Vec<usize>, LayerError> {
+        check_spatial_input(input_shape, self.input_size)?;
+        non_empty_spatial_shape(self.output_size)
+    }
+
+    fn expected_input_shape(&self) -> Option<Vec<usize>> {
+        Some(spatial_shape(self.input_size))
+    }
+
+    // the product of the unrolled receptive fields with the kernels, im2col only copying
+    fn flops(&self, _input_shape: &[usize]) -> u64 {
+        let (kernel_h, kernel_w, kernel_d, num_kernels) = self.kernels_size;
+        let (output_h, output_w, _) = self.output_size;
+        (2 * output_h * output_w * num_kernels * kernel_h * kernel_w * kernel_d) as u64
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -536,170 +1140,420 @@ impl Layer for ConvolutionalLayer {
     fn as_any_mut(&mut self) -> &mut dyn Any {
         self
     }
+
+    fn as_trainable(&self) -> Option<&dyn Trainable> {
+        Some(self)
+    }
+
+    fn as_trainable_mut(&mut self) -> Option<&mut dyn Trainable> {
+        Some(self)
+    }
+
+    fn set_backend(&mut self, backend: &Backend) {
+        self.backend = backend.clone();
+    }
 }
 
 impl Trainable for ConvolutionalLayer {
-    fn get_parameters(&self) -> Vec<ArrayD<f64>> {
+    fn get_parameters(&self) -> Vec<ArrayD<Float>> {
         vec![
             self.kernels.clone().into_dyn(),
             self.bias.clone().into_dyn(),
         ]
     }
 
-    fn get_parameters_mut(&mut self) -> Vec<&mut ArrayD<f64>> {
+    fn get_parameters_mut(&mut self) -> Vec<&mut ArrayD<Float>> {
         vec![&mut self.kernels, &mut self.bias]
     }
 
-    fn get_gradients(&self) -> Vec<ArrayD<f64>> {
-        vec![
-            self.kernel_gradient
-                .as_ref()
-                .expect("Illegal access to unset weights gradient")
-                .clone()
-                .into_dyn(),
-            self.bias_gradient
-                .as_ref()
-                .expect("Illegal access to unset biases gradient")
-                .clone()
-                .into_dyn(),
-        ]
+    fn get_gradients(&self) -> Result<Vec<ArrayD<Float>>, LayerError> {
+        match (&self.kernel_gradient, &self.bias_gradient) {
+            (Some(kernel_gradient), Some(bias_gradient)) => {
+                Ok(vec![kernel_gradient.clone(), bias_gradient.clone()])
+            }
+            _ => Err(LayerError::IllegalGradientAccess),
+        }
+    }
+
+    fn get_gradients_mut(&mut self) -> Result<Vec<&mut ArrayD<Float>>, LayerError> {
+        match (&mut self.kernel_gradient, &mut self.bias_gradient) {
+            (Some(kernel_gradient), Some(bias_gradient)) => {
+                Ok(vec![kernel_gradient, bias_gradient])
+            }
+            _ => Err(LayerError::IllegalGradientAccess),
+        }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Default)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct MaxPoolingLayer {
-    input: Option<ArrayD<f64>>,
+    // position of the max inside its window (dy * pool_width + dx) for every output element
+    #[serde(skip)]
     max_indices: Option<ArrayD<usize>>,
     input_size: (usize, usize, usize),
     output_size: (usize, usize, usize),
     pool_size: (usize, usize),
+    stride: (usize, usize),
 }
 
 impl MaxPoolingLayer {
+    /// Create a max pooling layer over non overlapping windows (the stride is the window size),
+    /// use `with_stride` for another stride
+    ///
+    /// # Arguments
+    /// * `input_size` - (height, width, channels)
+    /// * `pool_size` - window size as (height, width)
     pub fn new(input_size: (usize, usize, usize), pool_size: (usize, usize)) -> Self {
-        let (input_height, input_width, input_channel): (usize, usize, usize) = input_size;
-        let (pool_height, pool_width): (usize, usize) = pool_size;
-        let output_size: (usize, usize, usize) = (
-            input_height / pool_height,
-            input_width / pool_width,
-            input_channel,
-        );
-        Self { 
-            input: None, 
+        Self {
             max_indices: None,
             input_size,
-            output_size,
+            output_size: pooled_size(input_size, pool_size, pool_size),
             pool_size,
+            stride: pool_size,
         }
     }
 
-    fn find_max_indices(&mut self) {
-        let input = self
-            .input
-            .as_ref()
-            .expect("Input not set. Call feed_forward first.");
+    /// Set the (vertical, horizontal) stride between two windows
+    ///
+    /// # Panics
+    /// If a side of `stride` is zero
+    pub fn with_stride(mut self, stride: (usize, usize)) -> Self {
+        assert!(stride.0 > 0 && stride.1 > 0, "Pooling stride can't be zero");
+        self.stride = stride;
+        self.output_size = pooled_size(self.input_size, self.pool_size, stride);
+        self
+    }
 
+    /// Return the pooled output along with the position of each max in its window
+    fn pool_with_indices(&self, input: &ArrayD<Float>) -> (ArrayD<Float>, ArrayD<usize>) {
         let batch_size: usize = input.shape()[0];
         let (pool_height, pool_width): (usize, usize) = self.pool_size;
+        let (stride_height, stride_width) = self.stride;
         let (output_height, output_width, output_channel) = self.output_size;
 
-        let mut max_indices: ArrayD<usize> = ArrayD::zeros(IxDyn(&[batch_size, output_height, output_width, output_channel]));
+        let shape = IxDyn(&[batch_size, output_height, output_width, output_channel]);
+        let mut output: ArrayD<Float> = ArrayD::zeros(shape.clone());
+        let mut max_indices: ArrayD<usize> = ArrayD::zeros(shape);
         for batch_index in 0..batch_size {
             for channel in 0..output_channel {
                 for y in 0..output_height {
                     for x in 0..output_width {
-                        let height_start: usize = y * pool_height;
-                        let width_start: usize = x * pool_width;
+                        let height_start: usize = y * stride_height;
+                        let width_start: usize = x * stride_width;
                         let window = input.slice(s![
                             batch_index,
                             height_start..height_start + pool_height,
                             width_start..width_start + pool_width,
                             channel
                         ]);
-                        let (max_index, _) = window.indexed_iter().fold((0, f64::MIN), |(max_idx, max_value), (idx, &val)| {
-                            if val > max_value {
-                                (idx.0 * window.ncols() + idx.1, val)
-                            } else {
-                                (max_idx, max_value)
-                            }
-                        });
+                        let (max_index, max_value) = window.indexed_iter().fold(
+                            (0, Float::NEG_INFINITY),
+                            |(max_idx, max_value), ((dy, dx), &val)| {
+                                if val > max_value {
+                                    (dy * pool_width + dx, val)
+                                } else {
+                                    (max_idx, max_value)
+                                }
+                            },
+                        );
+                        output[[batch_index, y, x, channel]] = max_value;
                         max_indices[[batch_index, y, x, channel]] = max_index;
                     }
                 }
             }
         }
-        self.max_indices = Some(max_indices);
+        (output, max_indices)
+    }
+
+    /// Window size as (height, width)
+    pub fn pool_size(&self) -> (usize, usize) {
+        self.pool_size
+    }
+
+    /// Stride between two windows as (height, width)
+    pub fn stride(&self) -> (usize, usize) {
+        self.stride
+    }
+
+    /// Output size as (height, width, channels)
+    pub fn output_size(&self) -> (usize, usize, usize) {
+        self.output_size
     }
 }
 
+/// Output size (height, width, channels) of a pooling over `input_size` without padding. A side
+/// is empty when the window doesn't fit in the input.
+fn pooled_size(
+    input_size: (usize, usize, usize),
+    pool_size: (usize, usize),
+    stride: (usize, usize),
+) -> (usize, usize, usize) {
+    let (input_height, input_width, input_channel) = input_size;
+    let (pool_height, pool_width) = pool_size;
+    let (stride_height, stride_width) = stride;
+    let side = |input: usize, pool: usize, stride: usize| {
+        input.checked_sub(pool).map_or(0, |span| span / stride + 1)
+    };
+    (
+        side(input_height, pool_height, stride_height),
+        side(input_width, pool_width, stride_width),
+        input_channel,
+    )
+}
+
+/// Max pool a batched input (shape (n, h, w, c)) over windows of `pool_size` spaced by `stride`,
+/// returning an array of shape (n, output_h, output_w, c)
+pub(crate) fn max_pool<T: Copy + PartialOrd + num_traits::Zero>(
+    input: &ArrayD<T>,
+    pool_size: (usize, usize),
+    stride: (usize, usize),
+    output_size: (usize, usize),
+) -> ArrayD<T> {
+    let batch_size = input.shape()[0];
+    let channels = input.shape()[3];
+    let (pool_height, pool_width) = pool_size;
+    let (stride_height, stride_width) = stride;
+    let (output_height, output_width) = output_size;
+
+    let mut output = ArrayD::zeros(IxDyn(&[batch_size, output_height, output_width, channels]));
+    for batch_index in 0..batch_size {
+        for channel in 0..channels {
+            for y in 0..output_height {
+                for x in 0..output_width {
+                    let height_start = y * stride_height;
+                    let width_start = x * stride_width;
+                    let mut max = input[[batch_index, height_start, width_start, channel]];
+                    for dy in 0..pool_height {
+                        for dx in 0..pool_width {
+                            let value =
+                                input[[batch_index, height_start + dy, width_start + dx, channel]];
+                            if value > max {
+                                max = value;
+                            }
+                        }
+                    }
+                    output[[batch_index, y, x, channel]] = max;
+                }
+            }
+        }
+    }
+    output
+}
+
 impl Layer for MaxPoolingLayer {
-    fn feed_forward_save(&mut self, input: &ArrayD<f64>) -> Result<ArrayD<f64>, LayerError> {
-        self.input = Some(input.clone());
-        self.find_max_indices();
-        self.feed_forward(input)
+    fn feed_forward_save(&mut self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        let (output, max_indices) = self.pool_with_indices(input);
+        self.max_indices = Some(max_indices);
+        Ok(output)
     }
 
-    fn feed_forward(&self, input: &ArrayD<f64>) -> Result<ArrayD<f64>, LayerError> {
-        let batch_size: usize = input.shape()[0];
-        let (pool_height, pool_width): (usize, usize) = self.pool_size;
-        let (output_height, output_width, output_channel) = self.output_size;
+    fn feed_forward(&self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        let (output_height, output_width, _) = self.output_size;
+        Ok(max_pool(
+            input,
+            self.pool_size,
+            self.stride,
+            (output_height, output_width),
+        ))
+    }
 
+    /// Route every output gradient to the input position that was the max of its window,
+    /// positions shared by overlapping windows accumulate the gradients
+    fn propagate_backward(
+        &mut self,
+        output_gradient: &ArrayD<Float>,
+    ) -> Result<ArrayD<Float>, LayerError> {
         let max_indices = self
             .max_indices
             .as_ref()
-            .expect("Max_indices not set. Call feed_forward first.");
+            .ok_or(LayerError::IllegalInputAccess)?;
 
-        let mut output: ArrayD<f64> = ArrayD::zeros(IxDyn(&[batch_size, output_height, output_width, output_channel]));
+        let batch_size: usize = output_gradient.shape()[0];
+        let (input_height, input_width, input_channel): (usize, usize, usize) = self.input_size;
+        let (output_height, output_width, output_channel) = self.output_size;
+        let (_, pool_width): (usize, usize) = self.pool_size;
+        let (stride_height, stride_width) = self.stride;
+
+        let mut input_gradient: ArrayD<Float> = ArrayD::zeros(IxDyn(&[
+            batch_size,
+            input_height,
+            input_width,
+            input_channel,
+        ]));
 
         for batch_index in 0..batch_size {
             for channel in 0..output_channel {
                 for y in 0..output_height {
                     for x in 0..output_width {
                         let index = max_indices[[batch_index, y, x, channel]];
-                        let height_start = y * pool_height;
-                        let width_start = x * pool_width;
-                        let dy = index / pool_height;
+                        let height_start = y * stride_height;
+                        let width_start = x * stride_width;
+                        let dy = index / pool_width;
                         let dx = index % pool_width;
-                        output[[batch_index, y, x, channel]] = input[[batch_index, height_start + dy, width_start + dx, channel]];
+                        input_gradient
+                            [[batch_index, height_start + dy, width_start + dx, channel]] +=
+                            output_gradient[[batch_index, y, x, channel]];
                     }
                 }
             }
         }
+        Ok(input_gradient)
+    }
 
-        Ok(output)
+    fn output_shape(&self, input_shape: &[usize]) -> Result<Vec<usize>, LayerError> {
+        check_spatial_input(input_shape, self.input_size)?;
+        non_empty_spatial_shape(self.output_size)
     }
 
-    fn propagate_backward(
-            &mut self,
-            output_gradient: &ArrayD<f64>,
-        ) -> Result<ArrayD<f64>, LayerError> {
-        let input = self
-            .input
-            .as_ref()
-            .expect("Input not set. Call feed_forward first.");
+    fn expected_input_shape(&self) -> Option<Vec<usize>> {
+        Some(spatial_shape(self.input_size))
+    }
 
-        let max_indices = self
-            .max_indices
-            .as_ref()
-            .expect("Max_indices not set. Call feed_forward first");
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 
-        let batch_size: usize = input.shape()[0];
-        let (input_height, input_width, input_channel): (usize, usize, usize) = self.input_size;
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Average pooling over windows of `pool_size`, spaced by `stride` (by default the window size)
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct AveragePoolingLayer {
+    input_size: (usize, usize, usize),
+    output_size: (usize, usize, usize),
+    pool_size: (usize, usize),
+    stride: (usize, usize),
+}
+
+impl AveragePoolingLayer {
+    /// # Arguments
+    /// * `input_size` - (height, width, channels)
+    /// * `pool_size` - window size as (height, width)
+    pub fn new(input_size: (usize, usize, usize), pool_size: (usize, usize)) -> Self {
+        Self {
+            input_size,
+            output_size: pooled_size(input_size, pool_size, pool_size),
+            pool_size,
+            stride: pool_size,
+        }
+    }
+
+    /// Set the (vertical, horizontal) stride between two windows
+    ///
+    /// # Panics
+    /// If a side of `stride` is zero
+    pub fn with_stride(mut self, stride: (usize, usize)) -> Self {
+        assert!(stride.0 > 0 && stride.1 > 0, "Pooling stride can't be zero");
+        self.stride = stride;
+        self.output_size = pooled_size(self.input_size, self.pool_size, stride);
+        self
+    }
+
+    /// Window size as (height, width)
+    pub fn pool_size(&self) -> (usize, usize) {
+        self.pool_size
+    }
+
+    /// Stride between two windows as (height, width)
+    pub fn stride(&self) -> (usize, usize) {
+        self.stride
+    }
+
+    /// Output size as (height, width, channels)
+    pub fn output_size(&self) -> (usize, usize, usize) {
+        self.output_size
+    }
+}
+
+/// Average pool a batched input (shape (n, h, w, c)) over windows of `pool_size` spaced by
+/// `stride`, returning an array of shape (n, output_h, output_w, c)
+pub(crate) fn average_pool<T: num_traits::Float>(
+    input: &ArrayD<T>,
+    pool_size: (usize, usize),
+    stride: (usize, usize),
+    output_size: (usize, usize),
+) -> ArrayD<T> {
+    let batch_size = input.shape()[0];
+    let channels = input.shape()[3];
+    let (pool_height, pool_width) = pool_size;
+    let (stride_height, stride_width) = stride;
+    let (output_height, output_width) = output_size;
+    let window_size = T::from(pool_height * pool_width).unwrap();
+
+    let mut output = ArrayD::zeros(IxDyn(&[batch_size, output_height, output_width, channels]));
+    for batch_index in 0..batch_size {
+        for channel in 0..channels {
+            for y in 0..output_height {
+                for x in 0..output_width {
+                    let height_start = y * stride_height;
+                    let width_start = x * stride_width;
+                    let mut sum = T::zero();
+                    for dy in 0..pool_height {
+                        for dx in 0..pool_width {
+                            sum = sum
+                                + input
+                                    [[batch_index, height_start + dy, width_start + dx, channel]];
+                        }
+                    }
+                    output[[batch_index, y, x, channel]] = sum / window_size;
+                }
+            }
+        }
+    }
+    output
+}
+
+impl Layer for AveragePoolingLayer {
+    fn feed_forward_save(&mut self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        self.feed_forward(input)
+    }
+
+    fn feed_forward(&self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        let (output_height, output_width, _) = self.output_size;
+        Ok(average_pool(
+            input,
+            self.pool_size,
+            self.stride,
+            (output_height, output_width),
+        ))
+    }
+
+    /// Distribute every output gradient uniformly over its window
+    fn propagate_backward(
+        &mut self,
+        output_gradient: &ArrayD<Float>,
+    ) -> Result<ArrayD<Float>, LayerError> {
+        let batch_size: usize = output_gradient.shape()[0];
+        let (input_height, input_width, input_channel) = self.input_size;
         let (output_height, output_width, output_channel) = self.output_size;
-        let (pool_height, pool_width): (usize, usize) = self.pool_size;
+        let (pool_height, pool_width) = self.pool_size;
+        let (stride_height, stride_width) = self.stride;
+        let window_size = (pool_height * pool_width) as Float;
 
-        let mut input_gradient: ArrayD<f64> = ArrayD::zeros(IxDyn(&[batch_size, input_height, input_width, input_channel]));
+        let mut input_gradient: ArrayD<Float> = ArrayD::zeros(IxDyn(&[
+            batch_size,
+            input_height,
+            input_width,
+            input_channel,
+        ]));
 
         for batch_index in 0..batch_size {
             for channel in 0..output_channel {
                 for y in 0..output_height {
                     for x in 0..output_width {
-                        let index = max_indices[[batch_index, y, x, channel]];
-                        let height_start = y * pool_height;
-                        let width_start = x * pool_width;
-                        let dy = index / pool_height;
-                        let dx = index % pool_width;
-                        input_gradient[[batch_index, height_start + dy, width_start + dx, channel]] += output_gradient[[batch_index, y, x, channel]];
+                        let height_start = y * stride_height;
+                        let width_start = x * stride_width;
+                        let gradient = output_gradient[[batch_index, y, x, channel]] / window_size;
+                        input_gradient
+                            .slice_mut(s![
+                                batch_index,
+                                height_start..height_start + pool_height,
+                                width_start..width_start + pool_width,
+                                channel
+                            ])
+                            .mapv_inplace(|e| e + gradient);
                     }
                 }
             }
@@ -707,6 +1561,15 @@ impl Layer for MaxPoolingLayer {
         Ok(input_gradient)
     }
 
+    fn output_shape(&self, input_shape: &[usize]) -> Result<Vec<usize>, LayerError> {
+        check_spatial_input(input_shape, self.input_size)?;
+        non_empty_spatial_shape(self.output_size)
+    }
+
+    fn expected_input_shape(&self) -> Option<Vec<usize>> {
+        Some(spatial_shape(self.input_size))
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -716,9 +1579,83 @@ impl Layer for MaxPoolingLayer {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Default)]
+/// Average every channel over the whole spatial dimensions, turning an input of shape
+/// (n, h, w, c) into (n, c), to be used in place of a reshape before the dense head of a
+/// convolutional network
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct GlobalAveragePoolingLayer {
+    input_size: (usize, usize, usize),
+}
+
+impl GlobalAveragePoolingLayer {
+    /// # Arguments
+    /// * `input_size` - (height, width, channels)
+    pub fn new(input_size: (usize, usize, usize)) -> Self {
+        Self { input_size }
+    }
+}
+
+/// Average a batched input (shape (n, h, w, c)) over its spatial dimensions, returning an array
+/// of shape (n, c)
+pub(crate) fn global_average_pool<T: num_traits::Float>(input: &ArrayD<T>) -> ArrayD<T> {
+    let spatial_size = T::from(input.shape()[1] * input.shape()[2]).unwrap();
+    input
+        .fold_axis(Axis(1), T::zero(), |&sum, &e| sum + e)
+        .fold_axis(Axis(1), T::zero(), |&sum, &e| sum + e)
+        .mapv(|sum| sum / spatial_size)
+}
+
+impl Layer for GlobalAveragePoolingLayer {
+    fn feed_forward_save(&mut self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        self.feed_forward(input)
+    }
+
+    fn feed_forward(&self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        Ok(global_average_pool(input))
+    }
+
+    /// Spread every channel gradient uniformly over the spatial dimensions
+    fn propagate_backward(
+        &mut self,
+        output_gradient: &ArrayD<Float>,
+    ) -> Result<ArrayD<Float>, LayerError> {
+        let batch_size: usize = output_gradient.shape()[0];
+        let (input_height, input_width, input_channel) = self.input_size;
+        let spatial_size = (input_height * input_width) as Float;
+
+        let gradient = output_gradient
+            .view()
+            .into_shape((batch_size, 1, 1, input_channel))?
+            .mapv(|e| e / spatial_size);
+        Ok(gradient
+            .broadcast((batch_size, input_height, input_width, input_channel))
+            .ok_or(LayerError::DimensionMismatch)?
+            .to_owned()
+            .into_dyn())
+    }
+
+    fn output_shape(&self, input_shape: &[usize]) -> Result<Vec<usize>, LayerError> {
+        check_spatial_input(input_shape, self.input_size)?;
+        Ok(vec![self.input_size.2])
+    }
+
+    fn expected_input_shape(&self) -> Option<Vec<usize>> {
+        Some(spatial_shape(self.input_size))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct ReshapeLayer {
-    input: Option<ArrayD<f64>>,
+    #[serde(skip)]
+    input: Option<ArrayD<Float>>,
     input_shape: IxDyn,
     output_shape: IxDyn,
 }
@@ -738,21 +1675,31 @@ impl ReshapeLayer {
             output_shape: IxDyn(output_shape),
         })
     }
+
+    /// Input shape of a single sample, without the batch dimension
+    pub fn input_shape(&self) -> &[usize] {
+        self.input_shape.slice()
+    }
+
+    /// Output shape of a single sample, without the batch dimension
+    pub fn output_shape(&self) -> &[usize] {
+        self.output_shape.slice()
+    }
 }
 
 impl Layer for ReshapeLayer {
-    fn feed_forward_save(&mut self, input: &ArrayD<f64>) -> Result<ArrayD<f64>, LayerError> {
+    fn feed_forward_save(&mut self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
         self.input = Some(input.clone());
         self.feed_forward(input)
     }
 
-    fn feed_forward(&self, input: &ArrayD<f64>) -> Result<ArrayD<f64>, LayerError> {
+    fn feed_forward(&self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
         let batch_size: usize = input.shape()[0];
         let mut shape: Vec<usize> = Vec::with_capacity(self.output_shape.ndim() + 1);
         shape.push(batch_size);
         shape.extend_from_slice(self.output_shape.as_array_view().as_slice().unwrap());
 
-        if input.shape().iter().product::<usize>() != shape.iter().product() {
+        if input.shape().iter().product::<usize>() != shape.iter().product::<usize>() {
             return Err(LayerError::ReshapeError(ShapeError::from_kind(
                 ndarray::ErrorKind::IncompatibleShape,
             )));
@@ -762,13 +1709,13 @@ impl Layer for ReshapeLayer {
 
     fn propagate_backward(
         &mut self,
-        output_gradient: &ArrayD<f64>,
-    ) -> Result<ArrayD<f64>, LayerError> {
+        output_gradient: &ArrayD<Float>,
+    ) -> Result<ArrayD<Float>, LayerError> {
         let batch_size: usize = output_gradient.shape()[0];
         let mut shape: Vec<usize> = Vec::with_capacity(self.output_shape.ndim() + 1);
         shape.push(batch_size);
         shape.extend_from_slice(self.input_shape.as_array_view().as_slice().unwrap());
-        if output_gradient.shape().iter().product::<usize>() != shape.iter().product() {
+        if output_gradient.shape().iter().product::<usize>() != shape.iter().product::<usize>() {
             return Err(LayerError::ReshapeError(ShapeError::from_kind(
                 ndarray::ErrorKind::IncompatibleShape,
             )));
@@ -776,6 +1723,403 @@ impl Layer for ReshapeLayer {
         Ok(output_gradient.clone().into_shape(shape).unwrap())
     }
 
+    fn output_shape(&self, input_shape: &[usize]) -> Result<Vec<usize>, LayerError> {
+        if input_shape.iter().product::<usize>() != self.input_shape.size() {
+            return Err(LayerError::DimensionMismatch);
+        }
+        Ok(self.output_shape.slice().to_vec())
+    }
+
+    fn expected_input_shape(&self) -> Option<Vec<usize>> {
+        Some(self.input_shape.slice().to_vec())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Flatten every dimension after the batch one, (n, d1, d2, ...) into (n, d1 * d2 * ...),
+/// without being given the input shape (ex: between the convolutional and the dense layers)
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct FlattenLayer {
+    #[serde(skip)]
+    input_shape: Option<Vec<usize>>,
+}
+
+impl FlattenLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Layer for FlattenLayer {
+    fn feed_forward_save(&mut self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        self.input_shape = Some(input.shape().to_vec());
+        self.feed_forward(input)
+    }
+
+    fn feed_forward(&self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        Ok(flatten(input)?)
+    }
+
+    /// Reshape the gradient back into the shape of the last input
+    fn propagate_backward(
+        &mut self,
+        output_gradient: &ArrayD<Float>,
+    ) -> Result<ArrayD<Float>, LayerError> {
+        let input_shape = self
+            .input_shape
+            .as_ref()
+            .ok_or(LayerError::IllegalInputAccess)?;
+        Ok(output_gradient.clone().into_shape(input_shape.clone())?)
+    }
+
+    fn output_shape(&self, input_shape: &[usize]) -> Result<Vec<usize>, LayerError> {
+        Ok(vec![input_shape.iter().product()])
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Flatten a batched input (shape (n, ...)) into shape (n, features)
+pub(crate) fn flatten<T: Clone>(input: &ArrayD<T>) -> Result<ArrayD<T>, ShapeError> {
+    let batch_size = input.shape()[0];
+    let features = input.shape()[1..].iter().product::<usize>();
+    input.to_owned().into_shape(IxDyn(&[batch_size, features]))
+}
+
+/// Randomly zero a fraction `rate` of its inputs during training, scaling the kept ones by
+/// 1 / (1 - rate) (inverted dropout) so that the layer is the identity at inference time.
+///
+/// The mask is only drawn in training mode, turned on by `Sequential::train` (see
+/// `Layer::set_training`), the layer letting the input and the gradient through unchanged
+/// otherwise.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct DropoutLayer {
+    rate: Float,
+    #[serde(skip)]
+    training: bool,
+    #[serde(skip)]
+    mask: Option<ArrayD<Float>>,
+    #[serde(skip)]
+    generator: Option<ChaCha8Rng>,
+}
+
+impl DropoutLayer {
+    /// Returns `LayerError::InvalidDropoutRate` if `rate` isn't in [0, 1)
+    /// # Arguments
+    /// * `rate` - probability of dropping each input
+    pub fn new(rate: Float) -> Result<Self, LayerError> {
+        if !(0.0..1.0).contains(&rate) {
+            return Err(LayerError::InvalidDropoutRate(rate));
+        }
+        Ok(Self {
+            rate,
+            training: false,
+            mask: None,
+            generator: None,
+        })
+    }
+
+    pub fn rate(&self) -> Float {
+        self.rate
+    }
+}
+
+impl Layer for DropoutLayer {
+    fn feed_forward_save(&mut self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        if !self.training {
+            self.mask = None;
+            return Ok(input.clone());
+        }
+        let scale = 1.0 / (1.0 - self.rate);
+        let rate = self.rate;
+        let distribution = Uniform::new(0.0, 1.0);
+        let mask = match self.generator.as_mut() {
+            Some(rng) => ArrayD::random_using(input.raw_dim(), distribution, rng),
+            None => {
+                random::with_rng(|rng| ArrayD::random_using(input.raw_dim(), distribution, rng))
+            }
+        }
+        .mapv(|e: Float| if e < rate { 0.0 } else { scale });
+        let output = input * &mask;
+        self.mask = Some(mask);
+        Ok(output)
+    }
+
+    fn feed_forward(&self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        Ok(input.clone())
+    }
+
+    fn propagate_backward(
+        &mut self,
+        output_gradient: &ArrayD<Float>,
+    ) -> Result<ArrayD<Float>, LayerError> {
+        match (self.training, self.mask.as_ref()) {
+            (false, _) => Ok(output_gradient.clone()),
+            (true, Some(mask)) => Ok(output_gradient * mask),
+            (true, None) => Err(LayerError::IllegalInputAccess),
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+
+    fn set_generator(&mut self, seed: u64) {
+        self.generator = Some(ChaCha8Rng::seed_from_u64(seed));
+    }
+}
+
+/// Elman recurrent layer reading sequences of shape (n, time, features), its hidden state
+/// `h_t = tanh(x_t W + h_(t-1) U + b)` starting from zeros. The output is the last hidden state,
+/// of shape (n, hidden), or the hidden state of every time step, of shape (n, time, hidden),
+/// with `with_return_sequences` (ex: to stack recurrent layers).
+///
+/// The gradient is backpropagated through the whole sequence, or through its last k time steps
+/// only with `with_truncation(k)` (truncated backpropagation through time), bounding the cost
+/// of a training on long sequences.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct SimpleRnnLayer {
+    input_weights: ArrayD<Float>,
+    recurrent_weights: ArrayD<Float>,
+    bias: ArrayD<Float>,
+    return_sequences: bool,
+    truncation: Option<usize>,
+    #[serde(skip)]
+    input: Option<ArrayD<Float>>,
+    #[serde(skip)]
+    hidden_states: Option<ArrayD<Float>>,
+    #[serde(skip)]
+    gradients: Option<Vec<ArrayD<Float>>>,
+}
+
+impl SimpleRnnLayer {
+    /// Create a new `SimpleRnnLayer` filling it with random value, see `InitializerType`
+    /// # Arguments
+    /// * `input_size` - number of features of every time step
+    /// * `hidden_size` - size of the hidden state
+    pub fn new(input_size: usize, hidden_size: usize, init: InitializerType) -> Self {
+        Self {
+            input_weights: init.initialize(input_size, hidden_size, &[input_size, hidden_size]),
+            recurrent_weights: init.initialize(
+                hidden_size,
+                hidden_size,
+                &[hidden_size, hidden_size],
+            ),
+            bias: init.initialize(input_size, hidden_size, &[hidden_size]),
+            return_sequences: false,
+            truncation: None,
+            input: None,
+            hidden_states: None,
+            gradients: None,
+        }
+    }
+
+    /// Output the hidden state of every time step instead of the last one only
+    pub fn with_return_sequences(mut self) -> Self {
+        self.return_sequences = true;
+        self
+    }
+
+    /// Backpropagate the gradient through the last `time_steps` of the sequence only
+    pub fn with_truncation(mut self, time_steps: usize) -> Self {
+        assert!(time_steps > 0, "The truncation can't be zero time step");
+        self.truncation = Some(time_steps);
+        self
+    }
+
+    /// The weights of the inputs, of shape (input size, hidden size)
+    pub fn input_weights(&self) -> &ArrayD<Float> {
+        &self.input_weights
+    }
+
+    /// The weights of the previous hidden state, of shape (hidden size, hidden size)
+    pub fn recurrent_weights(&self) -> &ArrayD<Float> {
+        &self.recurrent_weights
+    }
+
+    /// The bias vector, of shape (hidden size)
+    pub fn bias(&self) -> &ArrayD<Float> {
+        &self.bias
+    }
+
+    fn input_size(&self) -> usize {
+        self.input_weights.shape()[0]
+    }
+
+    fn hidden_size(&self) -> usize {
+        self.input_weights.shape()[1]
+    }
+
+    /// The hidden state of every time step, of shape (n, time, hidden)
+    fn compute_hidden_states(&self, input: &ArrayD<Float>) -> Result<Array3<Float>, LayerError> {
+        let input = input.view().into_dimensionality::<Ix3>()?;
+        let (batch_size, time_steps, input_size) = input.dim();
+        if input_size != self.input_size() || time_steps == 0 {
+            return Err(LayerError::DimensionMismatch);
+        }
+        let input_weights = self.input_weights.view().into_dimensionality::<Ix2>()?;
+        let recurrent_weights = self.recurrent_weights.view().into_dimensionality::<Ix2>()?;
+        let bias = self.bias.view().into_dimensionality::<Ix1>()?;
+
+        let mut hidden_states = Array3::zeros((batch_size, time_steps, self.hidden_size()));
+        let mut hidden_state = Array2::zeros((batch_size, self.hidden_size()));
+        for t in 0..time_steps {
+            hidden_state = (input.index_axis(Axis(1), t).dot(&input_weights)
+                + hidden_state.dot(&recurrent_weights)
+                + bias)
+                .mapv(Float::tanh);
+            hidden_states
+                .index_axis_mut(Axis(1), t)
+                .assign(&hidden_state);
+        }
+        Ok(hidden_states)
+    }
+
+    fn output(&self, hidden_states: &Array3<Float>) -> ArrayD<Float> {
+        if self.return_sequences {
+            hidden_states.clone().into_dyn()
+        } else {
+            let last = hidden_states.shape()[1] - 1;
+            hidden_states
+                .index_axis(Axis(1), last)
+                .to_owned()
+                .into_dyn()
+        }
+    }
+}
+
+impl Layer for SimpleRnnLayer {
+    /// # Arguments
+    /// * `input` - shape (n, time, features)
+    fn feed_forward_save(&mut self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        let hidden_states = self.compute_hidden_states(input)?;
+        let output = self.output(&hidden_states);
+        self.input = Some(input.clone());
+        self.hidden_states = Some(hidden_states.into_dyn());
+        Ok(output)
+    }
+
+    /// # Arguments
+    /// * `input` - shape (n, time, features)
+    fn feed_forward(&self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        Ok(self.output(&self.compute_hidden_states(input)?))
+    }
+
+    /// Backpropagation through time, from the last time step to the first one (or to the
+    /// truncation), the gradient of every hidden state being the sum of the gradient of the
+    /// output at that step and of the one flowing back from the next hidden state.
+    ///
+    /// # Arguments
+    /// * `output_gradient` - shape (n, hidden), or (n, time, hidden) when returning sequences
+    fn propagate_backward(
+        &mut self,
+        output_gradient: &ArrayD<Float>,
+    ) -> Result<ArrayD<Float>, LayerError> {
+        let (Some(input), Some(hidden_states)) = (self.input.as_ref(), self.hidden_states.as_ref())
+        else {
+            return Err(LayerError::IllegalInputAccess);
+        };
+        let input = input.view().into_dimensionality::<Ix3>()?;
+        let hidden_states = hidden_states.view().into_dimensionality::<Ix3>()?;
+        let (batch_size, time_steps, hidden_size) = hidden_states.dim();
+        let input_weights = self.input_weights.view().into_dimensionality::<Ix2>()?;
+        let recurrent_weights = self.recurrent_weights.view().into_dimensionality::<Ix2>()?;
+
+        let expected_shape = if self.return_sequences {
+            vec![batch_size, time_steps, hidden_size]
+        } else {
+            vec![batch_size, hidden_size]
+        };
+        if output_gradient.shape() != expected_shape {
+            return Err(LayerError::DimensionMismatch);
+        }
+
+        // gradient of the output with respect to the hidden state of every time step
+        let mut state_gradients = Array3::zeros((batch_size, time_steps, hidden_size));
+        if self.return_sequences {
+            state_gradients.assign(&output_gradient.view().into_dimensionality::<Ix3>()?);
+        } else {
+            state_gradients
+                .index_axis_mut(Axis(1), time_steps - 1)
+                .assign(&output_gradient.view().into_dimensionality::<Ix2>()?);
+        }
+
+        let mut input_weights_gradient = Array2::zeros(input_weights.raw_dim());
+        let mut recurrent_weights_gradient = Array2::zeros(recurrent_weights.raw_dim());
+        let mut bias_gradient = Array1::zeros(hidden_size);
+        let mut input_gradient = Array3::zeros(input.raw_dim());
+        let mut next_state_gradient = Array2::zeros((batch_size, hidden_size));
+        let first_step = self
+            .truncation
+            .map_or(0, |truncation| time_steps.saturating_sub(truncation));
+        for t in (first_step..time_steps).rev() {
+            let hidden_state = hidden_states.index_axis(Axis(1), t);
+            let state_gradient = &state_gradients.index_axis(Axis(1), t) + &next_state_gradient;
+            // through the tanh
+            let gradient = state_gradient * hidden_state.mapv(|h| 1.0 - h * h);
+            input_weights_gradient += &input.index_axis(Axis(1), t).t().dot(&gradient);
+            if t > 0 {
+                let previous_state = hidden_states.index_axis(Axis(1), t - 1);
+                recurrent_weights_gradient += &previous_state.t().dot(&gradient);
+            }
+            bias_gradient += &gradient.sum_axis(Axis(0));
+            input_gradient
+                .index_axis_mut(Axis(1), t)
+                .assign(&gradient.dot(&input_weights.t()));
+            next_state_gradient = gradient.dot(&recurrent_weights.t());
+        }
+
+        // mean relative to the batch
+        let batch_size = batch_size as Float;
+        self.gradients = Some(vec![
+            (input_weights_gradient / batch_size).into_dyn(),
+            (recurrent_weights_gradient / batch_size).into_dyn(),
+            (bias_gradient / batch_size).into_dyn(),
+        ]);
+        Ok(input_gradient.into_dyn())
+    }
+
+    fn output_shape(&self, input_shape: &[usize]) -> Result<Vec<usize>, LayerError> {
+        match input_shape {
+            &[time_steps, input_size] if input_size == self.input_size() && time_steps > 0 => {
+                if self.return_sequences {
+                    Ok(vec![time_steps, self.hidden_size()])
+                } else {
+                    Ok(vec![self.hidden_size()])
+                }
+            }
+            _ => Err(LayerError::DimensionMismatch),
+        }
+    }
+
+    // the input and recurrent products of every time step
+    fn flops(&self, input_shape: &[usize]) -> u64 {
+        let time_steps = input_shape.first().copied().unwrap_or(1);
+        let (input_size, hidden_size) = (self.input_size(), self.hidden_size());
+        (time_steps * (2 * (input_size + hidden_size) * hidden_size + hidden_size)) as u64
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -783,6 +2127,222 @@ impl Layer for ReshapeLayer {
     fn as_any_mut(&mut self) -> &mut dyn Any {
         self
     }
+
+    fn as_trainable(&self) -> Option<&dyn Trainable> {
+        Some(self)
+    }
+
+    fn as_trainable_mut(&mut self) -> Option<&mut dyn Trainable> {
+        Some(self)
+    }
+}
+
+impl Trainable for SimpleRnnLayer {
+    fn get_parameters(&self) -> Vec<ArrayD<Float>> {
+        vec![
+            self.input_weights.clone(),
+            self.recurrent_weights.clone(),
+            self.bias.clone(),
+        ]
+    }
+
+    fn get_parameters_mut(&mut self) -> Vec<&mut ArrayD<Float>> {
+        vec![
+            &mut self.input_weights,
+            &mut self.recurrent_weights,
+            &mut self.bias,
+        ]
+    }
+
+    fn get_gradients(&self) -> Result<Vec<ArrayD<Float>>, LayerError> {
+        self.gradients
+            .clone()
+            .ok_or(LayerError::IllegalGradientAccess)
+    }
+
+    fn get_gradients_mut(&mut self) -> Result<Vec<&mut ArrayD<Float>>, LayerError> {
+        let gradients = self
+            .gradients
+            .as_mut()
+            .ok_or(LayerError::IllegalGradientAccess)?;
+        Ok(gradients.iter_mut().collect())
+    }
+}
+
+/// A layer combining the outputs of several layers into one, as the merge nodes of a
+/// `GraphModel`, its output gradient being split back into a gradient for every input
+pub trait MergeLayer {
+    fn feed_forward_save(&mut self, inputs: &[&ArrayD<Float>])
+        -> Result<ArrayD<Float>, LayerError>;
+
+    fn feed_forward(&self, inputs: &[&ArrayD<Float>]) -> Result<ArrayD<Float>, LayerError>;
+
+    /// Return the gradient with respect to every input, in the order of the inputs
+    fn propagate_backward(
+        &mut self,
+        output_gradient: &ArrayD<Float>,
+    ) -> Result<Vec<ArrayD<Float>>, LayerError>;
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// Element wise sum of inputs of the same shape (ex: the shortcut of a residual block)
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct AddLayer {
+    #[serde(skip)]
+    number_of_inputs: usize,
+}
+
+impl AddLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MergeLayer for AddLayer {
+    fn feed_forward_save(
+        &mut self,
+        inputs: &[&ArrayD<Float>],
+    ) -> Result<ArrayD<Float>, LayerError> {
+        self.number_of_inputs = inputs.len();
+        self.feed_forward(inputs)
+    }
+
+    fn feed_forward(&self, inputs: &[&ArrayD<Float>]) -> Result<ArrayD<Float>, LayerError> {
+        let (first, others) = inputs.split_first().ok_or(LayerError::DimensionMismatch)?;
+        let mut sum = (*first).clone();
+        for input in others {
+            if input.shape() != sum.shape() {
+                return Err(LayerError::DimensionMismatch);
+            }
+            sum += *input;
+        }
+        Ok(sum)
+    }
+
+    /// Every input receive the whole output gradient
+    fn propagate_backward(
+        &mut self,
+        output_gradient: &ArrayD<Float>,
+    ) -> Result<Vec<ArrayD<Float>>, LayerError> {
+        if self.number_of_inputs == 0 {
+            return Err(LayerError::IllegalInputAccess);
+        }
+        Ok(vec![output_gradient.clone(); self.number_of_inputs])
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Concatenation along the last axis (the features, or the channels of an image) of inputs of
+/// the same shape otherwise (ex: the branches of an inception block)
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ConcatLayer {
+    /// Size of the last axis of every input, to split the gradient back
+    #[serde(skip)]
+    split_sizes: Vec<usize>,
+}
+
+impl ConcatLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MergeLayer for ConcatLayer {
+    fn feed_forward_save(
+        &mut self,
+        inputs: &[&ArrayD<Float>],
+    ) -> Result<ArrayD<Float>, LayerError> {
+        self.split_sizes = inputs
+            .iter()
+            .map(|input| input.len_of(last_axis(input)))
+            .collect();
+        self.feed_forward(inputs)
+    }
+
+    fn feed_forward(&self, inputs: &[&ArrayD<Float>]) -> Result<ArrayD<Float>, LayerError> {
+        let first = inputs.first().ok_or(LayerError::DimensionMismatch)?;
+        let views = inputs.iter().map(|input| input.view()).collect::<Vec<_>>();
+        Ok(concatenate(last_axis(first), &views)?)
+    }
+
+    /// Every input receive the slice of the output gradient matching its position in the
+    /// concatenation
+    fn propagate_backward(
+        &mut self,
+        output_gradient: &ArrayD<Float>,
+    ) -> Result<Vec<ArrayD<Float>>, LayerError> {
+        if self.split_sizes.is_empty() {
+            return Err(LayerError::IllegalInputAccess);
+        }
+        let axis = last_axis(output_gradient);
+        if output_gradient.len_of(axis) != self.split_sizes.iter().sum::<usize>() {
+            return Err(LayerError::DimensionMismatch);
+        }
+        let mut start = 0;
+        Ok(self
+            .split_sizes
+            .iter()
+            .map(|&size| {
+                let gradient = output_gradient.slice_axis(axis, Slice::from(start..start + size));
+                start += size;
+                gradient.to_owned()
+            })
+            .collect())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+fn last_axis(array: &ArrayD<Float>) -> Axis {
+    Axis(array.ndim().saturating_sub(1))
+}
+
+/// Check that `weights` can replace `parameters`, as many and of the same shapes
+pub(crate) fn check_weights(
+    parameters: &[ArrayD<Float>],
+    weights: &[ArrayD<Float>],
+) -> Result<(), LayerError> {
+    if parameters.len() != weights.len()
+        || parameters
+            .iter()
+            .zip(weights.iter())
+            .any(|(parameter, weight)| parameter.shape() != weight.shape())
+    {
+        return Err(LayerError::DimensionMismatch);
+    }
+    Ok(())
+}
+
+/// Check that the shape of a single input of a spatial layer is its (height, width, channels)
+/// `input_size`
+fn check_spatial_input(
+    input_shape: &[usize],
+    input_size: (usize, usize, usize),
+) -> Result<(), LayerError> {
+    if input_shape != spatial_shape(input_size) {
+        return Err(LayerError::DimensionMismatch);
+    }
+    Ok(())
+}
+
+fn spatial_shape((height, width, channels): (usize, usize, usize)) -> Vec<usize> {
+    vec![height, width, channels]
+}
+
+/// The shape of a (height, width, channels) output, or `LayerError::DimensionMismatch` if it is
+/// empty, the window (or kernel) of the layer not fitting in its input
+fn non_empty_spatial_shape(output_size: (usize, usize, usize)) -> Result<Vec<usize>, LayerError> {
+    let shape = spatial_shape(output_size);
+    if shape.contains(&0) {
+        return Err(LayerError::DimensionMismatch);
+    }
+    Ok(shape)
 }
 
 #[derive(Error, Debug)]
@@ -795,4 +2355,37 @@ pub enum LayerError {
 
     #[error("Dimension don't match")]
     DimensionMismatch,
+
+    #[error("Expected data points of shape {expected:?}, got {actual:?}")]
+    InputShapeMismatch {
+        expected: Vec<usize>,
+        actual: Vec<usize>,
+    },
+
+    #[error("Access to the gradients of the layer before they were computed")]
+    IllegalGradientAccess,
+
+    #[error("Kernels of depth {kernel_depth} can't convolve inputs of {input_channels} channels")]
+    KernelMismatch {
+        kernel_depth: usize,
+        input_channels: usize,
+    },
+
+    #[error("NaN or infinite value in the {values} of layer {layer}")]
+    NonFinite { values: GuardedValues, layer: usize },
+
+    #[error("NaN or infinite loss: {0}")]
+    NonFiniteLoss(Float),
+
+    #[error("Backend error: {0}")]
+    Backend(#[from] BackendError),
+
+    #[error("Dropout rate must be in [0, 1), got {0}")]
+    InvalidDropoutRate(Float),
+
+    #[error("Invalid batch augmentation: {0}")]
+    InvalidAugmentation(String),
+
+    #[error("A thread of the data parallel training panicked")]
+    WorkerPanic,
 }
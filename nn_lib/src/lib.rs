@@ -1,7 +1,44 @@
+#[cfg(feature = "blas")]
+extern crate blas_src;
+
+#[macro_use]
+mod macros;
+
 pub mod activation;
+pub mod augmentation;
+pub mod backend;
+pub mod callback;
+pub mod config;
 pub mod cost;
+pub mod cross_validation;
+pub mod data;
+pub mod graph;
+pub mod guard;
+pub mod inference;
 pub mod initialization;
 pub mod layer;
 pub mod metrics;
+pub mod npy;
+pub mod onnx;
 pub mod sequential;
+pub mod serialization;
 pub mod optimizer;
+pub mod preprocessing;
+pub mod progress;
+pub mod random;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+/// The floating point type of the networks parameters and data, `f64` unless the crate is built
+/// with the `f32` feature, which halves the memory used by the training and speeds it up
+#[cfg(not(feature = "f32"))]
+pub type Float = f64;
+#[cfg(feature = "f32")]
+pub type Float = f32;
+
+#[cfg(not(feature = "f32"))]
+pub(crate) use std::f64::consts;
+#[cfg(feature = "f32")]
+pub(crate) use std::f32::consts;
@@ -0,0 +1,20 @@
+/// Enter a `tracing` span until the end of the current scope when the `tracing` feature is
+/// enabled, fields are recorded with their `Debug` representation.
+///
+/// `enter_span!(DEBUG, "batch", index = i, input_shape = x.shape())`
+#[cfg(feature = "tracing")]
+macro_rules! enter_span {
+    ($level:ident, $name:expr $(, $field:ident = $value:expr)*) => {
+        let _span_guard =
+            tracing::span!(tracing::Level::$level, $name $(, $field = ?$value)*).entered();
+    };
+}
+
+/// Without the `tracing` feature the span fields are only borrowed, so the values used
+/// exclusively for tracing don't trigger unused warnings.
+#[cfg(not(feature = "tracing"))]
+macro_rules! enter_span {
+    ($level:ident, $name:expr $(, $field:ident = $value:expr)*) => {
+        $(let _ = &$value;)*
+    };
+}
@@ -1,72 +1,831 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
 
-use ndarray::{ArrayD, Axis};
+use ndarray::{s, Array2, ArrayD, Axis};
 use ndarray_stats::QuantileExt;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, PartialEq, Debug, Default)]
+use crate::{
+    layer::{Layer, LayerError},
+    sequential::Sequential,
+    Float,
+};
+
+/// The benchmarks recorded during a training
+/// `epochs[i]` is the epoch during which `history[i]` was recorded, a validation history can
+/// have several entries for the same epoch, or none, depending on the validation frequency.
+/// `batches` is only filled in a train history, when enabled with
+/// `SequentialBuilder::record_batches`, and `profiles` when enabled with
+/// `SequentialBuilder::profile`.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
 pub struct History {
     pub history: Vec<Benchmark>,
+    pub epochs: Vec<usize>,
+    #[serde(default)]
+    pub batches: Vec<BatchRecord>,
+    #[serde(default)]
+    pub profiles: Vec<EpochProfile>,
+}
+
+/// The loss of a single training batch
+/// `elapsed` is the time since the start of the training call (`train`, or
+/// `train_from_checkpoint` for a resumed training)
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct BatchRecord {
+    pub epoch: usize,
+    pub batch: usize,
+    pub loss: Float,
+    pub elapsed: Duration,
+}
+
+/// The time spent by a layer in its forward and backward passes during a training epoch, with
+/// an estimate of the floating point operations done, see `Layer::flops`.
+/// The backward pass is estimated at twice the operations of the forward one (for a product,
+/// the gradients of both its operands).
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct LayerProfile {
+    pub name: Option<String>,
+    pub layer_type: String,
+    pub forward: Duration,
+    pub backward: Duration,
+    pub forward_flops: u64,
+    pub backward_flops: u64,
+}
+
+impl LayerProfile {
+    /// Time spent in both passes
+    pub fn total(&self) -> Duration {
+        self.forward + self.backward
+    }
+
+    /// Estimated operations per second over both passes, in GFLOP/s
+    pub fn gflops(&self) -> f64 {
+        let seconds = self.total().as_secs_f64();
+        if seconds == 0.0 {
+            return 0.0;
+        }
+        (self.forward_flops + self.backward_flops) as f64 / seconds / 1e9
+    }
+}
+
+/// The profile of every layer of a network, in feed forward order, over a training epoch.
+/// Prints as a table of the time spent by every layer and its share of the epoch.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct EpochProfile {
+    pub epoch: usize,
+    pub layers: Vec<LayerProfile>,
+}
+
+impl EpochProfile {
+    /// An empty profile for `layers`, named after `layer_names`
+    pub(crate) fn new(
+        epoch: usize,
+        layers: &[Box<dyn Layer>],
+        layer_names: &[Option<String>],
+    ) -> Self {
+        Self {
+            epoch,
+            layers: layers
+                .iter()
+                .zip(layer_names)
+                .map(|(layer, name)| LayerProfile {
+                    name: name.clone(),
+                    layer_type: layer.type_name().to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+        }
+    }
+
+    pub(crate) fn record_forward(&mut self, index: usize, elapsed: Duration, flops: u64) {
+        self.layers[index].forward += elapsed;
+        self.layers[index].forward_flops += flops;
+    }
+
+    pub(crate) fn record_backward(&mut self, index: usize, elapsed: Duration, flops: u64) {
+        self.layers[index].backward += elapsed;
+        self.layers[index].backward_flops += flops;
+    }
+
+    /// Time spent in all the layers, the remaining time of the epoch being spent in the cost
+    /// function, the optimizer and the data loading
+    pub fn total(&self) -> Duration {
+        self.layers.iter().map(LayerProfile::total).sum()
+    }
+}
+
+impl fmt::Display for EpochProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.total().as_secs_f64();
+        writeln!(f, "Epoch {}", self.epoch)?;
+        writeln!(
+            f,
+            "{:<32} {:>12} {:>12} {:>8} {:>10}",
+            "Layer (type)", "Forward (ms)", "Backward (ms)", "Share", "GFLOP/s"
+        )?;
+        writeln!(f, "{}", "=".repeat(78))?;
+        for (i, layer) in self.layers.iter().enumerate() {
+            let share = if total > 0.0 {
+                layer.total().as_secs_f64() / total * 100.0
+            } else {
+                0.0
+            };
+            writeln!(
+                f,
+                "{:<32} {:>12.1} {:>12.1} {:>7.1}% {:>10.2}",
+                format!(
+                    "{} ({})",
+                    layer.name.clone().unwrap_or_else(|| i.to_string()),
+                    layer.layer_type
+                ),
+                layer.forward.as_secs_f64() * 1000.0,
+                layer.backward.as_secs_f64() * 1000.0,
+                share,
+                layer.gflops()
+            )?;
+        }
+        writeln!(f, "{}", "=".repeat(78))?;
+        writeln!(f, "Total: {:.1} ms", total * 1000.0)
+    }
 }
 
 impl History {
     pub fn new() -> Self {
-        Self { history: vec![] }
+        Self {
+            history: vec![],
+            epochs: vec![],
+            batches: vec![],
+            profiles: vec![],
+        }
+    }
+
+    pub fn push_batch(&mut self, record: BatchRecord) {
+        self.batches.push(record);
     }
 
-    pub fn get_loss_time_series(&self) -> Vec<f64> {
+    pub fn push_profile(&mut self, profile: EpochProfile) {
+        self.profiles.push(profile);
+    }
+
+    /// The per layer profile of every profiled epoch, one table after the other, see
+    /// `EpochProfile`
+    pub fn profile_report(&self) -> String {
+        self.profiles
+            .iter()
+            .map(|profile| profile.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The loss of every recorded batch, see `BatchRecord`
+    pub fn get_batch_loss_time_series(&self) -> Vec<Float> {
+        self.batches.iter().map(|record| record.loss).collect()
+    }
+
+    pub fn push(&mut self, epoch: usize, bench: Benchmark) {
+        self.history.push(bench);
+        self.epochs.push(epoch);
+    }
+
+    /// Iterate over the recorded benchmarks along with the epoch they were recorded in
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Benchmark)> {
+        self.epochs.iter().copied().zip(self.history.iter())
+    }
+
+    /// Return the last benchmark if it was recorded during `epoch`
+    pub fn last_of_epoch(&self, epoch: usize) -> Option<&Benchmark> {
+        match self.epochs.last() {
+            Some(&last) if last == epoch => self.history.last(),
+            _ => None,
+        }
+    }
+
+    pub fn get_loss_time_series(&self) -> Vec<Float> {
         self.history.iter().map(|h| h.loss).collect::<Vec<_>>()
     }
 
-    pub fn get_metric_time_series(&self, metrics_type: MetricsType) -> Option<Vec<f64>> {
+    pub fn get_metric_time_series(&self, metrics_type: MetricsType) -> Option<Vec<Float>> {
         self.history
             .iter()
             .map(|h| h.metrics.get_metric(metrics_type))
             .collect::<Option<Vec<_>>>()
     }
+
+    /// Duration of every recorded benchmark, in seconds
+    pub fn get_duration_time_series(&self) -> Vec<f64> {
+        self.history
+            .iter()
+            .map(|h| h.duration.as_secs_f64())
+            .collect::<Vec<_>>()
+    }
+
+    /// Throughput of every recorded benchmark, in samples per second
+    pub fn get_throughput_time_series(&self) -> Vec<f64> {
+        self.history
+            .iter()
+            .map(|h| h.samples_per_second())
+            .collect::<Vec<_>>()
+    }
+
+    /// Time spent in all the recorded benchmarks up to each of them, in seconds, for a train
+    /// history this is the cumulative training time at the end of every epoch
+    pub fn get_cumulative_duration_time_series(&self) -> Vec<f64> {
+        self.history
+            .iter()
+            .scan(0.0, |total, h| {
+                *total += h.duration.as_secs_f64();
+                Some(*total)
+            })
+            .collect::<Vec<_>>()
+    }
+
+    /// Time spent in all the recorded benchmarks, the total training time for a train history
+    pub fn total_duration(&self) -> Duration {
+        self.history.iter().map(|h| h.duration).sum()
+    }
+
+    /// Every metric recorded in the history, in a stable order
+    fn watched_metrics(&self) -> Vec<MetricsType> {
+        let mut metrics = self
+            .history
+            .iter()
+            .flat_map(|bench| bench.metrics.get_all().keys().copied())
+            .collect::<Vec<_>>();
+        metrics.sort();
+        metrics.dedup();
+        metrics
+    }
+
+    /// Write the history as a CSV file with a row per record and the columns epoch, loss,
+    /// duration (in seconds), samples_per_second, then every watched metric (see `MetricsType`
+    /// display names), a metric missing from a record is left empty
+    pub fn to_csv<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let metrics = self.watched_metrics();
+        let mut writer = BufWriter::new(File::create(path)?);
+        write!(writer, "epoch,loss,duration,samples_per_second")?;
+        for metric in &metrics {
+            write!(writer, ",{}", metric)?;
+        }
+        writeln!(writer)?;
+        for (epoch, bench) in self.iter() {
+            write!(
+                writer,
+                "{},{},{},{}",
+                epoch,
+                bench.loss,
+                bench.duration.as_secs_f64(),
+                bench.samples_per_second()
+            )?;
+            for &metric in &metrics {
+                match bench.metrics.get_metric(metric) {
+                    Some(value) => write!(writer, ",{}", value)?,
+                    None => write!(writer, ",")?,
+                }
+            }
+            writeln!(writer)?;
+        }
+        writer.flush()
+    }
+
+    /// Write the history as a JSON array with an object per record, holding the same fields as
+    /// the columns of `to_csv`, a metric missing from a record is `null`
+    pub fn to_json<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let metrics = self.watched_metrics();
+        let records = self
+            .iter()
+            .map(|(epoch, bench)| {
+                let mut record = serde_json::Map::new();
+                record.insert("epoch".to_string(), epoch.into());
+                record.insert("loss".to_string(), bench.loss.into());
+                record.insert("duration".to_string(), bench.duration.as_secs_f64().into());
+                record.insert(
+                    "samples_per_second".to_string(),
+                    bench.samples_per_second().into(),
+                );
+                for &metric in &metrics {
+                    record.insert(metric.to_string(), bench.metrics.get_metric(metric).into());
+                }
+                serde_json::Value::Object(record)
+            })
+            .collect::<Vec<_>>();
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, &records)?;
+        writer.flush()
+    }
 }
 
-#[derive(Clone, PartialEq, Debug, Default)]
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
 pub struct Benchmark {
     pub metrics: Metrics,
-    pub loss: f64,
+    pub loss: Float,
+    /// only built by `Sequential::evaluate_with_confusion_matrix`
+    pub confusion_matrix: Option<ConfusionMatrix>,
+    /// wall-clock time spent on the benchmarked data (training and metrics for an epoch,
+    /// prediction and metrics for an evaluation)
+    pub duration: Duration,
+    /// number of data points processed
+    pub samples: usize,
 }
 
 impl Benchmark {
     pub fn new(metrics: &Vec<MetricsType>) -> Self {
         Self {
             metrics: Metrics::from(metrics),
-            loss: 0f64,
+            loss: 0.0,
+            confusion_matrix: None,
+            duration: Duration::ZERO,
+            samples: 0,
+        }
+    }
+
+    /// Throughput of the benchmarked pass, 0 if no time was recorded
+    pub fn samples_per_second(&self) -> f64 {
+        let seconds = self.duration.as_secs_f64();
+        if seconds == 0.0 {
+            0.0
+        } else {
+            self.samples as f64 / seconds
         }
     }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default, PartialOrd, Ord)]
+#[derive(
+    Copy, Clone, Eq, PartialEq, Hash, Debug, Default, PartialOrd, Ord, Serialize, Deserialize,
+)]
 pub enum MetricsType {
     #[default]
     Accuracy,
-    Recall,
-    Precision,
+    Recall(Averaging),
+    Precision(Averaging),
+    /// harmonic mean of the precision and the recall
+    F1(Averaging),
+    /// fraction of points whose observed class is among the k most probable predicted ones
+    TopK(usize),
+    /// mean absolute error
+    Mae,
+    /// root mean squared error
+    Rmse,
+    /// coefficient of determination, averaged over the outputs
+    R2,
 }
 
-#[derive(Clone, PartialEq, Debug, Default)]
+impl fmt::Display for MetricsType {
+    /// A snake case name, ex: `accuracy`, `recall_macro`, `top_3_accuracy`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let averaging = |averaging: &Averaging| match averaging {
+            Averaging::Macro => "macro",
+            Averaging::Micro => "micro",
+        };
+        match self {
+            Self::Accuracy => write!(f, "accuracy"),
+            Self::Recall(a) => write!(f, "recall_{}", averaging(a)),
+            Self::Precision(a) => write!(f, "precision_{}", averaging(a)),
+            Self::F1(a) => write!(f, "f1_{}", averaging(a)),
+            Self::TopK(k) => write!(f, "top_{}_accuracy", k),
+            Self::Mae => write!(f, "mae"),
+            Self::Rmse => write!(f, "rmse"),
+            Self::R2 => write!(f, "r2"),
+        }
+    }
+}
+
+impl MetricsType {
+    /// Whether the metric compare continuous values rather than classes
+    pub fn is_regression(&self) -> bool {
+        matches!(self, Self::Mae | Self::Rmse | Self::R2)
+    }
+}
+
+/// How the per class values of a multi-class metric are combined
+#[derive(
+    Copy, Clone, Eq, PartialEq, Hash, Debug, Default, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub enum Averaging {
+    /// compute the metric for every class then average them, each class weighting the same
+    #[default]
+    Macro,
+    /// compute the metric over the summed counts of all the classes, each point weighting the same
+    Micro,
+}
+
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
 pub struct Metrics {
-    pub metrics: HashMap<MetricsType, f64>,
+    pub metrics: HashMap<MetricsType, Float>,
+    // precision, recall and f1 can't be averaged over batches, their counts are accumulated
+    #[serde(skip)]
+    confusion_matrix: ConfusionMatrix,
+    // same for the regression metrics, their sums are accumulated
+    #[serde(skip)]
+    regression_sums: RegressionSums,
+}
+
+/// Sums accumulated over the batches for the regression metrics, per output where needed
+#[derive(Clone, PartialEq, Debug, Default)]
+struct RegressionSums {
+    points: usize,
+    absolute_error: Float,
+    squared_error: Vec<Float>,
+    observed: Vec<Float>,
+    observed_squared: Vec<Float>,
+}
+
+impl RegressionSums {
+    fn accumulate(&mut self, predictions: &ArrayD<Float>, observed: &ArrayD<Float>) {
+        let points = observed.shape()[0];
+        let outputs = observed.len() / points.max(1);
+        if self.squared_error.len() != outputs {
+            self.squared_error = vec![0.0; outputs];
+            self.observed = vec![0.0; outputs];
+            self.observed_squared = vec![0.0; outputs];
+        }
+        // both arrays are iterated in logical order, point by point
+        for (i, (&predicted, &observed)) in predictions.iter().zip(observed.iter()).enumerate() {
+            let output = i % outputs;
+            let error = predicted - observed;
+            self.absolute_error += error.abs();
+            self.squared_error[output] += error * error;
+            self.observed[output] += observed;
+            self.observed_squared[output] += observed * observed;
+        }
+        self.points += points;
+    }
+
+    fn values(&self) -> Float {
+        (self.points * self.squared_error.len()).max(1) as Float
+    }
+
+    fn mae(&self) -> Float {
+        self.absolute_error / self.values()
+    }
+
+    fn rmse(&self) -> Float {
+        (self.squared_error.iter().sum::<Float>() / self.values()).sqrt()
+    }
+
+    fn r2(&self) -> Float {
+        if self.squared_error.is_empty() {
+            return 0.0;
+        }
+        let points = self.points as Float;
+        self.squared_error
+            .iter()
+            .zip(self.observed.iter().zip(self.observed_squared.iter()))
+            .map(|(&residual, (&sum, &sum_squared))| {
+                // total sum of squares around the mean, computed from the accumulated sums
+                let total = sum_squared - sum * sum / points;
+                if total <= 0.0 {
+                    // constant observed values, only a perfect prediction explain them
+                    if residual == 0.0 {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                } else {
+                    1.0 - residual / total
+                }
+            })
+            .sum::<Float>()
+            / self.squared_error.len() as Float
+    }
+}
+
+/// Counts of every (observed class, predicted class) pair, `counts[[i, j]]` being the number
+/// of points of class i predicted as class j
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct ConfusionMatrix {
+    counts: Array2<usize>,
+}
+
+impl ConfusionMatrix {
+    pub fn new(number_of_class: usize) -> Self {
+        Self {
+            counts: Array2::zeros((number_of_class, number_of_class)),
+        }
+    }
+
+    /// Count a batch of predictions against the observed values, see `Metrics::accumulate`
+    /// # Arguments
+    /// * `predictions` a batched probability distribution of shape (n, i)
+    /// * `observed` a batched observed values of shape (n, i)
+    pub fn accumulate(&mut self, predictions: &ArrayD<Float>, observed: &ArrayD<Float>) {
+        self.count(
+            &classes(predictions),
+            &classes(observed),
+            number_of_class(predictions),
+        );
+    }
+
+    /// Count a batch of predicted classes against the observed ones, the matrix grows to
+    /// `number_of_class` if needed
+    fn count(&mut self, predicted: &[usize], observed: &[usize], number_of_class: usize) {
+        if self.counts.nrows() < number_of_class {
+            let mut counts = Array2::zeros((number_of_class, number_of_class));
+            let n = self.counts.nrows();
+            counts.slice_mut(s![..n, ..n]).assign(&self.counts);
+            self.counts = counts;
+        }
+        for (&predicted, &observed) in predicted.iter().zip(observed.iter()) {
+            self.counts[[observed, predicted]] += 1;
+        }
+    }
+
+    pub fn counts(&self) -> &Array2<usize> {
+        &self.counts
+    }
+
+    pub fn number_of_class(&self) -> usize {
+        self.counts.nrows()
+    }
+
+    /// Number of points counted
+    pub fn total(&self) -> usize {
+        self.counts.sum()
+    }
+
+    pub fn true_positives(&self, class: usize) -> usize {
+        self.counts[[class, class]]
+    }
+
+    /// Points of another class predicted as `class`
+    pub fn false_positives(&self, class: usize) -> usize {
+        self.counts.column(class).sum() - self.true_positives(class)
+    }
+
+    /// Points of `class` predicted as another class
+    pub fn false_negatives(&self, class: usize) -> usize {
+        self.counts.row(class).sum() - self.true_positives(class)
+    }
+
+    /// Number of points of `class`
+    pub fn support(&self, class: usize) -> usize {
+        self.counts.row(class).sum()
+    }
+
+    /// Fraction of the points predicted as `class` that are of this class, 0 if none are
+    pub fn precision(&self, class: usize) -> Float {
+        ratio(self.true_positives(class), self.false_positives(class))
+    }
+
+    /// Fraction of the points of `class` predicted as this class, 0 if there are none
+    pub fn recall(&self, class: usize) -> Float {
+        ratio(self.true_positives(class), self.false_negatives(class))
+    }
+
+    /// Harmonic mean of the precision and recall of `class`
+    pub fn f1(&self, class: usize) -> Float {
+        ratio(
+            2 * self.true_positives(class),
+            self.false_positives(class) + self.false_negatives(class),
+        )
+    }
+
+    /// Precision of all the classes combined with `averaging`
+    pub fn averaged_precision(&self, averaging: Averaging) -> Float {
+        match averaging {
+            Averaging::Micro => self.micro_average(),
+            Averaging::Macro => self.macro_average(Self::precision),
+        }
+    }
+
+    /// Recall of all the classes combined with `averaging`
+    pub fn averaged_recall(&self, averaging: Averaging) -> Float {
+        match averaging {
+            Averaging::Micro => self.micro_average(),
+            Averaging::Macro => self.macro_average(Self::recall),
+        }
+    }
+
+    /// F1 score of all the classes combined with `averaging`
+    pub fn averaged_f1(&self, averaging: Averaging) -> Float {
+        match averaging {
+            Averaging::Micro => self.micro_average(),
+            Averaging::Macro => self.macro_average(Self::f1),
+        }
+    }
+
+    /// With a single class per point, every error is both a false positive and a false negative,
+    /// so micro averaged precision, recall and f1 are all the fraction of correct predictions
+    fn micro_average(&self) -> Float {
+        let correct = self.counts.diag().sum();
+        ratio(correct, self.total() - correct)
+    }
+
+    /// Mean of `value` over the classes, classes never observed nor predicted are left out
+    fn macro_average(&self, value: impl Fn(&Self, usize) -> Float) -> Float {
+        let seen = (0..self.number_of_class())
+            .filter(|&class| self.support(class) + self.false_positives(class) > 0)
+            .collect::<Vec<_>>();
+        if seen.is_empty() {
+            return 0.0;
+        }
+        seen.iter().map(|&class| value(self, class)).sum::<Float>() / seen.len() as Float
+    }
+}
+
+/// tp / (tp + errors), 0 when both are 0
+fn ratio(true_positives: usize, errors: usize) -> Float {
+    if true_positives + errors == 0 {
+        0.0
+    } else {
+        true_positives as Float / (true_positives + errors) as Float
+    }
+}
+
+impl fmt::Display for ConfusionMatrix {
+    /// A table with a row per observed class and a column per predicted class
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .counts
+            .iter()
+            .chain(std::iter::once(&self.number_of_class()))
+            .max()
+            .map_or(1, |max| max.to_string().len())
+            .max(4);
+        write!(f, "{:>width$}", "true", width = width)?;
+        for class in 0..self.number_of_class() {
+            write!(f, " {:>width$}", class, width = width)?;
+        }
+        writeln!(f)?;
+        for (class, row) in self.counts.rows().into_iter().enumerate() {
+            write!(f, "{:>width$}", class, width = width)?;
+            for count in row {
+                write!(f, " {:>width$}", count, width = width)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Precision, recall, F1 score and support (number of points) of a class, or an average of them
+#[derive(Clone, Copy, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct ClassScores {
+    pub precision: Float,
+    pub recall: Float,
+    pub f1: Float,
+    pub support: usize,
+}
+
+/// Per class scores of a classifier, along with their averages, see `classification_report`
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct ClassificationReport {
+    /// scores of every class, indexed by class
+    pub classes: Vec<ClassScores>,
+    pub accuracy: Float,
+    /// mean of the class scores, see `Averaging::Macro`
+    pub macro_average: ClassScores,
+    /// mean of the class scores weighted by their support
+    pub weighted_average: ClassScores,
+}
+
+impl From<&ConfusionMatrix> for ClassificationReport {
+    fn from(matrix: &ConfusionMatrix) -> Self {
+        let classes = (0..matrix.number_of_class())
+            .map(|class| ClassScores {
+                precision: matrix.precision(class),
+                recall: matrix.recall(class),
+                f1: matrix.f1(class),
+                support: matrix.support(class),
+            })
+            .collect::<Vec<_>>();
+        let total = matrix.total();
+        let weighted = |score: fn(&ClassScores) -> Float| {
+            if total == 0 {
+                return 0.0;
+            }
+            classes
+                .iter()
+                .map(|scores| score(scores) * scores.support as Float)
+                .sum::<Float>()
+                / total as Float
+        };
+        let weighted_average = ClassScores {
+            precision: weighted(|scores| scores.precision),
+            recall: weighted(|scores| scores.recall),
+            f1: weighted(|scores| scores.f1),
+            support: total,
+        };
+        Self {
+            accuracy: matrix.averaged_precision(Averaging::Micro),
+            macro_average: ClassScores {
+                precision: matrix.averaged_precision(Averaging::Macro),
+                recall: matrix.averaged_recall(Averaging::Macro),
+                f1: matrix.averaged_f1(Averaging::Macro),
+                support: total,
+            },
+            weighted_average,
+            classes,
+        }
+    }
+}
+
+impl fmt::Display for ClassificationReport {
+    /// A table in the style of scikit-learn `classification_report`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>12} {:>9} {:>9} {:>9} {:>9}",
+            "", "precision", "recall", "f1-score", "support"
+        )?;
+        writeln!(f)?;
+        for (class, scores) in self.classes.iter().enumerate() {
+            write_scores_row(f, &class.to_string(), scores)?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:>12} {:>9} {:>9} {:>9.4} {:>9}",
+            "accuracy", "", "", self.accuracy, self.macro_average.support
+        )?;
+        write_scores_row(f, "macro avg", &self.macro_average)?;
+        write_scores_row(f, "weighted avg", &self.weighted_average)
+    }
+}
+
+fn write_scores_row(f: &mut fmt::Formatter<'_>, label: &str, scores: &ClassScores) -> fmt::Result {
+    writeln!(
+        f,
+        "{:>12} {:>9.4} {:>9.4} {:>9.4} {:>9}",
+        label, scores.precision, scores.recall, scores.f1, scores.support
+    )
+}
+
+/// Predict `x` with a trained classifier and report its per class precision, recall, F1 score
+/// and support against the observed values `y` (one-hot encoded, or a single 0/1 column for a
+/// binary classifier)
+pub fn classification_report(
+    network: &Sequential,
+    x: &ArrayD<Float>,
+    y: &ArrayD<Float>,
+) -> Result<ClassificationReport, LayerError> {
+    if x.shape()[0] != y.shape()[0] {
+        return Err(LayerError::DimensionMismatch);
+    }
+    let mut matrix = ConfusionMatrix::default();
+    matrix.accumulate(&network.predict(x)?, y);
+    Ok(ClassificationReport::from(&matrix))
+}
+
+/// Fraction of the points of a batch whose observed class is among the `k` highest predictions
+fn top_k_accuracy(predictions: &ArrayD<Float>, observed: &[usize], k: usize) -> Float {
+    let correct_preds = predictions
+        .axis_iter(Axis(0))
+        .zip(observed.iter())
+        .filter(|(prob, &true_label)| {
+            if prob.len() == 1 {
+                // a single output only has two classes
+                return k >= 2 || (prob[0] >= 0.5) as usize == true_label;
+            }
+            // the observed class is in the top k if less than k classes are more probable
+            let true_prob = prob[true_label];
+            prob.iter().filter(|&&p| p > true_prob).count() < k
+        })
+        .count();
+    correct_preds as Float / predictions.shape()[0] as Float
+}
+
+/// Number of classes of a batch, a single output (sigmoid) having two
+fn number_of_class(batch: &ArrayD<Float>) -> usize {
+    batch.shape()[1].max(2)
+}
+
+/// The class of every point of a batch, the most probable one, or for a single output (sigmoid)
+/// 1 if the output is at least 0.5 and 0 otherwise
+pub(crate) fn classes(batch: &ArrayD<Float>) -> Vec<usize> {
+    if batch.shape()[1] == 1 {
+        batch.iter().map(|&p| (p >= 0.5) as usize).collect()
+    } else {
+        batch
+            .map_axis(Axis(1), |prob| prob.argmax().unwrap())
+            .into_iter()
+            .collect()
+    }
 }
 
 impl Metrics {
     fn from(metrics: &Vec<MetricsType>) -> Self {
         let mut map = HashMap::new();
         for el in metrics {
-            map.insert(*el, 0f64);
+            map.insert(*el, 0.0);
+        }
+        Self {
+            metrics: map,
+            confusion_matrix: ConfusionMatrix::default(),
+            regression_sums: RegressionSums::default(),
         }
-        Self { metrics: map }
     }
 
-    pub fn get_all(&self) -> &HashMap<MetricsType, f64> {
+    pub fn get_all(&self) -> &HashMap<MetricsType, Float> {
         &self.metrics
     }
 
-    pub fn get_metric(&self, metric: MetricsType) -> Option<f64> {
+    pub fn get_metric(&self, metric: MetricsType) -> Option<Float> {
         if let Some(metric) = self.metrics.get(&metric) {
             return Some(*metric);
         }
@@ -77,43 +836,90 @@ impl Metrics {
     /// # Arguments
     /// * `predictions` a batched probability distribution of shape (n, i)
     /// * `true_labels` a batched observed values of shape (n, i)
-    pub fn accumulate(&mut self, predictions: &ArrayD<f64>, observed: &ArrayD<f64>) {
+    pub fn accumulate(&mut self, predictions: &ArrayD<Float>, observed: &ArrayD<Float>) {
+        // regression outputs have no class
+        let (pred_classes, true_classes, number_of_class) =
+            if self.metrics.keys().all(MetricsType::is_regression) {
+                (vec![], vec![], 0)
+            } else {
+                (
+                    classes(predictions),
+                    classes(observed),
+                    number_of_class(predictions),
+                )
+            };
+
+        let mut counted = false;
+        let mut summed = false;
         for (metric_type, value) in self.metrics.iter_mut() {
             match metric_type {
                 MetricsType::Accuracy => {
-                    let pred_classes = predictions.map_axis(Axis(1), |prob| prob.argmax().unwrap());
-
-                    let true_classes =
-                        observed.map_axis(Axis(1), |one_hot| one_hot.argmax().unwrap());
-
                     let correct_preds = pred_classes
                         .iter()
                         .zip(true_classes.iter())
                         .filter(|&(pred, true_label)| pred == true_label)
                         .count();
 
-                    let accuracy = correct_preds as f64 / predictions.shape()[0] as f64;
+                    let accuracy = correct_preds as Float / predictions.shape()[0] as Float;
                     *value += accuracy;
                 }
-                MetricsType::Recall => {
-                    todo!()
+                MetricsType::TopK(k) => {
+                    *value += top_k_accuracy(predictions, &true_classes, *k);
+                }
+                MetricsType::Recall(_) | MetricsType::Precision(_) | MetricsType::F1(_) => {
+                    if !counted {
+                        self.confusion_matrix
+                            .count(&pred_classes, &true_classes, number_of_class);
+                        counted = true;
+                    }
                 }
-                MetricsType::Precision => {
-                    todo!()
+                MetricsType::Mae | MetricsType::Rmse | MetricsType::R2 => {
+                    if !summed {
+                        self.regression_sums.accumulate(predictions, observed);
+                        summed = true;
+                    }
                 }
             }
         }
     }
 
+    /// Turn the accumulated value of `metric_type` into its final value, averaging the batch
+    /// values over `number_of_batch`, or computing the counted ones (precision, recall, f1) and
+    /// summed ones (mae, rmse, r2)
     pub fn mean(&mut self, metric_type: MetricsType, number_of_batch: usize) {
-        if let Some(m) = self.metrics.get_mut(&metric_type) {
-            *m /= number_of_batch as f64;
+        if let Some(&accumulated) = self.metrics.get(&metric_type) {
+            let value = self.finalize(metric_type, accumulated, number_of_batch);
+            self.metrics.insert(metric_type, value);
         }
     }
 
+    /// Same as `mean` for all the metrics
     pub fn mean_all(&mut self, number_of_batch: usize) {
-        for (&_, value) in self.metrics.iter_mut() {
-            *value /= number_of_batch as f64;
+        self.metrics = self
+            .metrics
+            .iter()
+            .map(|(&metric_type, &accumulated)| {
+                let value = self.finalize(metric_type, accumulated, number_of_batch);
+                (metric_type, value)
+            })
+            .collect();
+    }
+
+    fn finalize(
+        &self,
+        metric_type: MetricsType,
+        accumulated: Float,
+        number_of_batch: usize,
+    ) -> Float {
+        let confusion_matrix = &self.confusion_matrix;
+        match metric_type {
+            MetricsType::Accuracy | MetricsType::TopK(_) => accumulated / number_of_batch as Float,
+            MetricsType::Recall(averaging) => confusion_matrix.averaged_recall(averaging),
+            MetricsType::Precision(averaging) => confusion_matrix.averaged_precision(averaging),
+            MetricsType::F1(averaging) => confusion_matrix.averaged_f1(averaging),
+            MetricsType::Mae => self.regression_sums.mae(),
+            MetricsType::Rmse => self.regression_sums.rmse(),
+            MetricsType::R2 => self.regression_sums.r2(),
         }
     }
 }
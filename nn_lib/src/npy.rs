@@ -0,0 +1,141 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+};
+
+use ndarray::{ArrayD, IxDyn, ShapeBuilder, ShapeError};
+use thiserror::Error;
+
+use crate::Float;
+
+const MAGIC: &[u8; 6] = b"\x93NUMPY";
+
+/// Read a NumPy `.npy` file, see `read_npy`
+pub fn load_npy<P: AsRef<Path>>(path: P) -> Result<ArrayD<Float>, NpyError> {
+    read_npy(BufReader::new(File::open(path)?))
+}
+
+/// Read every array of a NumPy `.npz` archive (as written by `numpy.savez` or
+/// `numpy.savez_compressed`), keyed by array name
+pub fn load_npz<P: AsRef<Path>>(path: P) -> Result<BTreeMap<String, ArrayD<Float>>, NpyError> {
+    let mut archive = zip::ZipArchive::new(BufReader::new(File::open(path)?))?;
+    let mut arrays = BTreeMap::new();
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)?;
+        let name = file.name().trim_end_matches(".npy").to_string();
+        arrays.insert(name, read_npy(file)?);
+    }
+    Ok(arrays)
+}
+
+/// Read an array in the NumPy `.npy` format, converted to `Float`.
+/// Little endian floating point (`f4`, `f8`) and integer (`i4`, `i8`, `u1`) arrays are supported,
+/// in C or Fortran order, the returned array is always in standard (C) layout.
+pub fn read_npy<R: Read>(mut reader: R) -> Result<ArrayD<Float>, NpyError> {
+    let mut preamble = [0u8; 8];
+    reader.read_exact(&mut preamble)?;
+    if &preamble[..6] != MAGIC {
+        return Err(NpyError::InvalidHeader("missing magic string".to_string()));
+    }
+    let header_len = match preamble[6] {
+        1 => {
+            let mut len = [0u8; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_le_bytes(len) as usize
+        }
+        2 | 3 => {
+            let mut len = [0u8; 4];
+            reader.read_exact(&mut len)?;
+            u32::from_le_bytes(len) as usize
+        }
+        major => return Err(NpyError::UnsupportedVersion(major)),
+    };
+    let mut header = vec![0u8; header_len];
+    reader.read_exact(&mut header)?;
+    let header = String::from_utf8_lossy(&header);
+
+    let descr = header_value(&header, "descr")?.trim_matches(|c| c == '\'' || c == '"');
+    let fortran_order = header_value(&header, "fortran_order")? == "True";
+    let shape = parse_shape(header_value(&header, "shape")?)?;
+
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let values: Vec<Float> = match descr {
+        "<f8" => data
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()) as Float)
+            .collect(),
+        "<f4" => data
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()) as Float)
+            .collect(),
+        "<i8" => data
+            .chunks_exact(8)
+            .map(|b| i64::from_le_bytes(b.try_into().unwrap()) as Float)
+            .collect(),
+        "<i4" => data
+            .chunks_exact(4)
+            .map(|b| i32::from_le_bytes(b.try_into().unwrap()) as Float)
+            .collect(),
+        "|u1" => data.iter().map(|&b| b as Float).collect(),
+        other => return Err(NpyError::UnsupportedType(other.to_string())),
+    };
+
+    let array = if fortran_order {
+        ArrayD::from_shape_vec(IxDyn(&shape).f(), values)?
+    } else {
+        ArrayD::from_shape_vec(IxDyn(&shape), values)?
+    };
+    Ok(array.as_standard_layout().into_owned())
+}
+
+/// Raw value of `key` in the python dictionary literal of a `.npy` header
+fn header_value<'a>(header: &'a str, key: &str) -> Result<&'a str, NpyError> {
+    let missing = || NpyError::InvalidHeader(format!("missing key {}", key));
+    let start = header.find(&format!("'{}':", key)).ok_or_else(missing)? + key.len() + 3;
+    let rest = header[start..].trim_start();
+    // the shape is a tuple, holding commas of its own
+    let end = if rest.starts_with('(') {
+        rest.find(')').map(|end| end + 1)
+    } else {
+        rest.find([',', '}'])
+    }
+    .ok_or_else(missing)?;
+    Ok(rest[..end].trim())
+}
+
+fn parse_shape(shape: &str) -> Result<Vec<usize>, NpyError> {
+    shape
+        .trim_matches(|c| c == '(' || c == ')')
+        .split(',')
+        .map(str::trim)
+        .filter(|dim| !dim.is_empty())
+        .map(|dim| {
+            dim.parse()
+                .map_err(|_| NpyError::InvalidHeader(format!("invalid shape {}", shape)))
+        })
+        .collect()
+}
+
+#[derive(Error, Debug)]
+pub enum NpyError {
+    #[error("Invalid .npy header: {0}")]
+    InvalidHeader(String),
+
+    #[error("Unsupported .npy format version {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("Unsupported array type {0}")]
+    UnsupportedType(String),
+
+    #[error("The array data doesn't match its shape: {0}")]
+    ShapeError(#[from] ShapeError),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Invalid .npz archive: {0}")]
+    Zip(#[from] zip::result::ZipError),
+}
@@ -0,0 +1,466 @@
+use std::{fs, io, path::Path};
+
+use thiserror::Error;
+
+use crate::{
+    activation::{Activation, GELU_CUBIC, GELU_SCALE},
+    layer::{
+        ActivationLayer, AveragePoolingLayer, ConvolutionalLayer, DenseLayer, DropoutLayer,
+        FlattenLayer, GlobalAveragePoolingLayer, Layer, MaxPoolingLayer, PReLULayer, ReshapeLayer,
+    },
+    sequential::Sequential,
+    Float,
+};
+
+const IR_VERSION: i64 = 8;
+const OPSET_VERSION: i64 = 13;
+
+// values of the TensorProto.DataType enum
+const DATA_TYPE_FLOAT: i64 = 1;
+const DATA_TYPE_INT64: i64 = 7;
+
+// values of the AttributeProto.AttributeType enum
+const ATTRIBUTE_FLOAT: i64 = 1;
+const ATTRIBUTE_INT: i64 = 2;
+const ATTRIBUTE_INTS: i64 = 7;
+
+// the layers work on (n, h, w, c) inputs while the ONNX operators expect (n, c, h, w)
+const TO_CHANNELS_FIRST: [i64; 4] = [0, 3, 1, 2];
+const TO_CHANNELS_LAST: [i64; 4] = [0, 2, 3, 1];
+
+const INPUT_NAME: &str = "input";
+
+/// Export a trained network to an ONNX file, to run it with onnxruntime or in a browser.
+///
+/// The graph takes a `float` input named `input` of shape (n, dim i), the same shape given to
+/// `Sequential::predict`, with a dynamic batch size. Dropout layers are left out as they are the
+/// identity at inference time.
+pub fn export<P: AsRef<Path>>(network: &Sequential, path: P) -> Result<(), OnnxError> {
+    fs::write(path, to_bytes(network)?)?;
+    Ok(())
+}
+
+/// Encode a trained network as an ONNX model, see `export`
+pub fn to_bytes(network: &Sequential) -> Result<Vec<u8>, OnnxError> {
+    let layers = network.layers();
+    let input_shape = input_shape(layers).ok_or(OnnxError::UnknownInputShape)?;
+
+    let mut graph = Graph::default();
+    let mut current = INPUT_NAME.to_string();
+    let mut shape = input_shape.clone();
+    for (index, layer) in layers.iter().enumerate() {
+        let any = layer.as_any();
+        if let Some(dense) = any.downcast_ref::<DenseLayer>() {
+            if shape.len() != 1 {
+                current = graph.node("Flatten", &[&current], vec![int_attribute("axis", 1)]);
+            }
+            let weights = dense.weights();
+            let weights_name = graph.float_initializer(
+                &format!("layer_{}_weights", index),
+                weights.shape(),
+                weights.iter().copied(),
+            );
+            let bias_name = graph.float_initializer(
+                &format!("layer_{}_bias", index),
+                &[dense.bias().len()],
+                dense.bias().iter().copied(),
+            );
+            current = graph.node("Gemm", &[&current, &weights_name, &bias_name], vec![]);
+            shape = vec![weights.shape()[1]];
+        } else if let Some(activation) = any.downcast_ref::<ActivationLayer>() {
+            current = match activation.activation {
+                Activation::ReLU => graph.node("Relu", &[&current], vec![]),
+                Activation::Tanh => graph.node("Tanh", &[&current], vec![]),
+                Activation::Sigmoid => graph.node("Sigmoid", &[&current], vec![]),
+                Activation::LeakyReLU(alpha) => graph.node(
+                    "LeakyRelu",
+                    &[&current],
+                    vec![float_attribute("alpha", alpha)],
+                ),
+                Activation::ELU(alpha) => {
+                    graph.node("Elu", &[&current], vec![float_attribute("alpha", alpha)])
+                }
+                Activation::GELU => graph.gelu(index, &current),
+                Activation::SiLU => {
+                    let sigmoid = graph.node("Sigmoid", &[&current], vec![]);
+                    graph.node("Mul", &[&current, &sigmoid], vec![])
+                }
+                Activation::Custom { .. } => return Err(OnnxError::UnsupportedLayer(index)),
+                Activation::Softmax => {
+                    // the softmax is taken over every value of a sample, not only the last axis
+                    let flatten = shape.len() != 1;
+                    let input = if flatten {
+                        graph.node("Flatten", &[&current], vec![int_attribute("axis", 1)])
+                    } else {
+                        current
+                    };
+                    let output = graph.node("Softmax", &[&input], vec![int_attribute("axis", 1)]);
+                    if flatten {
+                        graph.reshape(index, &output, &shape)
+                    } else {
+                        output
+                    }
+                }
+            };
+        } else if let Some(prelu) = any.downcast_ref::<PReLULayer>() {
+            // the slopes broadcast over the batch dimension
+            let alpha = prelu.alpha();
+            let alpha_name = graph.float_initializer(
+                &format!("layer_{}_alpha", index),
+                alpha.shape(),
+                alpha.iter().copied(),
+            );
+            current = graph.node("PRelu", &[&current, &alpha_name], vec![]);
+        } else if let Some(convolutional) = any.downcast_ref::<ConvolutionalLayer>() {
+            let kernels = convolutional.kernels();
+            let (kernel_h, kernel_w, channels, num_kernels) = (
+                kernels.shape()[0],
+                kernels.shape()[1],
+                kernels.shape()[2],
+                kernels.shape()[3],
+            );
+            // the kernels memory is read as (kernels, kernel_h, kernel_w, channels) by the
+            // convolution, see `ConvolutionalLayer::convolve`
+            let kernels = kernels
+                .view()
+                .into_shape((num_kernels, kernel_h, kernel_w, channels))?
+                .permuted_axes([0, 3, 1, 2]);
+            let kernels_name = graph.float_initializer(
+                &format!("layer_{}_kernels", index),
+                &[num_kernels, channels, kernel_h, kernel_w],
+                kernels.iter().copied(),
+            );
+            let (pad_h, pad_w) = convolutional.padding();
+            let (stride_h, stride_w) = convolutional.stride();
+            let attributes = vec![
+                ints_attribute("kernel_shape", &[kernel_h as i64, kernel_w as i64]),
+                ints_attribute(
+                    "pads",
+                    &[pad_h as i64, pad_w as i64, pad_h as i64, pad_w as i64],
+                ),
+                ints_attribute("strides", &[stride_h as i64, stride_w as i64]),
+            ];
+            current = graph.channels_first(&current, "Conv", &[&kernels_name], attributes);
+            let (output_h, output_w, output_c) = convolutional.output_size();
+            shape = vec![output_h, output_w, output_c];
+        } else if let Some(pooling) = any.downcast_ref::<MaxPoolingLayer>() {
+            let attributes = pooling_attributes(pooling.pool_size(), pooling.stride());
+            current = graph.channels_first(&current, "MaxPool", &[], attributes);
+            let (output_h, output_w, output_c) = pooling.output_size();
+            shape = vec![output_h, output_w, output_c];
+        } else if let Some(pooling) = any.downcast_ref::<AveragePoolingLayer>() {
+            let attributes = pooling_attributes(pooling.pool_size(), pooling.stride());
+            current = graph.channels_first(&current, "AveragePool", &[], attributes);
+            let (output_h, output_w, output_c) = pooling.output_size();
+            shape = vec![output_h, output_w, output_c];
+        } else if any.is::<GlobalAveragePoolingLayer>() {
+            let transposed = graph.node(
+                "Transpose",
+                &[&current],
+                vec![ints_attribute("perm", &TO_CHANNELS_FIRST)],
+            );
+            let pooled = graph.node("GlobalAveragePool", &[&transposed], vec![]);
+            current = graph.node("Flatten", &[&pooled], vec![int_attribute("axis", 1)]);
+            shape = vec![shape[shape.len() - 1]];
+        } else if let Some(reshape) = any.downcast_ref::<ReshapeLayer>() {
+            current = graph.reshape(index, &current, reshape.output_shape());
+            shape = reshape.output_shape().to_vec();
+        } else if any.is::<FlattenLayer>() {
+            current = graph.node("Flatten", &[&current], vec![int_attribute("axis", 1)]);
+            shape = vec![shape.iter().product()];
+        } else if any.is::<DropoutLayer>() {
+            continue;
+        } else {
+            return Err(OnnxError::UnsupportedLayer(index));
+        }
+    }
+
+    let mut graph_message = Message::default();
+    for node in &graph.nodes {
+        graph_message.message(1, node);
+    }
+    graph_message.string(2, "sequential");
+    for initializer in &graph.initializers {
+        graph_message.message(5, initializer);
+    }
+    graph_message.message(11, &value_info(INPUT_NAME, &input_shape));
+    graph_message.message(12, &value_info(&current, &shape));
+
+    let mut opset = Message::default();
+    opset.string(1, "").int(2, OPSET_VERSION);
+
+    let mut model = Message::default();
+    model
+        .int(1, IR_VERSION)
+        .string(2, "nn_lib")
+        .string(3, env!("CARGO_PKG_VERSION"))
+        .message(7, &graph_message)
+        .message(8, &opset);
+    Ok(model.buffer)
+}
+
+/// Shape of a single input sample, deduced from the first layer with a known input size
+fn input_shape(layers: &[Box<dyn Layer>]) -> Option<Vec<usize>> {
+    for layer in layers {
+        let any = layer.as_any();
+        if let Some(dense) = any.downcast_ref::<DenseLayer>() {
+            return Some(vec![dense.weights().shape()[0]]);
+        } else if let Some(convolutional) = any.downcast_ref::<ConvolutionalLayer>() {
+            let (input_h, input_w, input_c) = convolutional.input_size();
+            return Some(vec![input_h, input_w, input_c]);
+        } else if let Some(reshape) = any.downcast_ref::<ReshapeLayer>() {
+            return Some(reshape.input_shape().to_vec());
+        } else if let Some(prelu) = any.downcast_ref::<PReLULayer>() {
+            return Some(prelu.alpha().shape().to_vec());
+        } else if !(any.is::<ActivationLayer>() || any.is::<DropoutLayer>()) {
+            // the other layers don't change with the input size
+            return None;
+        }
+    }
+    None
+}
+
+fn pooling_attributes(pool_size: (usize, usize), stride: (usize, usize)) -> Vec<Message> {
+    vec![
+        ints_attribute("kernel_shape", &[pool_size.0 as i64, pool_size.1 as i64]),
+        ints_attribute("strides", &[stride.0 as i64, stride.1 as i64]),
+    ]
+}
+
+/// The nodes and initializers (parameters) of the graph being built
+#[derive(Default)]
+struct Graph {
+    nodes: Vec<Message>,
+    initializers: Vec<Message>,
+}
+
+impl Graph {
+    /// Add a node, returning the name of its output
+    fn node(&mut self, op_type: &str, inputs: &[&str], attributes: Vec<Message>) -> String {
+        let name = format!("{}_{}", op_type.to_lowercase(), self.nodes.len());
+        let mut node = Message::default();
+        for input in inputs {
+            node.string(1, input);
+        }
+        node.string(2, &name).string(3, &name).string(4, op_type);
+        for attribute in &attributes {
+            node.message(5, attribute);
+        }
+        self.nodes.push(node);
+        name
+    }
+
+    /// Add a node working on (n, c, h, w) inputs, transposing `input` before and the output
+    /// after it
+    fn channels_first(
+        &mut self,
+        input: &str,
+        op_type: &str,
+        parameters: &[&str],
+        attributes: Vec<Message>,
+    ) -> String {
+        let transposed = self.node(
+            "Transpose",
+            &[input],
+            vec![ints_attribute("perm", &TO_CHANNELS_FIRST)],
+        );
+        let mut inputs = vec![transposed.as_str()];
+        inputs.extend_from_slice(parameters);
+        let output = self.node(op_type, &inputs, attributes);
+        self.node(
+            "Transpose",
+            &[&output],
+            vec![ints_attribute("perm", &TO_CHANNELS_LAST)],
+        )
+    }
+
+    /// Reshape `input` to (n, `shape`), keeping the batch size
+    fn reshape(&mut self, index: usize, input: &str, shape: &[usize]) -> String {
+        // a 0 in the target shape copy the matching input dimension
+        let target = std::iter::once(0)
+            .chain(shape.iter().map(|&dim| dim as i64))
+            .collect::<Vec<_>>();
+        let shape_name = self.int64_initializer(&format!("layer_{}_shape", index), &target);
+        self.node("Reshape", &[input, &shape_name], vec![])
+    }
+
+    /// The tanh approximation of the GELU of `input`, as `Activation::GELU` (the `Gelu` operator
+    /// needs a newer opset)
+    fn gelu(&mut self, index: usize, input: &str) -> String {
+        let constant = |graph: &mut Self, name: &str, value: Float| {
+            graph.float_initializer(
+                &format!("layer_{}_{}", index, name),
+                &[],
+                std::iter::once(value),
+            )
+        };
+        let half = constant(self, "half", 0.5);
+        let one = constant(self, "one", 1.0);
+        let cubic = constant(self, "cubic", GELU_CUBIC);
+        let scale = constant(self, "scale", GELU_SCALE);
+
+        let square = self.node("Mul", &[input, input], vec![]);
+        let cube = self.node("Mul", &[&square, input], vec![]);
+        let cube = self.node("Mul", &[&cube, &cubic], vec![]);
+        let inner = self.node("Add", &[input, &cube], vec![]);
+        let inner = self.node("Mul", &[&inner, &scale], vec![]);
+        let tanh = self.node("Tanh", &[&inner], vec![]);
+        let tanh = self.node("Add", &[&tanh, &one], vec![]);
+        let half_input = self.node("Mul", &[input, &half], vec![]);
+        self.node("Mul", &[&half_input, &tanh], vec![])
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    fn float_initializer<I: Iterator<Item = Float>>(
+        &mut self,
+        name: &str,
+        dims: &[usize],
+        values: I,
+    ) -> String {
+        let raw_data = values
+            .flat_map(|value| (value as f32).to_le_bytes())
+            .collect::<Vec<_>>();
+        let mut tensor = Message::default();
+        for &dim in dims {
+            tensor.int(1, dim as i64);
+        }
+        tensor
+            .int(2, DATA_TYPE_FLOAT)
+            .string(8, name)
+            .bytes(9, &raw_data);
+        self.initializers.push(tensor);
+        name.to_string()
+    }
+
+    fn int64_initializer(&mut self, name: &str, values: &[i64]) -> String {
+        let raw_data = values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect::<Vec<_>>();
+        let mut tensor = Message::default();
+        tensor
+            .int(1, values.len() as i64)
+            .int(2, DATA_TYPE_INT64)
+            .string(8, name)
+            .bytes(9, &raw_data);
+        self.initializers.push(tensor);
+        name.to_string()
+    }
+}
+
+#[allow(clippy::unnecessary_cast)]
+fn float_attribute(name: &str, value: Float) -> Message {
+    let mut attribute = Message::default();
+    attribute
+        .string(1, name)
+        .float(2, value as f32)
+        .int(20, ATTRIBUTE_FLOAT);
+    attribute
+}
+
+fn int_attribute(name: &str, value: i64) -> Message {
+    let mut attribute = Message::default();
+    attribute
+        .string(1, name)
+        .int(3, value)
+        .int(20, ATTRIBUTE_INT);
+    attribute
+}
+
+fn ints_attribute(name: &str, values: &[i64]) -> Message {
+    let mut attribute = Message::default();
+    attribute.string(1, name);
+    for &value in values {
+        attribute.int(8, value);
+    }
+    attribute.int(20, ATTRIBUTE_INTS);
+    attribute
+}
+
+/// A `float` tensor of shape (n, `shape`), with n the dynamic batch size
+fn value_info(name: &str, shape: &[usize]) -> Message {
+    let mut tensor_shape = Message::default();
+    let mut batch_dim = Message::default();
+    batch_dim.string(2, "n");
+    tensor_shape.message(1, &batch_dim);
+    for &dim in shape {
+        let mut dimension = Message::default();
+        dimension.int(1, dim as i64);
+        tensor_shape.message(1, &dimension);
+    }
+
+    let mut tensor_type = Message::default();
+    tensor_type
+        .int(1, DATA_TYPE_FLOAT)
+        .message(2, &tensor_shape);
+    let mut type_proto = Message::default();
+    type_proto.message(1, &tensor_type);
+
+    let mut value_info = Message::default();
+    value_info.string(1, name).message(2, &type_proto);
+    value_info
+}
+
+/// A protocol buffers message being encoded, only the wire types needed by the ONNX messages
+/// written here are supported (varint and length delimited)
+#[derive(Default)]
+struct Message {
+    buffer: Vec<u8>,
+}
+
+impl Message {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buffer.push((value & 0x7f) as u8 | 0x80);
+            value >>= 7;
+        }
+        self.buffer.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u32) {
+        self.varint(((field << 3) | wire_type) as u64);
+    }
+
+    fn int(&mut self, field: u32, value: i64) -> &mut Self {
+        self.key(field, 0);
+        // negative values are encoded on ten bytes, as their two's complement
+        self.varint(value as u64);
+        self
+    }
+
+    fn float(&mut self, field: u32, value: f32) -> &mut Self {
+        self.key(field, 5);
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn bytes(&mut self, field: u32, value: &[u8]) -> &mut Self {
+        self.key(field, 2);
+        self.varint(value.len() as u64);
+        self.buffer.extend_from_slice(value);
+        self
+    }
+
+    fn string(&mut self, field: u32, value: &str) -> &mut Self {
+        self.bytes(field, value.as_bytes())
+    }
+
+    fn message(&mut self, field: u32, value: &Message) -> &mut Self {
+        self.bytes(field, &value.buffer)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum OnnxError {
+    #[error("Layer {0} has no ONNX counterpart")]
+    UnsupportedLayer(usize),
+
+    #[error("The input shape of the network can't be deduced from its layers")]
+    UnknownInputShape,
+
+    #[error("Error reshaping array: {0}")]
+    ReshapeError(#[from] ndarray::ShapeError),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+}
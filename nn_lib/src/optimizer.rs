@@ -1,33 +1,84 @@
-use crate::layer::Trainable;
+use std::any::Any;
+
+use ndarray::ArrayD;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    layer::{LayerError, Trainable},
+    Float,
+};
 
 pub trait Optimizer: Sync + Send {
-    fn get_learning_rate(&self) -> f64;
-    fn step(&mut self, layer: &mut dyn Trainable);
+    fn get_learning_rate(&self) -> Float;
+    /// Update the parameters of `layer` with the gradients of its last backward pass
+    fn step(&mut self, layer: &mut dyn Trainable) -> Result<(), LayerError>;
+    fn as_any(&self) -> &dyn Any;
 }
 
-#[derive(Clone, Copy, PartialEq, PartialOrd, Debug, Default)]
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug, Default, Serialize, Deserialize)]
 pub struct GradientDescent {
-    learning_rate: f64,
+    learning_rate: Float,
 }
 
 impl GradientDescent {
-    pub fn new(learning_rate: f64) -> Self {
+    pub fn new(learning_rate: Float) -> Self {
         GradientDescent { learning_rate }
     }
 }
 
 impl Optimizer for GradientDescent {
-    fn get_learning_rate(&self) -> f64 {
+    fn get_learning_rate(&self) -> Float {
         self.learning_rate
     }
 
-    fn step(&mut self, layer: &mut dyn Trainable) {
-        let gradients = layer.get_gradients();
+    fn step(&mut self, layer: &mut dyn Trainable) -> Result<(), LayerError> {
+        let gradients = layer.get_gradients()?;
 
         let mut parameters = layer.get_parameters_mut();
 
         for (param, grad) in parameters.iter_mut().zip(gradients.iter()) {
             param.scaled_add(-self.learning_rate, grad);
         }
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Gradient clipping applied to the gradients of every trainable layer before the optimizer step,
+/// to keep an exploding gradient from turning the whole network into NaN
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum GradientClipping {
+    /// clamp every gradient element into [-x, x]
+    Value(Float),
+    /// rescale all the gradients together so that their global L2 norm is at most x
+    Norm(Float),
+}
+
+impl GradientClipping {
+    /// Clip in place `gradients`, the gradients of all the trainable layers of a network
+    pub fn clip(&self, gradients: &mut [&mut ArrayD<Float>]) {
+        match *self {
+            Self::Value(max) => {
+                for gradient in gradients.iter_mut() {
+                    gradient.mapv_inplace(|e| e.clamp(-max, max));
+                }
+            }
+            Self::Norm(max_norm) => {
+                let norm = gradients
+                    .iter()
+                    .map(|gradient| gradient.iter().map(|e| e * e).sum::<Float>())
+                    .sum::<Float>()
+                    .sqrt();
+                if norm > max_norm {
+                    let scale = max_norm / norm;
+                    for gradient in gradients.iter_mut() {
+                        gradient.mapv_inplace(|e| e * scale);
+                    }
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,138 @@
+//! Transforms of the data before the training, and back for the predictions: one hot encoding
+//! of class labels, standardization and min-max scaling of the features.
+//!
+//! The scalers are fitted on the training set only and applied to every set, so that no
+//! statistic of the validation or test data leaks into the training. A feature is an entry of
+//! a data point, every axis but the first one.
+
+use ndarray::{Array2, ArrayD, Axis, IxDyn};
+use serde::{Deserialize, Serialize};
+
+use crate::{layer::LayerError, metrics::classes, Float};
+
+/// One hot encode `labels` into a (labels, `num_classes`) array, the row of a label being 1 at
+/// its class and 0 elsewhere
+///
+/// # Panics
+/// If a label is not less than `num_classes`
+pub fn one_hot_encode<T: Copy + Into<usize>>(
+    labels: &ArrayD<T>,
+    num_classes: usize,
+) -> Array2<Float> {
+    let mut one_hot = Array2::<Float>::zeros((labels.len(), num_classes));
+    for (i, &label) in labels.iter().enumerate() {
+        one_hot[[i, label.into()]] = 1.0;
+    }
+    one_hot
+}
+
+/// The inverse of `one_hot_encode` for the predictions of a classifier: the class of every row
+/// of `predictions`, its most probable one (for a single output, 1 if it is at least 0.5)
+pub fn one_hot_decode(predictions: &ArrayD<Float>) -> Vec<usize> {
+    classes(predictions)
+}
+
+/// Center every feature on 0 with a standard deviation of 1, from the mean and standard
+/// deviation of the data it was fitted on. A constant feature is only centered.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StandardScaler {
+    mean: ArrayD<Float>,
+    std: ArrayD<Float>,
+}
+
+impl StandardScaler {
+    /// The mean and standard deviation of every feature of `x`
+    ///
+    /// # Panics
+    /// If `x` holds no data point
+    pub fn fit(x: &ArrayD<Float>) -> Self {
+        let mean = x.mean_axis(Axis(0)).expect("Can't fit a scaler on no data");
+        let std = x.std_axis(Axis(0), 0.0).mapv(non_zero);
+        Self { mean, std }
+    }
+
+    pub fn transform(&self, x: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        check_features(x, &self.mean)?;
+        Ok((x - &self.mean) / &self.std)
+    }
+
+    /// Back from the scaled values to the original ones, ex: for the predictions of a network
+    /// trained on standardized targets
+    pub fn inverse_transform(&self, x: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        check_features(x, &self.mean)?;
+        Ok(x * &self.std + &self.mean)
+    }
+
+    pub fn fit_transform(x: &ArrayD<Float>) -> (Self, ArrayD<Float>) {
+        let scaler = Self::fit(x);
+        let scaled = (x - &scaler.mean) / &scaler.std;
+        (scaler, scaled)
+    }
+}
+
+/// Scale every feature from [min, max] to [0, 1], either the minimum and maximum of the data it
+/// was fitted on, or a range known in advance shared by all the features (ex: [0, 255] for the
+/// pixels of an image). A constant feature is only shifted to 0.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MinMaxScaler {
+    min: ArrayD<Float>,
+    range: ArrayD<Float>,
+}
+
+impl MinMaxScaler {
+    /// The minimum and maximum of every feature of `x`
+    ///
+    /// # Panics
+    /// If `x` holds no data point
+    pub fn fit(x: &ArrayD<Float>) -> Self {
+        assert!(x.len_of(Axis(0)) > 0, "Can't fit a scaler on no data");
+        let min = x.fold_axis(Axis(0), Float::INFINITY, |&min, &v| min.min(v));
+        let max = x.fold_axis(Axis(0), Float::NEG_INFINITY, |&max, &v| max.max(v));
+        let range = (&max - &min).mapv(non_zero);
+        Self { min, range }
+    }
+
+    /// The same [`min`, `max`] range for all the features
+    pub fn with_range(min: Float, max: Float) -> Self {
+        Self {
+            min: ArrayD::from_elem(IxDyn(&[]), min),
+            range: ArrayD::from_elem(IxDyn(&[]), non_zero(max - min)),
+        }
+    }
+
+    pub fn transform(&self, x: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        check_features(x, &self.min)?;
+        Ok((x - &self.min) / &self.range)
+    }
+
+    /// Back from [0, 1] to the original range of every feature
+    pub fn inverse_transform(&self, x: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        check_features(x, &self.min)?;
+        Ok(x * &self.range + &self.min)
+    }
+
+    pub fn fit_transform(x: &ArrayD<Float>) -> (Self, ArrayD<Float>) {
+        let scaler = Self::fit(x);
+        let scaled = (x - &scaler.min) / &scaler.range;
+        (scaler, scaled)
+    }
+}
+
+/// The divisor of a feature, 1 for a constant one
+fn non_zero(value: Float) -> Float {
+    if value == 0.0 {
+        1.0
+    } else {
+        value
+    }
+}
+
+/// Check that the data points of `x` have the shape of `parameters`, the statistics of one
+/// data point, unless they are shared by all the features
+fn check_features(x: &ArrayD<Float>, parameters: &ArrayD<Float>) -> Result<(), LayerError> {
+    if parameters.ndim() == 0 || x.shape().get(1..) == Some(parameters.shape()) {
+        Ok(())
+    } else {
+        Err(LayerError::DimensionMismatch)
+    }
+}
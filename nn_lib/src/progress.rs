@@ -0,0 +1,107 @@
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::{
+    callback::{BatchLogs, Callback, CallbackAction, EpochLogs},
+    metrics::Benchmark,
+    Float,
+};
+
+/// How much a training report on the terminal, see `SequentialBuilder::verbosity`
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum Verbosity {
+    /// nothing is reported
+    #[default]
+    Silent,
+    /// a summary line (loss and metrics) at the end of every epoch
+    Epoch,
+    /// a progress bar per epoch with the batch progress, running loss and ETA, replaced by the
+    /// epoch summary once finished
+    Progress,
+}
+
+/// A `Callback` reporting the training progress on stderr, according to its `Verbosity`
+pub struct ProgressReporter {
+    verbosity: Verbosity,
+    epochs: usize,
+    bar: Option<ProgressBar>,
+    running_loss: Float,
+}
+
+impl ProgressReporter {
+    pub fn new(verbosity: Verbosity) -> Self {
+        Self {
+            verbosity,
+            epochs: 0,
+            bar: None,
+            running_loss: 0.0,
+        }
+    }
+
+    fn start_epoch(&mut self, logs: &BatchLogs) -> ProgressBar {
+        let bar = ProgressBar::new(logs.number_of_batch as u64);
+        bar.set_style(
+            ProgressStyle::with_template(
+                "{prefix} [{elapsed_precise}] {bar:30} {pos}/{len} ETA {eta} {msg}",
+            )
+            .unwrap(),
+        );
+        bar.set_prefix(format!("epoch {}/{}", logs.epoch + 1, self.epochs));
+        self.running_loss = 0.0;
+        bar
+    }
+}
+
+impl Callback for ProgressReporter {
+    fn on_train_begin(&mut self, epochs: usize) {
+        self.epochs = epochs;
+    }
+
+    fn on_batch_end(&mut self, logs: &BatchLogs) -> CallbackAction {
+        if self.verbosity != Verbosity::Progress {
+            return CallbackAction::Continue;
+        }
+        if logs.batch == 0 || self.bar.is_none() {
+            if let Some(bar) = self.bar.take() {
+                bar.abandon();
+            }
+            self.bar = Some(self.start_epoch(logs));
+        }
+        // mean of the batch losses of the epoch so far
+        self.running_loss += (logs.loss - self.running_loss) / (logs.batch + 1) as Float;
+        if let Some(bar) = &self.bar {
+            bar.set_message(format!("loss {:.4}", self.running_loss));
+            bar.inc(1);
+        }
+        CallbackAction::Continue
+    }
+
+    fn on_epoch_end(&mut self, logs: &EpochLogs) -> CallbackAction {
+        let summary = epoch_summary(logs);
+        match (self.verbosity, self.bar.take()) {
+            (Verbosity::Silent, _) => {}
+            (Verbosity::Progress, Some(bar)) => bar.finish_with_message(summary),
+            _ => eprintln!("epoch {}/{} {}", logs.epoch + 1, logs.epochs, summary),
+        }
+        CallbackAction::Continue
+    }
+}
+
+/// The loss and metrics of an epoch, prefixed by `val_` for the validation ones
+fn epoch_summary(logs: &EpochLogs) -> String {
+    let mut summary = benchmark_summary(logs.train, "");
+    if let Some(validation) = logs.validation {
+        summary.push_str(" - ");
+        summary.push_str(&benchmark_summary(validation, "val_"));
+    }
+    summary
+}
+
+fn benchmark_summary(bench: &Benchmark, prefix: &str) -> String {
+    let mut metrics = bench.metrics.get_all().iter().collect::<Vec<_>>();
+    metrics.sort_by_key(|(metric_type, _)| **metric_type);
+    let mut summary = format!("{}loss {:.4}", prefix, bench.loss);
+    for (metric_type, value) in metrics {
+        summary.push_str(&format!(" {}{:?} {:.4}", prefix, metric_type, value));
+    }
+    summary
+}
@@ -0,0 +1,216 @@
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{debug, warn};
+
+use crate::{
+    callback::{BatchLogs, Callback, CallbackAction, EpochLogs},
+    metrics::MetricsType,
+    Float,
+};
+
+/// Time given to a client to send its request and read the response, so that a stalled client
+/// doesn't hold back the next ones
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, PartialEq, Debug, Default)]
+struct TrainingState {
+    epoch: usize,
+    batch: usize,
+    batch_loss: Float,
+    train_loss: Option<Float>,
+    validation_loss: Option<Float>,
+    train_metrics: HashMap<MetricsType, Float>,
+    validation_metrics: HashMap<MetricsType, Float>,
+    samples_per_second: Float,
+}
+
+/// A `Callback` exposing the training progress on a `/metrics` HTTP endpoint, in the Prometheus
+/// text exposition format, so long training runs can be scraped by standard monitoring tools.
+pub struct PrometheusExporter {
+    state: Arc<Mutex<TrainingState>>,
+    epoch_start: Instant,
+    epoch_samples: usize,
+}
+
+impl PrometheusExporter {
+    /// Bind the HTTP server on `address`, requests are served in a background thread for the
+    /// whole lifetime of the program.
+    pub fn bind<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        debug!(
+            "Serving metrics on http://{}/metrics",
+            listener.local_addr()?
+        );
+
+        let state = Arc::new(Mutex::new(TrainingState::default()));
+        let served = Arc::clone(&state);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Err(e) = serve(stream, &served) {
+                    warn!("Failed to serve metrics request : {}", e);
+                }
+            }
+        });
+
+        Ok(Self {
+            state,
+            epoch_start: Instant::now(),
+            epoch_samples: 0,
+        })
+    }
+}
+
+fn serve(mut stream: TcpStream, state: &Mutex<TrainingState>) -> io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+
+    let (status, body) = if request_line.starts_with("GET /metrics") {
+        ("200 OK", render(&state.lock().unwrap()))
+    } else {
+        ("404 Not Found", String::from("not found\n"))
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// Resident memory of the process in bytes, only available on linux
+fn resident_memory() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(kilobytes * 1024)
+}
+
+fn gauge(output: &mut String, name: &str, help: &str, value: Float) {
+    let _ = writeln!(output, "# HELP {} {}", name, help);
+    let _ = writeln!(output, "# TYPE {} gauge", name);
+    let _ = writeln!(output, "{} {}", name, value);
+}
+
+fn labeled_gauge(
+    output: &mut String,
+    name: &str,
+    help: &str,
+    values: &HashMap<MetricsType, Float>,
+) {
+    if values.is_empty() {
+        return;
+    }
+    let _ = writeln!(output, "# HELP {} {}", name, help);
+    let _ = writeln!(output, "# TYPE {} gauge", name);
+    for (metric_type, value) in values {
+        let _ = writeln!(output, "{}{{metric=\"{:?}\"}} {}", name, metric_type, value);
+    }
+}
+
+fn render(state: &TrainingState) -> String {
+    let mut output = String::new();
+    gauge(
+        &mut output,
+        "nn_epoch",
+        "Current training epoch",
+        state.epoch as Float,
+    );
+    gauge(
+        &mut output,
+        "nn_batch",
+        "Current batch in the epoch",
+        state.batch as Float,
+    );
+    gauge(
+        &mut output,
+        "nn_batch_loss",
+        "Loss of the last batch",
+        state.batch_loss,
+    );
+    if let Some(loss) = state.train_loss {
+        gauge(
+            &mut output,
+            "nn_train_loss",
+            "Train loss of the last epoch",
+            loss,
+        );
+    }
+    if let Some(loss) = state.validation_loss {
+        gauge(
+            &mut output,
+            "nn_validation_loss",
+            "Validation loss of the last epoch",
+            loss,
+        );
+    }
+    labeled_gauge(
+        &mut output,
+        "nn_train_metric",
+        "Train metrics of the last epoch",
+        &state.train_metrics,
+    );
+    labeled_gauge(
+        &mut output,
+        "nn_validation_metric",
+        "Validation metrics of the last epoch",
+        &state.validation_metrics,
+    );
+    gauge(
+        &mut output,
+        "nn_samples_per_second",
+        "Training throughput of the current epoch",
+        state.samples_per_second,
+    );
+    if let Some(memory) = resident_memory() {
+        gauge(
+            &mut output,
+            "nn_resident_memory_bytes",
+            "Resident memory of the process",
+            memory as Float,
+        );
+    }
+    output
+}
+
+impl Callback for PrometheusExporter {
+    fn on_batch_end(&mut self, logs: &BatchLogs) -> CallbackAction {
+        if logs.batch == 0 {
+            self.epoch_start = Instant::now();
+            self.epoch_samples = 0;
+        }
+        self.epoch_samples += logs.batch_size;
+
+        let mut state = self.state.lock().unwrap();
+        state.epoch = logs.epoch;
+        state.batch = logs.batch;
+        state.batch_loss = logs.loss;
+        state.samples_per_second =
+            (self.epoch_samples as f64 / self.epoch_start.elapsed().as_secs_f64()) as Float;
+        CallbackAction::Continue
+    }
+
+    fn on_epoch_end(&mut self, logs: &EpochLogs) -> CallbackAction {
+        let mut state = self.state.lock().unwrap();
+        state.train_loss = Some(logs.train.loss);
+        state.train_metrics = logs.train.metrics.get_all().clone();
+        if let Some(validation) = logs.validation {
+            state.validation_loss = Some(validation.loss);
+            state.validation_metrics = validation.metrics.get_all().clone();
+        }
+        CallbackAction::Continue
+    }
+}
@@ -0,0 +1,30 @@
+use std::sync::Mutex;
+
+use ndarray_rand::rand::{thread_rng, Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// The generator set by `seed`, none until then
+static SEEDED: Mutex<Option<ChaCha8Rng>> = Mutex::new(None);
+
+/// Seed every random draw of the crate that isn't given a generator of its own: weights
+/// initialization, data shuffling, dropout masks and the training generator of the networks
+/// compiled afterward. Two runs seeded alike, with the same calls in the same order, draw the
+/// same values.
+pub fn seed(seed: u64) {
+    *SEEDED.lock().unwrap() = Some(ChaCha8Rng::seed_from_u64(seed));
+}
+
+/// A new generator, drawn from the seeded one if `seed` was called and from the entropy of the
+/// system otherwise. Use it to make draws outside of the crate (ex: data augmentation) follow
+/// the global seed.
+pub fn generator() -> ChaCha8Rng {
+    with_rng(|rng| ChaCha8Rng::seed_from_u64(rng.gen()))
+}
+
+/// Run `f` with the seeded generator if `seed` was called, otherwise with `thread_rng`
+pub(crate) fn with_rng<T>(f: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+    match SEEDED.lock().unwrap().as_mut() {
+        Some(rng) => f(rng),
+        None => f(&mut thread_rng()),
+    }
+}
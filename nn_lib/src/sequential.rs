@@ -1,27 +1,104 @@
 use crate::{
-    activation::Activation,
-    cost::CostFunction,
-    layer::{ActivationLayer, ConvolutionalLayer, DenseLayer, Layer, LayerError},
-    metrics::{Benchmark, History, MetricsType},
-    optimizer::Optimizer,
+    augmentation::BatchAugmentation,
+    backend::Backend,
+    callback::{BatchLogs, Callback, CallbackAction, EpochLogs},
+    config::{ConfigError, NetworkConfig},
+    cost::{Cost, CostFunction},
+    data::{split, ArrayLoader, DataLoader},
+    guard::{GuardedValues, NumericGuard},
+    layer::{check_weights, ActivationLayer, Layer, LayerError, Trainable},
+    metrics::{BatchRecord, Benchmark, ConfusionMatrix, EpochProfile, History, MetricsType},
+    npy::load_npz,
+    optimizer::{GradientClipping, Optimizer},
+    progress::{ProgressReporter, Verbosity},
+    random,
+    serialization::{
+        group_parameters, weights_key, SerializationError, SerializedCheckpoint, SerializedLayer,
+        SerializedModel, SerializedOptimizer, SerializedWeights, CHECKPOINT_FORMAT_VERSION,
+        FORMAT_VERSION, WEIGHTS_FORMAT_VERSION,
+    },
+    Float,
+};
+use log::{debug, warn};
+use ndarray::{concatenate, ArrayD, Axis};
+use ndarray_rand::rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fmt,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
-use log::debug;
-use ndarray::{ArrayD, Axis};
-use ndarray_rand::rand::seq::SliceRandom;
-use ndarray_rand::rand::thread_rng;
 use thiserror::Error;
 
+/// How often the validation data given to `Sequential::train` is evaluated.
+/// A frequency of 0 is treated as 1.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub enum ValidationFrequency {
+    /// Validate at the end of every N epochs, and always after the last one
+    Epochs(usize),
+    /// Validate every N batches, counted across epochs
+    Batches(usize),
+}
+
+impl Default for ValidationFrequency {
+    fn default() -> Self {
+        Self::Epochs(1)
+    }
+}
+
 #[derive(Default)]
 pub struct SequentialBuilder {
     layers: Vec<Box<dyn Layer>>,
+    layer_names: Vec<Option<String>>,
     metrics: Vec<MetricsType>,
+    callbacks: Vec<Box<dyn Callback>>,
+    validation_frequency: ValidationFrequency,
+    gradient_clipping: Option<GradientClipping>,
+    checkpoint: Option<Checkpoint>,
+    verbosity: Verbosity,
+    batch_recording: Option<usize>,
+    class_weights: Option<Vec<Float>>,
+    augmentations: Vec<BatchAugmentation>,
+    max_duration: Option<Duration>,
+    numeric_guard: NumericGuard,
+    seed: Option<u64>,
+    workers: usize,
+    backend: Backend,
+    profiling: bool,
+}
+
+/// Where and how often a training snapshot is written, see `SequentialBuilder::checkpoint`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Checkpoint {
+    pub path: PathBuf,
+    /// `None` to only snapshot the training when it is stopped
+    pub every_epochs: Option<usize>,
 }
 
 impl SequentialBuilder {
     pub fn new() -> SequentialBuilder {
         Self {
             layers: vec![],
+            layer_names: vec![],
             metrics: vec![],
+            callbacks: vec![],
+            validation_frequency: ValidationFrequency::default(),
+            gradient_clipping: None,
+            checkpoint: None,
+            verbosity: Verbosity::default(),
+            batch_recording: None,
+            class_weights: None,
+            augmentations: vec![],
+            max_duration: None,
+            numeric_guard: NumericGuard::default(),
+            seed: None,
+            workers: 1,
+            backend: Backend::Cpu,
+            profiling: false,
         }
     }
 
@@ -29,6 +106,15 @@ impl SequentialBuilder {
     /// in a sequential neural network, layers are added left to right (input -> hidden -> output)
     pub fn push(mut self, layer: impl Layer + 'static) -> Self {
         self.layers.push(Box::new(layer));
+        self.layer_names.push(None);
+        self
+    }
+
+    /// Same as `push`, the layer being retrievable by its `name` from the compiled network, see
+    /// `Sequential::get_layer`. The names must be unique.
+    pub fn push_named<S: Into<String>>(mut self, name: S, layer: impl Layer + 'static) -> Self {
+        self.layers.push(Box::new(layer));
+        self.layer_names.push(Some(name.into()));
         self
     }
 
@@ -46,83 +132,719 @@ impl SequentialBuilder {
         self
     }
 
-    /// Build the neural network.
+    /// Set how often the validation data is evaluated during training, every epoch by default
+    pub fn validation_frequency(mut self, frequency: ValidationFrequency) -> Self {
+        self.validation_frequency = frequency;
+        self
+    }
+
+    /// Clamp every gradient element into [-`max`, `max`] before the optimizer step
+    pub fn clip_value(mut self, max: Float) -> Self {
+        self.gradient_clipping = Some(GradientClipping::Value(max));
+        self
+    }
+
+    /// Rescale the gradients of all the layers so that their global L2 norm is at most `max_norm`
+    /// before the optimizer step
+    pub fn clip_norm(mut self, max_norm: Float) -> Self {
+        self.gradient_clipping = Some(GradientClipping::Norm(max_norm));
+        self
+    }
+
+    /// Snapshot the training state (parameters, optimizer state, random generator, current epoch
+    /// and histories) to `path` every `every_epochs` epochs, as well as after the last one or
+    /// when the training is stopped, see `Sequential::train_from_checkpoint` to resume it.
+    pub fn checkpoint<P: Into<PathBuf>>(mut self, path: P, every_epochs: usize) -> Self {
+        self.checkpoint = Some(Checkpoint {
+            path: path.into(),
+            every_epochs: Some(every_epochs),
+        });
+        self
+    }
+
+    /// Snapshot the training state to `path` only when the training is stopped before its last
+    /// epoch by a callback (ex: interrupted with a `CancellationToken`), so that the work done
+    /// isn't lost, see `checkpoint`
+    pub fn checkpoint_on_stop<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.checkpoint = Some(Checkpoint {
+            path: path.into(),
+            every_epochs: None,
+        });
+        self
+    }
+
+    /// Record the loss of every `every_batches` training batch (counted across epochs) in the
+    /// train history along with the elapsed time, see `History::batches`
+    pub fn record_batches(mut self, every_batches: usize) -> Self {
+        self.batch_recording = Some(every_batches);
+        self
+    }
+
+    /// Weight the training loss of every data point by `class_weights[class]` after its observed
+    /// class, the classes without weight counting for 1, see `Cost::weighted_cost`.
+    /// The loss reported by `evaluate` stays unweighted.
+    pub fn class_weights(mut self, class_weights: Vec<Float>) -> Self {
+        self.class_weights = Some(class_weights);
+        self
+    }
+
+    /// Augment every training batch with `augmentation`, after the augmentations added before,
+    /// see `BatchAugmentation`. The transformations are drawn from the training generator, so a
+    /// seeded training draws the same ones. The evaluations are done on the data as is.
+    pub fn augment(mut self, augmentation: BatchAugmentation) -> Self {
+        self.augmentations.push(augmentation);
+        self
+    }
+
+    /// Stop the training once it ran for `max_duration`, after the batch during which the budget
+    /// is exceeded, `train` returning the histories of the epochs done so far, the last one
+    /// being partial. Ex: to compare networks trained for the same time.
+    pub fn max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Look for NaN and infinite values in the outputs of the layers, the loss and the
+    /// gradients of every training batch, see `NumericGuard`. `NumericGuard::Warn` by default.
+    pub fn numeric_guard(mut self, numeric_guard: NumericGuard) -> Self {
+        self.numeric_guard = numeric_guard;
+        self
+    }
+
+    /// Split every training batch into `workers` parts processed on as many threads, each one
+    /// by its own copy of the layers, the gradients of the parts being averaged before a single
+    /// optimizer step. 1 (the default) trains on the calling thread only. The random draws of
+    /// the copies (ex: dropout masks) come from generators seeded by the one of the network, so
+    /// a seeded training stays reproducible for a given number of workers.
+    pub fn data_parallel(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    /// Compute the matrix products of the dense and convolutional layers on `backend`, the CPU
+    /// by default (ex: `Backend::wgpu()?` with the `wgpu` feature)
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Time the forward and backward passes of every layer, recorded per epoch in the train
+    /// history with an estimate of their floating point operations, see `History::profiles`.
+    /// With `data_parallel`, only the part of the batches processed by the calling thread is
+    /// profiled.
+    pub fn profile(mut self) -> Self {
+        self.profiling = true;
+        self
+    }
+
+    /// Report the training progress on the terminal, silent by default, see `Verbosity`
+    pub fn verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Seed the random generator shuffling the training data, drawn from the crate generator
+    /// otherwise (see `random::seed`, which also seed the weights initialization)
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Add a callback that will be notified during training, see `Callback`
+    pub fn callback(mut self, callback: impl Callback + 'static) -> Self {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// Build the neural network, with any cost function implementing `Cost` (ex: one of the
+    /// built-in `CostFunction`).
     /// Returns a `NeuralNetworkError` if the network is wrongly defined.
     /// See `NeuralNetworkError` for information on what can fail.
     pub fn compile(
-        self,
+        mut self,
         optimizer: impl Optimizer + 'static,
-        cost_function: CostFunction,
+        cost_function: impl Cost + 'static,
     ) -> Result<Sequential, NeuralNetworkError> {
-        // Check if the cost function is compatible with the last layer's activation function
+        // Check if the cost function is given clamped output by a last activation layer
         if cost_function.is_output_dependant() {
-            self.validate_last_layer_activation(&cost_function)?;
+            self.validate_last_layer_activation()?;
+        }
+        self.validate_layer_shapes()?;
+        self.validate_layer_names()?;
+
+        if self.verbosity != Verbosity::Silent {
+            self.callbacks
+                .insert(0, Box::new(ProgressReporter::new(self.verbosity)));
+        }
+        for layer in self.layers.iter_mut() {
+            layer.set_backend(&self.backend);
         }
 
         Ok(Sequential {
             layers: self.layers,
-            cost_function,
+            layer_names: self.layer_names,
+            cost_function: Box::new(cost_function),
             optimizer: Box::new(optimizer),
             metrics: self.metrics,
+            callbacks: self.callbacks,
+            validation_frequency: self.validation_frequency,
+            gradient_clipping: self.gradient_clipping,
+            checkpoint: self.checkpoint,
+            batch_recording: self.batch_recording,
+            class_weights: self.class_weights,
+            augmentations: self.augmentations,
+            max_duration: self.max_duration,
+            numeric_guard: self.numeric_guard,
+            workers: self.workers.max(1),
+            replicas: vec![],
+            backend: self.backend,
+            profiling: self.profiling,
+            rng: self
+                .seed
+                .map_or_else(random::generator, ChaCha8Rng::seed_from_u64),
         })
     }
 
-    /// Validates that the last layer's activation function is compatible with the given cost function.
-    fn validate_last_layer_activation(
-        &self,
-        cost_function: &CostFunction,
-    ) -> Result<(), NeuralNetworkError> {
-        self.layers
-            .last()
-            .and_then(|layer| layer.as_any().downcast_ref::<ActivationLayer>())
-            .map_or(
-                Err(NeuralNetworkError::MissingActivationLayer),
-                |activation_layer| match cost_function {
-                    CostFunction::Mse => Ok(()),
-                    CostFunction::CrossEntropy
-                        if activation_layer.activation == Activation::Softmax =>
-                    {
-                        Ok(())
-                    }
-                    CostFunction::BinaryCrossEntropy
-                        if activation_layer.activation == Activation::Sigmoid =>
-                    {
-                        Ok(())
-                    }
-                    _ => Err(NeuralNetworkError::WrongOutputActivationLayer),
-                },
-            )
+    /// Validates that the last layer is an activation layer.
+    /// Any activation is accepted, the one matching the cost function only making the gradient
+    /// computation cheaper, see `Cost::fused_activation`.
+    fn validate_last_layer_activation(&self) -> Result<(), NeuralNetworkError> {
+        match self.layers.last() {
+            Some(layer) if layer.as_any().is::<ActivationLayer>() => Ok(()),
+            _ => Err(NeuralNetworkError::MissingActivationLayer),
+        }
+    }
+
+    /// Validates that every layer can take the output of the previous one.
+    /// The shapes are followed from the first layer built for a given input shape (see
+    /// `Layer::expected_input_shape`), the layers before it taking any shape.
+    fn validate_layer_shapes(&self) -> Result<(), NeuralNetworkError> {
+        let mut shape: Option<Vec<usize>> = None;
+        for (index, layer) in self.layers.iter().enumerate() {
+            let input_shape = match shape.take().or_else(|| layer.expected_input_shape()) {
+                Some(input_shape) => input_shape,
+                None => continue,
+            };
+            shape = Some(layer.output_shape(&input_shape).map_err(|_| {
+                NeuralNetworkError::IncompatibleLayer {
+                    index,
+                    layer_type: layer.type_name(),
+                    input_shape,
+                }
+            })?);
+        }
+        Ok(())
+    }
+
+    /// Validates that no two layers were pushed with the same name
+    fn validate_layer_names(&self) -> Result<(), NeuralNetworkError> {
+        let mut names = HashSet::new();
+        for name in self.layer_names.iter().flatten() {
+            if !names.insert(name) {
+                return Err(NeuralNetworkError::DuplicateLayerName(name.clone()));
+            }
+        }
+        Ok(())
     }
 }
 
 /// a trainable `NeuralNetwork`
 /// # Fields
 /// * `layers` - A vector of layers (could be activation, convolutional, dense, etc..) in
-/// sequential order
-/// note that this crate dont use autodiff, so if you are planning to use a neural net architecture
-/// with cross entropy, or binary cross entropy, the network make and use the assumption of
-/// softmax, and sigmoid activation function respectively just before the cost function.
-/// Thus you don't need to include it in the layers. However if you use any kind of independent
-/// cost function (like mse) you can include whatever activation function you want after the
-/// output because the gradient calculation is independent of the last layer you choose.
-/// * cost_function - TODO
+///   sequential order
+/// * `layer_names` - the optional name of every layer, see `SequentialBuilder::push_named`
+///
+/// note that this crate dont use autodiff, the gradient of the cost function is backpropagated
+/// through every layer, whatever the output activation. If the last layer is the activation
+/// fused with the cost function (see `Cost::fused_activation`, ex: softmax for the cross
+/// entropy, sigmoid for the binary cross entropy), the gradient with respect to its input is
+/// computed at once by `Cost::fused_output_gradient`, cheaper and more stable, and the backward
+/// pass skips that layer. With any other last layer, the gradient with respect to the output of
+/// the network (`Cost::cost_output_gradient`) is propagated back through the last activation
+/// too. The cost functions expecting probabilities (like the cross entropies) still need an
+/// activation layer at the end of the network, see `Cost::is_output_dependant`.
+///
+/// * cost_function - the loss minimized by the training, see `Cost`
 /// * optimoizer - TODO
+/// * callbacks - notified at the end of every batch and epoch during training
+/// * validation_frequency - how often the validation data is evaluated during training
+/// * gradient_clipping - optional clipping of the gradients before the optimizer step
+/// * checkpoint - optional periodic snapshot of the training state
+/// * batch_recording - optional frequency, in batches, of the batch records of the train history
+/// * class_weights - optional weights of the training loss by observed class
+/// * augmentations - applied to every training batch, see `SequentialBuilder::augment`
+/// * max_duration - optional time budget of a training, see `SequentialBuilder::max_duration`
+/// * numeric_guard - check of the training values for NaN and infinities, see
+///   `SequentialBuilder::numeric_guard`
+/// * workers - number of threads sharing every training batch, see
+///   `SequentialBuilder::data_parallel`
+/// * replicas - copies of the layers used by the `workers - 1` helping threads, kept in sync
+///   with the layers after every optimizer step
+/// * backend - device computing the matrix products of the layers
+/// * profiling - whether the layers are timed during training, see `SequentialBuilder::profile`
+/// * rng - random generator used to shuffle the training data, saved in the checkpoints
 pub struct Sequential {
     layers: Vec<Box<dyn Layer>>,
-    cost_function: CostFunction,
+    layer_names: Vec<Option<String>>,
+    cost_function: Box<dyn Cost>,
     optimizer: Box<dyn Optimizer>,
     metrics: Vec<MetricsType>,
+    callbacks: Vec<Box<dyn Callback>>,
+    validation_frequency: ValidationFrequency,
+    gradient_clipping: Option<GradientClipping>,
+    checkpoint: Option<Checkpoint>,
+    batch_recording: Option<usize>,
+    class_weights: Option<Vec<Float>>,
+    augmentations: Vec<BatchAugmentation>,
+    max_duration: Option<Duration>,
+    numeric_guard: NumericGuard,
+    workers: usize,
+    replicas: Vec<Vec<Box<dyn Layer>>>,
+    backend: Backend,
+    profiling: bool,
+    rng: ChaCha8Rng,
 }
 
 impl Sequential {
+    /// Add a callback to an already compiled network, see `SequentialBuilder::callback`
+    pub fn add_callback(&mut self, callback: impl Callback + 'static) {
+        self.callbacks.push(Box::new(callback));
+    }
+
+    /// Remove the callbacks of the network, ex: before training it again once those of the
+    /// previous training are done
+    pub fn clear_callbacks(&mut self) {
+        self.callbacks.clear();
+    }
+
+    /// Set the training checkpoint of an already compiled network, see
+    /// `SequentialBuilder::checkpoint`
+    pub fn set_checkpoint<P: Into<PathBuf>>(&mut self, path: P, every_epochs: usize) {
+        self.checkpoint = Some(Checkpoint {
+            path: path.into(),
+            every_epochs: Some(every_epochs),
+        });
+    }
+
+    /// Snapshot the training state of an already compiled network only when the training is
+    /// stopped, see `SequentialBuilder::checkpoint_on_stop`
+    pub fn set_checkpoint_on_stop<P: Into<PathBuf>>(&mut self, path: P) {
+        self.checkpoint = Some(Checkpoint {
+            path: path.into(),
+            every_epochs: None,
+        });
+    }
+
+    /// Record the batch losses of an already compiled network, see
+    /// `SequentialBuilder::record_batches`
+    pub fn set_batch_recording(&mut self, every_batches: Option<usize>) {
+        self.batch_recording = every_batches;
+    }
+
+    /// Weight the training loss by class on an already compiled network, see
+    /// `SequentialBuilder::class_weights`
+    pub fn set_class_weights(&mut self, class_weights: Option<Vec<Float>>) {
+        self.class_weights = class_weights;
+    }
+
+    /// Replace the augmentations of the training batches of an already compiled network, see
+    /// `SequentialBuilder::augment`
+    pub fn set_augmentations(&mut self, augmentations: Vec<BatchAugmentation>) {
+        self.augmentations = augmentations;
+    }
+
+    /// Set or remove the time budget of the trainings of an already compiled network, see
+    /// `SequentialBuilder::max_duration`
+    pub fn set_max_duration(&mut self, max_duration: Option<Duration>) {
+        self.max_duration = max_duration;
+    }
+
+    /// Set how the trainings of an already compiled network handle NaN and infinite values, see
+    /// `SequentialBuilder::numeric_guard`
+    pub fn set_numeric_guard(&mut self, numeric_guard: NumericGuard) {
+        self.numeric_guard = numeric_guard;
+    }
+
+    /// Split the training batches of an already compiled network between threads, see
+    /// `SequentialBuilder::data_parallel`
+    pub fn set_data_parallel(&mut self, workers: usize) {
+        self.workers = workers.max(1);
+    }
+
+    /// Enable or disable the profiling of the layers of an already compiled network, see
+    /// `SequentialBuilder::profile`
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profiling = enabled;
+    }
+
+    /// Compute the matrix products of an already compiled (or loaded) network on `backend`, see
+    /// `SequentialBuilder::backend`
+    pub fn set_backend(&mut self, backend: Backend) {
+        for layer in self.layers.iter_mut() {
+            layer.set_backend(&backend);
+        }
+        self.backend = backend;
+    }
+
+    /// The layers of the network, in feed forward order
+    pub fn layers(&self) -> &[Box<dyn Layer>] {
+        &self.layers
+    }
+
+    /// The optimizer updating the parameters during training
+    pub fn optimizer(&self) -> &dyn Optimizer {
+        self.optimizer.as_ref()
+    }
+
+    /// The layer pushed with `name`, see `SequentialBuilder::push_named`
+    pub fn get_layer(&self, name: &str) -> Option<&dyn Layer> {
+        self.layer_index(name)
+            .map(|index| self.layers[index].as_ref())
+    }
+
+    /// Same as `get_layer`, mutably (ex: to overwrite the weights of a layer)
+    pub fn get_layer_mut(&mut self, name: &str) -> Option<&mut dyn Layer> {
+        let index = self.layer_index(name)?;
+        Some(self.layers[index].as_mut())
+    }
+
+    /// The name of the layer at `index`, if it was pushed with one
+    pub fn layer_name(&self, index: usize) -> Option<&str> {
+        self.layer_names.get(index)?.as_deref()
+    }
+
+    fn layer_index(&self, name: &str) -> Option<usize> {
+        self.layer_names
+            .iter()
+            .position(|layer_name| layer_name.as_deref() == Some(name))
+    }
+
+    /// The type, output shape and number of parameters of every layer, given the shape of a
+    /// single input of the network (without the batch dimension), printed as a table with
+    /// `println!("{}", network.summary(&[28, 28, 1])?)`.
+    /// Returns `LayerError::DimensionMismatch` if a layer can't take the output of the previous
+    /// one.
+    pub fn summary(&self, input_shape: &[usize]) -> Result<ModelSummary, LayerError> {
+        let mut shape = input_shape.to_vec();
+        let mut layers = Vec::with_capacity(self.layers.len());
+        for layer in self.layers.iter() {
+            shape = layer.output_shape(&shape)?;
+            layers.push(LayerSummary {
+                name: self.layer_name(layers.len()).map(str::to_string),
+                layer_type: layer.type_name(),
+                output_shape: shape.clone(),
+                parameters: layer.as_trainable().map_or(0, |layer| {
+                    layer.get_parameters().iter().map(|p| p.len()).sum()
+                }),
+            });
+        }
+        Ok(ModelSummary {
+            input_shape: input_shape.to_vec(),
+            layers,
+        })
+    }
+
+    /// Save the network architecture, its parameters, the optimizer and the cost function
+    /// into a binary file at `path`. The callbacks are not saved, and the cost function must be
+    /// one of the built-in `CostFunction`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SerializationError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        bincode::serialize_into(&mut writer, &FORMAT_VERSION)?;
+        bincode::serialize_into(&mut writer, &self.to_serialized()?)?;
+        Ok(())
+    }
+
+    /// Load a network saved with `save`, ready for prediction or further training
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SerializationError> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Same as `load`, from the content of a file written by `save`, ex: a network bundled in
+    /// a binary with `include_bytes!`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SerializationError> {
+        Self::from_reader(bytes)
+    }
+
+    fn from_reader<R: Read>(mut reader: R) -> Result<Self, SerializationError> {
+        // the version is read first, the rest of the file may not be decodable otherwise
+        let version: u32 = bincode::deserialize_from(&mut reader)?;
+        if version != FORMAT_VERSION {
+            return Err(SerializationError::UnsupportedVersion(version));
+        }
+        let model: SerializedModel = bincode::deserialize_from(reader)?;
+        Ok(Self::from_serialized(model))
+    }
+
+    /// Build a new network from the layers, optimizer, cost function, metrics and training
+    /// settings of a TOML, YAML or JSON file, see `NetworkConfig`. The number of epochs and the
+    /// batch size of the file are read with `NetworkConfig::from_file`.
+    pub fn from_config<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        NetworkConfig::from_file(path)?.build()
+    }
+
+    fn to_serialized(&self) -> Result<SerializedModel, SerializationError> {
+        Ok(SerializedModel {
+            layers: self
+                .layers
+                .iter()
+                .enumerate()
+                .map(|(i, layer)| SerializedLayer::from_layer(i, layer.as_ref()))
+                .collect::<Result<Vec<_>, _>>()?,
+            layer_names: self.layer_names.clone(),
+            cost_function: *self
+                .cost_function
+                .as_any()
+                .downcast_ref::<CostFunction>()
+                .ok_or(SerializationError::UnsupportedCostFunction)?,
+            optimizer: SerializedOptimizer::from_optimizer(self.optimizer.as_ref())?,
+            metrics: self.metrics.clone(),
+            validation_frequency: self.validation_frequency,
+            gradient_clipping: self.gradient_clipping,
+        })
+    }
+
+    fn from_serialized(model: SerializedModel) -> Self {
+        Self {
+            layers: model
+                .layers
+                .into_iter()
+                .map(SerializedLayer::into_layer)
+                .collect(),
+            layer_names: model.layer_names,
+            cost_function: Box::new(model.cost_function),
+            optimizer: model.optimizer.into_optimizer(),
+            metrics: model.metrics,
+            callbacks: vec![],
+            validation_frequency: model.validation_frequency,
+            gradient_clipping: model.gradient_clipping,
+            checkpoint: None,
+            batch_recording: None,
+            class_weights: None,
+            augmentations: vec![],
+            max_duration: None,
+            numeric_guard: NumericGuard::default(),
+            workers: 1,
+            replicas: vec![],
+            backend: Backend::Cpu,
+            profiling: false,
+            rng: random::generator(),
+        }
+    }
+
+    /// Write a snapshot of the training state after `epoch` to the checkpoint path, through a
+    /// temporary file so that a crash while writing never corrupt the previous snapshot
+    fn write_checkpoint(
+        &self,
+        path: &Path,
+        epoch: usize,
+        train_history: &History,
+        validation_history: &Option<History>,
+    ) -> Result<(), SerializationError> {
+        let checkpoint = SerializedCheckpoint {
+            model: self.to_serialized()?,
+            rng: self.rng.clone(),
+            epoch,
+            train_history: train_history.clone(),
+            validation_history: validation_history.clone(),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let temporary = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&temporary)?);
+            bincode::serialize_into(&mut writer, &CHECKPOINT_FORMAT_VERSION)?;
+            bincode::serialize_into(&mut writer, &checkpoint)?;
+        }
+        fs::rename(&temporary, path)?;
+        debug!("Checkpoint written to {:?} after epoch {}", path, epoch);
+        Ok(())
+    }
+
+    /// Resume a training from a snapshot written by the checkpointing (see
+    /// `SequentialBuilder::checkpoint`). The layers, optimizer state, random generator and
+    /// histories are restored from the file, the callbacks, checkpoint settings, class weights and
+    /// batch augmentations of this network are kept. The training continue from the epoch
+    /// following the snapshot up to `epochs`, the returned histories include the epochs before
+    /// the snapshot.
+    ///
+    /// `train_data` and `validation_data` must be the ones of the interrupted training.
+    pub fn train_from_checkpoint<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        train_data: (&ArrayD<Float>, &ArrayD<Float>),
+        validation_data: Option<(&ArrayD<Float>, &ArrayD<Float>)>,
+        epochs: usize,
+        batch_size: usize,
+    ) -> Result<(History, Option<History>), CheckpointError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let version: u32 =
+            bincode::deserialize_from(&mut reader).map_err(SerializationError::from)?;
+        if version != CHECKPOINT_FORMAT_VERSION {
+            return Err(SerializationError::UnsupportedVersion(version).into());
+        }
+        let checkpoint: SerializedCheckpoint =
+            bincode::deserialize_from(reader).map_err(SerializationError::from)?;
+
+        let restored = Self::from_serialized(checkpoint.model);
+        self.layers = restored.layers;
+        self.layer_names = restored.layer_names;
+        self.cost_function = restored.cost_function;
+        self.optimizer = restored.optimizer;
+        self.metrics = restored.metrics;
+        self.validation_frequency = restored.validation_frequency;
+        self.gradient_clipping = restored.gradient_clipping;
+        self.rng = checkpoint.rng;
+        // the restored layers are on the CPU
+        self.set_backend(self.backend.clone());
+        debug!("Resuming the training after epoch {}", checkpoint.epoch);
+
+        let validation_history = match validation_data {
+            Some(_) => Some(checkpoint.validation_history.unwrap_or_default()),
+            None => None,
+        };
+        let (x_train, y_train) = train_data;
+        let mut loader = ArrayLoader::with_rng(x_train, y_train, batch_size, &mut self.rng)?;
+        Ok(self.train_epochs(
+            checkpoint.epoch + 1,
+            &mut loader,
+            validation_data,
+            (checkpoint.train_history, validation_history),
+            epochs,
+            batch_size,
+        )?)
+    }
+
+    /// Save only the parameters of the trainable layers into a binary file at `path`, keyed by
+    /// layer index, see `load_weights`
+    pub fn save_weights<P: AsRef<Path>>(&self, path: P) -> Result<(), SerializationError> {
+        let weights: SerializedWeights = self
+            .layers
+            .iter()
+            .enumerate()
+            .filter_map(|(i, layer)| {
+                let trainable = layer.as_trainable()?;
+                Some((weights_key(i), trainable.get_parameters()))
+            })
+            .collect();
+        let mut writer = BufWriter::new(File::create(path)?);
+        bincode::serialize_into(&mut writer, &WEIGHTS_FORMAT_VERSION)?;
+        bincode::serialize_into(&mut writer, &weights)?;
+        Ok(())
+    }
+
+    /// Load parameters saved with `save_weights` into this network, whose architecture may
+    /// differ from the saved one: the trainable layers without saved weights keep their current
+    /// parameters (ex: a new classification head on a pretrained network), but every saved entry
+    /// must match a trainable layer at the same index with the same parameter shapes.
+    pub fn load_weights<P: AsRef<Path>>(&mut self, path: P) -> Result<(), SerializationError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let version: u32 = bincode::deserialize_from(&mut reader)?;
+        if version != WEIGHTS_FORMAT_VERSION {
+            return Err(SerializationError::UnsupportedVersion(version));
+        }
+        let weights: SerializedWeights = bincode::deserialize_from(reader)?;
+        self.assign_weights(weights)
+    }
+
+    /// Load parameters from a NumPy `.npz` archive (ex: exported from a PyTorch or Keras model)
+    /// into this network. Every array is named after the layer and the position of the parameter
+    /// in `Trainable::get_parameters`: `layer_{index}_{parameter}`, ex: `layer_0_0` for the
+    /// weights of a first dense layer and `layer_0_1` for its bias. The arrays must already be in
+    /// the layout of this crate (ex: dense weights as (input, output), where PyTorch store them
+    /// as (output, input)), the same rules as `load_weights` apply otherwise.
+    pub fn load_weights_from_npz<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<(), SerializationError> {
+        let weights = group_parameters(load_npz(path)?)?;
+        self.assign_weights(weights)
+    }
+
+    fn assign_weights(&mut self, mut weights: SerializedWeights) -> Result<(), SerializationError> {
+        // everything is checked before any parameter is replaced, so that a failed load
+        // leave the network untouched
+        let trainable_keys = self
+            .layers
+            .iter()
+            .enumerate()
+            .filter(|(_, layer)| layer.as_trainable().is_some())
+            .map(|(i, _)| weights_key(i))
+            .collect::<Vec<_>>();
+        if let Some(unknown) = weights.keys().find(|key| !trainable_keys.contains(*key)) {
+            return Err(SerializationError::UnknownWeights(unknown.clone()));
+        }
+        for (i, layer) in self.layers.iter().enumerate() {
+            let (Some(trainable), Some(saved)) =
+                (layer.as_trainable(), weights.get(&weights_key(i)))
+            else {
+                continue;
+            };
+            let parameters = trainable.get_parameters();
+            if parameters.len() != saved.len()
+                || parameters
+                    .iter()
+                    .zip(saved.iter())
+                    .any(|(parameter, saved)| parameter.shape() != saved.shape())
+            {
+                return Err(SerializationError::ShapeMismatch(weights_key(i)));
+            }
+        }
+
+        for (i, layer) in self.layers.iter_mut().enumerate() {
+            let Some(trainable) = layer.as_trainable_mut() else {
+                continue;
+            };
+            let key = weights_key(i);
+            let Some(saved) = weights.remove(&key) else {
+                warn!("No saved weights for {}, keeping its parameters", key);
+                continue;
+            };
+            for (parameter, saved) in trainable.get_parameters_mut().into_iter().zip(saved) {
+                *parameter = saved;
+            }
+        }
+        Ok(())
+    }
+
+    /// The parameters of every layer, see `Layer::get_weights`, an empty entry standing for a
+    /// layer without parameters
+    pub fn get_all_weights(&self) -> Vec<Vec<ArrayD<Float>>> {
+        self.layers
+            .iter()
+            .map(|layer| layer.get_weights())
+            .collect()
+    }
+
+    /// Overwrite the parameters of every layer with `weights`, in the layout of
+    /// `get_all_weights` (ex: the average of the weights of several trained networks).
+    /// Returns `LayerError::DimensionMismatch`, leaving the network untouched, if the weights
+    /// don't match the parameters of the layers.
+    pub fn set_all_weights(&mut self, weights: Vec<Vec<ArrayD<Float>>>) -> Result<(), LayerError> {
+        if weights.len() != self.layers.len() {
+            return Err(LayerError::DimensionMismatch);
+        }
+        for (layer, layer_weights) in self.layers.iter().zip(weights.iter()) {
+            check_weights(&layer.get_weights(), layer_weights)?;
+        }
+        for (layer, layer_weights) in self.layers.iter_mut().zip(weights) {
+            layer.set_weights(layer_weights)?;
+        }
+        Ok(())
+    }
+
     /// predict a value from the neural network
     /// the shape of the prediction is (n, dim o) where **dim o** is the dimension of the network
     /// last layer and **n** is the number of point in the batch.
     ///
     /// # Arguments
     /// * `input` : batched input, of size (n, dim i) where **dim i** is the dimension of the
-    /// network first layer and **n** is the number of point in the batch.
-    pub fn predict(&self, input: &ArrayD<f64>) -> Result<ArrayD<f64>, LayerError> {
+    ///   network first layer and **n** is the number of point in the batch.
+    ///
+    /// Returns `LayerError::InputShapeMismatch` if the data points of `input` don't have the
+    /// shape the layers expect.
+    pub fn predict(&self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        self.check_input_shape(input)?;
         let mut output = input.clone();
         for layer in &self.layers {
             output = layer.feed_forward(&output)?;
@@ -130,6 +852,41 @@ impl Sequential {
         Ok(output)
     }
 
+    /// Check the shape of the data points of `input` against the first layer expecting a shape,
+    /// the layers before it (ex: an activation) being given the shape they output
+    fn check_input_shape(&self, input: &ArrayD<Float>) -> Result<(), LayerError> {
+        let mut shape = input.shape().get(1..).unwrap_or_default().to_vec();
+        for layer in &self.layers {
+            if let Some(expected) = layer.expected_input_shape() {
+                if expected != shape {
+                    return Err(LayerError::InputShapeMismatch {
+                        expected,
+                        actual: shape,
+                    });
+                }
+                break;
+            }
+            shape = layer.output_shape(&shape)?;
+        }
+        Ok(())
+    }
+
+    /// The output of every layer for `input`, in the order of the layers, the last one being the
+    /// prediction. Ex: to look at the feature maps of the convolutional layers
+    pub fn predict_with_activations(
+        &self,
+        input: &ArrayD<Float>,
+    ) -> Result<Vec<ArrayD<Float>>, LayerError> {
+        self.check_input_shape(input)?;
+        let mut activations: Vec<ArrayD<Float>> = Vec::with_capacity(self.layers.len());
+        let mut output = input.clone();
+        for layer in &self.layers {
+            output = layer.feed_forward(&output)?;
+            activations.push(output.clone());
+        }
+        Ok(activations)
+    }
+
     /// Evaluate the **trained** neural network on a test input and observed values.
     /// returning a `Benchmark` containing the error on the test set, along with the metrics
     /// provided
@@ -138,160 +895,741 @@ impl Sequential {
     /// * `test_data` test data set, the outer dimension must contain the data
     /// * `metrics` optional metrics struct
     /// * `batch_size` the batch size, ie: number of data point treated simultaneously
+    ///
+    /// Returns `LayerError::DimensionMismatch` if the inputs and observed values don't have the
+    /// same number of data points, and `LayerError::InputShapeMismatch` if the data points don't
+    /// have the shape the layers expect.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(samples = test_data.0.shape()[0], batch_size = batch_size)
+        )
+    )]
     pub fn evaluate(
         &self,
-        test_data: (&ArrayD<f64>, &ArrayD<f64>),
+        test_data: (&ArrayD<Float>, &ArrayD<Float>),
+        batch_size: usize,
+    ) -> Result<Benchmark, LayerError> {
+        self.evaluate_batches(test_data, batch_size, false)
+    }
+
+    /// Same as `evaluate`, also counting every (observed class, predicted class) pair into the
+    /// `ConfusionMatrix` of the returned `Benchmark`
+    pub fn evaluate_with_confusion_matrix(
+        &self,
+        test_data: (&ArrayD<Float>, &ArrayD<Float>),
+        batch_size: usize,
+    ) -> Result<Benchmark, LayerError> {
+        self.evaluate_batches(test_data, batch_size, true)
+    }
+
+    fn evaluate_batches(
+        &self,
+        test_data: (&ArrayD<Float>, &ArrayD<Float>),
         batch_size: usize,
-    ) -> Benchmark {
+        with_confusion_matrix: bool,
+    ) -> Result<Benchmark, LayerError> {
+        let evaluation_started = Instant::now();
         let mut bench = Benchmark::new(&self.metrics);
+        if with_confusion_matrix {
+            bench.confusion_matrix = Some(ConfusionMatrix::default());
+        }
         let (x, y) = test_data;
-        assert_eq!(x.shape()[0], y.shape()[0]);
-        let batches = Self::create_batches(x, y, batch_size);
+        let mut loader = ArrayLoader::in_order(x, y, batch_size)?;
 
         let mut total_loss = 0.0;
         let mut batch_count = 0;
 
-        for (batched_x, batched_y) in batches.into_iter() {
-            let output = self.predict(&batched_x).unwrap();
+        while let Some((batched_x, batched_y)) = loader.next_batch() {
+            let output = self.predict(&batched_x)?;
 
             let batch_loss = self.cost_function.cost(&output, &batched_y);
 
             if !self.metrics.is_empty() {
                 bench.metrics.accumulate(&output, &batched_y);
             }
+            if let Some(confusion_matrix) = bench.confusion_matrix.as_mut() {
+                confusion_matrix.accumulate(&output, &batched_y);
+            }
 
             total_loss += batch_loss;
             batch_count += 1;
         }
 
-        bench.metrics.mean_all(batch_count);
-        bench.loss = total_loss / batch_count as f64;
-        bench
+        // an empty test set keeps a zero loss and zero metrics instead of dividing by zero
+        if batch_count > 0 {
+            bench.metrics.mean_all(batch_count);
+            bench.loss = total_loss / batch_count as Float;
+        }
+        bench.samples = x.shape()[0];
+        bench.duration = evaluation_started.elapsed();
+        Ok(bench)
     }
 
     /// Train the neural network with Gradient descent Algorithm
     /// # Arguments
     /// * `train_data`
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(samples = train_data.0.shape()[0], epochs = epochs, batch_size = batch_size)
+        )
+    )]
     pub fn train(
         &mut self,
-        train_data: (&ArrayD<f64>, &ArrayD<f64>),
-        validation_data: Option<(&ArrayD<f64>, &ArrayD<f64>)>,
+        train_data: (&ArrayD<Float>, &ArrayD<Float>),
+        validation_data: Option<(&ArrayD<Float>, &ArrayD<Float>)>,
         epochs: usize,
         batch_size: usize,
     ) -> Result<(History, Option<History>), LayerError> {
         let (x_train, y_train) = train_data;
+        let mut loader = ArrayLoader::with_rng(x_train, y_train, batch_size, &mut self.rng)?;
+        self.train_with_loader(&mut loader, validation_data, epochs, batch_size)
+    }
 
-        if x_train.shape()[0] != y_train.shape()[0] {
-            return Err(LayerError::DimensionMismatch);
+    /// Same as `train`, with the validation data carved out of `x` and `y` instead of being given
+    /// apart: `validation_fraction` of the data points, drawn at random with the training
+    /// random generator, are held out of the training and evaluated as validation data.
+    /// When `stratify` is set the class proportions of `y` are kept in both sets.
+    ///
+    /// # Panics
+    /// If `validation_fraction` is not between 0 and 1
+    pub fn train_with_split(
+        &mut self,
+        x: &ArrayD<Float>,
+        y: &ArrayD<Float>,
+        validation_fraction: Float,
+        stratify: bool,
+        epochs: usize,
+        batch_size: usize,
+    ) -> Result<(History, Option<History>), LayerError> {
+        let ((x_train, y_train), (x_validation, y_validation)) =
+            split(x, y, validation_fraction, stratify, &mut self.rng)?;
+        let validation_data = if x_validation.is_empty() {
+            None
+        } else {
+            Some((&x_validation, &y_validation))
+        };
+        self.train((&x_train, &y_train), validation_data, epochs, batch_size)
+    }
+
+    /// Same as `train`, with the training batches produced on demand by `loader` instead of
+    /// being drawn from arrays in memory.
+    /// `batch_size` is only used to evaluate the validation data.
+    pub fn train_with_loader(
+        &mut self,
+        loader: &mut dyn DataLoader,
+        validation_data: Option<(&ArrayD<Float>, &ArrayD<Float>)>,
+        epochs: usize,
+        batch_size: usize,
+    ) -> Result<(History, Option<History>), LayerError> {
+        let histories = (History::new(), validation_data.map(|_| History::new()));
+        self.train_epochs(0, loader, validation_data, histories, epochs, batch_size)
+    }
+
+    /// Train from `first_epoch` to `epochs`, appending to the given train and validation
+    /// histories. The layers are in training mode until the training ends, see
+    /// `Layer::set_training`.
+    fn train_epochs(
+        &mut self,
+        first_epoch: usize,
+        loader: &mut dyn DataLoader,
+        validation_data: Option<(&ArrayD<Float>, &ArrayD<Float>)>,
+        histories: (History, Option<History>),
+        epochs: usize,
+        batch_size: usize,
+    ) -> Result<(History, Option<History>), LayerError> {
+        self.replicas = self.replicate_layers();
+        self.set_training(true);
+        let result = self.run_epochs(
+            first_epoch,
+            loader,
+            validation_data,
+            histories,
+            epochs,
+            batch_size,
+        );
+        self.set_training(false);
+        result
+    }
+
+    /// Switch the layers, and their copies of a data parallel training, between training and
+    /// inference
+    fn set_training(&mut self, training: bool) {
+        for layer in self
+            .layers
+            .iter_mut()
+            .chain(self.replicas.iter_mut().flatten())
+        {
+            layer.set_training(training);
         }
+    }
 
-        let mut train_history = History::new();
-        let mut validation_history = validation_data.map(|_| History::new());
+    fn run_epochs(
+        &mut self,
+        first_epoch: usize,
+        loader: &mut dyn DataLoader,
+        validation_data: Option<(&ArrayD<Float>, &ArrayD<Float>)>,
+        histories: (History, Option<History>),
+        epochs: usize,
+        batch_size: usize,
+    ) -> Result<(History, Option<History>), LayerError> {
+        let (mut train_history, mut validation_history) = histories;
+        let started = Instant::now();
 
-        let batches = Self::create_batches(x_train, y_train, batch_size);
+        for callback in self.callbacks.iter_mut() {
+            callback.on_train_begin(epochs);
+        }
 
-        for e in 0..epochs {
+        for e in first_epoch..epochs {
             debug!("Training epochs : {}", e);
-            let epoch_result = self.process_epoch(&batches)?;
-            train_history.history.push(epoch_result);
-
-            if let Some((x_val, y_val)) = validation_data {
-                let validation_bench = self.evaluate((x_val, y_val), batch_size);
-                validation_history
-                    .as_mut()
-                    .unwrap()
-                    .history
-                    .push(validation_bench);
+            let (epoch_result, mut action) = self.process_epoch(
+                e,
+                loader,
+                validation_data,
+                (&mut train_history, &mut validation_history),
+                batch_size,
+                started,
+            )?;
+            train_history.push(e, epoch_result);
+
+            if let ValidationFrequency::Epochs(n) = self.validation_frequency {
+                if (e + 1) % n.max(1) == 0 || e + 1 == epochs {
+                    self.validate(e, validation_data, &mut validation_history, batch_size)?;
+                }
             }
+
+            let logs = EpochLogs {
+                epoch: e,
+                epochs,
+                train: train_history.history.last().unwrap(),
+                validation: validation_history
+                    .as_ref()
+                    .and_then(|history| history.last_of_epoch(e)),
+                layers: &self.layers,
+            };
+            for callback in self.callbacks.iter_mut() {
+                if callback.on_epoch_end(&logs) == CallbackAction::Stop {
+                    action = CallbackAction::Stop;
+                }
+            }
+
+            if let Some(checkpoint) = &self.checkpoint {
+                let periodic = checkpoint.every_epochs.is_some_and(|every_epochs| {
+                    (e + 1) % every_epochs.max(1) == 0 || e + 1 == epochs
+                });
+                if periodic || action == CallbackAction::Stop {
+                    // a failed snapshot should not kill the training it is meant to protect
+                    if let Err(error) = self.write_checkpoint(
+                        &checkpoint.path,
+                        e,
+                        &train_history,
+                        &validation_history,
+                    ) {
+                        warn!(
+                            "Failed to write the checkpoint {:?} : {}",
+                            checkpoint.path, error
+                        );
+                    }
+                }
+            }
+
+            if action == CallbackAction::Stop {
+                debug!("Training stopped by a callback after epoch {}", e);
+                break;
+            }
+        }
+
+        for callback in self.callbacks.iter_mut() {
+            callback.on_train_end(&mut self.layers);
         }
 
         Ok((train_history, validation_history))
     }
 
+    /// Evaluate the validation data, if any, and record the result in the validation history
+    fn validate(
+        &self,
+        epoch: usize,
+        validation_data: Option<(&ArrayD<Float>, &ArrayD<Float>)>,
+        validation_history: &mut Option<History>,
+        batch_size: usize,
+    ) -> Result<(), LayerError> {
+        if let (Some(data), Some(history)) = (validation_data, validation_history.as_mut()) {
+            history.push(epoch, self.evaluate(data, batch_size)?);
+        }
+        Ok(())
+    }
+
+    /// Process one epoch over the batches of `loader`, returning the epoch `Benchmark` and whether
+    /// a callback asked to stop the training. In that case the epoch is cut short after the
+    /// current batch and the benchmark only account for the processed batches.
+    /// The validation data is evaluated here if the validation frequency is expressed in batches,
+    /// and the batches are recorded in the train history if enabled, with their time elapsed
+    /// since `started`.
     fn process_epoch(
         &mut self,
-        batches: &[(ArrayD<f64>, ArrayD<f64>)],
-    ) -> Result<Benchmark, LayerError> {
+        epoch: usize,
+        loader: &mut dyn DataLoader,
+        validation_data: Option<(&ArrayD<Float>, &ArrayD<Float>)>,
+        histories: (&mut History, &mut Option<History>),
+        batch_size: usize,
+        started: Instant,
+    ) -> Result<(Benchmark, CallbackAction), LayerError> {
+        let (train_history, validation_history) = histories;
+        let epoch_started = Instant::now();
+        enter_span!(DEBUG, "epoch", index = epoch);
         let mut bench = Benchmark::new(&self.metrics);
         let mut total_loss = 0.0;
+        let mut processed = 0;
+        let mut samples = 0;
+        let mut action = CallbackAction::Continue;
+        let number_of_batch = loader.number_of_batch();
+        let mut profile = self
+            .profiling
+            .then(|| EpochProfile::new(epoch, &self.layers, &self.layer_names));
 
-        for (batched_x, batched_y) in batches.iter() {
-            let output = self.feed_forward(batched_x)?;
-            let batch_loss = self.cost_function.cost(&output, batched_y);
+        loader.start_epoch(epoch);
+        let mut i = 0;
+        while let Some((mut batched_x, mut batched_y)) = loader.next_batch() {
+            enter_span!(DEBUG, "batch", index = i, size = batched_x.shape()[0]);
+            if batched_x.shape()[0] != batched_y.shape()[0] {
+                return Err(LayerError::DimensionMismatch);
+            }
+            // the augmented observed outputs are the ones the loss and the metrics are computed on
+            for augmentation in self.augmentations.iter() {
+                augmentation.apply(&mut batched_x, &mut batched_y, &mut self.rng)?;
+            }
+            let (output, batch_loss) =
+                self.train_batch(&batched_x, &batched_y, profile.as_mut())?;
 
             // the cost function is already meant over the data point of the batch
             total_loss += batch_loss;
+            processed += 1;
+            samples += batched_x.shape()[0];
+
+            bench.metrics.accumulate(&output, &batched_y);
+
+            let batch_count = epoch * number_of_batch + i + 1;
+            if let Some(n) = self.batch_recording {
+                if batch_count.is_multiple_of(n.max(1)) {
+                    train_history.push_batch(BatchRecord {
+                        epoch,
+                        batch: i,
+                        loss: batch_loss,
+                        elapsed: started.elapsed(),
+                    });
+                }
+            }
+
+            if let ValidationFrequency::Batches(n) = self.validation_frequency {
+                if batch_count.is_multiple_of(n.max(1)) {
+                    self.validate(epoch, validation_data, validation_history, batch_size)?;
+                }
+            }
 
-            bench.metrics.accumulate(&output, batched_y);
-            self.backpropagation(&output, batched_y)?;
+            let logs = BatchLogs {
+                epoch,
+                batch: i,
+                number_of_batch,
+                batch_size: batched_x.shape()[0],
+                loss: batch_loss,
+            };
+            for callback in self.callbacks.iter_mut() {
+                if callback.on_batch_end(&logs) == CallbackAction::Stop {
+                    action = CallbackAction::Stop;
+                }
+            }
+            if let Some(max_duration) = self.max_duration {
+                if started.elapsed() >= max_duration {
+                    debug!(
+                        "Training stopped after its time budget of {:?}",
+                        max_duration
+                    );
+                    action = CallbackAction::Stop;
+                }
+            }
+            if action == CallbackAction::Stop {
+                break;
+            }
+            i += 1;
         }
 
-        bench.metrics.mean_all(batches.len());
-        bench.loss = total_loss / batches.len() as f64;
+        // a loader without batches gives a zero loss instead of NaN
+        bench.metrics.mean_all(processed.max(1));
+        bench.loss = total_loss / processed.max(1) as Float;
+        bench.samples = samples;
+        bench.duration = epoch_started.elapsed();
+        if let Some(profile) = profile {
+            train_history.push_profile(profile);
+        }
 
-        Ok(bench)
+        Ok((bench, action))
     }
 
-    fn create_batches(
-        x_train: &ArrayD<f64>,
-        y_train: &ArrayD<f64>,
-        batch_size: usize,
-    ) -> Vec<(ArrayD<f64>, ArrayD<f64>)> {
-        let mut indices = (0..x_train.shape()[0]).collect::<Vec<_>>();
-        let mut rng = thread_rng();
-        indices.shuffle(&mut rng);
-        indices
-            .chunks(batch_size)
-            .map(|batch_indices| {
-                (
-                    x_train.select(Axis(0), batch_indices),
-                    y_train.select(Axis(0), batch_indices),
-                )
-            })
-            .collect::<Vec<_>>()
+    pub fn feed_forward(&mut self, input: &ArrayD<Float>) -> Result<ArrayD<Float>, LayerError> {
+        feed_forward(&mut self.layers, input, NumericGuard::Off, None)
     }
 
-    pub fn feed_forward(&mut self, input: &ArrayD<f64>) -> Result<ArrayD<f64>, LayerError> {
-        let mut output = input.clone();
-        for layer in &mut self.layers {
-            output = layer.feed_forward_save(&output)?;
+    /// Whether the last layer is the activation fused with the cost function, see
+    /// `Cost::fused_activation`
+    fn is_output_fused(&self) -> bool {
+        let last_activation = self
+            .layers
+            .last()
+            .and_then(|layer| layer.as_any().downcast_ref::<ActivationLayer>())
+            .map(|layer| layer.activation);
+        last_activation.is_some() && last_activation == self.cost_function.fused_activation()
+    }
+
+    /// Copies of the layers for the threads helping the training, none if it isn't data
+    /// parallel or if a layer can't be copied. Every copy draws its random values from a
+    /// generator seeded by `rng`, see `Layer::set_generator`.
+    fn replicate_layers(&mut self) -> Vec<Vec<Box<dyn Layer>>> {
+        let mut replicate = || {
+            self.layers
+                .iter()
+                .enumerate()
+                .map(|(i, layer)| {
+                    let mut replica = SerializedLayer::from_layer(i, layer.as_ref())?.into_layer();
+                    replica.set_backend(&self.backend);
+                    replica.set_generator(self.rng.gen());
+                    Ok::<_, SerializationError>(replica)
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        match (1..self.workers).map(|_| replicate()).collect() {
+            Ok(replicas) => replicas,
+            Err(error) => {
+                warn!(
+                    "Training on a single thread, the layers can't be copied : {}",
+                    error
+                );
+                vec![]
+            }
         }
-        Ok(output)
     }
 
-    fn backpropagation(
+    /// Compute the gradients of a batch, on several threads if the training is data parallel,
+    /// then update the parameters. Returns the output of the network and the loss of the batch.
+    /// The layers passes are timed into `profile` if given.
+    fn train_batch(
         &mut self,
-        net_output: &ArrayD<f64>,
-        observed: &ArrayD<f64>,
-    ) -> Result<(), LayerError> {
-        let mut grad = self
-            .cost_function
-            .cost_output_gradient(net_output, observed);
+        x: &ArrayD<Float>,
+        y: &ArrayD<Float>,
+        profile: Option<&mut EpochProfile>,
+    ) -> Result<(ArrayD<Float>, Float), LayerError> {
+        let objective = Objective {
+            cost_function: self.cost_function.as_ref(),
+            class_weights: self.class_weights.as_deref(),
+            fused: self.is_output_fused(),
+            numeric_guard: self.numeric_guard,
+        };
+        let result = if self.replicas.is_empty() || x.shape()[0] < 2 {
+            objective.compute_gradients(&mut self.layers, x, y, profile)?
+        } else {
+            data_parallel_gradients(
+                &mut self.layers,
+                &mut self.replicas,
+                objective,
+                (x, y),
+                profile,
+            )?
+        };
+        self.apply_gradients()?;
+        self.sync_replicas()?;
+        Ok(result)
+    }
+
+    /// Copy the parameters of the layers, updated by the last step, into their replicas
+    fn sync_replicas(&mut self) -> Result<(), LayerError> {
+        for replica in self.replicas.iter_mut() {
+            for (layer, copy) in self.layers.iter().zip(replica.iter_mut()) {
+                if layer.as_trainable().is_some() {
+                    copy.set_weights(layer.get_weights())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Update the parameters with the gradients left in the layers by the last batch
+    fn apply_gradients(&mut self) -> Result<(), LayerError> {
+        // every gradient is computed before the first step, so the clipping can be done
+        // with respect to all the layers at once
+        let mut trainable_layers = trainable_layers_mut(&mut self.layers);
+        if let Some(clipping) = self.gradient_clipping {
+            let mut gradients = Vec::new();
+            for layer in trainable_layers.iter_mut() {
+                gradients.extend(layer.get_gradients_mut()?);
+            }
+            clipping.clip(&mut gradients);
+        }
+        for layer in trainable_layers {
+            self.optimizer.step(layer)?;
+        }
+        Ok(())
+    }
+}
 
-        // if the cost function is dependant of the last layer, the gradient calculation
-        // have been done with respect to the net logits directly, thus skip the last layer
+/// The loss minimized by a training, shared by the threads of a data parallel training
+#[derive(Clone, Copy)]
+struct Objective<'a> {
+    cost_function: &'a dyn Cost,
+    class_weights: Option<&'a [Float]>,
+    // the last layer is the activation fused with the cost function, see `Cost::fused_activation`
+    fused: bool,
+    numeric_guard: NumericGuard,
+}
+
+impl Objective<'_> {
+    /// Feed `x` forward through `layers` then propagate the gradient of the loss back, leaving
+    /// the gradients in the trainable layers. Returns the output and the loss of the batch.
+    /// The layers passes are timed into `profile` if given.
+    fn compute_gradients(
+        &self,
+        layers: &mut [Box<dyn Layer>],
+        x: &ArrayD<Float>,
+        y: &ArrayD<Float>,
+        mut profile: Option<&mut EpochProfile>,
+    ) -> Result<(ArrayD<Float>, Float), LayerError> {
+        let guard = self.numeric_guard;
+        let output = feed_forward(layers, x, guard, profile.as_deref_mut())?;
+        let cost_function = self.cost_function;
+        let loss = match self.class_weights {
+            Some(class_weights) => cost_function.weighted_cost(&output, y, class_weights),
+            None => cost_function.cost(&output, y),
+        };
+        guard.check_loss(loss)?;
+
+        // if the last layer is the activation fused with the cost function, the gradient
+        // calculation is done with respect to the net logits directly, thus skip the last layer
         // in the gradients backpropagation
-        let skip_layer = if self.cost_function.is_output_dependant() {
-            1
-        } else {
-            0
+        let mut grad = match (self.class_weights, self.fused) {
+            (Some(class_weights), true) => {
+                cost_function.weighted_fused_output_gradient(&output, y, class_weights)
+            }
+            (Some(class_weights), false) => {
+                cost_function.weighted_cost_output_gradient(&output, y, class_weights)
+            }
+            (None, true) => cost_function.fused_output_gradient(&output, y),
+            (None, false) => cost_function.cost_output_gradient(&output, y),
         };
+        let skip_layer = usize::from(self.fused);
 
-        for layer in self.layers.iter_mut().rev().skip(skip_layer) {
+        for (i, layer) in layers.iter_mut().enumerate().rev().skip(skip_layer) {
+            enter_span!(
+                TRACE,
+                "layer_backward",
+                index = i,
+                gradient_shape = grad.shape()
+            );
+            let started = Instant::now();
             grad = layer.propagate_backward(&grad)?;
-
-            // Downcast to Trainable and call optimizes step method if possible
-            // is other layers (like convolutional implement trainable, need to downcast
-            // explicitly)
-            if let Some(trainable_layer) = layer.as_any_mut().downcast_mut::<DenseLayer>() {
-                self.optimizer.step(trainable_layer);
+            if let Some(profile) = profile.as_deref_mut() {
+                // twice the forward operations, see `LayerProfile`
+                let flops = 2 * layer.flops(&grad.shape()[1..]) * grad.shape()[0] as u64;
+                profile.record_backward(i, started.elapsed(), flops);
             }
+            guard.check(&grad, GuardedValues::InputGradient, i)?;
+            guard.check_parameter_gradients(layer.as_mut(), i)?;
+        }
+        Ok((output, loss))
+    }
+}
+
+/// Feed `input` through `layers`, saving what the backward pass needs, checking their outputs
+/// with `guard`, and timing every layer into `profile` if given
+fn feed_forward(
+    layers: &mut [Box<dyn Layer>],
+    input: &ArrayD<Float>,
+    guard: NumericGuard,
+    mut profile: Option<&mut EpochProfile>,
+) -> Result<ArrayD<Float>, LayerError> {
+    let mut output = input.clone();
+    for (i, layer) in layers.iter_mut().enumerate() {
+        enter_span!(
+            TRACE,
+            "layer_forward",
+            index = i,
+            input_shape = output.shape()
+        );
+        let started = Instant::now();
+        let next = layer.feed_forward_save(&output)?;
+        if let Some(profile) = profile.as_deref_mut() {
+            let flops = layer.flops(&output.shape()[1..]) * output.shape()[0] as u64;
+            profile.record_forward(i, started.elapsed(), flops);
+        }
+        guard.check(&next, GuardedValues::Output, i)?;
+        output = next;
+    }
+    Ok(output)
+}
 
-            if let Some(trainable_layer) = layer.as_any_mut().downcast_mut::<ConvolutionalLayer>() {
-                self.optimizer.step(trainable_layer);
+/// Split the batch into parts, the first one processed by `layers` and the others by the
+/// `replicas`, each on its own thread, then average the gradients of the parts into `layers`,
+/// weighted by the size of the parts. Returns the output and the loss of the whole batch.
+/// Only the first part is timed into `profile`, if given.
+fn data_parallel_gradients(
+    layers: &mut [Box<dyn Layer>],
+    replicas: &mut [Vec<Box<dyn Layer>>],
+    objective: Objective<'_>,
+    (x, y): (&ArrayD<Float>, &ArrayD<Float>),
+    profile: Option<&mut EpochProfile>,
+) -> Result<(ArrayD<Float>, Float), LayerError> {
+    let batch_size = x.shape()[0];
+    let part_size = batch_size.div_ceil(replicas.len() + 1);
+    let x_parts = x.axis_chunks_iter(Axis(0), part_size).collect::<Vec<_>>();
+    let y_parts = y.axis_chunks_iter(Axis(0), part_size).collect::<Vec<_>>();
+    let parts = x_parts
+        .iter()
+        .zip(&y_parts)
+        .map(|(x, y)| (x.to_owned(), y.to_owned()))
+        .collect::<Vec<_>>();
+    // the size of a part relative to the batch
+    let weights = parts
+        .iter()
+        .map(|(x, _)| x.shape()[0] as Float / batch_size as Float)
+        .collect::<Vec<_>>();
+    let replicas = &mut replicas[..parts.len() - 1];
+
+    let results = std::thread::scope(|scope| {
+        let workers = replicas
+            .iter_mut()
+            .zip(&parts[1..])
+            .map(|(replica, (x, y))| {
+                scope.spawn(move || objective.compute_gradients(replica, x, y, None))
+            })
+            .collect::<Vec<_>>();
+        let (x, y) = &parts[0];
+        let mut results = vec![objective.compute_gradients(layers, x, y, profile)];
+        results.extend(
+            workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap_or(Err(LayerError::WorkerPanic))),
+        );
+        results
+    });
+
+    let mut outputs = Vec::with_capacity(results.len());
+    let mut loss = 0.0;
+    for (result, weight) in results.into_iter().zip(&weights) {
+        let (output, part_loss) = result?;
+        loss += part_loss * weight;
+        outputs.push(output);
+    }
+
+    for (index, layer) in layers.iter_mut().enumerate() {
+        let Some(trainable) = layer.as_trainable_mut() else {
+            continue;
+        };
+        let replica_gradients = replicas
+            .iter()
+            .map(|replica| match replica[index].as_trainable() {
+                Some(replica) => replica.get_gradients(),
+                None => Err(LayerError::IllegalGradientAccess),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        for (i, gradient) in trainable.get_gradients_mut()?.into_iter().enumerate() {
+            *gradient *= weights[0];
+            for (gradients, weight) in replica_gradients.iter().zip(&weights[1..]) {
+                gradient.scaled_add(*weight, &gradients[i]);
             }
         }
-        Ok(())
+    }
+
+    let outputs = outputs
+        .iter()
+        .map(|output| output.view())
+        .collect::<Vec<_>>();
+    Ok((concatenate(Axis(0), &outputs)?, loss))
+}
+
+/// A row of `ModelSummary`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct LayerSummary {
+    pub name: Option<String>,
+    pub layer_type: &'static str,
+    /// Shape of the output of a single data point, without the batch dimension
+    pub output_shape: Vec<usize>,
+    pub parameters: usize,
+}
+
+/// The layers of a network with their output shape and number of parameters, see
+/// `Sequential::summary`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ModelSummary {
+    pub input_shape: Vec<usize>,
+    pub layers: Vec<LayerSummary>,
+}
+
+impl ModelSummary {
+    /// The number of parameters of the whole network, all of them being trainable
+    pub fn total_parameters(&self) -> usize {
+        self.layers.iter().map(|layer| layer.parameters).sum()
+    }
+}
+
+impl fmt::Display for ModelSummary {
+    /// A table in the style of Keras `model.summary()`, the batch dimension written as `None`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let shape = |shape: &[usize]| {
+            let dimensions = shape.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+            format!("(None, {})", dimensions.join(", "))
+        };
+        writeln!(
+            f,
+            "{:<32} {:<24} {:>12}",
+            "Layer (type)", "Output Shape", "Param #"
+        )?;
+        writeln!(f, "{}", "=".repeat(70))?;
+        writeln!(
+            f,
+            "{:<32} {:<24} {:>12}",
+            "input",
+            shape(&self.input_shape),
+            0
+        )?;
+        for (i, layer) in self.layers.iter().enumerate() {
+            writeln!(
+                f,
+                "{:<32} {:<24} {:>12}",
+                format!(
+                    "{} ({})",
+                    layer.name.clone().unwrap_or_else(|| i.to_string()),
+                    layer.layer_type
+                ),
+                shape(&layer.output_shape),
+                layer.parameters
+            )?;
+        }
+        writeln!(f, "{}", "=".repeat(70))?;
+        writeln!(f, "Total params: {}", self.total_parameters())
+    }
+}
+
+/// Return the layers that can be updated by the optimizer
+fn trainable_layers_mut(layers: &mut [Box<dyn Layer>]) -> Vec<&mut dyn Trainable> {
+    layers
+        .iter_mut()
+        .filter_map(|layer| layer.as_trainable_mut())
+        .collect()
+}
+
+#[derive(Error, Debug)]
+pub enum CheckpointError {
+    #[error("Can't read the checkpoint: {0}")]
+    Serialization(#[from] SerializationError),
+
+    #[error("Training error: {0}")]
+    Layer(#[from] LayerError),
+}
+
+impl From<std::io::Error> for CheckpointError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Serialization(error.into())
     }
 }
 
@@ -300,9 +1638,13 @@ pub enum NeuralNetworkError {
     #[error("Missing a last activation layer before the output")]
     MissingActivationLayer,
 
-    #[error(
-        "Invalid output activation layer,
-        see CostFunction::output_dependant for detailed explanation"
-    )]
-    WrongOutputActivationLayer,
+    #[error("Two layers are named {0}")]
+    DuplicateLayerName(String),
+
+    #[error("Layer {index} ({layer_type}) can't take an input of shape {input_shape:?}")]
+    IncompatibleLayer {
+        index: usize,
+        layer_type: &'static str,
+        input_shape: Vec<usize>,
+    },
 }
@@ -0,0 +1,213 @@
+use std::{collections::BTreeMap, io};
+
+use ndarray::ArrayD;
+use rand_chacha::ChaCha8Rng;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    activation::Activation,
+    cost::CostFunction,
+    layer::{
+        ActivationLayer, AveragePoolingLayer, ConvolutionalLayer, DenseLayer, DropoutLayer,
+        FlattenLayer, GlobalAveragePoolingLayer, Layer, MaxPoolingLayer, PReLULayer, ReshapeLayer,
+        SimpleRnnLayer,
+    },
+    metrics::{History, MetricsType},
+    npy::NpyError,
+    optimizer::{GradientClipping, GradientDescent, Optimizer},
+    sequential::ValidationFrequency,
+    Float,
+};
+
+/// Version of the model file format, bumped on every incompatible change
+pub(crate) const FORMAT_VERSION: u32 = 3;
+
+/// Version of the weights file format, bumped on every incompatible change
+pub(crate) const WEIGHTS_FORMAT_VERSION: u32 = 1;
+
+/// Version of the checkpoint file format, bumped on every incompatible change
+pub(crate) const CHECKPOINT_FORMAT_VERSION: u32 = 3;
+
+/// A layer with its type, shapes, hyperparameters and parameters, the values cached for
+/// backpropagation (inputs, gradients, masks) are not saved.
+#[derive(Serialize, Deserialize)]
+pub(crate) enum SerializedLayer {
+    Dense(DenseLayer),
+    Activation(ActivationLayer),
+    Convolutional(ConvolutionalLayer),
+    MaxPooling(MaxPoolingLayer),
+    AveragePooling(AveragePoolingLayer),
+    GlobalAveragePooling(GlobalAveragePoolingLayer),
+    Reshape(ReshapeLayer),
+    Dropout(DropoutLayer),
+    PReLU(PReLULayer),
+    SimpleRnn(SimpleRnnLayer),
+    Flatten(FlattenLayer),
+}
+
+impl SerializedLayer {
+    pub(crate) fn from_layer(index: usize, layer: &dyn Layer) -> Result<Self, SerializationError> {
+        let any = layer.as_any();
+        if let Some(layer) = any.downcast_ref::<DenseLayer>() {
+            Ok(Self::Dense(layer.clone()))
+        } else if let Some(layer) = any.downcast_ref::<ActivationLayer>() {
+            // the functions of a custom activation can't be written to a file
+            if let Activation::Custom { .. } = layer.activation {
+                return Err(SerializationError::UnsupportedLayer(index));
+            }
+            Ok(Self::Activation(ActivationLayer::from(layer.activation)))
+        } else if let Some(layer) = any.downcast_ref::<ConvolutionalLayer>() {
+            Ok(Self::Convolutional(layer.clone()))
+        } else if let Some(layer) = any.downcast_ref::<MaxPoolingLayer>() {
+            Ok(Self::MaxPooling(layer.clone()))
+        } else if let Some(layer) = any.downcast_ref::<AveragePoolingLayer>() {
+            Ok(Self::AveragePooling(layer.clone()))
+        } else if let Some(layer) = any.downcast_ref::<GlobalAveragePoolingLayer>() {
+            Ok(Self::GlobalAveragePooling(layer.clone()))
+        } else if let Some(layer) = any.downcast_ref::<ReshapeLayer>() {
+            Ok(Self::Reshape(layer.clone()))
+        } else if let Some(layer) = any.downcast_ref::<DropoutLayer>() {
+            Ok(Self::Dropout(layer.clone()))
+        } else if let Some(layer) = any.downcast_ref::<PReLULayer>() {
+            Ok(Self::PReLU(layer.clone()))
+        } else if let Some(layer) = any.downcast_ref::<SimpleRnnLayer>() {
+            Ok(Self::SimpleRnn(layer.clone()))
+        } else if any.is::<FlattenLayer>() {
+            Ok(Self::Flatten(FlattenLayer::new()))
+        } else {
+            Err(SerializationError::UnsupportedLayer(index))
+        }
+    }
+
+    pub(crate) fn into_layer(self) -> Box<dyn Layer> {
+        match self {
+            Self::Dense(layer) => Box::new(layer),
+            Self::Activation(layer) => Box::new(layer),
+            Self::Convolutional(layer) => Box::new(layer),
+            Self::MaxPooling(layer) => Box::new(layer),
+            Self::AveragePooling(layer) => Box::new(layer),
+            Self::GlobalAveragePooling(layer) => Box::new(layer),
+            Self::Reshape(layer) => Box::new(layer),
+            Self::Dropout(layer) => Box::new(layer),
+            Self::PReLU(layer) => Box::new(layer),
+            Self::SimpleRnn(layer) => Box::new(layer),
+            Self::Flatten(layer) => Box::new(layer),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) enum SerializedOptimizer {
+    GradientDescent(GradientDescent),
+}
+
+impl SerializedOptimizer {
+    pub(crate) fn from_optimizer(optimizer: &dyn Optimizer) -> Result<Self, SerializationError> {
+        if let Some(optimizer) = optimizer.as_any().downcast_ref::<GradientDescent>() {
+            Ok(Self::GradientDescent(*optimizer))
+        } else {
+            Err(SerializationError::UnsupportedOptimizer)
+        }
+    }
+
+    pub(crate) fn into_optimizer(self) -> Box<dyn Optimizer> {
+        match self {
+            Self::GradientDescent(optimizer) => Box::new(optimizer),
+        }
+    }
+}
+
+/// Everything needed to rebuild a `Sequential`, callbacks excepted.
+/// In a model file, it is preceded by the `FORMAT_VERSION`
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SerializedModel {
+    pub(crate) layers: Vec<SerializedLayer>,
+    pub(crate) layer_names: Vec<Option<String>>,
+    pub(crate) cost_function: CostFunction,
+    pub(crate) optimizer: SerializedOptimizer,
+    pub(crate) metrics: Vec<MetricsType>,
+    pub(crate) validation_frequency: ValidationFrequency,
+    pub(crate) gradient_clipping: Option<GradientClipping>,
+}
+
+/// A snapshot of a training after `epoch`.
+/// In a checkpoint file, it is preceded by the `CHECKPOINT_FORMAT_VERSION`
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SerializedCheckpoint {
+    pub(crate) model: SerializedModel,
+    pub(crate) rng: ChaCha8Rng,
+    pub(crate) epoch: usize,
+    pub(crate) train_history: History,
+    pub(crate) validation_history: Option<History>,
+}
+
+/// Parameters of the trainable layers only, keyed by `weights_key`, every entry holding the
+/// parameters in the order of `Trainable::get_parameters`.
+/// In a weights file, it is preceded by the `WEIGHTS_FORMAT_VERSION`
+pub(crate) type SerializedWeights = BTreeMap<String, Vec<ArrayD<Float>>>;
+
+/// Key of the parameters of the layer at `index` in a weights file
+pub(crate) fn weights_key(index: usize) -> String {
+    format!("layer_{}", index)
+}
+
+/// Group arrays named `layer_{index}_{parameter}` into the parameters of every layer
+pub(crate) fn group_parameters(
+    arrays: BTreeMap<String, ArrayD<Float>>,
+) -> Result<SerializedWeights, SerializationError> {
+    let mut layers: BTreeMap<String, BTreeMap<usize, ArrayD<Float>>> = BTreeMap::new();
+    for (name, array) in arrays {
+        let Some((layer, Ok(parameter))) = name
+            .rsplit_once('_')
+            .map(|(layer, parameter)| (layer, parameter.parse::<usize>()))
+        else {
+            return Err(SerializationError::UnknownWeights(name));
+        };
+        layers
+            .entry(layer.to_string())
+            .or_default()
+            .insert(parameter, array);
+    }
+    layers
+        .into_iter()
+        .map(|(layer, parameters)| {
+            // parameters must be numbered from 0 without gap
+            if parameters.keys().copied().ne(0..parameters.len()) {
+                return Err(SerializationError::ShapeMismatch(layer));
+            }
+            Ok((layer, parameters.into_values().collect()))
+        })
+        .collect()
+}
+
+#[derive(Error, Debug)]
+pub enum SerializationError {
+    #[error("Layer {0} can't be serialized")]
+    UnsupportedLayer(usize),
+
+    #[error("The optimizer can't be serialized")]
+    UnsupportedOptimizer,
+
+    #[error("The cost function can't be serialized, only the built-in ones can")]
+    UnsupportedCostFunction,
+
+    #[error("Unsupported file format version {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("Weights {0} don't match the shapes of the layer parameters")]
+    ShapeMismatch(String),
+
+    #[error("Weights {0} don't match any trainable layer")]
+    UnknownWeights(String),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Encoding error: {0}")]
+    Encoding(#[from] bincode::Error),
+
+    #[error("NumPy error: {0}")]
+    Npy(#[from] NpyError),
+}
@@ -0,0 +1,148 @@
+use std::{
+    io,
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use log::{debug, warn};
+use serde_json::{json, Map, Value};
+use tungstenite::{Message, WebSocket};
+
+use crate::{
+    callback::{BatchLogs, Callback, CallbackAction, EpochLogs},
+    metrics::Benchmark,
+};
+
+/// Time given to a client to complete the WebSocket handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Time after which a client not reading its messages is disconnected
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Number of messages queued for a client before the next ones are dropped for it
+const CLIENT_BACKLOG: usize = 1024;
+
+/// A `Callback` streaming the training metrics as JSON messages to every client connected on a
+/// local WebSocket.
+///
+/// Every epoch produce a message of the form
+/// `{"type": "epoch", "epoch": 0, "epochs": 10, "train": {...}, "validation": {...}}`,
+/// and if enabled with `with_batches`, every batch produce a message of the form
+/// `{"type": "batch", "epoch": 0, "batch": 12, "number_of_batch": 375, "loss": 0.3}`.
+pub struct MetricsStreamer {
+    clients: Arc<Mutex<Vec<SyncSender<String>>>>,
+    stream_batches: bool,
+}
+
+impl MetricsStreamer {
+    /// Bind a WebSocket server on `address`, clients are accepted in a background thread for the
+    /// whole lifetime of the program.
+    /// Every client is then served by its own thread, so that neither a stalled handshake nor a
+    /// slow reader holds back the other clients or the training.
+    pub fn bind<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        debug!("Streaming metrics on ws://{}", listener.local_addr()?);
+
+        let clients = Arc::new(Mutex::new(vec![]));
+        let accepted = Arc::clone(&clients);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let clients = Arc::clone(&accepted);
+                thread::spawn(move || serve_client(stream, &clients));
+            }
+        });
+
+        Ok(Self {
+            clients,
+            stream_batches: false,
+        })
+    }
+
+    /// Also stream a message at the end of every batch
+    pub fn with_batches(mut self, stream_batches: bool) -> Self {
+        self.stream_batches = stream_batches;
+        self
+    }
+
+    fn broadcast(&self, message: Value) {
+        let text = message.to_string();
+        let mut clients = self.clients.lock().unwrap();
+        // a client lagging behind misses the messages past its backlog, and disconnected clients
+        // are dropped on the first failed send
+        clients.retain(|client| match client.try_send(text.clone()) {
+            Ok(()) | Err(TrySendError::Full(_)) => true,
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+    }
+}
+
+/// Complete the handshake of the client connected on `stream`, then send it the messages
+/// broadcast from then on, until it disconnects or stops reading them
+fn serve_client(stream: TcpStream, clients: &Mutex<Vec<SyncSender<String>>>) {
+    if let Err(e) = stream
+        .set_read_timeout(Some(HANDSHAKE_TIMEOUT))
+        .and_then(|()| stream.set_write_timeout(Some(WRITE_TIMEOUT)))
+    {
+        warn!("Failed to set the WebSocket client timeouts : {}", e);
+        return;
+    }
+    let socket = match tungstenite::accept(stream) {
+        Ok(socket) => socket,
+        Err(e) => {
+            warn!("WebSocket handshake failed : {}", e);
+            return;
+        }
+    };
+    let (sender, messages) = mpsc::sync_channel(CLIENT_BACKLOG);
+    clients.lock().unwrap().push(sender);
+    send_messages(socket, messages);
+}
+
+fn send_messages(mut socket: WebSocket<TcpStream>, messages: Receiver<String>) {
+    for text in messages {
+        if let Err(e) = socket.send(Message::Text(text)) {
+            debug!("WebSocket client disconnected : {}", e);
+            return;
+        }
+    }
+}
+
+fn benchmark_to_json(bench: &Benchmark) -> Value {
+    let mut metrics = Map::new();
+    for (metric_type, value) in bench.metrics.get_all() {
+        metrics.insert(format!("{:?}", metric_type), json!(value));
+    }
+    json!({
+        "loss": bench.loss,
+        "metrics": metrics,
+    })
+}
+
+impl Callback for MetricsStreamer {
+    fn on_batch_end(&mut self, logs: &BatchLogs) -> CallbackAction {
+        if self.stream_batches {
+            self.broadcast(json!({
+                "type": "batch",
+                "epoch": logs.epoch,
+                "batch": logs.batch,
+                "number_of_batch": logs.number_of_batch,
+                "loss": logs.loss,
+            }));
+        }
+        CallbackAction::Continue
+    }
+
+    fn on_epoch_end(&mut self, logs: &EpochLogs) -> CallbackAction {
+        self.broadcast(json!({
+            "type": "epoch",
+            "epoch": logs.epoch,
+            "epochs": logs.epochs,
+            "train": benchmark_to_json(logs.train),
+            "validation": logs.validation.map(benchmark_to_json),
+        }));
+        CallbackAction::Continue
+    }
+}
@@ -1,35 +1,163 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
 use eframe::{App, Frame};
 use egui::{
-    epaint::PathShape, CentralPanel, Color32, Context, Painter, Pos2, Rect, Response, Sense, Shape,
-    Stroke, Ui, Vec2, Visuals,
+    epaint::PathShape, Button, CentralPanel, Color32, ColorImage, Context, Key, Modifiers, Painter,
+    Pos2, ProgressBar, Rect, Response, Sense, Shape, Slider, Stroke, TextureHandle, TextureOptions,
+    Ui, Vec2, Visuals, Window,
 };
-use egui_plot::{Bar, BarChart, Plot};
+use egui_plot::{Bar, BarChart, Legend, Line, Plot};
 use image::{GrayImage, ImageBuffer};
+use mnist::{dataset::Dataset, drawings::Drawings};
 use ndarray::{Array2, ArrayD};
-use nn_lib::{layer::LayerError, sequential::Sequential};
+use nn_lib::{
+    inference::{InferenceError, InferenceModel},
+    sequential::Sequential,
+};
+
+use crate::{test_set::TestSetBrowser, training::BackgroundTraining, visualization::FeatureMaps};
+
+/// Side of the preview of the image given to the networks, in points
+const PREVIEW_SIZE: f32 = 140.0;
+
+/// Half the thickness of the strokes, in pixels of the canvas, until changed with the slider
+const DEFAULT_BRUSH_RADIUS: i32 = 8;
+
+/// Number of epochs and batch size of the fine-tuning of the networks on the drawings
+const FINE_TUNE_EPOCHS: usize = 5;
+const FINE_TUNE_BATCH_SIZE: usize = 16;
+
+/// An image file classified in place of the drawing, see `load_image`
+struct OpenedImage {
+    name: String,
+    input: ArrayD<f32>,
+}
+
+/// A stroke drawn on the canvas, in white, or in black with the eraser
+#[derive(Clone, Debug, PartialEq)]
+struct BrushStroke {
+    points: Vec<Pos2>,
+    radius: i32,
+    eraser: bool,
+}
+
+/// A network of the app, usable for predictions once trained
+pub enum NetworkSource {
+    /// The trained network, and its single precision copy making the predictions
+    Ready {
+        network: Box<Sequential>,
+        model: InferenceModel,
+    },
+    Training(BackgroundTraining),
+    Failed(String),
+}
+
+impl NetworkSource {
+    /// A trained network, frozen into a single precision model for the predictions
+    pub fn ready(network: Sequential) -> Result<Self, InferenceError> {
+        Ok(Self::Ready {
+            model: InferenceModel::from_sequential(&network)?,
+            network: Box::new(network),
+        })
+    }
+
+    /// Collect the progress of the training, if any, and take the network once trained
+    fn poll(&mut self) {
+        if let Self::Training(training) = self {
+            if let Some(result) = training.poll() {
+                *self = match result.and_then(|network| Ok(Self::ready(network)?)) {
+                    Ok(network) => network,
+                    Err(error) => Self::Failed(error.to_string()),
+                };
+            }
+        }
+    }
+
+    /// The probability of every digit for `image`, none until the network is trained
+    fn predict(&self, image: &ArrayD<f32>) -> Option<ArrayD<f32>> {
+        match self {
+            Self::Ready { model, .. } => model.predict(image).ok(),
+            _ => None,
+        }
+    }
+
+    /// Train the network a few more epochs on `drawings` on a worker thread, if it is trained
+    fn fine_tune(&mut self, drawings: Drawings) {
+        if !matches!(self, Self::Ready { .. }) {
+            return;
+        }
+        let Self::Ready { mut network, .. } = std::mem::replace(self, Self::Failed(String::new()))
+        else {
+            return;
+        };
+        *self = Self::Training(BackgroundTraining::spawn(move |progress| {
+            // the callbacks of the previous training are done
+            network.clear_callbacks();
+            network.add_callback(progress);
+            mnist::network_definition::fine_tune(
+                &mut network,
+                &drawings,
+                FINE_TUNE_EPOCHS,
+                FINE_TUNE_BATCH_SIZE,
+            )?;
+            Ok(*network)
+        }));
+    }
+
+    fn model(&self) -> Option<&InferenceModel> {
+        match self {
+            Self::Ready { model, .. } => Some(model),
+            _ => None,
+        }
+    }
+
+    fn network(&self) -> Option<&Sequential> {
+        match self {
+            Self::Ready { network, .. } => Some(network.as_ref()),
+            _ => None,
+        }
+    }
+}
 
 pub struct Application {
-    multilayer_perceptron: Sequential,
-    convolutional_network: Option<Sequential>,
-    conv_chosen: bool,
+    multilayer_perceptron: NetworkSource,
+    convolutional_network: Option<NetworkSource>,
     painter_size: Vec2,
-    paths: Vec<Vec<Pos2>>,
+    paths: Vec<BrushStroke>,
     current_path: Vec<Pos2>,
     path_shape: PathShape,
+    brush_radius: i32,
+    eraser: bool,
     predicted_number: Option<u8>,
+    preview: Option<TextureHandle>,
+    opened_image: Option<OpenedImage>,
+    open_error: Option<String>,
+    show_feature_maps: bool,
+    feature_maps: Option<FeatureMaps>,
+    show_test_set: bool,
+    test_set: Option<Result<TestSetBrowser, String>>,
+    drawings: Drawings,
+    drawings_dir: PathBuf,
+    drawings_error: Option<String>,
+    dataset: Dataset,
 }
 
 impl Application {
     pub fn new(
         creation_context: &eframe::CreationContext<'_>,
-        multilayer_perceptron: Sequential,
-        convolutional_network: Option<Sequential>,
+        multilayer_perceptron: NetworkSource,
+        convolutional_network: Option<NetworkSource>,
+        drawings: Drawings,
+        drawings_dir: PathBuf,
+        dataset: Dataset,
     ) -> Self {
         creation_context.egui_ctx.set_visuals(Visuals::light());
         Self {
             multilayer_perceptron,
             convolutional_network,
-            conv_chosen: false,
             painter_size: Vec2::new(280.0, 280.0),
             paths: Vec::default(),
             current_path: Vec::default(),
@@ -37,55 +165,179 @@ impl Application {
                 points: Vec::default(),
                 closed: false,
                 fill: Color32::TRANSPARENT,
-                stroke: Stroke::new(30.0, Color32::WHITE),
+                stroke: Stroke::NONE,
             },
             predicted_number: None,
+            preview: None,
+            brush_radius: DEFAULT_BRUSH_RADIUS,
+            eraser: false,
+            opened_image: None,
+            open_error: None,
+            show_feature_maps: false,
+            feature_maps: None,
+            show_test_set: false,
+            test_set: None,
+            drawings,
+            drawings_dir,
+            drawings_error: None,
+            dataset,
         }
     }
 
-    fn resize_img_into_28x28(&self) -> anyhow::Result<ArrayD<f64>> {
+    fn resize_img_into_28x28(&self) -> anyhow::Result<ArrayD<f32>> {
         let mut img: GrayImage = ImageBuffer::from_pixel(
             self.painter_size.x as u32,
             self.painter_size.y as u32,
             image::Luma([0]),
         );
-        for path in self.paths.clone() {
-            for window in path.windows(2) {
+        let current = (&self.current_path, self.brush_radius, self.eraser);
+        let strokes = self
+            .paths
+            .iter()
+            .map(|stroke| (&stroke.points, stroke.radius, stroke.eraser))
+            .chain(std::iter::once(current));
+        for (points, radius, eraser) in strokes {
+            let value = if eraser { 0 } else { 255 };
+            for window in points.windows(2) {
                 if let [start, end] = window {
-                    self.draw_thick_line(&mut img, *start, *end, 8);
+                    self.draw_thick_line(&mut img, *start, *end, radius, value);
                 }
             }
         }
-        for window in self.current_path.windows(2) {
-            if let [start, end] = window {
-                self.draw_thick_line(&mut img, *start, *end, 8)
-            }
-        }
         let resized_img: GrayImage =
             image::imageops::resize(&img, 28, 28, image::imageops::FilterType::Lanczos3);
-        let _ = resized_img.save("output.png");
-        let normalized_pixels: Vec<f64> =
-            resized_img.pixels().map(|p| p[0] as f64 / 255.0).collect();
+        let normalized_pixels: Vec<f32> =
+            resized_img.pixels().map(|p| p[0] as f32 / 255.0).collect();
         let arr = Array2::from_shape_vec((1, 28 * 28), normalized_pixels)?;
         Ok(arr.into_dyn())
     }
 
-    fn predict_number(&mut self, image: ArrayD<f64>) -> Result<ArrayD<f64>, LayerError> {
-        if self.conv_chosen {
-            self.convolutional_network
-                .as_ref()
-                .expect("trying to predict with unset convo network")
-                .predict(&image)
-        } else {
-            self.multilayer_perceptron.predict(&image)
+    /// Ask for an image file with the native file dialog and classify it in place of the drawing
+    fn open_image(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Image", &["png", "jpg", "jpeg"])
+            .pick_file()
+        else {
+            return;
+        };
+        match load_image(&path) {
+            Ok(input) => {
+                self.opened_image = Some(OpenedImage {
+                    name: path.file_name().map_or_else(
+                        || path.display().to_string(),
+                        |name| name.to_string_lossy().into_owned(),
+                    ),
+                    input,
+                });
+                self.open_error = None;
+            }
+            Err(error) => self.open_error = Some(format!("Can't open {:?} : {}", path, error)),
+        }
+    }
+
+    /// Add `image` to the drawings as a `label`, and save them
+    fn collect_drawing(&mut self, image: &ArrayD<f32>, label: u8) {
+        let pixels: Vec<u8> = image
+            .iter()
+            .map(|pixel| (pixel * 255.0).round().clamp(0.0, 255.0) as u8)
+            .collect();
+        self.drawings.push(&pixels, label);
+        self.drawings_error = self
+            .drawings
+            .save(&self.drawings_dir)
+            .err()
+            .map(|error| format!("Can't save the drawings : {}", error));
+    }
+
+    /// Fine-tune the trained networks on the drawings
+    fn fine_tune(&mut self) {
+        self.multilayer_perceptron.fine_tune(self.drawings.clone());
+        if let Some(convolutional_network) = &mut self.convolutional_network {
+            convolutional_network.fine_tune(self.drawings.clone());
         }
+        self.feature_maps = None;
+    }
+
+    /// Run the convolutional network on `image` to show its feature maps, unless they were
+    /// already computed for it
+    fn update_feature_maps(&mut self, context: &Context, image: Option<&ArrayD<f32>>) {
+        let network = self
+            .convolutional_network
+            .as_ref()
+            .and_then(NetworkSource::network);
+        let (Some(network), Some(image)) = (network, image) else {
+            self.feature_maps = None;
+            return;
+        };
+        if !self
+            .feature_maps
+            .as_ref()
+            .is_some_and(|feature_maps| feature_maps.is_for(image))
+        {
+            self.feature_maps = FeatureMaps::new(context, network, image).ok();
+        }
+    }
+
+    /// Paint the image given to the networks into `rect`, scaled up without smoothing
+    fn show_preview(&mut self, ui: &Ui, rect: Rect, image: Option<&ArrayD<f32>>) {
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, Color32::BLACK);
+        let Some(image) = image else {
+            return;
+        };
+        let pixels: Vec<u8> = image
+            .iter()
+            .map(|pixel| (pixel * 255.0).round().clamp(0.0, 255.0) as u8)
+            .collect();
+        let preview = ColorImage::from_gray([28, 28], &pixels);
+        match &mut self.preview {
+            Some(texture) => texture.set(preview, TextureOptions::NEAREST),
+            None => {
+                self.preview = Some(ui.ctx().load_texture(
+                    "input preview",
+                    preview,
+                    TextureOptions::NEAREST,
+                ))
+            }
+        }
+        if let Some(texture) = &self.preview {
+            painter.image(
+                texture.id(),
+                rect,
+                Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)),
+                Color32::WHITE,
+            );
+        }
+    }
+
+    /// The shape of a stroke on the canvas, of the thickness it has in the image given to the
+    /// networks
+    fn stroke_shape(&self, points: &[Pos2], radius: i32, eraser: bool, offset: Vec2) -> Shape {
+        let color = if eraser {
+            Color32::BLACK
+        } else {
+            Color32::WHITE
+        };
+        Shape::Path(PathShape {
+            points: points.iter().map(|point: &Pos2| *point + offset).collect(),
+            stroke: Stroke::new((2 * radius + 1) as f32, color),
+            ..self.path_shape
+        })
     }
 
-    fn draw_thick_line(&self, img: &mut GrayImage, start: Pos2, end: Pos2, thickness: i32) {
+    fn draw_thick_line(
+        &self,
+        img: &mut GrayImage,
+        start: Pos2,
+        end: Pos2,
+        thickness: i32,
+        value: u8,
+    ) {
         for i in -thickness..=thickness {
             for j in -thickness..=thickness {
                 self.draw_line(
                     img,
+                    value,
                     Pos2 {
                         x: start.x + i as f32,
                         y: start.y + j as f32,
@@ -99,7 +351,7 @@ impl Application {
         }
     }
 
-    fn draw_line(&self, img: &mut GrayImage, start: Pos2, end: Pos2) {
+    fn draw_line(&self, img: &mut GrayImage, value: u8, start: Pos2, end: Pos2) {
         let start = (start.x as i32, start.y as i32);
         let end = (end.x as i32, end.y as i32);
 
@@ -113,7 +365,7 @@ impl Application {
 
         loop {
             if let Some(pixel) = img.get_pixel_mut_checked(x0 as u32, y0 as u32) {
-                *pixel = image::Luma([255]);
+                *pixel = image::Luma([value]);
             }
             if x0 == end.0 && y0 == end.1 {
                 break;
@@ -131,88 +383,350 @@ impl Application {
     }
 }
 
+/// The progress of the training, with its loss and accuracy curves
+fn show_training(ui: &mut Ui, training: &BackgroundTraining) {
+    if training.epochs == 0 {
+        ui.label("Loading the dataset...");
+        return;
+    }
+    ui.label(format!(
+        "Training, epoch {}/{}, batch {}/{}",
+        training.epoch + 1,
+        training.epochs,
+        training.batch,
+        training.number_of_batch
+    ));
+    ui.add(ProgressBar::new(training.fraction()).show_percentage());
+    Plot::new("Training loss")
+        .height(120.0)
+        .legend(Legend::default())
+        .show(ui, |plot_ui| {
+            plot_ui.line(Line::new(training.batch_loss.clone()).name("batch loss"));
+            plot_ui.line(Line::new(training.train_loss.clone()).name("train loss"));
+            plot_ui.line(Line::new(training.validation_loss.clone()).name("validation loss"));
+        });
+    Plot::new("Training accuracy")
+        .height(120.0)
+        .legend(Legend::default())
+        .include_y(0.0)
+        .include_y(1.0)
+        .show(ui, |plot_ui| {
+            plot_ui.line(Line::new(training.train_accuracy.clone()).name("train accuracy"));
+            plot_ui
+                .line(Line::new(training.validation_accuracy.clone()).name("validation accuracy"));
+        });
+}
+
+/// An image file as an input of the networks: in grayscale, resized to 28x28 and scaled to
+/// [0, 1]. As the mnist digits are light on a dark background, a mostly light image (ex: a
+/// scanned digit written on paper) is inverted.
+fn load_image(path: &Path) -> anyhow::Result<ArrayD<f32>> {
+    let image = image::open(path)?.to_luma8();
+    let resized_img: GrayImage =
+        image::imageops::resize(&image, 28, 28, image::imageops::FilterType::Lanczos3);
+    let mut pixels: Vec<f32> = resized_img.pixels().map(|p| p[0] as f32 / 255.0).collect();
+    let mean = pixels.iter().sum::<f32>() / pixels.len() as f32;
+    if mean > 0.5 {
+        pixels.iter_mut().for_each(|pixel| *pixel = 1.0 - *pixel);
+    }
+    let arr = Array2::from_shape_vec((1, 28 * 28), pixels)?;
+    Ok(arr.into_dyn())
+}
+
+/// The digit given the highest probability
+fn guess(predictions: &ArrayD<f32>) -> usize {
+    predictions
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map_or(0, |(digit, _)| digit)
+}
+
+/// The state of a network under its `name`: its training, or the probability of every class of
+/// `dataset` for the drawing, the guessed class being highlighted when the networks disagree
+fn show_network(
+    ui: &mut Ui,
+    name: &str,
+    dataset: Dataset,
+    network: &NetworkSource,
+    predictions: Option<&ArrayD<f32>>,
+    disagree: bool,
+) {
+    ui.heading(name);
+    match network {
+        NetworkSource::Training(training) => show_training(ui, training),
+        NetworkSource::Failed(error) => {
+            ui.colored_label(Color32::RED, format!("Training failed : {}", error));
+        }
+        NetworkSource::Ready { .. } => {
+            let Some(predictions) = predictions else {
+                return;
+            };
+            let guessed = guess(predictions);
+            let bars = predictions
+                .iter()
+                .enumerate()
+                .map(|(index, prediction)| {
+                    let bar: Bar =
+                        Bar::new(index as f64, *prediction as f64).name(dataset.class_name(index));
+                    if disagree && index == guessed {
+                        bar.fill(Color32::RED)
+                    } else {
+                        bar
+                    }
+                })
+                .collect();
+
+            let bar_chart = BarChart::new(bars)
+                .name("Prediction Score")
+                .color(egui::Color32::GREEN);
+            Plot::new(format!("{} prediction score", name))
+                .view_aspect(2.0)
+                .show(ui, |plot_ui| {
+                    plot_ui.bar_chart(bar_chart);
+                });
+        }
+    }
+}
+
 impl App for Application {
     fn update(&mut self, context: &Context, _frame: &mut Frame) {
+        self.multilayer_perceptron.poll();
+        if let Some(convolutional_network) = &mut self.convolutional_network {
+            convolutional_network.poll();
+        }
+        let training = matches!(self.multilayer_perceptron, NetworkSource::Training(_))
+            || matches!(self.convolutional_network, Some(NetworkSource::Training(_)));
+        if training {
+            // the training thread doesn't wake up the window, the progress is polled instead
+            context.request_repaint_after(Duration::from_millis(100));
+        }
+
         CentralPanel::default().show(context, |ui: &mut Ui| {
-            ui.heading("Draw a number");
-            ui.heading(if self.conv_chosen {
-                "ConvNet running"
-            } else {
-                "MLP running"
+            ui.heading(match self.dataset {
+                Dataset::Digits => "Draw a number",
+                Dataset::Fashion => "Draw a piece of clothing",
             });
 
-            if let Some(_) = self.convolutional_network {
+            ui.horizontal(|ui| {
+                ui.add(Slider::new(&mut self.brush_radius, 2..=16).text("Brush size"));
+                ui.toggle_value(&mut self.eraser, "Eraser");
+                let undo = ui.input_mut(|input| input.consume_key(Modifiers::COMMAND, Key::Z));
                 if ui
-                    .button(if self.conv_chosen { "MLP" } else { "ConvNet" })
+                    .add_enabled(!self.paths.is_empty(), Button::new("Undo"))
+                    .on_hover_text("Remove the last stroke (Ctrl+Z)")
                     .clicked()
+                    || undo
                 {
-                    self.conv_chosen = !self.conv_chosen;
+                    self.paths.pop();
+                }
+                if ui.button("Clear").clicked() {
+                    self.current_path.clear();
+                    self.paths.clear();
+                    self.predicted_number = None;
+                    self.opened_image = None;
                 }
+                if ui.button("Open image…").clicked() {
+                    self.open_image();
+                }
+                if self.convolutional_network.is_some() {
+                    ui.toggle_value(&mut self.show_feature_maps, "Feature maps");
+                }
+                ui.toggle_value(&mut self.show_test_set, "Test set");
+            });
+            if let Some(opened_image) = &self.opened_image {
+                ui.label(format!(
+                    "Classifying {}, draw on the canvas to go back to the drawing",
+                    opened_image.name
+                ));
+            }
+            if let Some(error) = &self.open_error {
+                ui.colored_label(Color32::RED, error);
             }
 
-            let (response, painter): (Response, Painter) =
-                ui.allocate_painter(self.painter_size, Sense::drag());
+            let (response, painter, preview_rect): (Response, Painter, Rect) = ui
+                .horizontal(|ui| {
+                    let (response, painter) = ui.allocate_painter(self.painter_size, Sense::drag());
+                    let (preview_rect, preview_response) =
+                        ui.allocate_exact_size(Vec2::splat(PREVIEW_SIZE), Sense::hover());
+                    preview_response.on_hover_text("The 28x28 image given to the networks");
+                    (response, painter, preview_rect)
+                })
+                .inner;
             let rectangle_painter: Rect = response.rect;
             painter.rect_filled(rectangle_painter, 0.0, Color32::BLACK);
 
             let left_top_corner_painter: Vec2 = rectangle_painter.min.to_vec2();
 
+            if response.drag_started() {
+                self.opened_image = None;
+            }
             if response.dragged() {
                 if let Some(pos) = response.hover_pos() {
                     self.current_path.push(pos - left_top_corner_painter);
                 }
             } else if response.drag_stopped() && !self.current_path.is_empty() {
-                self.paths.push(self.current_path.clone());
-                self.current_path.clear();
+                self.paths.push(BrushStroke {
+                    points: std::mem::take(&mut self.current_path),
+                    radius: self.brush_radius,
+                    eraser: self.eraser,
+                });
             }
 
-            for path in &self.paths {
-                painter.add(Shape::Path(PathShape {
-                    points: path
-                        .iter()
-                        .map(|point: &Pos2| *point + left_top_corner_painter)
-                        .collect(),
-                    ..self.path_shape
-                }));
+            for stroke in &self.paths {
+                painter.add(self.stroke_shape(
+                    &stroke.points,
+                    stroke.radius,
+                    stroke.eraser,
+                    left_top_corner_painter,
+                ));
             }
 
             if !self.current_path.is_empty() {
-                painter.add(Shape::Path(PathShape {
-                    points: self
-                        .current_path
-                        .iter()
-                        .map(|point: &Pos2| *point + left_top_corner_painter)
-                        .collect(),
-                    ..self.path_shape
-                }));
-            }
-
-            if ui.button("Clear").clicked() {
-                self.current_path.clear();
-                self.paths.clear();
-                self.predicted_number = None;
-            }
-
-            if !self.paths.is_empty() || !self.current_path.is_empty() {
-                if let Ok(image) = self.resize_img_into_28x28() {
-                    let mut bars = vec![];
-                    if let Ok(predictions) = self.predict_number(image) {
-                        for (index, prediction) in predictions.iter().enumerate() {
-                            let bar: Bar = Bar::new(index as f64, *prediction).name(index);
-                            bars.push(bar);
+                painter.add(self.stroke_shape(
+                    &self.current_path,
+                    self.brush_radius,
+                    self.eraser,
+                    left_top_corner_painter,
+                ));
+            }
+
+            let drawn = !self.paths.is_empty() || !self.current_path.is_empty();
+            let image = match &self.opened_image {
+                Some(opened_image) => Some(opened_image.input.clone()),
+                None => drawn.then(|| self.resize_img_into_28x28().ok()).flatten(),
+            };
+            self.show_preview(ui, preview_rect, image.as_ref());
+            let mlp_predictions = image
+                .as_ref()
+                .and_then(|image| self.multilayer_perceptron.predict(image));
+            let conv_predictions = image.as_ref().and_then(|image| {
+                self.convolutional_network
+                    .as_ref()
+                    .and_then(|network| network.predict(image))
+            });
+            let guesses = (
+                mlp_predictions.as_ref().map(guess),
+                conv_predictions.as_ref().map(guess),
+            );
+            let disagree = matches!(guesses, (Some(mlp), Some(conv)) if mlp != conv);
+
+            match &self.convolutional_network {
+                Some(convolutional_network) => ui.columns(2, |columns| {
+                    show_network(
+                        &mut columns[0],
+                        "MLP",
+                        self.dataset,
+                        &self.multilayer_perceptron,
+                        mlp_predictions.as_ref(),
+                        disagree,
+                    );
+                    show_network(
+                        &mut columns[1],
+                        "ConvNet",
+                        self.dataset,
+                        convolutional_network,
+                        conv_predictions.as_ref(),
+                        disagree,
+                    );
+                }),
+                None => show_network(
+                    ui,
+                    "MLP",
+                    self.dataset,
+                    &self.multilayer_perceptron,
+                    mlp_predictions.as_ref(),
+                    false,
+                ),
+            }
+            if let (Some(mlp), Some(conv)) = guesses {
+                if mlp != conv {
+                    ui.colored_label(
+                        Color32::RED,
+                        format!(
+                            "The networks disagree : the MLP guess {}, the ConvNet {}",
+                            self.dataset.class_name(mlp),
+                            self.dataset.class_name(conv)
+                        ),
+                    );
+                }
+            }
+
+            if let Some(image) = &image {
+                let mut label = None;
+                ui.horizontal(|ui| {
+                    ui.label("Wrong guess ? It is a");
+                    for (class, name) in self.dataset.class_names().iter().enumerate() {
+                        if ui.button(*name).clicked() {
+                            label = Some(class as u8);
                         }
                     }
-
-                    let bar_chart = BarChart::new(bars)
-                        .name("Prediction Score")
-                        .color(egui::Color32::GREEN);
-                    Plot::new("Prediction score")
-                        .view_aspect(2.0)
-                        .show(ui, |plot_ui| {
-                            plot_ui.bar_chart(bar_chart);
-                        });
+                });
+                if let Some(label) = label {
+                    self.collect_drawing(image, label);
+                }
+            }
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "{} drawings collected in {:?}",
+                    self.drawings.len(),
+                    self.drawings_dir
+                ));
+                let ready = self.multilayer_perceptron.model().is_some()
+                    || self
+                        .convolutional_network
+                        .as_ref()
+                        .is_some_and(|network| network.model().is_some());
+                if ui
+                    .add_enabled(
+                        ready && !training && !self.drawings.is_empty(),
+                        Button::new("Fine-tune on my drawings"),
+                    )
+                    .clicked()
+                {
+                    self.fine_tune();
                 }
+            });
+            if let Some(error) = &self.drawings_error {
+                ui.colored_label(Color32::RED, error);
+            }
+
+            if self.show_feature_maps {
+                self.update_feature_maps(ui.ctx(), image.as_ref());
             }
         });
+
+        Window::new("Feature maps of the ConvNet")
+            .open(&mut self.show_feature_maps)
+            .vscroll(true)
+            .show(context, |ui| match &self.feature_maps {
+                Some(feature_maps) => feature_maps.show(ui),
+                None => {
+                    ui.label("Draw a number to see the feature maps of the trained ConvNet");
+                }
+            });
+
+        if self.show_test_set && self.test_set.is_none() {
+            self.test_set =
+                Some(TestSetBrowser::load(self.dataset).map_err(|error| error.to_string()));
+        }
+        let mut networks = vec![("MLP", self.multilayer_perceptron.model())];
+        if let Some(convolutional_network) = &self.convolutional_network {
+            networks.push(("ConvNet", convolutional_network.model()));
+        }
+        let mut clicked = None;
+        Window::new("MNIST test set")
+            .open(&mut self.show_test_set)
+            .vscroll(true)
+            .show(context, |ui| match &mut self.test_set {
+                Some(Ok(test_set)) => clicked = test_set.show(ui, &networks),
+                Some(Err(error)) => {
+                    ui.colored_label(Color32::RED, format!("Can't load the test set : {}", error));
+                }
+                None => {}
+            });
+        if let Some((name, input)) = clicked {
+            self.opened_image = Some(OpenedImage { name, input });
+        }
     }
 }
@@ -1,6 +1,7 @@
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use nn_lib::Float;
 
-#[derive(Parser, Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Ord, Hash, Default)]
+#[derive(Parser, Debug, Clone, PartialEq, PartialOrd, Default)]
 #[command(
     name = "neural network from scratch",
     about = "A simple neural network library written in rust",
@@ -10,15 +11,33 @@ use clap::{Parser, Subcommand, ValueEnum};
 pub struct Arguments {
     #[command(subcommand)]
     pub mode: Mode,
+
+    /// Seed the weights initialization, data shuffling, dropout and augmentation, for
+    /// reproducible runs
+    #[arg(long, global = true)]
+    pub seed: Option<u64>,
+
+    /// The images the mnist networks are trained and evaluated on, and the dataset mode inspects
+    #[arg(long, global = true, default_value = "digits")]
+    pub mnist: ArgsMnistDataset,
 }
 
-#[derive(Subcommand, Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Ord, Hash)]
+#[derive(Subcommand, Debug, Clone, PartialEq, PartialOrd)]
 pub enum Mode {
     /// Run in GUI mode
     Gui(GuiOptions),
 
     /// Run benchmarks
     Benchmark(BenchmarkOptions),
+
+    /// Train a network and save it to a file
+    Train(TrainOptions),
+
+    /// Evaluate a saved network on a dataset
+    Evaluate(EvaluateOptions),
+
+    /// Inspect the mnist dataset
+    Dataset(DatasetOptions),
 }
 
 impl Default for Mode {
@@ -27,25 +46,237 @@ impl Default for Mode {
     }
 }
 
-#[derive(Parser, Debug, Clone, Hash, PartialEq, Default, PartialOrd, Copy, Ord, Eq)]
+#[derive(Parser, Debug, Clone, Hash, PartialEq, Default, PartialOrd, Ord, Eq)]
 pub struct GuiOptions {
+    /// Train the mnist networks on the training images followed by randomly rotated and shifted
+    /// copies of them
     #[arg(short, long, default_value = "false")]
     pub augment: bool,
 
+    /// Number of augmented copies of every training image with `--augment`
+    #[arg(long, default_value = "1", requires = "augment")]
+    pub augmented_copies: usize,
+
     #[arg(short, long, default_value = "false")]
     pub with_conv: bool,
+
+    /// Directory where the trained networks are saved, and loaded from instead of being
+    /// trained again when present
+    #[arg(short, long, default_value = "models")]
+    pub model_dir: std::path::PathBuf,
+
+    /// File of the multilayer perceptron, as written by the train mode, loaded in place of the
+    /// one of the model directory
+    #[arg(long)]
+    pub model: Option<std::path::PathBuf>,
+
+    /// File of the convolutional network, as written by the train mode, loaded in place of the
+    /// one of the model directory (implies `--with-conv`)
+    #[arg(long)]
+    pub conv_model: Option<std::path::PathBuf>,
+
+    /// Train the networks again, instead of loading those of the model directory or the one
+    /// bundled with the `embedded-model` feature
+    #[arg(long, conflicts_with_all = ["model", "conv_model"])]
+    pub retrain: bool,
+
+    /// Directory where the drawings labeled in the app are collected, to fine-tune the networks
+    /// on them
+    #[arg(long, default_value = "drawings")]
+    pub drawings_dir: std::path::PathBuf,
 }
 
-#[derive(Parser, Debug, Clone, PartialEq, Default, PartialOrd, Copy, Ord, Eq, Hash)]
+/// The training hyperparameters, shared by the benchmark and train modes
+#[derive(Args, Debug, Clone, PartialEq, Default, PartialOrd)]
+pub struct HyperparameterOptions {
+    /// Number of training epochs
+    /// [default: 2000 for xor, 1000 for parity and 10 for mnist and cifar10]
+    #[arg(short, long)]
+    pub epochs: Option<usize>,
+
+    /// Number of samples of the training batches
+    /// [default: 16 for xor and parity, 128 for mnist, 64 for cifar10]
+    #[arg(long)]
+    pub batch_size: Option<usize>,
+
+    /// Learning rate of the mnist and cifar10 networks
+    /// [default: 0.1 for mlp, 0.01 for conv and cifar10]
+    #[arg(long)]
+    pub learning_rate: Option<Float>,
+
+    /// Optimizer of the mnist networks
+    #[arg(long, default_value = "sgd")]
+    pub optimizer: ArgsOptimizer,
+
+    /// Sizes of the hidden dense layers of the mnist networks, comma separated (ex: 512,256),
+    /// each one followed by a ReLU. For the conv network, they come after the convolution block
+    #[arg(long, value_delimiter = ',')]
+    pub hidden_sizes: Option<Vec<usize>>,
+
+    /// Mix the images and labels of every training batch of the mnist and cifar10 networks, the
+    /// mixing weights being drawn from a Beta(ALPHA, ALPHA) distribution (ex: 0.2)
+    #[arg(long, value_name = "ALPHA")]
+    pub mixup: Option<Float>,
+
+    /// Erase a square of this side, in pixels, from half the images of every training batch of
+    /// the mnist and cifar10 networks (ex: 8)
+    #[arg(long, value_name = "SIZE")]
+    pub cutout: Option<usize>,
+
+    /// Stop the training after this many minutes, keeping the epochs done so far, ex: to compare
+    /// networks trained for the same time
+    #[arg(long)]
+    pub max_minutes: Option<f64>,
+
+    /// What the training does on a NaN or infinite value in the outputs of the layers, the loss
+    /// or the gradients [default: warn, or the one of the --config file]
+    #[arg(long)]
+    pub nan_guard: Option<ArgsNumericGuard>,
+
+    /// File defining the network, its optimizer, cost, metrics and training hyperparameters, as
+    /// TOML, YAML or JSON, in place of the built-in network of the example. The epochs, batch
+    /// size and learning rate given on the command line take precedence over the file
+    #[arg(long)]
+    pub config: Option<std::path::PathBuf>,
+}
+
+#[derive(Parser, Debug, Clone, PartialEq, Default, PartialOrd)]
 pub struct BenchmarkOptions {
     #[arg(short, long, default_value = "xor")]
     pub run: Exemple,
 
-    #[arg(short, long)]
-    pub epochs: Option<usize>,
-
     #[arg(short, long, default_value = "mlp")]
     pub net_type: ArgsNetType,
+
+    #[command(flatten)]
+    pub hyperparameters: HyperparameterOptions,
+
+    /// Number of input bits of the parity benchmark
+    #[arg(short, long, default_value = "4")]
+    pub bits: usize,
+
+    /// Stop the training once the validation loss didn't improve for this many epochs, and
+    /// restore the weights of the best epoch
+    #[arg(long)]
+    pub patience: Option<usize>,
+
+    /// Show a progress bar with the running loss and ETA during training
+    #[arg(long)]
+    pub progress: bool,
+
+    /// Write the hyperparameters, the loss, metrics and duration of every epoch and the test
+    /// results of the trained networks to this file, as CSV for a `.csv` path and JSON otherwise
+    #[arg(long)]
+    pub report: Option<std::path::PathBuf>,
+
+    /// Time the forward and backward passes of every layer, and report them after the training
+    #[arg(long)]
+    pub profile: bool,
+
+    /// Directory the training state and histories of the network are written to when the
+    /// training is interrupted by Ctrl+C, to resume it with `Sequential::train_from_checkpoint`
+    #[arg(long, default_value = "checkpoints")]
+    pub checkpoint_dir: std::path::PathBuf,
+
+    /// Stream the training metrics as JSON over a WebSocket bound on this address
+    #[cfg(feature = "websocket")]
+    #[arg(long)]
+    pub stream: Option<std::net::SocketAddr>,
+
+    /// Expose a Prometheus `/metrics` endpoint bound on this address while training
+    #[cfg(feature = "prometheus")]
+    #[arg(long)]
+    pub metrics_address: Option<std::net::SocketAddr>,
+
+    /// Compute the matrix products of the dense and convolutional layers on the GPU
+    #[cfg(feature = "wgpu")]
+    #[arg(long)]
+    pub gpu: bool,
+}
+
+#[derive(Parser, Debug, Clone, PartialEq, Default, PartialOrd)]
+pub struct TrainOptions {
+    #[arg(long, default_value = "mnist")]
+    pub example: Exemple,
+
+    #[arg(long, visible_alias = "net-type", default_value = "mlp")]
+    pub net: ArgsNetType,
+
+    /// Number of input bits of the parity network
+    #[arg(short, long, default_value = "4")]
+    pub bits: usize,
+
+    /// Train the mnist networks on the training images followed by randomly rotated and shifted
+    /// copies of them
+    #[arg(short, long)]
+    pub augment: bool,
+
+    /// Number of augmented copies of every training image with `--augment`
+    #[arg(long, default_value = "1", requires = "augment")]
+    pub augmented_copies: usize,
+
+    /// File the trained network is written to, to be loaded with `Sequential::load`
+    #[arg(short, long)]
+    pub out: std::path::PathBuf,
+
+    /// File the training state and histories of the network are written to when the training is
+    /// interrupted by Ctrl+C, to resume it with `Sequential::train_from_checkpoint` [default: the
+    /// output file with the `checkpoint` extension]
+    #[arg(long)]
+    pub checkpoint: Option<std::path::PathBuf>,
+
+    /// Show a progress bar with the running loss and ETA during training
+    #[arg(long)]
+    pub progress: bool,
+
+    #[command(flatten)]
+    pub hyperparameters: HyperparameterOptions,
+}
+
+#[derive(Parser, Debug, Clone, PartialEq, Eq, Default, PartialOrd, Ord, Hash)]
+pub struct EvaluateOptions {
+    /// File of the network, as written by the train mode or `Sequential::save`
+    #[arg(short, long)]
+    pub model: std::path::PathBuf,
+
+    #[arg(short, long, default_value = "mnist-test")]
+    pub dataset: EvaluationDataset,
+
+    #[arg(short, long, default_value = "128")]
+    pub batch_size: usize,
+}
+
+#[derive(Parser, Debug, Clone, Eq, PartialEq, PartialOrd, Ord, Hash)]
+pub struct DatasetOptions {
+    #[command(subcommand)]
+    pub command: DatasetCommand,
+}
+
+#[derive(Subcommand, Debug, Clone, Eq, PartialEq, PartialOrd, Ord, Hash)]
+pub enum DatasetCommand {
+    /// Print statistics about the training and test sets
+    Info {
+        /// Number of samples rendered as ascii art for each set
+        #[arg(short, long, default_value = "2")]
+        previews: usize,
+    },
+
+    /// Write the training and test sets as uncompressed IDX files, to reload them with
+    /// `MnistData::load_from`
+    Export {
+        /// Directory the files are written to
+        #[arg(short, long)]
+        out: std::path::PathBuf,
+
+        /// Follow the images of the training set by randomly rotated and shifted copies of them,
+        /// as the `--augment` trainings
+        #[arg(short, long)]
+        augment: bool,
+
+        /// Number of augmented copies of every training image with `--augment`
+        #[arg(long, default_value = "1", requires = "augment")]
+        augmented_copies: usize,
+    },
 }
 
 #[derive(Copy, Clone, ValueEnum, Debug, PartialOrd, Eq, PartialEq, Ord, Hash, Default)]
@@ -57,6 +288,24 @@ pub enum ArgsNetType {
     Conv,
 }
 
+#[derive(Copy, Clone, ValueEnum, Debug, PartialOrd, Eq, PartialEq, Ord, Hash, Default)]
+pub enum ArgsOptimizer {
+    /// Plain gradient descent
+    #[clap(alias = "sgd")]
+    #[default]
+    Sgd,
+}
+
+#[derive(Copy, Clone, ValueEnum, Debug, PartialOrd, Eq, PartialEq, Ord, Hash)]
+pub enum ArgsNumericGuard {
+    /// Don't check the values
+    Off,
+    /// Log a warning and keep training
+    Warn,
+    /// Stop the training with an error naming the layer
+    Abort,
+}
+
 #[derive(Copy, Clone, ValueEnum, Debug, PartialOrd, Eq, PartialEq, Ord, Default, Hash)]
 pub enum Exemple {
     #[clap(alias = "mnist")]
@@ -64,4 +313,28 @@ pub enum Exemple {
     Mnist,
     #[clap(alias = "xor")]
     Xor,
+    #[clap(alias = "parity")]
+    Parity,
+    /// The color photos of CIFAR-10, with an example convolutional network
+    #[clap(alias = "cifar10")]
+    Cifar10,
+}
+
+#[derive(Copy, Clone, ValueEnum, Debug, PartialOrd, Eq, PartialEq, Ord, Hash, Default)]
+pub enum ArgsMnistDataset {
+    /// The handwritten digits of mnist
+    #[default]
+    Digits,
+    /// Fashion-MNIST, photos of clothes and shoes in the mnist format, its files going in
+    /// `mnist/resources/fashion/compressed`
+    Fashion,
+}
+
+#[derive(Copy, Clone, ValueEnum, Debug, PartialOrd, Eq, PartialEq, Ord, Hash, Default)]
+pub enum EvaluationDataset {
+    /// The 10000 images of the test set
+    #[default]
+    MnistTest,
+    /// The 60000 images of the training set
+    MnistTrain,
 }
@@ -1,61 +1,608 @@
 mod app;
 mod args;
-mod xor;
+mod parity;
+mod report;
+mod test_set;
+mod training;
+mod visualization;
 
-use app::Application;
-use args::{ArgsNetType, Arguments, Exemple, Mode};
+use app::{Application, NetworkSource};
+use args::{
+    ArgsMnistDataset, ArgsNetType, ArgsNumericGuard, ArgsOptimizer, Arguments, BenchmarkOptions,
+    DatasetCommand, EvaluateOptions, EvaluationDataset, Exemple, GuiOptions, HyperparameterOptions,
+    Mode, TrainOptions,
+};
 use clap::Parser;
-use mnist::network_definition::NetType;
+use log::{info, warn};
+use mnist::{
+    dataset::Dataset,
+    network_definition::{NetConfig, NetType, OptimizerType},
+};
+use nn_lib::{
+    callback::{CancellationToken, EarlyStopping, Monitor},
+    config::NetworkConfig,
+    guard::NumericGuard,
+    metrics::ClassificationReport,
+    progress::{ProgressReporter, Verbosity},
+    sequential::Sequential,
+};
+use report::{BenchmarkReport, Hyperparameters, RunReport};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use training::BackgroundTraining;
+
+/// Batch size and number of epochs of the mnist trainings
+const MNIST_BATCH_SIZE: usize = 128;
+const MNIST_EPOCHS: usize = 10;
+
+/// Batch size and number of epochs of the cifar10 trainings
+const CIFAR_BATCH_SIZE: usize = 64;
+const CIFAR_EPOCHS: usize = 10;
 
 fn main() -> anyhow::Result<()> {
-    pretty_env_logger::init();
+    init_logging();
     let cli = Arguments::parse();
+    if let Some(seed) = cli.seed {
+        nn_lib::random::seed(seed);
+    }
+    // the other modes keep the default Ctrl+C behaviour, ending the process at once
+    let cancellation = match &cli.mode {
+        Mode::Benchmark(_) | Mode::Train(_) => install_interrupt_handler()?,
+        _ => CancellationToken::new(),
+    };
+    let dataset = mnist_dataset(cli.mnist);
 
     match &cli.mode {
         Mode::Gui(options) => {
             let native_options = eframe::NativeOptions::default();
 
-            let mut multilayer_perceptron = mnist::get_neural_net(NetType::Mlp)?;
+            let multilayer_perceptron = load_or_train(
+                NetType::Mlp,
+                dataset,
+                options.model.as_deref(),
+                options,
+                &cancellation,
+            )?;
 
-            let mut convolutional_perceptron = if options.with_conv {
-                Some(mnist::get_neural_net(NetType::Conv)?)
+            let convolutional_perceptron = if options.with_conv || options.conv_model.is_some() {
+                Some(load_or_train(
+                    NetType::Conv,
+                    dataset,
+                    options.conv_model.as_deref(),
+                    options,
+                    &cancellation,
+                )?)
             } else {
                 None
             };
 
-            mnist::start(&mut multilayer_perceptron, 128, 10, options.augment)?;
-
-            if let Some(ref mut cnn) = convolutional_perceptron {
-                mnist::start(cnn, 128, 10, options.augment)?
-            }
+            let drawings_dir = dataset_dir(&options.drawings_dir, dataset);
+            let drawings = mnist::drawings::Drawings::load(&drawings_dir)?;
 
             eframe::run_native(
                 "Draw a number",
                 native_options,
-                Box::new(|cc| {
+                Box::new(move |cc| {
                     Box::new(Application::new(
                         cc,
                         multilayer_perceptron,
                         convolutional_perceptron,
+                        drawings,
+                        drawings_dir,
+                        dataset,
                     ))
                 }),
             )
             .unwrap();
         }
-        Mode::Benchmark(options) => match options.run {
-            Exemple::Xor => {
-                let net = xor::build_neural_net()?;
-                xor::start(net)?;
+        Mode::Benchmark(options) => {
+            let mut benchmark_report = BenchmarkReport::default();
+            let config = load_config(&options.hyperparameters)?;
+            let hyperparameters = &with_config_defaults(&options.hyperparameters, config.as_ref());
+            match options.run {
+                Exemple::Parity if config.is_none() => {
+                    let epochs = hyperparameters.epochs.unwrap_or(1000);
+                    let batch_size = hyperparameters.batch_size.unwrap_or(parity::BATCH_SIZE);
+                    let reports =
+                        parity::benchmark(options.bits, epochs, batch_size, &cancellation)?;
+                    for (parity_config, report) in reports {
+                        benchmark_report.runs.push(RunReport::new(
+                            Hyperparameters {
+                                network: parity_config.name(),
+                                epochs,
+                                batch_size,
+                                learning_rate: parity::LEARNING_RATE,
+                                seed: cli.seed,
+                            },
+                            &report.train_history,
+                            Some(&report.validation_history),
+                            None,
+                        ));
+                    }
+                }
+                Exemple::Xor | Exemple::Parity => {
+                    let (bits, default_epochs, default_name) = match options.run {
+                        Exemple::Xor => (2, 2000, String::from("xor")),
+                        _ => (options.bits, 1000, format!("{}-bit parity", options.bits)),
+                    };
+                    let epochs = hyperparameters.epochs.unwrap_or(default_epochs);
+                    let batch_size = hyperparameters.batch_size.unwrap_or(parity::BATCH_SIZE);
+                    let (mut net, network) = match &config {
+                        Some(config) => (config.build()?, config_name(config)),
+                        None => (
+                            parity::build_neural_net(&parity::ParityConfig::new(bits))?,
+                            default_name,
+                        ),
+                    };
+                    attach_callbacks(&mut net, &network, options, &cancellation)?;
+                    let report = parity::start(&mut net, bits, epochs, batch_size)?;
+                    info!("{} accuracy : {:.2}%", network, report.accuracy * 100.0);
+                    benchmark_report.runs.push(RunReport::new(
+                        Hyperparameters {
+                            network,
+                            epochs,
+                            batch_size,
+                            learning_rate: net.optimizer().get_learning_rate(),
+                            seed: cli.seed,
+                        },
+                        &report.train_history,
+                        Some(&report.validation_history),
+                        None,
+                    ));
+                }
+                Exemple::Mnist => {
+                    let epochs = hyperparameters.epochs.unwrap_or(MNIST_EPOCHS);
+                    let batch_size = hyperparameters.batch_size.unwrap_or(MNIST_BATCH_SIZE);
+                    let (mut net, network) = match &config {
+                        Some(config) => (config.build()?, config_name(config)),
+                        None => mnist_net(options.net_type, dataset, hyperparameters)?,
+                    };
+                    attach_callbacks(&mut net, &network, options, &cancellation)?;
+                    let outcome = mnist::start(&mut net, dataset, batch_size, epochs, 0)?;
+                    benchmark_report.runs.push(RunReport::new(
+                        Hyperparameters {
+                            network,
+                            epochs,
+                            batch_size,
+                            learning_rate: net.optimizer().get_learning_rate(),
+                            seed: cli.seed,
+                        },
+                        &outcome.train_history,
+                        Some(&outcome.validation_history),
+                        Some(&outcome.test),
+                    ));
+                }
+                Exemple::Cifar10 => {
+                    let epochs = hyperparameters.epochs.unwrap_or(CIFAR_EPOCHS);
+                    let batch_size = hyperparameters.batch_size.unwrap_or(CIFAR_BATCH_SIZE);
+                    let (mut net, network) = match &config {
+                        Some(config) => (config.build()?, config_name(config)),
+                        None => (
+                            mnist::cifar::get_cifar_net(&net_config(hyperparameters))?,
+                            String::from("cifar10 conv"),
+                        ),
+                    };
+                    attach_callbacks(&mut net, &network, options, &cancellation)?;
+                    let outcome = mnist::cifar::start_cifar(&mut net, batch_size, epochs)?;
+                    benchmark_report.runs.push(RunReport::new(
+                        Hyperparameters {
+                            network,
+                            epochs,
+                            batch_size,
+                            learning_rate: net.optimizer().get_learning_rate(),
+                            seed: cli.seed,
+                        },
+                        &outcome.train_history,
+                        Some(&outcome.validation_history),
+                        Some(&outcome.test),
+                    ));
+                }
             }
-            Exemple::Mnist => {
-                let net_type = match options.net_type {
-                    ArgsNetType::Mlp => NetType::Mlp,
-                    ArgsNetType::Conv => NetType::Conv,
-                };
-                let mut net = mnist::get_neural_net(net_type)?;
-                mnist::start(&mut net, 128, 10, false)?;
+            if let Some(path) = &options.report {
+                benchmark_report.write(path)?;
+                info!("Benchmark report written to {:?}", path);
             }
+            if cancellation.is_cancelled() {
+                warn!(
+                    "Benchmark interrupted, the training state is saved in {:?}",
+                    options.checkpoint_dir
+                );
+            }
+        }
+        Mode::Train(options) => train(options, dataset, &cancellation)?,
+        Mode::Evaluate(options) => evaluate(options, dataset)?,
+        Mode::Dataset(options) => match &options.command {
+            DatasetCommand::Info { previews } => {
+                let dataset = mnist::dataset::load_dataset(dataset)?;
+                println!("Training set\n{}", dataset.training_stats(*previews));
+                println!("Test set\n{}", dataset.test_stats(*previews));
+            }
+            DatasetCommand::Export {
+                out,
+                augment,
+                augmented_copies: copies,
+            } => {
+                let mut dataset = mnist::dataset::load_dataset(dataset)?;
+                if *augment {
+                    let (images, labels) = &dataset.training;
+                    dataset.training = mnist::augments::augment_with_copies(
+                        images,
+                        labels,
+                        *copies,
+                        &mut nn_lib::random::generator(),
+                    )?;
+                }
+                dataset.save(out)?;
+            }
+        },
+    }
+    Ok(())
+}
+
+/// With the `tracing` feature, spans around training, epochs, batches and layer passes are
+/// reported along with their timing when closed, `log` records are forwarded to the same
+/// subscriber. Both are filtered with `RUST_LOG`.
+#[cfg(feature = "tracing")]
+fn init_logging() {
+    use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_span_events(FmtSpan::CLOSE)
+        .init();
+}
+
+#[cfg(not(feature = "tracing"))]
+fn init_logging() {
+    pretty_env_logger::init();
+}
+
+/// In the benchmark and train modes, the first Ctrl+C cancel the running trainings, which stop
+/// cleanly after their current batch with a partial history, their state being written to their
+/// checkpoint file. A second Ctrl+C abort the process.
+fn install_interrupt_handler() -> anyhow::Result<CancellationToken> {
+    let cancellation = CancellationToken::new();
+    let handler_token = cancellation.clone();
+    ctrlc::set_handler(move || {
+        if handler_token.is_cancelled() {
+            std::process::exit(130);
+        }
+        warn!("Interrupted, stopping the training after the current batch (Ctrl+C again to abort)");
+        handler_token.cancel();
+    })?;
+    Ok(cancellation)
+}
+
+fn mnist_dataset(mnist: ArgsMnistDataset) -> Dataset {
+    match mnist {
+        ArgsMnistDataset::Digits => Dataset::Digits,
+        ArgsMnistDataset::Fashion => Dataset::Fashion,
+    }
+}
+
+/// The directory of the files of the GUI for `dataset` (trained networks, drawings), a
+/// `fashion` subdirectory of `dir` for Fashion-MNIST
+fn dataset_dir(dir: &Path, dataset: Dataset) -> PathBuf {
+    match dataset {
+        Dataset::Digits => dir.to_path_buf(),
+        Dataset::Fashion => dir.join("fashion"),
+    }
+}
+
+/// The mnist network hyperparameters requested on the command line
+fn net_config(hyperparameters: &HyperparameterOptions) -> NetConfig {
+    NetConfig {
+        learning_rate: hyperparameters.learning_rate,
+        optimizer: match hyperparameters.optimizer {
+            ArgsOptimizer::Sgd => OptimizerType::GradientDescent,
         },
+        hidden_sizes: hyperparameters.hidden_sizes.clone(),
+        mixup: hyperparameters.mixup,
+        cutout: hyperparameters.cutout,
+    }
+}
+
+/// The time budget of the trainings, if any
+fn max_duration(hyperparameters: &HyperparameterOptions) -> Option<Duration> {
+    hyperparameters
+        .max_minutes
+        .map(|minutes| Duration::from_secs_f64(minutes.max(0.0) * 60.0))
+}
+
+/// Set the NaN and infinite values handling requested on the command line, if any
+fn set_numeric_guard(net: &mut Sequential, hyperparameters: &HyperparameterOptions) {
+    let guard = match hyperparameters.nan_guard {
+        Some(ArgsNumericGuard::Off) => NumericGuard::Off,
+        Some(ArgsNumericGuard::Warn) => NumericGuard::Warn,
+        Some(ArgsNumericGuard::Abort) => NumericGuard::AbortWithError,
+        None => return,
+    };
+    net.set_numeric_guard(guard);
+}
+
+/// Number of augmented copies of the training images, none without `--augment`
+fn augmented_copies(augment: bool, copies: usize) -> usize {
+    if augment {
+        copies
+    } else {
+        0
+    }
+}
+
+/// The mnist network of `net_type` with the hyperparameters requested on the command line, and
+/// its name in the reports, ex: `mnist conv 512-256`
+fn mnist_net(
+    net_type: ArgsNetType,
+    dataset: Dataset,
+    hyperparameters: &HyperparameterOptions,
+) -> anyhow::Result<(Sequential, String)> {
+    let (net_type, mut network) = match net_type {
+        ArgsNetType::Mlp => (NetType::Mlp, format!("{} mlp", dataset.name())),
+        ArgsNetType::Conv => (NetType::Conv, format!("{} conv", dataset.name())),
+    };
+    if let Some(hidden_sizes) = &hyperparameters.hidden_sizes {
+        let sizes = hidden_sizes.iter().map(|size| size.to_string());
+        network = format!("{} {}", network, sizes.collect::<Vec<_>>().join("-"));
+    }
+    let net = mnist::get_neural_net(net_type, &net_config(hyperparameters))?;
+    Ok((net, network))
+}
+
+/// The network file given with `--config` if any, the learning rate of the command line taking
+/// precedence over the one of the file
+fn load_config(hyperparameters: &HyperparameterOptions) -> anyhow::Result<Option<NetworkConfig>> {
+    let Some(path) = &hyperparameters.config else {
+        return Ok(None);
+    };
+    let mut config = NetworkConfig::from_file(path)?;
+    if let Some(learning_rate) = hyperparameters.learning_rate {
+        config.optimizer.set_learning_rate(learning_rate);
+    }
+    if config.name.is_none() {
+        config.name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned());
+    }
+    info!("Network defined by {:?}", path);
+    Ok(Some(config))
+}
+
+/// The name of a network defined by a file, in the logs and reports
+fn config_name(config: &NetworkConfig) -> String {
+    config
+        .name
+        .clone()
+        .unwrap_or_else(|| String::from("config"))
+}
+
+/// The hyperparameters of the command line, the epochs and batch size left unset being taken
+/// from the training section of the network file
+fn with_config_defaults(
+    hyperparameters: &HyperparameterOptions,
+    config: Option<&NetworkConfig>,
+) -> HyperparameterOptions {
+    let mut hyperparameters = hyperparameters.clone();
+    if let Some(training) = config.map(|config| &config.training) {
+        hyperparameters.epochs = hyperparameters.epochs.or(training.epochs);
+        hyperparameters.batch_size = hyperparameters.batch_size.or(training.batch_size);
+    }
+    hyperparameters
+}
+
+/// Build and train the requested network, then write it to the output file, unless the
+/// training was interrupted
+fn train(
+    options: &TrainOptions,
+    dataset: Dataset,
+    cancellation: &CancellationToken,
+) -> anyhow::Result<()> {
+    let config = load_config(&options.hyperparameters)?;
+    let hyperparameters = &with_config_defaults(&options.hyperparameters, config.as_ref());
+    let mut net = match (&config, options.example) {
+        (Some(config), _) => config.build()?,
+        (None, Exemple::Mnist) => mnist_net(options.net, dataset, hyperparameters)?.0,
+        (None, Exemple::Xor) => parity::build_neural_net(&parity::ParityConfig::new(2))?,
+        (None, Exemple::Parity) => {
+            parity::build_neural_net(&parity::ParityConfig::new(options.bits))?
+        }
+        (None, Exemple::Cifar10) => mnist::cifar::get_cifar_net(&net_config(hyperparameters))?,
+    };
+    net.add_callback(cancellation.clone());
+    if options.progress {
+        net.add_callback(ProgressReporter::new(Verbosity::Progress));
+    }
+    let checkpoint = options
+        .checkpoint
+        .clone()
+        .unwrap_or_else(|| options.out.with_extension("checkpoint"));
+    net.set_checkpoint_on_stop(&checkpoint);
+    net.set_max_duration(max_duration(hyperparameters));
+    set_numeric_guard(&mut net, hyperparameters);
+
+    match options.example {
+        Exemple::Mnist => {
+            mnist::start(
+                &mut net,
+                dataset,
+                hyperparameters.batch_size.unwrap_or(MNIST_BATCH_SIZE),
+                hyperparameters.epochs.unwrap_or(MNIST_EPOCHS),
+                augmented_copies(options.augment, options.augmented_copies),
+            )?;
+        }
+        Exemple::Cifar10 => {
+            mnist::cifar::start_cifar(
+                &mut net,
+                hyperparameters.batch_size.unwrap_or(CIFAR_BATCH_SIZE),
+                hyperparameters.epochs.unwrap_or(CIFAR_EPOCHS),
+            )?;
+        }
+        Exemple::Xor | Exemple::Parity => {
+            let (bits, default_epochs) = match options.example {
+                Exemple::Xor => (2, 2000),
+                _ => (options.bits, 1000),
+            };
+            let report = parity::start(
+                &mut net,
+                bits,
+                hyperparameters.epochs.unwrap_or(default_epochs),
+                hyperparameters.batch_size.unwrap_or(parity::BATCH_SIZE),
+            )?;
+            info!("Accuracy : {:.2}%", report.accuracy * 100.0);
+        }
+    }
+
+    if cancellation.is_cancelled() {
+        warn!(
+            "Training interrupted, the network is not saved, its training state is in {:?}",
+            checkpoint
+        );
+        return Ok(());
+    }
+    if let Some(dir) = options.out.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    net.save(&options.out)?;
+    info!("Trained network saved to {:?}", options.out);
+    Ok(())
+}
+
+/// Load a saved network and print its loss, accuracy, confusion matrix and classification
+/// report on the requested dataset
+fn evaluate(options: &EvaluateOptions, dataset: Dataset) -> anyhow::Result<()> {
+    let net = Sequential::load(&options.model)?;
+    let (train, test) = mnist::network_definition::get_evaluation_data(dataset)?;
+    let (x, y) = match options.dataset {
+        EvaluationDataset::MnistTest => test,
+        EvaluationDataset::MnistTrain => train,
+    };
+    let bench = net.evaluate_with_confusion_matrix((&x, &y), options.batch_size)?;
+
+    println!("Loss : {}", bench.loss);
+    if let Some(confusion_matrix) = &bench.confusion_matrix {
+        let report = ClassificationReport::from(confusion_matrix);
+        println!("Accuracy : {:.2}%", report.accuracy * 100.0);
+        println!("Confusion matrix :\n{}", confusion_matrix);
+        println!("Classification report :\n{}", report);
+    }
+    Ok(())
+}
+
+/// The mnist multilayer perceptron bundled in the binary with the `embedded-model` feature,
+/// loaded by the GUI when none is saved in the model directory
+#[cfg(feature = "embedded-model")]
+const EMBEDDED_MLP: Option<&[u8]> = Some(include_bytes!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/models/embedded/mlp.bin"
+)));
+#[cfg(not(feature = "embedded-model"))]
+const EMBEDDED_MLP: Option<&[u8]> = None;
+
+/// Load the network from `model` if given, or else from the model directory if it was saved
+/// there (unless `--retrain`), or else the bundled multilayer perceptron, otherwise train it on
+/// `dataset` on a worker thread while the app is running, saving it in the model directory for
+/// the next run.
+fn load_or_train(
+    net_type: NetType,
+    dataset: Dataset,
+    model: Option<&Path>,
+    options: &GuiOptions,
+    cancellation: &CancellationToken,
+) -> anyhow::Result<NetworkSource> {
+    let file_name = match net_type {
+        NetType::Mlp => "mlp.bin",
+        NetType::Conv => "conv.bin",
+    };
+    let model_dir = dataset_dir(&options.model_dir, dataset);
+    let path = model.map_or_else(|| model_dir.join(file_name), Path::to_path_buf);
+    if model.is_some() || (path.exists() && !options.retrain) {
+        info!("Loading the trained network from {:?}", path);
+        return Ok(NetworkSource::ready(Sequential::load(&path)?)?);
+    }
+    let bundled = match (&net_type, dataset) {
+        (NetType::Mlp, Dataset::Digits) if !options.retrain => EMBEDDED_MLP,
+        _ => None,
+    };
+    if let Some(bytes) = bundled {
+        match Sequential::from_bytes(bytes) {
+            Ok(net) => {
+                info!("Loading the network bundled in the binary");
+                return Ok(NetworkSource::ready(net)?);
+            }
+            Err(error) => warn!(
+                "The bundled network can't be loaded, training it: {}",
+                error
+            ),
+        }
+    }
+    info!(
+        "No trained network in {:?}, training it in the background",
+        model_dir
+    );
+
+    let augmented_copies = augmented_copies(options.augment, options.augmented_copies);
+    let cancellation = cancellation.clone();
+    let training = BackgroundTraining::spawn(move |progress| {
+        let mut net = mnist::get_neural_net(net_type, &NetConfig::default())?;
+        net.add_callback(cancellation.clone());
+        net.add_callback(progress);
+        mnist::start(
+            &mut net,
+            dataset,
+            MNIST_BATCH_SIZE,
+            MNIST_EPOCHS,
+            augmented_copies,
+        )?;
+        if cancellation.is_cancelled() {
+            warn!("Training interrupted, the network is not saved");
+        } else {
+            std::fs::create_dir_all(&model_dir)?;
+            net.save(&path)?;
+            info!("Trained network saved to {:?}", path);
+        }
+        Ok(net)
+    });
+    Ok(NetworkSource::Training(training))
+}
+
+/// Register the training callbacks, and select the backend, requested on the command line,
+/// the training state of `network` being written to the checkpoint directory if interrupted
+#[allow(unused_variables)]
+fn attach_callbacks(
+    net: &mut Sequential,
+    network: &str,
+    options: &BenchmarkOptions,
+    cancellation: &CancellationToken,
+) -> anyhow::Result<()> {
+    net.add_callback(cancellation.clone());
+    net.set_max_duration(max_duration(&options.hyperparameters));
+    set_numeric_guard(net, &options.hyperparameters);
+    let file_name = format!("{}.checkpoint", network.replace(' ', "-"));
+    net.set_checkpoint_on_stop(options.checkpoint_dir.join(file_name));
+    if options.progress {
+        net.add_callback(ProgressReporter::new(Verbosity::Progress));
+    }
+    if options.profile {
+        net.set_profiling(true);
+    }
+    if let Some(patience) = options.patience {
+        net.add_callback(
+            EarlyStopping::new(Monitor::ValidationLoss, patience).with_restore_best_weights(true),
+        );
+    }
+    #[cfg(feature = "websocket")]
+    if let Some(address) = options.stream {
+        net.add_callback(nn_lib::websocket::MetricsStreamer::bind(address)?.with_batches(true));
+    }
+    #[cfg(feature = "prometheus")]
+    if let Some(address) = options.metrics_address {
+        net.add_callback(nn_lib::prometheus::PrometheusExporter::bind(address)?);
+    }
+    #[cfg(feature = "wgpu")]
+    if options.gpu {
+        let backend = nn_lib::backend::Backend::wgpu()?;
+        info!("Computing the matrix products on {}", backend.device_name());
+        net.set_backend(backend);
     }
     Ok(())
 }
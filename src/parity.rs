@@ -0,0 +1,190 @@
+use log::{debug, info};
+use ndarray::{Array2, ArrayD};
+use nn_lib::{
+    activation::Activation,
+    callback::CancellationToken,
+    cost::CostFunction,
+    initialization::InitializerType,
+    layer::{ActivationLayer, DenseLayer},
+    metrics::{History, MetricsType},
+    optimizer::GradientDescent,
+    sequential::{Sequential, SequentialBuilder},
+    Float,
+};
+
+/// Default size of the training batches of the parity networks
+pub const BATCH_SIZE: usize = 16;
+
+/// Learning rate of the parity networks
+pub const LEARNING_RATE: Float = 0.05;
+
+/// Architecture of a network learning the parity of `bits` inputs
+/// `depth` is the number of hidden layers, each of `width` neurons followed by `activation`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ParityConfig {
+    pub bits: usize,
+    pub depth: usize,
+    pub width: usize,
+    pub activation: Activation,
+}
+
+impl ParityConfig {
+    /// A single hidden ReLU layer, of width growing linearly with the number of bits
+    pub fn new(bits: usize) -> Self {
+        Self {
+            bits,
+            depth: 1,
+            width: 4 * bits,
+            activation: Activation::ReLU,
+        }
+    }
+
+    /// A short description of the architecture, ex: `6-bit parity depth 2 width 12 Tanh`
+    pub fn name(&self) -> String {
+        format!(
+            "{}-bit parity depth {} width {} {:?}",
+            self.bits, self.depth, self.width, self.activation
+        )
+    }
+}
+
+/// Outcome of the training of a parity network
+/// `solved_at` is the first epoch after which the whole truth table was correctly classified
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParityReport {
+    pub loss: Float,
+    pub accuracy: Float,
+    pub solved_at: Option<usize>,
+    pub train_history: History,
+    pub validation_history: History,
+}
+
+/// Generate the truth table of the parity function over `bits` inputs
+/// inputs are of shape (2^bits, bits), labels are one hot encoded as (even, odd) with shape
+/// (2^bits, 2)
+pub fn truth_table(bits: usize) -> (ArrayD<Float>, ArrayD<Float>) {
+    let rows = 1 << bits;
+    let x = Array2::from_shape_fn((rows, bits), |(row, bit)| ((row >> bit) & 1) as Float);
+    let y = Array2::from_shape_fn((rows, 2), |(row, class)| {
+        (row.count_ones() as usize % 2 == class) as u8 as Float
+    });
+    (x.into_dyn(), y.into_dyn())
+}
+
+pub fn build_neural_net(config: &ParityConfig) -> anyhow::Result<Sequential> {
+    let mut net = SequentialBuilder::new();
+    let mut input_size = config.bits;
+    for _ in 0..config.depth {
+        net = net
+            .push(DenseLayer::new(
+                input_size,
+                config.width,
+                InitializerType::GlorotUniform,
+            ))
+            .push(ActivationLayer::from(config.activation));
+        input_size = config.width;
+    }
+    let net = net
+        .push(DenseLayer::new(
+            input_size,
+            2,
+            InitializerType::GlorotUniform,
+        ))
+        .push(ActivationLayer::from(Activation::Softmax))
+        .watch(MetricsType::Accuracy);
+    Ok(net.compile(
+        GradientDescent::new(LEARNING_RATE),
+        CostFunction::cross_entropy(),
+    )?)
+}
+
+/// Train the network on the full truth table of the parity over `bits` inputs, the table is also
+/// used as validation data to detect the epoch at which it is solved
+pub fn start(
+    neural_network: &mut Sequential,
+    bits: usize,
+    epochs: usize,
+    batch_size: usize,
+) -> anyhow::Result<ParityReport> {
+    let (x, y) = truth_table(bits);
+
+    let (train_hist, validation_hist) =
+        neural_network.train((&x, &y), Some((&x, &y)), epochs, batch_size)?;
+
+    for (epoch, bench) in train_hist.iter() {
+        debug!("Error for epochs {} : {}", epoch, bench.loss);
+    }
+    if let Some(profile) = train_hist.profiles.last() {
+        info!("time spent by layer during the last epoch :\n{}", profile);
+    }
+
+    let validation_hist = validation_hist.expect("validation data was given to train");
+    let solved_at = validation_hist
+        .iter()
+        .find(|(_, bench)| bench.metrics.get_metric(MetricsType::Accuracy) == Some(1.0))
+        .map(|(epoch, _)| epoch);
+    let last = validation_hist.history.last().cloned().unwrap_or_default();
+
+    let predictions = neural_network.predict(&x)?;
+    for (input, prediction) in x.outer_iter().zip(predictions.outer_iter()) {
+        debug!(
+            "Parity prediction: {:.4} for input {}",
+            prediction[1],
+            input.iter().map(|bit| bit.to_string()).collect::<String>()
+        );
+    }
+
+    Ok(ParityReport {
+        loss: last.loss,
+        accuracy: last
+            .metrics
+            .get_metric(MetricsType::Accuracy)
+            .unwrap_or_default(),
+        solved_at,
+        train_history: train_hist,
+        validation_history: validation_hist,
+    })
+}
+
+/// Train a grid of architectures on the parity over `bits` inputs, varying the depth, the width
+/// (relative to the number of bits) and the hidden activation, and report which of them learn it.
+pub fn benchmark(
+    bits: usize,
+    epochs: usize,
+    batch_size: usize,
+    cancellation: &CancellationToken,
+) -> anyhow::Result<Vec<(ParityConfig, ParityReport)>> {
+    let mut reports = vec![];
+    for depth in [1, 2, 3] {
+        for width in [bits, 2 * bits, 4 * bits] {
+            for activation in [Activation::ReLU, Activation::Tanh, Activation::Sigmoid] {
+                if cancellation.is_cancelled() {
+                    return Ok(reports);
+                }
+                let config = ParityConfig {
+                    bits,
+                    depth,
+                    width,
+                    activation,
+                };
+                let mut net = build_neural_net(&config)?;
+                net.add_callback(cancellation.clone());
+                let report = start(&mut net, bits, epochs, batch_size)?;
+                info!(
+                    "{}-bit parity | depth {} | width {:>3} | {:<7} | loss {:.4} | accuracy {:>6.2}% | solved at {}",
+                    bits,
+                    depth,
+                    width,
+                    format!("{:?}", activation),
+                    report.loss,
+                    report.accuracy * 100.0,
+                    report
+                        .solved_at
+                        .map_or(String::from("never"), |epoch| format!("epoch {}", epoch))
+                );
+                reports.push((config, report));
+            }
+        }
+    }
+    Ok(reports)
+}
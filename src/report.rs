@@ -0,0 +1,148 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use nn_lib::{
+    metrics::{Benchmark, History, MetricsType},
+    Float,
+};
+use serde::Serialize;
+
+/// The results of a benchmark, a run per trained network, written with `--report` to track
+/// them from one version to the next
+#[derive(Serialize, Debug, Default)]
+pub struct BenchmarkReport {
+    pub runs: Vec<RunReport>,
+}
+
+/// The training of a network, with the final evaluation on the test set when there is one
+#[derive(Serialize, Debug)]
+pub struct RunReport {
+    pub hyperparameters: Hyperparameters,
+    pub epochs: Vec<EpochRecord>,
+    pub test_loss: Option<Float>,
+    pub test_accuracy: Option<Float>,
+}
+
+/// What the network was and how it was trained
+/// `network` is a short description of the architecture, ex: `mnist conv`
+#[derive(Serialize, Clone, Debug)]
+pub struct Hyperparameters {
+    pub network: String,
+    pub epochs: usize,
+    pub batch_size: usize,
+    pub learning_rate: Float,
+    pub seed: Option<u64>,
+}
+
+/// The train loss and accuracy of an epoch, with its duration in seconds, and the last
+/// validation of the epoch if any
+#[derive(Serialize, Clone, Debug)]
+pub struct EpochRecord {
+    pub epoch: usize,
+    pub train_loss: Float,
+    pub train_accuracy: Option<Float>,
+    pub validation_loss: Option<Float>,
+    pub validation_accuracy: Option<Float>,
+    pub duration: f64,
+    pub samples_per_second: f64,
+}
+
+impl RunReport {
+    pub fn new(
+        hyperparameters: Hyperparameters,
+        train_history: &History,
+        validation_history: Option<&History>,
+        test: Option<&Benchmark>,
+    ) -> Self {
+        let epochs = train_history
+            .iter()
+            .map(|(epoch, train)| {
+                let validation = validation_history.and_then(|history| {
+                    history
+                        .iter()
+                        .filter(|(validated, _)| *validated == epoch)
+                        .map(|(_, validation)| validation)
+                        .last()
+                });
+                EpochRecord {
+                    epoch,
+                    train_loss: train.loss,
+                    train_accuracy: train.metrics.get_metric(MetricsType::Accuracy),
+                    validation_loss: validation.map(|validation| validation.loss),
+                    validation_accuracy: validation.and_then(|validation| {
+                        validation.metrics.get_metric(MetricsType::Accuracy)
+                    }),
+                    duration: train.duration.as_secs_f64(),
+                    samples_per_second: train.samples_per_second(),
+                }
+            })
+            .collect();
+        Self {
+            hyperparameters,
+            epochs,
+            test_loss: test.map(|test| test.loss),
+            test_accuracy: test.and_then(|test| test.metrics.get_metric(MetricsType::Accuracy)),
+        }
+    }
+}
+
+impl BenchmarkReport {
+    /// Write the report as CSV if `path` has the `.csv` extension, as JSON otherwise
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("csv") => self.to_csv(path),
+            _ => self.to_json(path),
+        }
+    }
+
+    /// A JSON object with the runs, each one holding its hyperparameters, its epochs and the
+    /// test results
+    fn to_json(&self, path: &Path) -> anyhow::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// A row per epoch of every run, repeating the hyperparameters and test results of the run,
+    /// the missing values being left empty
+    fn to_csv(&self, path: &Path) -> anyhow::Result<()> {
+        let optional = |value: Option<Float>| value.map_or(String::new(), |v| v.to_string());
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(
+            writer,
+            "network,epochs,batch_size,learning_rate,seed,epoch,train_loss,train_accuracy,validation_loss,\
+             validation_accuracy,duration,samples_per_second,test_loss,test_accuracy"
+        )?;
+        for run in &self.runs {
+            let hyperparameters = &run.hyperparameters;
+            for record in &run.epochs {
+                writeln!(
+                    writer,
+                    "{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                    hyperparameters.network,
+                    hyperparameters.epochs,
+                    hyperparameters.batch_size,
+                    hyperparameters.learning_rate,
+                    hyperparameters
+                        .seed
+                        .map_or(String::new(), |seed| seed.to_string()),
+                    record.epoch,
+                    record.train_loss,
+                    optional(record.train_accuracy),
+                    optional(record.validation_loss),
+                    optional(record.validation_accuracy),
+                    record.duration,
+                    record.samples_per_second,
+                    optional(run.test_loss),
+                    optional(run.test_accuracy)
+                )?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+
+use egui::{Color32, ColorImage, Grid, ImageButton, TextureHandle, TextureOptions, Ui, Vec2};
+use mnist::dataset::Dataset;
+use ndarray::{s, Array2, ArrayD, Axis};
+use nn_lib::inference::InferenceModel;
+
+/// Number of test images shown by page
+const PAGE_SIZE: usize = 24;
+const COLUMNS: usize = 6;
+
+/// Side of the displayed test images, in points
+const IMAGE_SIZE: f32 = 56.0;
+
+/// Number of test images classified by frame, so that the window stays responsive while the
+/// whole set is classified
+const CHUNK_SIZE: usize = 250;
+
+/// The guess of a network for a test image, and the probability it gave to it
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Prediction {
+    digit: usize,
+    confidence: f32,
+}
+
+/// Pages through the test set of a mnist dataset, showing the label of every image and the guess
+/// of the selected network, optionally only the misclassified images
+pub struct TestSetBrowser {
+    /// (images, 784), scaled to [0, 1] like the drawings
+    images: Array2<f32>,
+    labels: Vec<usize>,
+    dataset: Dataset,
+    /// The predictions of every network, in the order they are given to `show`, filled chunk by
+    /// chunk
+    predictions: Vec<Vec<Prediction>>,
+    error: Option<String>,
+    selected: usize,
+    misclassified_only: bool,
+    page: usize,
+    textures: HashMap<usize, TextureHandle>,
+}
+
+impl TestSetBrowser {
+    /// Map the test set of `dataset` from the dataset cache
+    pub fn load(dataset: Dataset) -> anyhow::Result<Self> {
+        let (images, labels) = mnist::dataset::load_dataset_mapped(dataset)?.test;
+        let images = images
+            .view()
+            .mapv(|pixel| pixel as f32 / 255.0)
+            .into_shape((images.len(), 28 * 28))?;
+        let labels = labels.view().iter().map(|&label| label as usize).collect();
+        Ok(Self {
+            images,
+            labels,
+            dataset,
+            predictions: vec![],
+            error: None,
+            selected: 0,
+            misclassified_only: false,
+            page: 0,
+            textures: HashMap::new(),
+        })
+    }
+
+    /// Show the current page of the test set, classified by the selected network of `networks`,
+    /// (name, model once trained). Return the name and the input of the image clicked, if any.
+    pub fn show(
+        &mut self,
+        ui: &mut Ui,
+        networks: &[(&str, Option<&InferenceModel>)],
+    ) -> Option<(String, ArrayD<f32>)> {
+        self.predictions.resize(networks.len(), vec![]);
+        self.selected = self.selected.min(networks.len().saturating_sub(1));
+
+        ui.horizontal(|ui| {
+            for (index, (name, _)) in networks.iter().enumerate() {
+                if ui
+                    .selectable_value(&mut self.selected, index, *name)
+                    .changed()
+                {
+                    self.page = 0;
+                }
+            }
+            ui.separator();
+            if ui
+                .checkbox(&mut self.misclassified_only, "Misclassified only")
+                .changed()
+            {
+                self.page = 0;
+            }
+        });
+
+        let Some((_, Some(model))) = networks.get(self.selected) else {
+            ui.label("Waiting for the network to be trained");
+            return None;
+        };
+        if let Some(error) = &self.error {
+            ui.colored_label(Color32::RED, format!("Classification failed : {}", error));
+            return None;
+        }
+        self.classify_chunk(model);
+        let predictions = &self.predictions[self.selected];
+        if predictions.len() < self.labels.len() {
+            ui.label(format!(
+                "Classifying the test set, {}/{}",
+                predictions.len(),
+                self.labels.len()
+            ));
+            ui.ctx().request_repaint();
+        } else {
+            let errors = self.misclassified().count();
+            ui.label(format!(
+                "Accuracy : {:.2}%, {} misclassified of {}",
+                (1.0 - errors as f64 / self.labels.len() as f64) * 100.0,
+                errors,
+                self.labels.len()
+            ));
+        }
+
+        let shown: Vec<usize> = if self.misclassified_only {
+            self.misclassified().collect()
+        } else {
+            (0..self.labels.len()).collect()
+        };
+        let pages = shown.len().div_ceil(PAGE_SIZE).max(1);
+        self.page = self.page.min(pages - 1);
+        ui.horizontal(|ui| {
+            if ui.button("◀").clicked() {
+                self.page = self.page.saturating_sub(1);
+            }
+            ui.label(format!("Page {}/{}", self.page + 1, pages));
+            if ui.button("▶").clicked() {
+                self.page = (self.page + 1).min(pages - 1);
+            }
+        });
+
+        let mut clicked = None;
+        let page = shown.iter().skip(self.page * PAGE_SIZE).take(PAGE_SIZE);
+        Grid::new("test set page").show(ui, |ui| {
+            for (position, &index) in page.enumerate() {
+                ui.vertical(|ui| {
+                    let texture = self.texture(ui, index);
+                    if ui
+                        .add(ImageButton::new((texture.id(), Vec2::splat(IMAGE_SIZE))))
+                        .on_hover_text("Classify this image in the main window")
+                        .clicked()
+                    {
+                        clicked = Some(index);
+                    }
+                    let label = self.labels[index];
+                    match self.predictions[self.selected].get(index) {
+                        Some(prediction) => {
+                            let text = format!(
+                                "{} → {} ({:.0}%)",
+                                self.dataset.class_name(label),
+                                self.dataset.class_name(prediction.digit),
+                                prediction.confidence * 100.0
+                            );
+                            if prediction.digit == label {
+                                ui.label(text);
+                            } else {
+                                ui.colored_label(Color32::RED, text);
+                            }
+                        }
+                        None => {
+                            ui.label(format!("{} → ?", self.dataset.class_name(label)));
+                        }
+                    }
+                });
+                if (position + 1) % COLUMNS == 0 {
+                    ui.end_row();
+                }
+            }
+        });
+
+        clicked.map(|index| {
+            (
+                format!(
+                    "test image {} ({})",
+                    index,
+                    self.dataset.class_name(self.labels[index])
+                ),
+                self.images
+                    .slice(s![index..index + 1, ..])
+                    .to_owned()
+                    .into_dyn(),
+            )
+        })
+    }
+
+    /// Classify the next chunk of the test set with the selected network
+    fn classify_chunk(&mut self, model: &InferenceModel) {
+        let predictions = &mut self.predictions[self.selected];
+        let start = predictions.len();
+        let end = (start + CHUNK_SIZE).min(self.labels.len());
+        if start == end {
+            return;
+        }
+        let chunk = self.images.slice(s![start..end, ..]).to_owned().into_dyn();
+        match model.predict(&chunk) {
+            Ok(output) => predictions.extend(output.axis_iter(Axis(0)).map(|probabilities| {
+                let (digit, confidence) = probabilities
+                    .iter()
+                    .enumerate()
+                    .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                    .map_or((0, 0.0), |(digit, probability)| (digit, *probability));
+                Prediction { digit, confidence }
+            })),
+            Err(error) => self.error = Some(error.to_string()),
+        }
+    }
+
+    /// The indices of the images classified so far whose guess isn't their label
+    fn misclassified(&self) -> impl Iterator<Item = usize> + '_ {
+        self.predictions[self.selected]
+            .iter()
+            .zip(&self.labels)
+            .enumerate()
+            .filter(|(_, (prediction, label))| prediction.digit != **label)
+            .map(|(index, _)| index)
+    }
+
+    /// The texture of a test image, created the first time it is shown
+    fn texture(&mut self, ui: &Ui, index: usize) -> TextureHandle {
+        let images = &self.images;
+        self.textures
+            .entry(index)
+            .or_insert_with(|| {
+                let pixels: Vec<u8> = images
+                    .row(index)
+                    .iter()
+                    .map(|pixel| (pixel * 255.0).round() as u8)
+                    .collect();
+                ui.ctx().load_texture(
+                    format!("test image {}", index),
+                    ColorImage::from_gray([28, 28], &pixels),
+                    TextureOptions::NEAREST,
+                )
+            })
+            .clone()
+    }
+}
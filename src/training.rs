@@ -0,0 +1,180 @@
+use std::{
+    sync::mpsc::{self, Receiver, Sender, TryRecvError},
+    thread,
+};
+
+use nn_lib::{
+    callback::{BatchLogs, Callback, CallbackAction, EpochLogs},
+    metrics::MetricsType,
+    sequential::Sequential,
+    Float,
+};
+
+/// Progress of a training running on a worker thread, see `BackgroundTraining`
+pub enum TrainingEvent {
+    Batch {
+        epoch: usize,
+        epochs: usize,
+        batch: usize,
+        number_of_batch: usize,
+        loss: Float,
+    },
+    Epoch {
+        epoch: usize,
+        train_loss: Float,
+        train_accuracy: Option<Float>,
+        validation_loss: Option<Float>,
+        validation_accuracy: Option<Float>,
+    },
+    Finished(Box<anyhow::Result<Sequential>>),
+}
+
+/// Callback sending the progress of the training to the GUI, stopping it once the GUI is closed
+pub struct TrainingProgress {
+    sender: Sender<TrainingEvent>,
+    epochs: usize,
+}
+
+impl Callback for TrainingProgress {
+    fn on_train_begin(&mut self, epochs: usize) {
+        self.epochs = epochs;
+    }
+
+    fn on_batch_end(&mut self, logs: &BatchLogs) -> CallbackAction {
+        self.send(TrainingEvent::Batch {
+            epoch: logs.epoch,
+            epochs: self.epochs,
+            batch: logs.batch,
+            number_of_batch: logs.number_of_batch,
+            loss: logs.loss,
+        })
+    }
+
+    fn on_epoch_end(&mut self, logs: &EpochLogs) -> CallbackAction {
+        self.send(TrainingEvent::Epoch {
+            epoch: logs.epoch,
+            train_loss: logs.train.loss,
+            train_accuracy: logs.train.metrics.get_metric(MetricsType::Accuracy),
+            validation_loss: logs.validation.map(|validation| validation.loss),
+            validation_accuracy: logs
+                .validation
+                .and_then(|validation| validation.metrics.get_metric(MetricsType::Accuracy)),
+        })
+    }
+}
+
+impl TrainingProgress {
+    fn send(&self, event: TrainingEvent) -> CallbackAction {
+        match self.sender.send(event) {
+            Ok(()) => CallbackAction::Continue,
+            Err(_) => CallbackAction::Stop,
+        }
+    }
+}
+
+/// A network trained on a worker thread, its progress being collected for display by `poll`
+/// The curves are (epoch, value) points, the batches being placed at fractions of their epoch
+pub struct BackgroundTraining {
+    receiver: Receiver<TrainingEvent>,
+    pub epoch: usize,
+    pub epochs: usize,
+    pub batch: usize,
+    pub number_of_batch: usize,
+    pub batch_loss: Vec<[f64; 2]>,
+    pub train_loss: Vec<[f64; 2]>,
+    pub validation_loss: Vec<[f64; 2]>,
+    pub train_accuracy: Vec<[f64; 2]>,
+    pub validation_accuracy: Vec<[f64; 2]>,
+}
+
+impl BackgroundTraining {
+    /// Run `train` on a new thread, it must register the given `TrainingProgress` as a callback
+    /// of the trained network and return the network once trained
+    pub fn spawn<F>(train: F) -> Self
+    where
+        F: FnOnce(TrainingProgress) -> anyhow::Result<Sequential> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let progress = TrainingProgress {
+            sender: sender.clone(),
+            epochs: 0,
+        };
+        thread::spawn(move || {
+            let result = train(progress);
+            let _ = sender.send(TrainingEvent::Finished(Box::new(result)));
+        });
+        Self {
+            receiver,
+            epoch: 0,
+            epochs: 0,
+            batch: 0,
+            number_of_batch: 0,
+            batch_loss: vec![],
+            train_loss: vec![],
+            validation_loss: vec![],
+            train_accuracy: vec![],
+            validation_accuracy: vec![],
+        }
+    }
+
+    /// Collect the progress sent since the last call, returning the outcome of the training
+    /// once it is over
+    // the losses are `Float`, only an `f64` with the default precision
+    #[allow(clippy::unnecessary_cast)]
+    pub fn poll(&mut self) -> Option<anyhow::Result<Sequential>> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(TrainingEvent::Batch {
+                    epoch,
+                    epochs,
+                    batch,
+                    number_of_batch,
+                    loss,
+                }) => {
+                    self.epoch = epoch;
+                    self.epochs = epochs;
+                    self.batch = batch + 1;
+                    self.number_of_batch = number_of_batch;
+                    let position = epoch as f64 + self.batch as f64 / number_of_batch as f64;
+                    self.batch_loss.push([position, loss as f64]);
+                }
+                Ok(TrainingEvent::Epoch {
+                    epoch,
+                    train_loss,
+                    train_accuracy,
+                    validation_loss,
+                    validation_accuracy,
+                }) => {
+                    let position = (epoch + 1) as f64;
+                    self.train_loss.push([position, train_loss as f64]);
+                    let curves = [
+                        (&mut self.train_accuracy, train_accuracy),
+                        (&mut self.validation_loss, validation_loss),
+                        (&mut self.validation_accuracy, validation_accuracy),
+                    ];
+                    for (curve, value) in curves {
+                        if let Some(value) = value {
+                            curve.push([position, value as f64]);
+                        }
+                    }
+                }
+                Ok(TrainingEvent::Finished(result)) => return Some(*result),
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    return Some(Err(anyhow::anyhow!(
+                        "The training thread stopped unexpectedly"
+                    )))
+                }
+            }
+        }
+    }
+
+    /// Fraction of the training done, from 0 to 1
+    pub fn fraction(&self) -> f32 {
+        if self.epochs == 0 || self.number_of_batch == 0 {
+            return 0.0;
+        }
+        let batches = self.epoch * self.number_of_batch + self.batch;
+        batches as f32 / (self.epochs * self.number_of_batch) as f32
+    }
+}
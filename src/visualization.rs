@@ -0,0 +1,116 @@
+use egui::{ColorImage, Context, TextureHandle, TextureOptions, Ui, Vec2};
+use ndarray::{ArrayD, ArrayView2, Axis};
+use nn_lib::{
+    layer::{ConvolutionalLayer, LayerError},
+    sequential::Sequential,
+    Float,
+};
+
+/// Side of the displayed kernels and feature maps, in points
+const IMAGE_SIZE: f32 = 56.0;
+
+/// Images of a layer, under the index and type of the layer
+struct LayerImages {
+    title: String,
+    images: Vec<TextureHandle>,
+}
+
+/// The learned kernels of the convolutional layers of a network, and the feature maps of its
+/// layers with a spatial output (height, width, channels) for an input
+pub struct FeatureMaps {
+    input: ArrayD<f32>,
+    kernels: Vec<LayerImages>,
+    maps: Vec<LayerImages>,
+}
+
+impl FeatureMaps {
+    /// Run `network` layer by layer on `input` and turn its kernels and feature maps into
+    /// textures, every image being scaled from its own minimum (black) to its maximum (white)
+    pub fn new(
+        context: &Context,
+        network: &Sequential,
+        input: &ArrayD<f32>,
+    ) -> Result<Self, LayerError> {
+        let activations = network.predict_with_activations(&input.mapv(|x| x as Float))?;
+        let mut kernels = vec![];
+        let mut maps = vec![];
+        for (index, (layer, activation)) in network.layers().iter().zip(&activations).enumerate() {
+            let title = format!("{} {}", index, layer.type_name());
+            if let Some(convolution) = layer.as_any().downcast_ref::<ConvolutionalLayer>() {
+                // (kernel height, kernel width, input channels, kernels), averaged over the
+                // input channels
+                let weights = convolution.kernels().mean_axis(Axis(2)).unwrap();
+                kernels.push(LayerImages {
+                    title: title.clone(),
+                    images: channels(context, &title, &weights),
+                });
+            }
+            // a batch of one (height, width, channels) output
+            if activation.ndim() == 4 {
+                let activation = activation.index_axis(Axis(0), 0);
+                maps.push(LayerImages {
+                    images: channels(context, &title, &activation.to_owned()),
+                    title,
+                });
+            }
+        }
+        Ok(Self {
+            input: input.clone(),
+            kernels,
+            maps,
+        })
+    }
+
+    /// Whether the feature maps were computed for `input`
+    pub fn is_for(&self, input: &ArrayD<f32>) -> bool {
+        self.input == *input
+    }
+
+    pub fn show(&self, ui: &mut Ui) {
+        ui.heading("Kernels");
+        show_layers(ui, &self.kernels);
+        ui.separator();
+        ui.heading("Feature maps");
+        show_layers(ui, &self.maps);
+    }
+}
+
+fn show_layers(ui: &mut Ui, layers: &[LayerImages]) {
+    for layer in layers {
+        ui.label(layer.title.as_str());
+        ui.horizontal_wrapped(|ui| {
+            for image in &layer.images {
+                ui.image((image.id(), Vec2::splat(IMAGE_SIZE)));
+            }
+        });
+    }
+}
+
+/// A texture per channel of a (height, width, channels) array
+fn channels(context: &Context, name: &str, array: &ArrayD<Float>) -> Vec<TextureHandle> {
+    array
+        .axis_iter(Axis(2))
+        .enumerate()
+        .filter_map(|(channel, values)| {
+            let values = values.into_dimensionality().ok()?;
+            Some(context.load_texture(
+                format!("{} channel {}", name, channel),
+                grayscale(values),
+                TextureOptions::NEAREST,
+            ))
+        })
+        .collect()
+}
+
+/// The values of a (height, width) array scaled between black and white
+fn grayscale(values: ArrayView2<Float>) -> ColorImage {
+    let (height, width) = values.dim();
+    let min = values.iter().copied().fold(Float::INFINITY, Float::min);
+    let max = values.iter().copied().fold(Float::NEG_INFINITY, Float::max);
+    let range = if max > min { max - min } else { 1.0 };
+    let pixels: Vec<u8> = values
+        .iter()
+        .map(|value| ((value - min) / range * 255.0).round() as u8)
+        .collect();
+    ColorImage::from_gray([width, height], &pixels)
+}